@@ -0,0 +1,58 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate Demo.
+
+// Substrate Demo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate Demo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate Demo.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate Demo chain specifications.
+
+use keyring::Keyring;
+use demo_runtime::genesismap::GenesisConfig;
+use substrate_cli::chain_spec;
+
+/// A chain specification with a Substrate Demo runtime genesis.
+pub type ChainSpec = chain_spec::ChainSpec<GenesisConfig>;
+
+/// Single-validator development chain, keyed by the well-known development key.
+pub fn development() -> ChainSpec {
+	let god_key = hex!["3d866ec8a9190c8343c2fc593d21d8a6d0c5c4763aaab2349de3a6111d64d124"];
+	ChainSpec::from_genesis("Development", Vec::new(), GenesisConfig {
+		validators: vec![god_key.clone()],
+		authorities: vec![god_key.clone()],
+		balances: vec![(god_key.clone(), 1u64 << 63)].into_iter().collect(),
+		block_time: 5,			// 5 second block time.
+		session_length: 720,	// that's 1 hour per session.
+		sessions_per_era: 24,	// 24 hours per era.
+		bonding_duration: 90,	// 90 days per bond.
+		launch_period: 120 * 24 * 14,	// 2 weeks per public referendum
+		voting_period: 120 * 24 * 28,	// 4 weeks to discuss & vote on an active referendum
+		minimum_deposit: 1000,	// 1000 as the minimum deposit for a referendum
+		candidacy_bond: 1000,	// 1000 to become a council candidate
+		voter_bond: 100,		// 100 down to vote for a candidate
+		present_slash_per_voter: 1,	// slash by 1 per voter for an invalid presentation.
+		carry_count: 24,		// carry over the 24 runners-up to the next council election
+		presentation_duration: 120 * 24,	// one day for presenting winners.
+		council_election_voting_period: 7 * 120 * 24,	// one week period between possible council elections.
+		council_term_duration: 180 * 120 * 24,	// 180 day term duration for the council.
+		desired_seats: 0, // start with no council: we'll raise this once the stake has been dispersed a bit.
+		inactive_grace_period: 1,	// one addition vote should go by before an inactive voter can be reaped.
+		cooloff_period: 90 * 120 * 24, // 90 day cooling off period if council member vetoes a proposal.
+		council_proposal_voting_period: 7 * 120 * 24, // 7 day voting period for council members.
+	}, GenesisConfig::genesis_map)
+}
+
+/// Local testnet with Alice and Bob as validators.
+pub fn local_testnet() -> ChainSpec {
+	let validators = vec![Keyring::Alice.to_raw_public(), Keyring::Bob.to_raw_public()];
+	ChainSpec::from_genesis("Local Testnet", Vec::new(), GenesisConfig::new_simple(validators, 1u64 << 60), GenesisConfig::genesis_map)
+}
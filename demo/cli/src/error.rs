@@ -17,6 +17,7 @@
 //! Initialization errors.
 
 use client;
+use substrate_cli;
 
 error_chain! {
 	foreign_links {
@@ -25,5 +26,13 @@ error_chain! {
 	}
 	links {
 		Client(client::error::Error, client::error::ErrorKind) #[doc="Client error"];
+		Node(substrate_cli::error::Error, substrate_cli::error::ErrorKind) #[doc="Node error"];
     }
+	errors {
+		/// Invalid command line argument value.
+		Input(m: String) {
+			description("Invalid input"),
+			display("{}", m),
+		}
+	}
 }
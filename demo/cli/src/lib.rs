@@ -18,14 +18,19 @@
 
 #![warn(missing_docs)]
 
-extern crate env_logger;
+extern crate tokio_core;
 extern crate ed25519;
 extern crate triehash;
 extern crate substrate_codec as codec;
 extern crate substrate_state_machine as state_machine;
 extern crate substrate_client as client;
+extern crate substrate_client_db as client_db;
+extern crate substrate_cli;
+extern crate substrate_executor;
+extern crate substrate_network as network;
 extern crate substrate_primitives as primitives;
-extern crate substrate_rpc_servers as rpc;
+extern crate substrate_rpc;
+extern crate substrate_keyring as keyring;
 extern crate demo_primitives;
 extern crate demo_executor;
 extern crate demo_runtime;
@@ -39,11 +44,60 @@ extern crate error_chain;
 #[macro_use]
 extern crate log;
 
+pub mod chain_spec;
 pub mod error;
 
+use std::path::Path;
+use std::sync::Arc;
+
+use client::Client;
 use codec::Slicable;
 use demo_runtime::genesismap::{additional_storage_with_genesis, GenesisConfig};
+use chain_spec::ChainSpec;
 use client::genesis;
+use primitives::block;
+use substrate_executor::NativeExecutor;
+use substrate_rpc::author;
+use substrate_rpc::system::SystemInfo;
+use tokio_core::reactor;
+
+/// The demo node has no transaction pool yet, so transactions submitted over RPC or
+/// received from the network are rejected.
+struct DummyPool;
+
+impl author::AuthorApi for DummyPool {
+	type Metadata = substrate_rpc::metadata::Metadata;
+
+	fn submit_transaction(&self, _: block::Transaction) -> author::error::Result<block::TransactionHash> {
+		Err(author::error::ErrorKind::Unimplemented.into())
+	}
+
+	fn pending_transactions(&self) -> author::error::Result<Vec<block::Transaction>> {
+		Ok(Vec::new())
+	}
+
+	fn transaction_status(&self, _: block::TransactionHash) -> author::error::Result<author::TransactionStatus> {
+		Ok(author::TransactionStatus::Unknown)
+	}
+
+	fn watch_transaction(&self, _: Self::Metadata, subscriber: substrate_rpc::pubsub::Subscriber<author::TransactionStatus>, _: block::Transaction) {
+		let _ = subscriber.reject(author::error::Error::from(author::error::ErrorKind::Unimplemented).into());
+	}
+
+	fn unwatch_transaction(&self, _: substrate_rpc::SubscriptionId) -> substrate_rpc::RpcResult<bool> {
+		Ok(false)
+	}
+}
+
+impl network::TransactionPool for DummyPool {
+	fn transactions(&self) -> Vec<(block::TransactionHash, Vec<u8>)> {
+		Vec::new()
+	}
+
+	fn import(&self, _: &[u8]) -> Option<block::TransactionHash> {
+		None
+	}
+}
 
 /// Parse command line arguments and start the node.
 ///
@@ -62,73 +116,105 @@ pub fn run<I, T>(args: I) -> error::Result<()> where
 
 	// TODO [ToDr] Split parameters parsing from actual execution.
 	let log_pattern = matches.value_of("log").unwrap_or("");
-	init_logger(log_pattern);
+	substrate_cli::init_logger(log_pattern);
+
+	if let Some(sub_matches) = matches.subcommand_matches("build-spec") {
+		let spec = load_spec(&matches)?;
+		info!("Building chain spec");
+		let json = spec.to_json(sub_matches.is_present("raw")).map_err(error::ErrorKind::Input)?;
+		println!("{}", json);
+		return Ok(());
+	}
+
+	if matches.subcommand_name().is_none() {
+		println!("No command given.\n");
+		let _ = clap::App::from_yaml(yaml).print_long_help();
+		return Ok(());
+	}
+
+	let spec = load_spec(&matches)?;
+	info!("Chain specification: {}", spec.name());
 
 	// Create client
 	let executor = demo_executor::Executor::new();
 	let mut storage = Default::default();
-	let god_key = hex!["3d866ec8a9190c8343c2fc593d21d8a6d0c5c4763aaab2349de3a6111d64d124"];
-
-	let genesis_config = GenesisConfig {
-		validators: vec![god_key.clone()],
-		authorities: vec![god_key.clone()],
-		balances: vec![(god_key.clone(), 1u64 << 63)].into_iter().collect(),
-		block_time: 5,			// 5 second block time.
-		session_length: 720,	// that's 1 hour per session.
-		sessions_per_era: 24,	// 24 hours per era.
-		bonding_duration: 90,	// 90 days per bond.
-		launch_period: 120 * 24 * 14,	// 2 weeks per public referendum
-		voting_period: 120 * 24 * 28,	// 4 weeks to discuss & vote on an active referendum
-		minimum_deposit: 1000,	// 1000 as the minimum deposit for a referendum
-		candidacy_bond: 1000,	// 1000 to become a council candidate
-		voter_bond: 100,		// 100 down to vote for a candidate
-		present_slash_per_voter: 1,	// slash by 1 per voter for an invalid presentation.
-		carry_count: 24,		// carry over the 24 runners-up to the next council election
-		presentation_duration: 120 * 24,	// one day for presenting winners.
-		council_election_voting_period: 7 * 120 * 24,	// one week period between possible council elections.
-		council_term_duration: 180 * 120 * 24,	// 180 day term duration for the council.
-		desired_seats: 0, // start with no council: we'll raise this once the stake has been dispersed a bit.
-		inactive_grace_period: 1,	// one addition vote should go by before an inactive voter can be reaped.
-		cooloff_period: 90 * 120 * 24, // 90 day cooling off period if council member vetoes a proposal.
-		council_proposal_voting_period: 7 * 120 * 24, // 7 day voting period for council members.
-	};
+
 	let prepare_genesis = || {
-		storage = genesis_config.genesis_map();
+		storage = spec.genesis_map();
 		let block = genesis::construct_genesis_block(&storage);
 		storage.extend(additional_storage_with_genesis(&block));
 		(primitives::block::Header::decode(&mut block.header.encode().as_ref()).expect("to_vec() always gives a valid serialisation; qed"), storage.into_iter().collect())
 	};
-	let client = client::new_in_mem(executor, prepare_genesis)?;
 
-	let address = "127.0.0.1:9933".parse().unwrap();
-	let handler = rpc::rpc_handler(client);
-	let server = rpc::start_http(&address, handler)?;
+	let base_path = substrate_cli::base_path(&matches, "Substrate Demo");
 
-	if let Some(_) = matches.subcommand_matches("validator") {
-		info!("Starting validator.");
-		server.wait();
-		return Ok(());
+	if let Some(sub_matches) = matches.subcommand_matches("purge-chain") {
+		return Ok(substrate_cli::purge_chain(&base_path.join("db"), sub_matches.is_present("yes"))?);
 	}
 
-	println!("No command given.\n");
-	let _ = clap::App::from_yaml(yaml).print_long_help();
+	if matches.is_present("light") {
+		info!("Starting light client.");
+		let on_demand = Arc::new(network::OnDemand::new(Arc::new(client::light::LightDataChecker::new(executor))));
+		let client = Arc::new(client::light::new_light(on_demand.clone(), prepare_genesis)?);
+		return run_node(client, &matches, &base_path, &spec, network::Role::LIGHT, Some(on_demand));
+	}
 
-	Ok(())
+	if matches.is_present("dev") {
+		info!("Running in development mode with in-memory storage.");
+		let client = Arc::new(client::new_in_mem(executor, prepare_genesis)?);
+		execute(client, &matches, &base_path, &spec)
+	} else {
+		let db_settings = substrate_cli::database_settings(&matches, &base_path)?;
+		let client = Arc::new(client_db::new_client(db_settings, executor, prepare_genesis)?);
+		execute(client, &matches, &base_path, &spec)
+	}
 }
 
-fn init_logger(pattern: &str) {
-	let mut builder = env_logger::LogBuilder::new();
-	// Disable info logging by default for some modules:
-	builder.filter(Some("hyper"), log::LogLevelFilter::Warn);
-	// Enable info for others.
-	builder.filter(None, log::LogLevelFilter::Info);
+// Load the chain specification named by `--chain`.
+fn load_spec(matches: &clap::ArgMatches) -> error::Result<ChainSpec> {
+	Ok(substrate_cli::load_spec(matches, chain_spec::development, chain_spec::local_testnet, GenesisConfig::genesis_map)?)
+}
 
-	if let Ok(lvl) = std::env::var("RUST_LOG") {
-		builder.parse(&lvl);
+// Run the subcommand given on the command line against the client.
+fn execute<B>(
+	client: Arc<Client<B, NativeExecutor<demo_executor::Executor>>>,
+	matches: &clap::ArgMatches,
+	base_path: &Path,
+	spec: &ChainSpec,
+) -> error::Result<()> where
+	B: client::backend::Backend + Send + Sync + 'static,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	if substrate_cli::execute_chain_command(&*client, matches)? {
+		return Ok(());
 	}
 
-	builder.parse(pattern);
+	info!("Starting validator.");
+	run_node(client, matches, base_path, spec, network::Role::FULL | network::Role::VALIDATOR, None)
+}
 
+// Start the network and RPC servers on top of the given client and run until the
+// process is killed.
+fn run_node<B, E>(
+	client: Arc<Client<B, E>>,
+	matches: &clap::ArgMatches,
+	base_path: &Path,
+	spec: &ChainSpec,
+	role: network::Role,
+	on_demand: Option<Arc<network::OnDemand>>,
+) -> error::Result<()> where
+	B: client::backend::Backend + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let system_info = SystemInfo {
+		impl_name: "substrate-demo".into(),
+		impl_version: crate_version!().into(),
+		chain_name: spec.name().into(),
+	};
 
-	builder.init().expect("Logger initialized only once.");
+	let core = reactor::Core::new()?;
+	// There is no proposer for the demo runtime, so the node doesn't take part in the block authoring.
+	substrate_cli::run_node(core, client, matches, base_path, spec.boot_nodes(), system_info, role, on_demand, Arc::new(DummyPool), |_, _| DummyPool, None, |_, _| ())?;
+	Ok(())
 }
@@ -26,6 +26,9 @@ use runtime::staking::Balance;
 use runtime::{staking, session, consensus, system, democracy, council, council_vote};
 
 /// Configuration of a general Substrate Demo genesis block.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
 pub struct GenesisConfig {
 	pub validators: Vec<AccountId>,
 	pub authorities: Vec<AccountId>,
@@ -78,7 +81,8 @@ impl GenesisConfig {
 	}
 
 	pub fn genesis_map(&self) -> HashMap<Vec<u8>, Vec<u8>> {
-		let wasm_runtime = include_bytes!("../wasm/genesis.wasm").to_vec();
+		// the runtime built by `wasm/build.sh`, which the native executor is matched against.
+		let wasm_runtime = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/demo_runtime.compact.wasm").to_vec();
 		vec![
 			(session::SessionLength::key(), vec![].and(&self.session_length)),
 			(session::Validators::key(), vec![].and(&self.validators)),
@@ -65,9 +65,14 @@ storage_items! {
 
 	// The code associated with an account.
 	pub CodeOf: b"sta:cod:" => default map [ AccountId => Vec<u8> ];	// TODO Vec<u8> values should be optimised to not do a length prefix.
+}
 
-	// The storage items associated with an account/key.
-	pub StorageOf: b"sta:sto:" => map [ (AccountId, Vec<u8>) => Vec<u8> ];	// TODO: keys should also be able to take AsRef<KeyType> to ensure Vec<u8>s can be passed as &[u8]
+/// The storage key of the child trie holding the storage items of the account `who`.
+pub fn storage_key_of(who: &AccountId) -> Vec<u8> {
+	let mut key = storage::child::CHILD_STORAGE_KEY_PREFIX.to_vec();
+	key.extend_from_slice(b"sta:sto:");
+	key.extend_from_slice(&who[..]);
+	key
 }
 
 /// The length of a staking era in blocks.
@@ -260,7 +265,7 @@ mod private {
 	pub struct DirectExt;
 	impl Externalities for DirectExt {
 		fn get_storage(&self, account: &AccountId, location: &[u8]) -> Option<Vec<u8>> {
-			StorageOf::get(&(*account, location.to_vec()))
+			storage::child::get_raw(&storage_key_of(account), location)
 		}
 		fn get_code(&self, account: &AccountId) -> Vec<u8> {
 			CodeOf::get(account)
@@ -291,14 +296,15 @@ mod private {
 			if let Some(balance) = changed.balance {
 				FreeBalanceOf::insert(address, balance);
 			}
+			let storage_key = storage_key_of(&address);
 			if let Some(code) = changed.code {
 				CodeOf::insert(&address, &code);
 			}
 			for (k, v) in changed.storage.into_iter() {
 				if let Some(value) = v {
-					StorageOf::insert(&(address, k), &value);
+					storage::child::put_raw(&storage_key, &k, &value);
 				} else {
-					StorageOf::remove(&(address, k));
+					storage::child::kill(&storage_key, &k);
 				}
 			}
 		}
@@ -0,0 +1,330 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The `author` RPC namespace, served from the transaction pool.
+
+use std::sync::Arc;
+
+use codec::Slicable;
+use futures::{stream, Future, Sink, Stream};
+use parking_lot::Mutex;
+use polkadot_api::PolkadotApi;
+use polkadot_primitives::block::Id as BlockId;
+use polkadot_primitives::transaction::UncheckedTransaction;
+use primitives::block::{Transaction, TransactionHash};
+use substrate_rpc::{self, pubsub, RpcResult, SubscriptionId, Subscriptions};
+use substrate_rpc::author::{self, AuthorApi, TransactionStatus};
+use transaction_pool::{self, Ready, TransactionPool};
+
+/// Serves the `author` RPC namespace from a shared transaction pool.
+pub struct AuthorHandle<A> {
+	pool: Arc<Mutex<TransactionPool>>,
+	api: Arc<A>,
+	subscriptions: Subscriptions,
+	on_import: Option<Box<Fn() + Send + Sync>>,
+}
+
+impl<A: PolkadotApi> AuthorHandle<A> {
+	/// Create a new handle. Readiness is evaluated at the block the pool was
+	/// last culled against.
+	pub fn new(pool: Arc<Mutex<TransactionPool>>, api: Arc<A>, subscriptions: Subscriptions) -> Self {
+		AuthorHandle { pool, api, subscriptions, on_import: None }
+	}
+
+	/// Call the given function after each transaction submitted to the pool,
+	/// e.g. to propagate it to the network.
+	pub fn on_import<F: Fn() + Send + Sync + 'static>(mut self, on_import: F) -> Self {
+		self.on_import = Some(Box::new(on_import));
+		self
+	}
+
+	fn import(&self, transaction: Transaction) -> author::error::Result<transaction_pool::TransactionHash> {
+		let transaction: UncheckedTransaction = Slicable::decode(&mut transaction.encode().as_slice())
+			.ok_or(author::error::ErrorKind::BadFormat)?;
+
+		let verified = self.pool.lock().import(transaction)
+			.map_err(|e| author::error::ErrorKind::Verification(Box::new(e)))?;
+
+		if let Some(ref on_import) = self.on_import {
+			on_import();
+		}
+		Ok(verified.hash().clone())
+	}
+
+	fn best_block_id(&self, pool: &TransactionPool) -> Option<A::CheckedBlockId> {
+		pool.best_block().and_then(|hash| self.api.check_id(BlockId::Hash(hash)).ok())
+	}
+
+	fn status(&self, pool: &TransactionPool, hash: &transaction_pool::TransactionHash) -> TransactionStatus {
+		let ready = self.best_block_id(pool).map(|id| Ready::create(id, &*self.api));
+		to_rpc_status(pool.transaction_status(hash, ready))
+	}
+}
+
+impl<A> AuthorApi for AuthorHandle<A> where
+	A: PolkadotApi + Send + Sync + 'static,
+{
+	type Metadata = substrate_rpc::metadata::Metadata;
+
+	fn submit_transaction(&self, transaction: Transaction) -> author::error::Result<TransactionHash> {
+		self.import(transaction).map(|hash| hash.0.into())
+	}
+
+	fn pending_transactions(&self) -> author::error::Result<Vec<Transaction>> {
+		let pool = self.pool.lock();
+		let pending = match self.best_block_id(&pool) {
+			Some(id) => pool.pending(Ready::create(id, &*self.api))
+				.map(|tx| Slicable::decode(&mut tx.as_transaction().encode().as_slice())
+					.expect("unchecked transactions are encoded as length-prefixed bytes; qed"))
+				.collect(),
+			None => Vec::new(),
+		};
+
+		Ok(pending)
+	}
+
+	fn transaction_status(&self, hash: TransactionHash) -> author::error::Result<TransactionStatus> {
+		let pool = self.pool.lock();
+		Ok(self.status(&pool, &hash.0.into()))
+	}
+
+	fn watch_transaction(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<TransactionStatus>, transaction: Transaction) {
+		let hash = match self.import(transaction) {
+			Ok(hash) => hash,
+			Err(e) => {
+				let _ = subscriber.reject(e.into());
+				return;
+			}
+		};
+
+		let (status, updates) = {
+			let mut pool = self.pool.lock();
+			(self.status(&pool, &hash), pool.watch(hash))
+		};
+
+		self.subscriptions.add(subscriber, |sink| {
+			let statuses = stream::iter_ok::<_, ()>(vec![Ok(status)])
+				.chain(updates.map(|status| Ok(to_rpc_status(status))));
+			sink
+				.sink_map_err(|e| warn!("Error sending transaction status: {:?}", e))
+				.send_all(statuses)
+				.map(|_| ())
+		});
+	}
+
+	fn unwatch_transaction(&self, id: SubscriptionId) -> RpcResult<bool> {
+		Ok(self.subscriptions.cancel(id))
+	}
+}
+
+fn to_rpc_status(status: transaction_pool::TransactionStatus) -> TransactionStatus {
+	match status {
+		transaction_pool::TransactionStatus::Ready => TransactionStatus::Ready,
+		transaction_pool::TransactionStatus::Future => TransactionStatus::Future,
+		transaction_pool::TransactionStatus::Included(block) => TransactionStatus::Included(block.0.into()),
+		transaction_pool::TransactionStatus::Dropped => TransactionStatus::Dropped,
+		transaction_pool::TransactionStatus::Unknown => TransactionStatus::Unknown,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use keyring::Keyring;
+	use polkadot_api::{BlockBuilder, CheckedBlockId, ErrorKind as ApiErrorKind, Result as ApiResult};
+	use polkadot_primitives::{AccountId, SessionKey, Timestamp, TxOrder};
+	use polkadot_primitives::block::{Block, HeaderHash};
+	use polkadot_primitives::parachain::DutyRoster;
+	use polkadot_primitives::transaction::{Function, Transaction as PolkadotTransaction};
+	use substrate_rpc::author::error::{Error as AuthorError, ErrorKind as AuthorErrorKind};
+	use tokio_core::reactor::Core;
+
+	#[derive(Clone)]
+	struct TestCheckedId(BlockId);
+
+	impl CheckedBlockId for TestCheckedId {
+		fn block_id(&self) -> &BlockId {
+			&self.0
+		}
+	}
+
+	// blocks are never built when serving the RPC.
+	enum TestBlockBuilder {}
+
+	impl BlockBuilder for TestBlockBuilder {
+		fn push_transaction(&mut self, _transaction: UncheckedTransaction) -> ApiResult<()> {
+			match *self {}
+		}
+
+		fn bake(self) -> Block {
+			match self {}
+		}
+	}
+
+	// only the nonce is used by the pool; it is the same for every account and block.
+	#[derive(Default)]
+	struct TestApi {
+		nonce: Mutex<TxOrder>,
+	}
+
+	impl PolkadotApi for TestApi {
+		type CheckedBlockId = TestCheckedId;
+		type BlockBuilder = TestBlockBuilder;
+
+		fn check_id(&self, id: BlockId) -> ApiResult<TestCheckedId> {
+			Ok(TestCheckedId(id))
+		}
+
+		fn session_keys(&self, _at: &TestCheckedId) -> ApiResult<Vec<SessionKey>> {
+			Ok(Vec::new())
+		}
+
+		fn validators(&self, _at: &TestCheckedId) -> ApiResult<Vec<AccountId>> {
+			Ok(Vec::new())
+		}
+
+		fn duty_roster(&self, _at: &TestCheckedId) -> ApiResult<DutyRoster> {
+			Ok(Default::default())
+		}
+
+		fn timestamp(&self, _at: &TestCheckedId) -> ApiResult<Timestamp> {
+			Ok(0)
+		}
+
+		fn nonce(&self, _at: &TestCheckedId, _account: AccountId) -> ApiResult<TxOrder> {
+			Ok(*self.nonce.lock())
+		}
+
+		fn evaluate_block(&self, _at: &TestCheckedId, _block: Block) -> ApiResult<()> {
+			Ok(())
+		}
+
+		fn build_block(&self, _parent: &TestCheckedId, _timestamp: Timestamp) -> ApiResult<TestBlockBuilder> {
+			Err(ApiErrorKind::UnknownRuntime.into())
+		}
+	}
+
+	fn transaction(nonce: u64) -> UncheckedTransaction {
+		let transaction = PolkadotTransaction {
+			signed: Keyring::One.to_raw_public(),
+			nonce,
+			function: Function::StakingTransfer(Keyring::Two.to_raw_public(), 69),
+		};
+		let signature = Keyring::One.sign(&transaction.encode());
+
+		UncheckedTransaction { transaction, signature }
+	}
+
+	fn substrate_transaction(nonce: u64) -> Transaction {
+		Slicable::decode(&mut transaction(nonce).encode().as_slice()).unwrap()
+	}
+
+	fn ready_at(block: HeaderHash, api: &TestApi) -> Ready<TestApi> {
+		Ready::create(TestCheckedId(BlockId::Hash(block)), api)
+	}
+
+	#[test]
+	fn author_submits_and_reports_transactions() {
+		let core = Core::new().unwrap();
+		let api = Arc::new(TestApi::default());
+		let pool = Arc::new(Mutex::new(TransactionPool::new(Default::default())));
+		let author = AuthorHandle::new(pool.clone(), api.clone(), Subscriptions::new(core.remote()));
+
+		let hash = author.submit_transaction(substrate_transaction(1)).unwrap();
+
+		// readiness can't be evaluated before the pool knows the best block.
+		assert_eq!(author.transaction_status(hash.clone()).unwrap(), TransactionStatus::Future);
+		assert!(author.pending_transactions().unwrap().is_empty());
+
+		pool.lock().cull_block(1.into(), &[], ready_at(1.into(), &api));
+		assert_eq!(author.transaction_status(hash).unwrap(), TransactionStatus::Ready);
+		assert_eq!(author.pending_transactions().unwrap(), vec![substrate_transaction(1)]);
+
+		let future = author.submit_transaction(substrate_transaction(3)).unwrap();
+		assert_eq!(author.transaction_status(future).unwrap(), TransactionStatus::Future);
+		assert_eq!(author.transaction_status(2.into()).unwrap(), TransactionStatus::Unknown);
+	}
+
+	#[test]
+	fn author_rejects_invalid_transactions() {
+		let core = Core::new().unwrap();
+		let pool = Arc::new(Mutex::new(TransactionPool::new(Default::default())));
+		let author = AuthorHandle::new(pool, Arc::new(TestApi::default()), Subscriptions::new(core.remote()));
+
+		match author.submit_transaction(Transaction(vec![1, 2, 3])) {
+			Err(AuthorError(AuthorErrorKind::BadFormat, _)) => {},
+			other => panic!("unexpected result: {:?}", other),
+		}
+
+		let mut bad = transaction(1);
+		bad.transaction.nonce = 2;
+		let bad: Transaction = Slicable::decode(&mut bad.encode().as_slice()).unwrap();
+		match author.submit_transaction(bad) {
+			Err(AuthorError(AuthorErrorKind::Verification(_), _)) => {},
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn author_reports_imported_transactions() {
+		let core = Core::new().unwrap();
+		let pool = Arc::new(Mutex::new(TransactionPool::new(Default::default())));
+		let imported = Arc::new(AtomicUsize::new(0));
+		let counter = imported.clone();
+		let author = AuthorHandle::new(pool, Arc::new(TestApi::default()), Subscriptions::new(core.remote()))
+			.on_import(move || { counter.fetch_add(1, Ordering::SeqCst); });
+
+		author.submit_transaction(substrate_transaction(1)).unwrap();
+		assert!(author.submit_transaction(Transaction(vec![1, 2, 3])).is_err());
+		assert_eq!(imported.load(Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn author_notifies_watchers_until_included() {
+		let mut core = Core::new().unwrap();
+		let api = Arc::new(TestApi::default());
+		let pool = Arc::new(Mutex::new(TransactionPool::new(Default::default())));
+		let author = AuthorHandle::new(pool.clone(), api.clone(), Subscriptions::new(core.remote()));
+		let (subscriber, id, transport) = pubsub::Subscriber::new_test("test");
+
+		author.watch_transaction(Default::default(), subscriber, substrate_transaction(1));
+		assert!(core.run(id).unwrap().is_ok());
+
+		// the current status is sent straight away.
+		let (notification, transport) = core.run(transport.into_future()).map_err(|_| ()).unwrap();
+		assert!(notification.unwrap().contains(r#""result":"future""#));
+
+		*api.nonce.lock() = 1;
+		pool.lock().cull_block(1.into(), &[substrate_transaction(1)], ready_at(1.into(), &api));
+		let (notification, _) = core.run(transport.into_future()).map_err(|_| ()).unwrap();
+		assert!(notification.unwrap().contains(r#""result":{"included":"#));
+	}
+
+	#[test]
+	fn author_cancels_watchers() {
+		let mut core = Core::new().unwrap();
+		let pool = Arc::new(Mutex::new(TransactionPool::new(Default::default())));
+		let author = AuthorHandle::new(pool, Arc::new(TestApi::default()), Subscriptions::new(core.remote()));
+		let (subscriber, id, _transport) = pubsub::Subscriber::new_test("test");
+
+		author.watch_transaction(Default::default(), subscriber, substrate_transaction(1));
+		let id = core.run(id).unwrap().unwrap();
+
+		assert_eq!(author.unwatch_transaction(id.clone()), Ok(true));
+		assert_eq!(author.unwatch_transaction(id), Ok(false));
+	}
+}
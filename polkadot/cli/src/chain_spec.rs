@@ -0,0 +1,45 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Polkadot chain specifications.
+
+use keyring::Keyring;
+use polkadot_runtime::genesismap::GenesisConfig;
+use substrate_cli::chain_spec;
+
+/// A chain specification with a Polkadot runtime genesis.
+pub type ChainSpec = chain_spec::ChainSpec<GenesisConfig>;
+
+/// Single-validator development chain, keyed by the well-known development key.
+pub fn development() -> ChainSpec {
+	let god_key = hex!["3d866ec8a9190c8343c2fc593d21d8a6d0c5c4763aaab2349de3a6111d64d124"];
+	ChainSpec::from_genesis("Development", Vec::new(), GenesisConfig {
+		validators: vec![god_key.clone()],
+		authorities: vec![god_key.clone()],
+		balances: vec![(god_key.clone(), 1u64 << 63)].into_iter().collect(),
+		block_time: 5,			// 5 second block time.
+		session_length: 720,	// that's 1 hour per session.
+		sessions_per_era: 24,	// 24 hours per era.
+		bonding_duration: 90,	// 90 days per bond.
+		approval_ratio: 667,	// 66.7% approvals required for legislation.
+	}, GenesisConfig::genesis_map)
+}
+
+/// Local testnet with Alice and Bob as validators.
+pub fn local_testnet() -> ChainSpec {
+	let validators = vec![Keyring::Alice.to_raw_public(), Keyring::Bob.to_raw_public()];
+	ChainSpec::from_genesis("Local Testnet", Vec::new(), GenesisConfig::new_simple(validators, 1u64 << 60), GenesisConfig::genesis_map)
+}
@@ -17,6 +17,7 @@
 //! Initialization errors.
 
 use client;
+use substrate_cli;
 
 error_chain! {
 	foreign_links {
@@ -25,6 +26,7 @@ error_chain! {
 	}
 	links {
 		Client(client::error::Error, client::error::ErrorKind) #[doc="Client error"];
+		Node(substrate_cli::error::Error, substrate_cli::error::ErrorKind) #[doc="Node error"];
     }
 	errors {
 		/// Key store errors
@@ -32,5 +34,10 @@ error_chain! {
 			description("Keystore error"),
 			display("Keystore error: {:?}", e),
 		}
+		/// Invalid command line argument value.
+		Input(m: String) {
+			description("Invalid input"),
+			display("{}", m),
+		}
 	}
 }
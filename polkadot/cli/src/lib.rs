@@ -18,19 +18,28 @@
 
 #![warn(missing_docs)]
 
-extern crate app_dirs;
-extern crate env_logger;
+extern crate futures;
+extern crate parking_lot;
+extern crate tokio_core;
 extern crate ed25519;
 extern crate triehash;
 extern crate substrate_codec as codec;
 extern crate substrate_state_machine as state_machine;
 extern crate substrate_client as client;
+extern crate substrate_client_db as client_db;
+extern crate substrate_cli;
+extern crate substrate_executor;
+extern crate substrate_network as network;
 extern crate substrate_primitives as primitives;
-extern crate substrate_rpc_servers as rpc;
+extern crate substrate_rpc;
+extern crate substrate_keyring as keyring;
 extern crate polkadot_primitives;
+extern crate polkadot_api;
+extern crate polkadot_consensus as consensus;
 extern crate polkadot_executor;
 extern crate polkadot_runtime;
 extern crate polkadot_keystore as keystore;
+extern crate polkadot_transaction_pool as transaction_pool;
 
 #[macro_use]
 extern crate hex_literal;
@@ -41,12 +50,31 @@ extern crate error_chain;
 #[macro_use]
 extern crate log;
 
+pub mod chain_spec;
 pub mod error;
 
-use std::path::{Path, PathBuf};
+mod author;
 
+use std::path::Path;
+use std::sync::Arc;
+
+use client::Client;
 use codec::Slicable;
+use futures::{future, Stream};
+use parking_lot::Mutex;
+use polkadot_api::PolkadotApi;
+use polkadot_primitives::parachain::{BlockData, CandidateReceipt, Extrinsic};
+use polkadot_primitives::transaction::UncheckedTransaction;
 use polkadot_runtime::genesismap::{additional_storage_with_genesis, GenesisConfig};
+use substrate_executor::NativeExecutor;
+use primitives::block::{HeaderHash, Id as BlockId, Transaction, TransactionHash};
+use primitives::hexdisplay::HexDisplay;
+use substrate_rpc::Subscriptions;
+use substrate_rpc::system::SystemInfo;
+use tokio_core::reactor;
+use transaction_pool::{Ready, TransactionPool};
+use author::AuthorHandle;
+use chain_spec::ChainSpec;
 use client::genesis;
 use keystore::Store as Keystore;
 
@@ -67,88 +95,280 @@ pub fn run<I, T>(args: I) -> error::Result<()> where
 
 	// TODO [ToDr] Split parameters parsing from actual execution.
 	let log_pattern = matches.value_of("log").unwrap_or("");
-	init_logger(log_pattern);
+	substrate_cli::init_logger(log_pattern);
+
+	if let Some(sub_matches) = matches.subcommand_matches("build-spec") {
+		let spec = load_spec(&matches)?;
+		info!("Building chain spec");
+		let json = spec.to_json(sub_matches.is_present("raw")).map_err(error::ErrorKind::Input)?;
+		println!("{}", json);
+		return Ok(());
+	}
+
+	if matches.subcommand_name().is_none() {
+		println!("No command given.\n");
+		let _ = clap::App::from_yaml(yaml).print_long_help();
+		return Ok(());
+	}
+
+	let spec = load_spec(&matches)?;
+	info!("Chain specification: {}", spec.name());
 
 	// Create client
 	let executor = polkadot_executor::Executor::new();
 	let mut storage = Default::default();
-	let god_key = hex!["3d866ec8a9190c8343c2fc593d21d8a6d0c5c4763aaab2349de3a6111d64d124"];
-
-	let genesis_config = GenesisConfig {
-		validators: vec![god_key.clone()],
-		authorities: vec![god_key.clone()],
-		balances: vec![(god_key.clone(), 1u64 << 63)].into_iter().collect(),
-		block_time: 5,			// 5 second block time.
-		session_length: 720,	// that's 1 hour per session.
-		sessions_per_era: 24,	// 24 hours per era.
-		bonding_duration: 90,	// 90 days per bond.
-		approval_ratio: 667,	// 66.7% approvals required for legislation.
-	};
 
 	let prepare_genesis = || {
-		storage = genesis_config.genesis_map();
+		storage = spec.genesis_map();
 		let block = genesis::construct_genesis_block(&storage);
 		storage.extend(additional_storage_with_genesis(&block));
 		(primitives::block::Header::decode(&mut block.header.encode().as_ref()).expect("to_vec() always gives a valid serialisation; qed"), storage.into_iter().collect())
 	};
 
-	let keystore_path = matches.value_of("keystore")
+	let base_path = substrate_cli::base_path(&matches, "Polkadot");
+
+	if let Some(sub_matches) = matches.subcommand_matches("purge-chain") {
+		return Ok(substrate_cli::purge_chain(&base_path.join("db"), sub_matches.is_present("yes"))?);
+	}
+
+	let keystore_path = matches.value_of("keystore-path")
 		.map(|x| Path::new(x).to_owned())
-		.unwrap_or_else(default_keystore_path);
+		.unwrap_or_else(|| base_path.join("keystore"));
 
-	let _keystore = Keystore::open(keystore_path).map_err(::error::ErrorKind::Keystore)?;
-	let client = client::new_in_mem(executor, prepare_genesis)?;
+	let keystore = Keystore::open(keystore_path).map_err(::error::ErrorKind::Keystore)?;
 
-	let address = "127.0.0.1:9933".parse().unwrap();
-	let handler = rpc::rpc_handler(client);
-	let server = rpc::start_http(&address, handler)?;
+	if matches.is_present("dev") {
+		info!("Running in development mode with in-memory storage.");
+		let client = Arc::new(client::new_in_mem(executor, prepare_genesis)?);
+		execute(client, &matches, &base_path, &spec, &keystore)
+	} else {
+		let db_settings = substrate_cli::database_settings(&matches, &base_path)?;
+		let client = Arc::new(client_db::new_client(db_settings, executor, prepare_genesis)?);
+		execute(client, &matches, &base_path, &spec, &keystore)
+	}
+}
+
+// Load the chain specification named by `--chain`.
+fn load_spec(matches: &clap::ArgMatches) -> error::Result<ChainSpec> {
+	Ok(substrate_cli::load_spec(matches, chain_spec::development, chain_spec::local_testnet, GenesisConfig::genesis_map)?)
+}
 
-	if let Some(_) = matches.subcommand_matches("collator") {
-		info!("Starting collator.");
-		server.wait();
+// Run the subcommand given on the command line against the client.
+fn execute<B>(
+	client: Arc<Client<B, NativeExecutor<polkadot_executor::Executor>>>,
+	matches: &clap::ArgMatches,
+	base_path: &Path,
+	spec: &ChainSpec,
+	keystore: &Keystore,
+) -> error::Result<()> where
+	B: client::backend::Backend + Send + Sync + 'static,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	if substrate_cli::execute_chain_command(&*client, matches)? {
 		return Ok(());
 	}
 
-	if let Some(_) = matches.subcommand_matches("validator") {
-		info!("Starting validator.");
-		server.wait();
-		return Ok(());
+	let (role, validator_key) = match matches.subcommand_name() {
+		Some("collator") => {
+			info!("Starting collator.");
+			(network::Role::COLLATOR, None)
+		},
+		_ => {
+			info!("Starting validator.");
+			let key = load_validator_key(keystore, matches.subcommand_matches("validator"))?;
+			(network::Role::FULL | network::Role::VALIDATOR, Some(Arc::new(key)))
+		},
+	};
+	run_node(client, matches, role, validator_key, base_path, spec)
+}
+
+// The first key of the keystore, unlocked with the password given on the command line.
+// A key is only generated when asked to.
+fn load_validator_key(keystore: &Keystore, matches: Option<&clap::ArgMatches>) -> error::Result<ed25519::Pair> {
+	let password = match matches.and_then(|m| m.value_of("password")) {
+		Some(password) => password,
+		None => bail!(error::ErrorKind::Input("A password is required to unlock the validator key, use --password".into())),
+	};
+	let key = match keystore.contents().map_err(error::ErrorKind::Keystore)?.first() {
+		Some(public) => keystore.load(public, password).map_err(error::ErrorKind::Keystore)?,
+		None if matches.map_or(false, |m| m.is_present("generate-key")) => {
+			info!("Generating a new validator key");
+			keystore.generate(password).map_err(error::ErrorKind::Keystore)?
+		},
+		None => bail!(error::ErrorKind::Input("The keystore holds no validator key, use --generate-key to create one".into())),
+	};
+	info!("Validator key: {}", HexDisplay::from(&key.public().0));
+	Ok(key)
+}
+
+// Start the transaction pool on top of the given client, then the network and RPC
+// servers, and run until the process is killed. A validator authors blocks with
+// the given key.
+fn run_node<B>(
+	client: Arc<Client<B, NativeExecutor<polkadot_executor::Executor>>>,
+	matches: &clap::ArgMatches,
+	role: network::Role,
+	validator_key: Option<Arc<ed25519::Pair>>,
+	base_path: &Path,
+	spec: &ChainSpec,
+) -> error::Result<()> where
+	B: client::backend::Backend + Send + Sync + 'static,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let core = reactor::Core::new()?;
+
+	let transaction_pool = Arc::new(Mutex::new(TransactionPool::new(Default::default())));
+	cull_transaction_pool(&*client, &transaction_pool, client.info()?.chain.best_hash, &[]);
+	{
+		// culling runs on the event loop, so it stops together with the node.
+		let client = client.clone();
+		let transaction_pool = transaction_pool.clone();
+		let new_best = client.best_block_notification_stream();
+		core.handle().spawn(new_best.for_each(move |notification| {
+			let body = |hash| client.body(&BlockId::Hash(hash)).ok().and_then(|body| body).unwrap_or_default();
+			match notification.tree_route {
+				Some(ref route) => {
+					// transactions of the retracted blocks go back to the pool before
+					// the pool is culled along the new best chain.
+					for retracted in route.retracted() {
+						reimport_transactions(&transaction_pool, &body(retracted.hash));
+					}
+					for enacted in route.enacted() {
+						cull_transaction_pool(&*client, &transaction_pool, enacted.hash, &body(enacted.hash));
+					}
+				}
+				None => cull_transaction_pool(&*client, &transaction_pool, notification.hash, &body(notification.hash)),
+			}
+			Ok(())
+		}));
 	}
 
-	println!("No command given.\n");
-	let _ = clap::App::from_yaml(yaml).print_long_help();
+	let system_info = SystemInfo {
+		impl_name: "parity-polkadot".into(),
+		impl_version: crate_version!().into(),
+		chain_name: spec.name().into(),
+	};
 
+	let network_pool = Arc::new(TransactionPoolAdapter {
+		pool: transaction_pool.clone(),
+		api: client.clone(),
+	});
+	let author = |remote, network: Arc<network::Service>| AuthorHandle::new(transaction_pool.clone(), client.clone(), Subscriptions::new(remote))
+		.on_import(move || network.on_new_transactions());
+	let factory = consensus::ProposerFactory {
+		client: client.clone(),
+		transaction_pool: transaction_pool.clone(),
+		network: LocalTableRouter,
+	};
+	let bft_client = client.clone();
+	let bft_key = validator_key.clone();
+	let start_consensus = move |handle: &reactor::Handle, network: Arc<network::Service>| {
+		if let Some(key) = bft_key {
+			substrate_cli::start_bft(handle, bft_client, network, key, factory);
+		}
+	};
+	// There is no light mode for Polkadot yet: the transaction pool and the Polkadot API
+	// execute the runtime against the local state, so there are no on-demand requests.
+	substrate_cli::run_node(core, client.clone(), matches, base_path, spec.boot_nodes(), system_info, role, None, network_pool, author, validator_key, start_consensus)?;
 	Ok(())
 }
 
-fn default_keystore_path() -> PathBuf {
-	use app_dirs::{AppInfo, AppDataType};
+// Parachain candidates are not exchanged over the network yet: the statement table
+// only holds the local statements.
+struct LocalTableRouter;
 
-	let app_info = AppInfo {
-		name: "Polkadot",
-		author: "Parity Technologies",
-	};
+impl consensus::Network for LocalTableRouter {
+	type TableRouter = LocalTableRouter;
+
+	fn table_router(&self, _table: Arc<consensus::SharedTable>) -> LocalTableRouter {
+		LocalTableRouter
+	}
+}
+
+impl consensus::TableRouter for LocalTableRouter {
+	type Error = ();
+	type FetchCandidate = future::Empty<BlockData, ()>;
+	type FetchExtrinsic = future::Empty<Extrinsic, ()>;
+
+	fn local_candidate_data(&self, _block_data: BlockData, _extrinsic: Extrinsic) {}
+
+	fn fetch_block_data(&self, _candidate: &CandidateReceipt) -> Self::FetchCandidate {
+		future::empty()
+	}
+
+	fn fetch_extrinsic_data(&self, _candidate: &CandidateReceipt) -> Self::FetchExtrinsic {
+		future::empty()
+	}
+}
 
-	app_dirs::get_app_dir(
-		AppDataType::UserData,
-		&app_info,
-		"keystore",
-	).expect("app directories exist on all supported platforms; qed")
+// Exposes the transaction pool to the network for propagation.
+struct TransactionPoolAdapter<A> {
+	pool: Arc<Mutex<TransactionPool>>,
+	api: Arc<A>,
 }
 
-fn init_logger(pattern: &str) {
-	let mut builder = env_logger::LogBuilder::new();
-	// Disable info logging by default for some modules:
-	builder.filter(Some("hyper"), log::LogLevelFilter::Warn);
-	// Enable info for others.
-	builder.filter(None, log::LogLevelFilter::Info);
+impl<A> network::TransactionPool for TransactionPoolAdapter<A> where
+	A: PolkadotApi + Send + Sync,
+{
+	fn transactions(&self) -> Vec<(TransactionHash, Vec<u8>)> {
+		let pool = self.pool.lock();
+		let id = match pool.best_block().and_then(|hash| self.api.check_id(BlockId::Hash(hash)).ok()) {
+			Some(id) => id,
+			None => return Vec::new(),
+		};
+
+		pool.pending(Ready::create(id, &*self.api))
+			.map(|tx| (tx.hash().0.into(), tx.as_transaction().encode()))
+			.collect()
+	}
+
+	fn import(&self, transaction: &[u8]) -> Option<TransactionHash> {
+		let transaction = match UncheckedTransaction::decode(&mut &transaction[..]) {
+			Some(transaction) => transaction,
+			None => {
+				debug!(target: "transaction-pool", "Received malformed transaction from the network");
+				return None;
+			}
+		};
 
-	if let Ok(lvl) = std::env::var("RUST_LOG") {
-		builder.parse(&lvl);
+		match self.pool.lock().import(transaction) {
+			Ok(verified) => Some(verified.hash().0.into()),
+			Err(e) => {
+				debug!(target: "transaction-pool", "Unable to import transaction from the network: {}", e);
+				None
+			}
+		}
 	}
+}
 
-	builder.parse(pattern);
+// Sync the transaction pool with a new best block, dropping the transactions it included.
+fn cull_transaction_pool<A: PolkadotApi>(api: &A, pool: &Mutex<TransactionPool>, hash: HeaderHash, included: &[Transaction]) {
+	let id = match api.check_id(BlockId::Hash(hash)) {
+		Ok(id) => id,
+		Err(e) => {
+			warn!(target: "transaction-pool", "Unable to cull transaction pool at {:?}: {}", hash, e);
+			return;
+		}
+	};
 
+	let culled = pool.lock().cull_block(hash, included, Ready::create(id, api));
+	debug!(target: "transaction-pool", "Culled {} transactions at {:?}", culled, hash);
+}
 
-	builder.init().expect("Logger initialized only once.");
+// Return the transactions of a block which is no longer on the best chain to the pool,
+// skipping the inherent ones.
+fn reimport_transactions(pool: &Mutex<TransactionPool>, transactions: &[Transaction]) {
+	let mut pool = pool.lock();
+	for transaction in transactions {
+		let transaction = match UncheckedTransaction::decode(&mut transaction.encode().as_slice()) {
+			Some(transaction) => transaction,
+			None => continue,
+		};
+		if transaction.is_inherent() {
+			continue;
+		}
+		if let Err(e) = pool.import(transaction) {
+			debug!(target: "transaction-pool", "Unable to re-import retracted transaction: {}", e);
+		}
+	}
 }
@@ -24,6 +24,9 @@ use primitives::Block;
 use polkadot_primitives::{Balance, BlockNumber, AccountId};
 
 /// Configuration of a general Polkadot genesis block.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
 pub struct GenesisConfig {
 	pub validators: Vec<AccountId>,
 	pub authorities: Vec<AccountId>,
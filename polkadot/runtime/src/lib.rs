@@ -30,6 +30,13 @@ extern crate substrate_keyring as keyring;
 #[cfg(feature = "std")]
 extern crate rustc_hex;
 
+#[cfg(feature = "std")]
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(feature = "std")]
+extern crate serde;
+
 #[cfg_attr(any(test, feature = "std"), macro_use)]
 extern crate substrate_primitives as primitives;
 
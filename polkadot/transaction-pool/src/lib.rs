@@ -21,17 +21,27 @@ extern crate substrate_primitives as substrate_primitives;
 extern crate substrate_codec as codec;
 extern crate ed25519;
 extern crate ethereum_types;
+extern crate futures;
+extern crate parking_lot;
 
 #[macro_use]
 extern crate error_chain;
 
-use std::collections::HashMap;
+#[cfg(test)]
+extern crate substrate_keyring as keyring;
+
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::cmp::Ordering;
 use std::sync::Arc;
 
+use codec::Slicable;
+use futures::sync::mpsc;
+use parking_lot::Mutex;
 use polkadot_api::PolkadotApi;
 use primitives::AccountId;
+use primitives::block::HeaderHash;
 use primitives::transaction::UncheckedTransaction;
+use substrate_primitives::block::Transaction as SubstrateTransaction;
 use transaction_pool::{Pool, Readiness};
 use transaction_pool::scoring::{Change, Choice};
 
@@ -39,6 +49,9 @@ use transaction_pool::scoring::{Change, Choice};
 pub use ethereum_types::{Address as TruncatedAccountId, H256 as TransactionHash};
 pub use transaction_pool::{Options, Status, LightStatus, NoopListener, VerifiedTransaction as VerifiedTransactionOps};
 
+/// Maximum number of removed transactions whose fate is remembered for status queries.
+const MAX_REMOVED_RECORDS: usize = 4096;
+
 /// Truncate an account ID to 160 bits.
 pub fn truncate_id(id: &AccountId) -> TruncatedAccountId {
 	TruncatedAccountId::from_slice(&id[..20])
@@ -46,7 +59,7 @@ pub fn truncate_id(id: &AccountId) -> TruncatedAccountId {
 
 /// Iterator over pending transactions.
 pub type PendingIterator<'a, C> =
-	transaction_pool::PendingIterator<'a, VerifiedTransaction, Ready<'a, C>, Scoring, NoopListener>;
+	transaction_pool::PendingIterator<'a, VerifiedTransaction, Ready<'a, C>, Scoring, RemovalListener>;
 
 error_chain! {
 	errors {
@@ -85,15 +98,14 @@ impl VerifiedTransaction {
 			bail!(ErrorKind::IsInherent(tx))
 		}
 
-		let message = codec::Slicable::encode(&tx);
+		let message = Slicable::encode(&tx.transaction);
 		if ed25519::verify(&*tx.signature, &message, &tx.transaction.signed[..]) {
-			// TODO: make transaction-pool use generic types.
-			let hash = substrate_primitives::hashing::blake2_256(&message);
+			let encoded = Slicable::encode(&tx);
 			let address = truncate_id(&tx.transaction.signed);
 			Ok(VerifiedTransaction {
 				inner: tx,
-				hash: hash.into(),
-				encoded_size: message.len(),
+				hash: hash_encoded(&encoded),
+				encoded_size: encoded.len(),
 				address,
 				insertion_id,
 			})
@@ -152,6 +164,11 @@ impl transaction_pool::VerifiedTransaction for VerifiedTransaction {
 	}
 }
 
+// TODO: make transaction-pool use generic types.
+fn hash_encoded(encoded: &[u8]) -> TransactionHash {
+	substrate_primitives::hashing::blake2_256(encoded).into()
+}
+
 /// Scoring implementation for polkadot transactions.
 pub struct Scoring;
 
@@ -232,20 +249,91 @@ impl<'a, T: 'a + PolkadotApi> transaction_pool::Ready<VerifiedTransaction> for R
 	}
 }
 
+// Readiness evaluator treating every transaction as ready, used to iterate
+// over the whole pool.
+struct AllReady;
+
+impl transaction_pool::Ready<VerifiedTransaction> for AllReady {
+	fn is_ready(&mut self, _tx: &VerifiedTransaction) -> Readiness {
+		Readiness::Ready
+	}
+}
+
+/// Status of a transaction, as far as the pool knows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus {
+	/// In the pool and ready to be included in the next block.
+	Ready,
+	/// In the pool, but waiting for earlier transactions from the same sender.
+	Future,
+	/// Included in the block with the given hash.
+	Included(HeaderHash),
+	/// Removed from the pool without being included.
+	Dropped,
+	/// Not known to the pool.
+	Unknown,
+}
+
+// The reason a transaction left the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Removal {
+	Included(HeaderHash),
+	Dropped,
+}
+
+/// Listener collecting the transactions the inner pool removes by itself: those
+/// replaced by a newer transaction, evicted when the pool is full or removed as
+/// invalid or cancelled.
+#[derive(Default)]
+pub struct RemovalListener {
+	removed: Arc<Mutex<Vec<TransactionHash>>>,
+}
+
+impl transaction_pool::Listener<VerifiedTransaction> for RemovalListener {
+	fn added(&mut self, _tx: &Arc<VerifiedTransaction>, old: Option<&Arc<VerifiedTransaction>>) {
+		if let Some(old) = old {
+			self.removed.lock().push(old.hash().clone());
+		}
+	}
+
+	fn dropped(&mut self, tx: &Arc<VerifiedTransaction>) {
+		self.removed.lock().push(tx.hash().clone());
+	}
+
+	fn invalid(&mut self, tx: &Arc<VerifiedTransaction>) {
+		self.removed.lock().push(tx.hash().clone());
+	}
+
+	fn cancelled(&mut self, tx: &Arc<VerifiedTransaction>) {
+		self.removed.lock().push(tx.hash().clone());
+	}
+}
+
 /// The polkadot transaction pool.
 ///
 /// Wraps a `transaction-pool::Pool`.
 pub struct TransactionPool {
-	inner: transaction_pool::Pool<VerifiedTransaction, Scoring>,
+	inner: transaction_pool::Pool<VerifiedTransaction, Scoring, RemovalListener>,
 	insertion_index: u64, // TODO: use AtomicU64 when it stabilizes
+	best_block: Option<HeaderHash>,
+	dropped: Arc<Mutex<Vec<TransactionHash>>>,
+	removed: HashMap<TransactionHash, Removal>,
+	removed_order: VecDeque<TransactionHash>,
+	watchers: HashMap<TransactionHash, Vec<mpsc::UnboundedSender<TransactionStatus>>>,
 }
 
 impl TransactionPool {
 	/// Create a new transaction pool.
 	pub fn new(options: Options) -> Self {
+		let listener = RemovalListener::default();
 		TransactionPool {
-			inner: Pool::new(NoopListener, Scoring, options),
+			dropped: listener.removed.clone(),
+			inner: Pool::new(listener, Scoring, options),
 			insertion_index: 0,
+			best_block: None,
+			removed: HashMap::new(),
+			removed_order: VecDeque::new(),
+			watchers: HashMap::new(),
 		}
 	}
 
@@ -257,24 +345,111 @@ impl TransactionPool {
 		let verified = VerifiedTransaction::create(tx, insertion_index)?;
 
 		// TODO: just use a foreign link when the error type is made public.
-		self.inner.import(verified)
+		let imported = self.inner.import(verified)
 			.map_err(|e| ErrorKind::Import(Box::new(e)))
-			.map_err(Into::into)
+			.map_err(Into::into);
+		self.record_dropped();
+
+		// a transaction retracted from the chain is pending again.
+		if let Ok(ref verified) = imported {
+			self.removed.remove(verified.hash());
+		}
+		imported
 	}
 
 	/// Clear the pool.
 	pub fn clear(&mut self) {
+		for hash in self.hashes() {
+			self.record_removal(hash, Removal::Dropped);
+		}
 		self.inner.clear();
+		self.dropped.lock().clear();
 	}
 
 	/// Remove from the pool.
 	pub fn remove(&mut self, hash: &TransactionHash, is_valid: bool) -> Option<Arc<VerifiedTransaction>> {
-		self.inner.remove(hash, is_valid)
+		let removed = self.inner.remove(hash, is_valid);
+		if removed.is_some() {
+			self.record_removal(hash.clone(), Removal::Dropped);
+		}
+		self.record_dropped();
+		removed
 	}
 
 	/// Cull transactions from the queue.
 	pub fn cull<T: PolkadotApi>(&mut self, senders: Option<&[TruncatedAccountId]>, ready: Ready<T>) -> usize {
-		self.inner.cull(senders, ready)
+		// stalled transactions are removed without notifying the listener.
+		let before = self.hashes();
+		let culled = self.inner.cull(senders, ready);
+		if culled > 0 {
+			let after = self.hashes();
+			for hash in before.difference(&after) {
+				if !self.removed.contains_key(hash) {
+					self.record_removal(hash.clone(), Removal::Dropped);
+				}
+			}
+		}
+		self.record_dropped();
+		culled
+	}
+
+	/// Note a new best block, recording the given transactions as included in it and
+	/// culling those which are no longer valid. The readiness evaluator should be
+	/// created at the new best block.
+	pub fn cull_block<T: PolkadotApi>(
+		&mut self,
+		block: HeaderHash,
+		included: &[SubstrateTransaction],
+		ready: Ready<T>,
+	) -> usize {
+		for transaction in included {
+			self.record_removal(hash_encoded(&transaction.encode()), Removal::Included(block));
+		}
+		self.best_block = Some(block);
+		self.cull(None, ready)
+	}
+
+	/// Get a stream which is notified once the given transaction is included in
+	/// a block or dropped from the pool.
+	pub fn watch(&mut self, hash: TransactionHash) -> mpsc::UnboundedReceiver<TransactionStatus> {
+		// forget the watchers which have been unsubscribed in the meantime.
+		self.watchers.retain(|_, sinks| {
+			sinks.retain(|sink| !sink.is_closed());
+			!sinks.is_empty()
+		});
+
+		let (sink, stream) = mpsc::unbounded();
+		if let Some(status) = self.removal_status(&hash) {
+			let _ = sink.unbounded_send(status);
+		} else {
+			self.watchers.entry(hash).or_insert_with(Vec::new).push(sink);
+		}
+		stream
+	}
+
+	/// The best block the pool was last culled against.
+	pub fn best_block(&self) -> Option<HeaderHash> {
+		self.best_block
+	}
+
+	/// Get the status of a transaction. Readiness is only determined if an evaluator is given,
+	/// otherwise all transactions in the pool are reported as future.
+	pub fn transaction_status<T: PolkadotApi>(&self, hash: &TransactionHash, ready: Option<Ready<T>>) -> TransactionStatus {
+		if let Some(status) = self.removal_status(hash) {
+			return status;
+		}
+
+		if let Some(ready) = ready {
+			if self.inner.pending(ready).any(|tx| tx.hash() == hash) {
+				return TransactionStatus::Ready;
+			}
+		}
+
+		if self.inner.pending(AllReady).any(|tx| tx.hash() == hash) {
+			TransactionStatus::Future
+		} else {
+			TransactionStatus::Unknown
+		}
 	}
 
 	/// Get an iterator of pending transactions.
@@ -291,8 +466,209 @@ impl TransactionPool {
 	pub fn light_status(&self) -> LightStatus {
 		self.inner.light_status()
 	}
+
+	fn hashes(&self) -> HashSet<TransactionHash> {
+		self.inner.pending(AllReady).map(|tx| tx.hash().clone()).collect()
+	}
+
+	fn removal_status(&self, hash: &TransactionHash) -> Option<TransactionStatus> {
+		self.removed.get(hash).map(|removal| match *removal {
+			Removal::Included(block) => TransactionStatus::Included(block),
+			Removal::Dropped => TransactionStatus::Dropped,
+		})
+	}
+
+	// record the transactions reported by the listener as dropped.
+	fn record_dropped(&mut self) {
+		let dropped = ::std::mem::replace(&mut *self.dropped.lock(), Vec::new());
+		for hash in dropped {
+			self.record_removal(hash, Removal::Dropped);
+		}
+	}
+
+	// remember why a transaction left the pool, forgetting the oldest records
+	// once there are too many. watchers of the transaction are notified.
+	fn record_removal(&mut self, hash: TransactionHash, removal: Removal) {
+		if let Some(watchers) = self.watchers.remove(&hash) {
+			let status = match removal {
+				Removal::Included(block) => TransactionStatus::Included(block),
+				Removal::Dropped => TransactionStatus::Dropped,
+			};
+			for watcher in watchers {
+				let _ = watcher.unbounded_send(status.clone());
+			}
+		}
+
+		if self.removed.insert(hash.clone(), removal).is_none() {
+			self.removed_order.push_back(hash);
+			if self.removed_order.len() > MAX_REMOVED_RECORDS {
+				if let Some(oldest) = self.removed_order.pop_front() {
+					self.removed.remove(&oldest);
+				}
+			}
+		}
+	}
 }
 
 #[cfg(test)]
 mod tests {
+	use super::*;
+	use futures::{Future, Stream};
+	use keyring::Keyring;
+	use polkadot_api::{BlockBuilder, CheckedBlockId, ErrorKind as ApiErrorKind, Result as ApiResult};
+	use primitives::{SessionKey, Timestamp, TxOrder};
+	use primitives::block::{Block, Id as BlockId};
+	use primitives::parachain::DutyRoster;
+	use primitives::transaction::{Function, Transaction};
+
+	#[derive(Clone)]
+	struct TestCheckedId(BlockId);
+
+	impl CheckedBlockId for TestCheckedId {
+		fn block_id(&self) -> &BlockId {
+			&self.0
+		}
+	}
+
+	// blocks are never built by the pool.
+	enum TestBlockBuilder {}
+
+	impl BlockBuilder for TestBlockBuilder {
+		fn push_transaction(&mut self, _transaction: UncheckedTransaction) -> ApiResult<()> {
+			match *self {}
+		}
+
+		fn bake(self) -> Block {
+			match self {}
+		}
+	}
+
+	// only the nonce is used by the pool; it is the same for every account and block.
+	#[derive(Default)]
+	struct TestApi {
+		nonce: Mutex<TxOrder>,
+	}
+
+	impl PolkadotApi for TestApi {
+		type CheckedBlockId = TestCheckedId;
+		type BlockBuilder = TestBlockBuilder;
+
+		fn check_id(&self, id: BlockId) -> ApiResult<TestCheckedId> {
+			Ok(TestCheckedId(id))
+		}
+
+		fn session_keys(&self, _at: &TestCheckedId) -> ApiResult<Vec<SessionKey>> {
+			Ok(Vec::new())
+		}
+
+		fn validators(&self, _at: &TestCheckedId) -> ApiResult<Vec<AccountId>> {
+			Ok(Vec::new())
+		}
+
+		fn duty_roster(&self, _at: &TestCheckedId) -> ApiResult<DutyRoster> {
+			Ok(Default::default())
+		}
+
+		fn timestamp(&self, _at: &TestCheckedId) -> ApiResult<Timestamp> {
+			Ok(0)
+		}
+
+		fn nonce(&self, _at: &TestCheckedId, _account: AccountId) -> ApiResult<TxOrder> {
+			Ok(*self.nonce.lock())
+		}
+
+		fn evaluate_block(&self, _at: &TestCheckedId, _block: Block) -> ApiResult<()> {
+			Ok(())
+		}
+
+		fn build_block(&self, _parent: &TestCheckedId, _timestamp: Timestamp) -> ApiResult<TestBlockBuilder> {
+			Err(ApiErrorKind::UnknownRuntime.into())
+		}
+	}
+
+	fn transaction(nonce: u64) -> UncheckedTransaction {
+		let transaction = Transaction {
+			signed: Keyring::One.to_raw_public(),
+			nonce,
+			function: Function::StakingTransfer(Keyring::Two.to_raw_public(), 69),
+		};
+		let signature = Keyring::One.sign(&transaction.encode());
+
+		UncheckedTransaction { transaction, signature }
+	}
+
+	fn substrate_transaction(nonce: u64) -> SubstrateTransaction {
+		Slicable::decode(&mut transaction(nonce).encode().as_slice()).unwrap()
+	}
+
+	fn ready_at(block: HeaderHash, api: &TestApi) -> Ready<TestApi> {
+		Ready::create(TestCheckedId(BlockId::Hash(block)), api)
+	}
+
+	#[test]
+	fn imports_signed_transactions_only() {
+		let mut pool = TransactionPool::new(Default::default());
+		assert!(pool.import(transaction(0)).is_ok());
+
+		let mut bad = transaction(1);
+		bad.transaction.nonce = 2;
+		assert!(pool.import(bad).is_err());
+		assert_eq!(pool.light_status().transaction_count, 1);
+	}
+
+	#[test]
+	fn remembers_removed_transactions() {
+		let api = TestApi::default();
+		let mut pool = TransactionPool::new(Default::default());
+		let hash = pool.import(transaction(0)).unwrap().hash().clone();
+		assert_eq!(pool.removal_status(&hash), None);
+
+		pool.remove(&hash, false);
+		assert_eq!(pool.removal_status(&hash), Some(TransactionStatus::Dropped));
+		assert!(pool.hashes().is_empty());
+
+		let included = pool.import(transaction(1)).unwrap().hash().clone();
+		*api.nonce.lock() = 1;
+		assert_eq!(pool.cull_block(5.into(), &[substrate_transaction(1)], ready_at(5.into(), &api)), 1);
+		assert_eq!(pool.removal_status(&included), Some(TransactionStatus::Included(5.into())));
+		assert_eq!(pool.best_block(), Some(5.into()));
+		assert!(pool.hashes().is_empty());
+
+		// a retracted transaction is pending again once re-imported.
+		pool.import(transaction(1)).unwrap();
+		assert_eq!(pool.removal_status(&included), None);
+	}
+
+	#[test]
+	fn notifies_watchers_of_removal() {
+		let mut pool = TransactionPool::new(Default::default());
+		let hash = pool.import(transaction(0)).unwrap().hash().clone();
+		let updates = pool.watch(hash.clone());
+
+		pool.remove(&hash, false);
+		assert_eq!(updates.collect().wait(), Ok(vec![TransactionStatus::Dropped]));
+	}
+
+	#[test]
+	fn notifies_watchers_of_cleared_transactions() {
+		let mut pool = TransactionPool::new(Default::default());
+		let hash = pool.import(transaction(0)).unwrap().hash().clone();
+		let updates = pool.watch(hash.clone());
+
+		pool.clear();
+		assert_eq!(updates.collect().wait(), Ok(vec![TransactionStatus::Dropped]));
+		assert_eq!(pool.removal_status(&hash), Some(TransactionStatus::Dropped));
+	}
+
+	#[test]
+	fn forgets_closed_watchers() {
+		let mut pool = TransactionPool::new(Default::default());
+		let first = pool.import(transaction(0)).unwrap().hash().clone();
+		let second = pool.import(transaction(1)).unwrap().hash().clone();
+
+		drop(pool.watch(first.clone()));
+		let _updates = pool.watch(second.clone());
+		assert!(!pool.watchers.contains_key(&first));
+		assert!(pool.watchers.contains_key(&second));
+	}
 }
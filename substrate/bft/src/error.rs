@@ -48,6 +48,24 @@ error_chain! {
 			display("Unable to dispatch agreement future: {:?}", e),
 		}
 
+		/// Message sender is not one of the authorities.
+		InvalidAuthority(a: ::primitives::AuthorityId) {
+			description("Message sender is not a valid authority"),
+			display("Message sender {:?} is not a valid authority.", a),
+		}
+
+		/// Message signature is invalid.
+		InvalidSignature(s: ::ed25519::Signature, a: ::primitives::AuthorityId) {
+			description("Message signature is invalid"),
+			display("Message signature {:?} by {:?} is invalid.", s, a),
+		}
+
+		/// Justification doesn't have enough valid signatures.
+		InvalidJustification {
+			description("Invalid justification"),
+			display("Justification doesn't have enough valid signatures."),
+		}
+
 		/// Some other error.
 		Other(e: Box<::std::error::Error + Send>) {
 			description("Other error")
@@ -41,7 +41,7 @@ use primitives::bft::{Message as PrimitiveMessage, Action as PrimitiveAction, Ju
 use primitives::block::{Block, Id as BlockId, Header, HeaderHash};
 use primitives::AuthorityId;
 
-use futures::{stream, task, Async, Sink, Future, IntoFuture};
+use futures::{task, Async, Stream, Sink, Future, IntoFuture};
 use futures::future::Executor;
 use futures::sync::oneshot;
 use tokio_timer::Timer;
@@ -50,6 +50,9 @@ use parking_lot::Mutex;
 pub use generic::InputStreamConcluded;
 pub use error::{Error, ErrorKind};
 
+// round timeouts grow as a power of two of the round number times this many seconds.
+const DEFAULT_ROUND_TIMEOUT_MULTIPLIER: u64 = 4;
+
 /// Messages over the proposal.
 /// Each message carries an associated round number.
 pub type Message = generic::Message<Block, HeaderHash>;
@@ -62,6 +65,21 @@ pub type LocalizedMessage = generic::LocalizedMessage<
 	LocalizedSignature
 >;
 
+/// A localized proposal message.
+pub type LocalizedProposal = generic::LocalizedProposal<
+	Block,
+	HeaderHash,
+	AuthorityId,
+	LocalizedSignature
+>;
+
+/// A localized vote message.
+pub type LocalizedVote = generic::LocalizedVote<
+	HeaderHash,
+	AuthorityId,
+	LocalizedSignature
+>;
+
 /// Justification of some hash.
 pub type Justification = generic::Justification<HeaderHash, LocalizedSignature>;
 
@@ -219,34 +237,22 @@ impl<P: Proposer> generic::Context for BftInstance<P> {
 	}
 }
 
-type Input<E> = stream::Empty<Communication, E>;
-
-// "black hole" output sink.
-struct Output<E>(::std::marker::PhantomData<E>);
-
-impl<E> Sink for Output<E> {
-	type SinkItem = Communication;
-	type SinkError = E;
-
-	fn start_send(&mut self, _item: Communication) -> ::futures::StartSend<Communication, E> {
-		Ok(::futures::AsyncSink::Ready)
-	}
-
-	fn poll_complete(&mut self) -> ::futures::Poll<(), E> {
-		Ok(Async::Ready(()))
-	}
-}
-
 /// A future that resolves either when canceled (witnessing a block from the network at same height)
 /// or when agreement completes.
-pub struct BftFuture<P: Proposer, I> {
-	inner: generic::Agreement<BftInstance<P>, Input<P::Error>, Output<P::Error>>,
+pub struct BftFuture<P: Proposer, I, InStream, OutSink> {
+	inner: generic::Agreement<BftInstance<P>, InStream, OutSink>,
 	cancel: Arc<AtomicBool>,
 	send_task: Option<oneshot::Sender<task::Task>>,
 	import: Arc<I>,
 }
 
-impl<P: Proposer, I: BlockImport> Future for BftFuture<P, I> {
+impl<P, I, InStream, OutSink> Future for BftFuture<P, I, InStream, OutSink>
+	where
+		P: Proposer,
+		I: BlockImport,
+		InStream: Stream<Item=Communication, Error=P::Error>,
+		OutSink: Sink<SinkItem=Communication, SinkError=P::Error>,
+{
 	type Item = ();
 	type Error = ();
 
@@ -274,7 +280,7 @@ impl<P: Proposer, I: BlockImport> Future for BftFuture<P, I> {
 	}
 }
 
-impl<P: Proposer, I> Drop for BftFuture<P, I> {
+impl<P: Proposer, I, InStream, OutSink> Drop for BftFuture<P, I, InStream, OutSink> {
 	fn drop(&mut self) {
 		// TODO: have a trait member to pass misbehavior reports into.
 		let misbehavior = self.inner.drain_misbehavior().collect::<Vec<_>>();
@@ -317,14 +323,32 @@ pub struct BftService<P, E, I> {
 impl<P, E, I> BftService<P, E, I>
 	where
 		P: ProposerFactory,
-		E: Executor<BftFuture<P::Proposer, I>>,
 		I: BlockImport + Authorities,
 {
+	/// Create a new BFT service signing with the given key.
+	pub fn new(client: Arc<I>, key: Arc<ed25519::Pair>, factory: P, executor: E) -> Self {
+		BftService {
+			client,
+			executor,
+			live_agreements: Mutex::new(HashMap::new()),
+			timer: Timer::default(),
+			round_timeout_multiplier: DEFAULT_ROUND_TIMEOUT_MULTIPLIER,
+			key,
+			factory,
+		}
+	}
+
 	/// Signal that a valid block with the given header has been imported.
 	///
 	/// If the local signing key is an authority, this will begin the consensus process to build a
-	/// block on top of it. If the executor fails to run the future, an error will be returned.
-	pub fn build_upon(&self, header: &Header) -> Result<(), P::Error> {
+	/// block on top of it, exchanging messages with the other authorities through the given input
+	/// stream and output sink. If the executor fails to run the future, an error will be returned.
+	pub fn build_upon<InStream, OutSink>(&self, header: &Header, input: InStream, output: OutSink) -> Result<(), P::Error>
+		where
+			InStream: Stream<Item=Communication, Error=<P::Proposer as Proposer>::Error>,
+			OutSink: Sink<SinkItem=Communication, SinkError=<P::Proposer as Proposer>::Error>,
+			E: Executor<BftFuture<P::Proposer, I, InStream, OutSink>>,
+	{
 		let hash = header.hash();
 		let mut _preempted_consensus = None; // defers drop of live to the end.
 
@@ -355,8 +379,8 @@ impl<P, E, I> BftService<P, E, I>
 			bft_instance,
 			n,
 			max_faulty,
-			stream::empty(),
-			Output(Default::default()),
+			input,
+			output,
 		);
 
 		let cancel = Arc::new(AtomicBool::new(false));
@@ -385,6 +409,45 @@ impl<P, E, I> BftService<P, E, I>
 	}
 }
 
+/// Check a proposal signed by one of the given authorities on top of the given parent.
+pub fn check_proposal(authorities: &[AuthorityId], parent_hash: &HeaderHash, propose: &LocalizedProposal) -> Result<(), Error> {
+	if !authorities.contains(&propose.sender) {
+		return Err(ErrorKind::InvalidAuthority(propose.sender).into());
+	}
+
+	let action_header = PrimitiveAction::ProposeHeader(propose.round_number as u32, propose.digest);
+	let action_propose = PrimitiveAction::Propose(propose.round_number as u32, propose.proposal.clone());
+	check_action(action_header, parent_hash, &propose.sender, &propose.digest_signature)?;
+	check_action(action_propose, parent_hash, &propose.sender, &propose.full_signature)
+}
+
+/// Check a vote signed by one of the given authorities on top of the given parent.
+pub fn check_vote(authorities: &[AuthorityId], parent_hash: &HeaderHash, vote: &LocalizedVote) -> Result<(), Error> {
+	if !authorities.contains(&vote.sender) {
+		return Err(ErrorKind::InvalidAuthority(vote.sender).into());
+	}
+
+	let action = match vote.vote {
+		::generic::Vote::Prepare(r, h) => PrimitiveAction::Prepare(r as u32, h),
+		::generic::Vote::Commit(r, h) => PrimitiveAction::Commit(r as u32, h),
+		::generic::Vote::AdvanceRound(r) => PrimitiveAction::AdvanceRound(r as u32),
+	};
+	check_action(action, parent_hash, &vote.sender, &vote.signature)
+}
+
+fn check_action(action: PrimitiveAction, parent_hash: &HeaderHash, sender: &AuthorityId, sig: &LocalizedSignature) -> Result<(), Error> {
+	let message = Slicable::encode(&PrimitiveMessage {
+		parent: *parent_hash,
+		action,
+	});
+
+	if sig.signer.0 == *sender && ed25519::verify_strong(&sig.signature, &message, &sig.signer) {
+		Ok(())
+	} else {
+		Err(ErrorKind::InvalidSignature(sig.signature.clone(), sig.signer.0).into())
+	}
+}
+
 /// Given a total number of authorities, yield the maximum faulty that would be allowed.
 /// This will always be under 1/3.
 pub fn max_faulty_of(n: usize) -> usize {
@@ -488,6 +551,7 @@ pub fn sign_message(message: Message, key: &ed25519::Pair, parent_hash: HeaderHa
 mod tests {
 	use super::*;
 	use std::collections::HashSet;
+	use futures::stream;
 	use primitives::block;
 	use self::tokio_core::reactor::{Core, Handle};
 	use self::keyring::Keyring;
@@ -543,18 +607,26 @@ mod tests {
 		fn import_misbehavior(&self, _misbehavior: Vec<(AuthorityId, Misbehavior)>) {}
 	}
 
+	// "black hole" output sink.
+	struct Output<E>(::std::marker::PhantomData<E>);
+
+	impl<E> Sink for Output<E> {
+		type SinkItem = Communication;
+		type SinkError = E;
+
+		fn start_send(&mut self, _item: Communication) -> ::futures::StartSend<Communication, E> {
+			Ok(::futures::AsyncSink::Ready)
+		}
+
+		fn poll_complete(&mut self) -> ::futures::Poll<(), E> {
+			Ok(Async::Ready(()))
+		}
+	}
+
 	fn make_service(client: FakeClient, handle: Handle)
 		-> BftService<DummyFactory, Handle, FakeClient>
 	{
-		BftService {
-			client: Arc::new(client),
-			executor: handle,
-			live_agreements: Mutex::new(HashMap::new()),
-			timer: Timer::default(),
-			round_timeout_multiplier: 4,
-			key: Arc::new(Keyring::One.into()),
-			factory: DummyFactory
-		}
+		BftService::new(Arc::new(client), Arc::new(Keyring::One.into()), DummyFactory, handle)
 	}
 
 	fn sign_vote(vote: ::generic::Vote<HeaderHash>, key: &ed25519::Pair, parent_hash: HeaderHash) -> LocalizedSignature {
@@ -587,13 +659,13 @@ mod tests {
 		second.parent_hash = first_hash;
 		let second_hash = second.hash();
 
-		service.build_upon(&first).unwrap();
+		service.build_upon(&first, stream::empty(), Output(Default::default())).unwrap();
 		assert!(service.live_agreements.lock().contains_key(&first_hash));
 
 		// turn the core so the future gets polled and sends its task to the
 		// service. otherwise it deadlocks.
 		core.turn(Some(::std::time::Duration::from_millis(100)));
-		service.build_upon(&second).unwrap();
+		service.build_upon(&second, stream::empty(), Output(Default::default())).unwrap();
 		assert!(!service.live_agreements.lock().contains_key(&first_hash));
 		assert!(service.live_agreements.lock().contains_key(&second_hash));
 
@@ -671,4 +743,34 @@ mod tests {
 
 		assert!(check_justification(&authorities, parent_hash, unchecked).is_err());
 	}
+
+	#[test]
+	fn vote_and_proposal_checks_work() {
+		let parent_hash = Default::default();
+		let authorities = vec![Keyring::One.to_raw_public(), Keyring::Two.to_raw_public()];
+		let key: ed25519::Pair = Keyring::One.into();
+
+		let block = Block {
+			header: Header::from_block_number(1),
+			transactions: Default::default(),
+		};
+		let proposal = match sign_message(generic::Message::Propose(1, block), &key, parent_hash) {
+			generic::LocalizedMessage::Propose(proposal) => proposal,
+			_ => panic!("signing proposal leads to signed proposal"),
+		};
+		assert!(check_proposal(&authorities, &parent_hash, &proposal).is_ok());
+		assert!(check_proposal(&authorities[1..], &parent_hash, &proposal).is_err());
+		assert!(check_proposal(&authorities, &[0xff; 32].into(), &proposal).is_err());
+
+		let vote = match sign_message(generic::Vote::Prepare(1, [0xff; 32].into()).into(), &key, parent_hash) {
+			generic::LocalizedMessage::Vote(vote) => vote,
+			_ => panic!("signing vote leads to signed vote"),
+		};
+		assert!(check_vote(&authorities, &parent_hash, &vote).is_ok());
+		assert!(check_vote(&authorities[1..], &parent_hash, &vote).is_err());
+
+		let mut forged = vote.clone();
+		forged.sender = Keyring::Two.to_raw_public();
+		assert!(check_vote(&authorities, &parent_hash, &forged).is_err());
+	}
 }
@@ -0,0 +1,285 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Block export and import, for backing up and replaying a chain.
+
+use std::io::{self, Read, Write};
+
+use client::{self, Client, ImportResult};
+use codec::{Input, Slicable};
+use primitives::bft::Justification;
+use primitives::block::{self, Block, Id as BlockId};
+use serde_json;
+use state_machine::{self, CodeExecutor};
+
+use error;
+
+/// Log progress every this many blocks.
+const PROGRESS_INTERVAL: block::Number = 10_000;
+
+/// A block along with the justification it was finalized with.
+#[derive(Serialize, Deserialize)]
+struct JustifiedBlock {
+	block: Block,
+	justification: Justification,
+}
+
+impl Slicable for JustifiedBlock {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(JustifiedBlock {
+			block: Slicable::decode(input)?,
+			justification: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.block.encode();
+		v.extend(self.justification.encode());
+		v
+	}
+}
+
+// Adapts an `io::Read` to the codec `Input`, counting the bytes read so that the
+// end of the stream can be told apart from a truncated record.
+struct ReadInput<R> {
+	reader: R,
+	read: usize,
+	error: Option<io::Error>,
+}
+
+impl<R: Read> Input for ReadInput<R> {
+	fn read(&mut self, into: &mut [u8]) -> usize {
+		let mut total = 0;
+		while total < into.len() {
+			match self.reader.read(&mut into[total..]) {
+				Ok(0) => break,
+				Ok(n) => total += n,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => {
+					self.error = Some(e);
+					break;
+				}
+			}
+		}
+		self.read += total;
+		total
+	}
+}
+
+/// Write the blocks `from` to `to` (the best block if `None`) of the best chain
+/// along with their justifications, either as `Slicable`-encoded records or as
+/// one JSON object per line.
+pub fn export_blocks<B, E, W>(
+	client: &Client<B, E>,
+	mut output: W,
+	from: block::Number,
+	to: Option<block::Number>,
+	json: bool,
+) -> error::Result<()> where
+	B: client::backend::Backend,
+	E: CodeExecutor,
+	W: Write,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let to = match to {
+		Some(to) => to,
+		None => client.info()?.chain.best_number,
+	};
+	if from == 0 || from > to {
+		return Err(error::ErrorKind::Input(format!("Invalid block range #{}..#{}", from, to)).into());
+	}
+
+	info!("Exporting blocks #{}..#{}", from, to);
+	for number in from..(to + 1) {
+		let id = BlockId::Number(number);
+		let header = client.header(&id)?.ok_or_else(|| client::error::ErrorKind::UnknownBlock(id))?;
+		let transactions = client.body(&id)?.unwrap_or_default();
+		let justification = client.justification(&id)?.ok_or_else(|| client::error::ErrorKind::BadJustification(id))?;
+		let record = JustifiedBlock {
+			block: Block { header, transactions },
+			justification,
+		};
+
+		if json {
+			serde_json::to_writer(&mut output, &record)?;
+			output.write_all(b"\n")?;
+		} else {
+			output.write_all(&record.encode())?;
+		}
+
+		if number % PROGRESS_INTERVAL == 0 {
+			info!("Exported #{}", number);
+		}
+	}
+	output.flush()?;
+	info!("Exported {} blocks", to - from + 1);
+	Ok(())
+}
+
+/// Read blocks written by `export_blocks` and import them into the client,
+/// checking each justification against the authorities at its parent.
+pub fn import_blocks<B, E, R>(
+	client: &Client<B, E>,
+	input: R,
+	json: bool,
+) -> error::Result<()> where
+	B: client::backend::Backend,
+	E: CodeExecutor,
+	R: Read,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let mut count = 0;
+	if json {
+		for record in serde_json::Deserializer::from_reader(input).into_iter::<JustifiedBlock>() {
+			import_block(client, record?)?;
+			count += 1;
+		}
+	} else {
+		let mut input = ReadInput { reader: input, read: 0, error: None };
+		loop {
+			input.read = 0;
+			match JustifiedBlock::decode(&mut input) {
+				Some(record) => import_block(client, record)?,
+				None => match input.error.take() {
+					Some(e) => return Err(e.into()),
+					None if input.read == 0 => break,
+					None => return Err(error::ErrorKind::Input(format!("Invalid block record after {} blocks", count)).into()),
+				},
+			}
+			count += 1;
+		}
+	}
+	info!("Imported {} blocks, best is #{}", count, client.info()?.chain.best_number);
+	Ok(())
+}
+
+fn import_block<B, E>(client: &Client<B, E>, record: JustifiedBlock) -> error::Result<()> where
+	B: client::backend::Backend,
+	E: CodeExecutor,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let JustifiedBlock { block: Block { header, transactions }, justification } = record;
+	let number = header.number;
+	let hash = header.hash();
+	let justified = client.check_justification(header, justification.into())?;
+	match client.import_block(justified, Some(transactions))? {
+		ImportResult::Imported | ImportResult::AlreadyInChain => {
+			// exported blocks are justified blocks of the best chain.
+			client.finalize_block(BlockId::Hash(hash))?;
+			if number % PROGRESS_INTERVAL == 0 {
+				info!("Imported #{}", number);
+			}
+			Ok(())
+		},
+		result => Err(error::ErrorKind::Input(format!("Error importing block #{}: {:?}", number, result)).into()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bft;
+	use client::{genesis, in_mem};
+	use keyring::Keyring;
+	use substrate_executor::NativeExecutor;
+	use test_runtime;
+	use test_runtime::genesismap::{GenesisConfig, additional_storage_with_genesis};
+
+	native_executor_instance!(Executor, test_runtime::api::dispatch, include_bytes!("../../test-runtime/wasm/target/wasm32-unknown-unknown/release/substrate_test_runtime.compact.wasm"));
+
+	type TestClient = Client<in_mem::Backend, NativeExecutor<Executor>>;
+
+	fn prepare_genesis() -> (block::Header, Vec<(Vec<u8>, Vec<u8>)>) {
+		let mut storage = GenesisConfig::new_simple(vec![Keyring::Alice.to_raw_public()], 1000).genesis_map();
+		let block = genesis::construct_genesis_block(&storage);
+		storage.extend(additional_storage_with_genesis(&block));
+		(block::Header::decode(&mut block.header.encode().as_ref()).expect("to_vec() always gives a valid serialisation; qed"), storage.into_iter().collect())
+	}
+
+	fn justify(header: &block::Header) -> bft::UncheckedJustification {
+		let hash = header.hash();
+		let authorities = vec![Keyring::Alice.into()];
+
+		bft::UncheckedJustification {
+			digest: hash,
+			signatures: authorities.iter().map(|key| {
+				let msg = bft::sign_message(
+					bft::generic::Vote::Commit(1, hash).into(),
+					key,
+					header.parent_hash
+				);
+
+				match msg {
+					bft::generic::LocalizedMessage::Vote(vote) => vote.signature,
+					_ => panic!("signing vote leads to signed vote"),
+				}
+			}).collect(),
+			round_number: 1,
+		}
+	}
+
+	fn chain_with_blocks(count: usize) -> TestClient {
+		let client = client::new_in_mem(Executor::new(), prepare_genesis).unwrap();
+		for _ in 0..count {
+			let block = client.new_block().unwrap().bake().unwrap();
+			let justification = justify(&block.header);
+			let justified = client.check_justification(block.header, justification).unwrap();
+			client.import_block(justified, Some(block.transactions)).unwrap();
+		}
+		client
+	}
+
+	fn export_and_import(json: bool) {
+		let source = chain_with_blocks(3);
+		let mut exported = Vec::new();
+		export_blocks(&source, &mut exported, 1, None, json).unwrap();
+
+		let target = chain_with_blocks(0);
+		import_blocks(&target, &exported[..], json).unwrap();
+
+		let best = source.info().unwrap().chain;
+		let imported = target.info().unwrap().chain;
+		assert_eq!(imported.best_number, 3);
+		assert_eq!(imported.best_hash, best.best_hash);
+		assert_eq!(imported.finalized_hash, best.best_hash);
+		for number in 1..4 {
+			let id = BlockId::Number(number);
+			assert_eq!(target.justification(&id).unwrap(), source.justification(&id).unwrap());
+		}
+	}
+
+	#[test]
+	fn exported_blocks_import_into_fresh_chain() {
+		export_and_import(false);
+	}
+
+	#[test]
+	fn exported_json_blocks_import_into_fresh_chain() {
+		export_and_import(true);
+	}
+
+	#[test]
+	fn truncated_export_is_rejected() {
+		let source = chain_with_blocks(2);
+		let mut exported = Vec::new();
+		export_blocks(&source, &mut exported, 1, None, false).unwrap();
+		exported.pop();
+
+		let target = chain_with_blocks(0);
+		assert!(import_blocks(&target, &exported[..], false).is_err());
+		assert_eq!(target.info().unwrap().chain.best_number, 1);
+	}
+}
@@ -0,0 +1,165 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Chain specifications, generic over the genesis configuration of the runtime.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::Path;
+
+use primitives::storage::{StorageKey, StorageData};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+/// Builds the genesis storage from a runtime genesis configuration.
+pub type BuildStorage<G> = fn(&G) -> HashMap<Vec<u8>, Vec<u8>>;
+
+/// Genesis state of the chain, either as a runtime configuration or as the raw
+/// storage it builds.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+enum Genesis<G> {
+	Runtime(G),
+	Raw(BTreeMap<StorageKey, StorageData>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(deny_unknown_fields)]
+struct ChainSpecFile<G> {
+	name: String,
+	boot_nodes: Vec<String>,
+	genesis: Genesis<G>,
+}
+
+/// A specification of a chain: its name, the nodes to bootstrap from and the
+/// genesis state.
+pub struct ChainSpec<G> {
+	spec: ChainSpecFile<G>,
+	build_storage: BuildStorage<G>,
+}
+
+impl<G: Serialize + DeserializeOwned> ChainSpec<G> {
+	/// Create a chain specification with the genesis given as a runtime configuration.
+	pub fn from_genesis(name: &str, boot_nodes: Vec<String>, genesis: G, build_storage: BuildStorage<G>) -> Self {
+		ChainSpec {
+			spec: ChainSpecFile {
+				name: name.to_owned(),
+				boot_nodes,
+				genesis: Genesis::Runtime(genesis),
+			},
+			build_storage,
+		}
+	}
+
+	/// The human-readable name of the chain.
+	pub fn name(&self) -> &str {
+		&self.spec.name
+	}
+
+	/// The nodes to connect to when starting up.
+	pub fn boot_nodes(&self) -> &[String] {
+		&self.spec.boot_nodes
+	}
+
+	/// The storage of the chain before the genesis block is built.
+	pub fn genesis_map(&self) -> HashMap<Vec<u8>, Vec<u8>> {
+		match self.spec.genesis {
+			Genesis::Runtime(ref config) => (self.build_storage)(config),
+			Genesis::Raw(ref map) => map.iter().map(|(k, v)| (k.0.clone(), v.0.clone())).collect(),
+		}
+	}
+
+	/// Load a chain specification from a JSON file.
+	pub fn from_json_file(path: &Path, build_storage: BuildStorage<G>) -> Result<Self, String> {
+		let file = File::open(path)
+			.map_err(|e| format!("Error opening spec file {}: {}", path.display(), e))?;
+		let spec = serde_json::from_reader(file)
+			.map_err(|e| format!("Error parsing spec file {}: {}", path.display(), e))?;
+		Ok(ChainSpec { spec, build_storage })
+	}
+
+	/// Dump the chain specification to JSON. In raw mode the genesis is given as
+	/// the storage key/values it builds rather than the runtime configuration.
+	pub fn to_json(mut self, raw: bool) -> Result<String, String> {
+		if raw {
+			let storage = self.genesis_map();
+			self.spec.genesis = Genesis::Raw(storage.into_iter().map(|(k, v)| (StorageKey(k), StorageData(v))).collect());
+		}
+		serde_json::to_string_pretty(&self.spec).map_err(|e| format!("Error generating spec json: {}", e))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use tempdir::TempDir;
+
+	#[derive(Serialize, Deserialize)]
+	#[serde(rename_all = "camelCase")]
+	struct TestGenesis {
+		balance: u64,
+	}
+
+	fn build_storage(genesis: &TestGenesis) -> HashMap<Vec<u8>, Vec<u8>> {
+		vec![
+			(b"balance".to_vec(), genesis.balance.to_string().into_bytes()),
+			(b"code".to_vec(), vec![1, 2, 3]),
+		].into_iter().collect()
+	}
+
+	fn spec() -> ChainSpec<TestGenesis> {
+		ChainSpec::from_genesis("Test", vec!["/ip4/127.0.0.1/tcp/30333".into()], TestGenesis { balance: 69 }, build_storage)
+	}
+
+	fn load(json: &str) -> Result<ChainSpec<TestGenesis>, String> {
+		let dir = TempDir::new("chain_spec").unwrap();
+		let path = dir.path().join("spec.json");
+		File::create(&path).unwrap().write_all(json.as_bytes()).unwrap();
+		ChainSpec::from_json_file(&path, build_storage)
+	}
+
+	#[test]
+	fn spec_survives_json_round_trip() {
+		let json = spec().to_json(false).unwrap();
+		assert!(json.contains("\"runtime\""));
+
+		let loaded = load(&json).unwrap();
+		assert_eq!(loaded.name(), "Test");
+		assert_eq!(loaded.boot_nodes(), &["/ip4/127.0.0.1/tcp/30333".to_owned()][..]);
+		assert_eq!(loaded.genesis_map(), spec().genesis_map());
+		assert_eq!(loaded.to_json(false).unwrap(), json);
+	}
+
+	#[test]
+	fn raw_spec_holds_genesis_storage() {
+		let json = spec().to_json(true).unwrap();
+		assert!(json.contains("\"raw\""));
+		assert!(!json.contains("\"runtime\""));
+
+		let loaded = load(&json).unwrap();
+		assert_eq!(loaded.genesis_map(), spec().genesis_map());
+	}
+
+	#[test]
+	fn unknown_fields_are_rejected() {
+		let json = spec().to_json(false).unwrap().replacen("\"name\"", "\"unknown\": 1,\n  \"name\"", 1);
+		assert!(load(&json).is_err());
+	}
+}
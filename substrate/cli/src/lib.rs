@@ -0,0 +1,375 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Command line handling shared by Substrate-based nodes: chain specifications,
+//! block export and import and starting the network and RPC servers.
+
+#![warn(missing_docs)]
+
+extern crate app_dirs;
+extern crate clap;
+extern crate ed25519;
+extern crate env_logger;
+extern crate futures;
+extern crate tokio_core;
+extern crate serde;
+extern crate serde_json;
+extern crate substrate_bft as bft;
+extern crate substrate_client as client;
+extern crate substrate_client_db as client_db;
+extern crate substrate_codec as codec;
+extern crate substrate_network as network;
+extern crate substrate_primitives as primitives;
+extern crate substrate_rpc;
+extern crate substrate_rpc_servers as rpc;
+extern crate substrate_state_machine as state_machine;
+
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(test)]
+#[macro_use]
+extern crate substrate_executor;
+#[cfg(test)]
+extern crate substrate_keyring as keyring;
+#[cfg(test)]
+extern crate substrate_test_runtime as test_runtime;
+#[cfg(test)]
+extern crate tempdir;
+
+pub mod chain_ops;
+pub mod chain_spec;
+pub mod error;
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Weak};
+
+use client::Client;
+use futures::{future, Stream};
+use network::ManageNetwork;
+use primitives::block::Id as BlockId;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use state_machine::CodeExecutor;
+use substrate_rpc::Subscriptions;
+use substrate_rpc::author::AuthorApi;
+use substrate_rpc::chain::Chain;
+use substrate_rpc::state::State;
+use substrate_rpc::system::{System, SystemInfo};
+use tokio_core::reactor;
+
+use chain_spec::{BuildStorage, ChainSpec};
+
+/// Initialize the logger, enabling info logging unless overridden by `RUST_LOG`
+/// or the given pattern.
+pub fn init_logger(pattern: &str) {
+	let mut builder = env_logger::LogBuilder::new();
+	// Disable info logging by default for some modules:
+	builder.filter(Some("hyper"), log::LogLevelFilter::Warn);
+	// Enable info for others.
+	builder.filter(None, log::LogLevelFilter::Info);
+
+	if let Ok(lvl) = std::env::var("RUST_LOG") {
+		builder.parse(&lvl);
+	}
+
+	builder.parse(pattern);
+
+
+	builder.init().expect("Logger initialized only once.");
+}
+
+/// The directory the node keeps its files in: `--base-path` if given, otherwise
+/// the user data directory of the named application.
+pub fn base_path(matches: &clap::ArgMatches, app_name: &'static str) -> PathBuf {
+	use app_dirs::{AppInfo, AppDataType};
+
+	matches.value_of("base-path")
+		.map(|x| Path::new(x).to_owned())
+		.unwrap_or_else(|| {
+			let app_info = AppInfo {
+				name: app_name,
+				author: "Parity Technologies",
+			};
+
+			app_dirs::get_app_root(
+				AppDataType::UserData,
+				&app_info,
+			).expect("app directories exist on all supported platforms; qed")
+		})
+}
+
+/// Load the chain specification named by `--chain`: one of the built-in development
+/// and local testnet chains or a path to a JSON spec file.
+pub fn load_spec<G: Serialize + DeserializeOwned>(
+	matches: &clap::ArgMatches,
+	development: fn() -> ChainSpec<G>,
+	local_testnet: fn() -> ChainSpec<G>,
+	build_storage: BuildStorage<G>,
+) -> error::Result<ChainSpec<G>> {
+	let spec = match matches.value_of("chain") {
+		Some("dev") | None => development(),
+		Some("local") => local_testnet(),
+		Some(path) => ChainSpec::from_json_file(Path::new(path), build_storage).map_err(error::ErrorKind::Input)?,
+	};
+	Ok(spec)
+}
+
+/// Database settings from the command line, keeping the database under the base path.
+pub fn database_settings(matches: &clap::ArgMatches, base_path: &Path) -> error::Result<client_db::DatabaseSettings> {
+	Ok(client_db::DatabaseSettings {
+		cache_size: None,
+		path: base_path.join("db"),
+		pruning: match matches.value_of("pruning") {
+			Some("archive") => client_db::PruningMode::ArchiveAll,
+			Some(s) => client_db::PruningMode::KeepLast(s.parse()
+				.map_err(|_| error::ErrorKind::Input("Invalid pruning mode specified".to_owned()))?),
+			None => client_db::PruningMode::default(),
+		},
+	})
+}
+
+/// Remove the database after asking for confirmation, unless already given.
+pub fn purge_chain(db_path: &Path, confirmed: bool) -> error::Result<()> {
+	if !confirmed {
+		print!("Are you sure to remove {:?}? (y/N) ", db_path);
+		io::stdout().flush()?;
+		let mut input = String::new();
+		io::stdin().read_line(&mut input)?;
+		match input.trim() {
+			"y" | "Y" => (),
+			_ => {
+				println!("Aborted");
+				return Ok(());
+			},
+		}
+	}
+
+	match fs::remove_dir_all(db_path) {
+		Ok(()) => {
+			println!("{:?} removed.", db_path);
+			Ok(())
+		},
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+			println!("{:?} did not exist.", db_path);
+			Ok(())
+		},
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Run the `export-blocks`, `import-blocks` or `revert` subcommand against the client.
+/// Returns `false` without doing anything if another subcommand was given.
+pub fn execute_chain_command<B, E>(client: &Client<B, E>, matches: &clap::ArgMatches) -> error::Result<bool> where
+	B: client::backend::Backend,
+	E: CodeExecutor,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	match matches.subcommand() {
+		("export-blocks", Some(sub_matches)) => {
+			let from = parse_value(sub_matches, "from", 1, "Invalid starting block number specified")?;
+			let to = match sub_matches.value_of("to") {
+				Some(to) => Some(to.parse().map_err(|_| error::ErrorKind::Input("Invalid last block number specified".to_owned()))?),
+				None => None,
+			};
+			let json = sub_matches.is_present("json");
+			match sub_matches.value_of("OUTPUT") {
+				Some(path) => chain_ops::export_blocks(client, io::BufWriter::new(File::create(path)?), from, to, json)?,
+				None => {
+					let stdout = io::stdout();
+					chain_ops::export_blocks(client, stdout.lock(), from, to, json)?
+				},
+			}
+		},
+		("revert", Some(sub_matches)) => {
+			let blocks = parse_value(sub_matches, "NUM", 256, "Invalid number of blocks specified")?;
+			let reverted = client.revert(blocks)?;
+			let info = client.info()?.chain;
+			info!("Reverted {} blocks. Best: #{} ({})", reverted, info.best_number, info.best_hash);
+		},
+		("import-blocks", Some(sub_matches)) => {
+			let json = sub_matches.is_present("json");
+			match sub_matches.value_of("INPUT") {
+				Some(path) => chain_ops::import_blocks(client, io::BufReader::new(File::open(path)?), json)?,
+				None => {
+					let stdin = io::stdin();
+					chain_ops::import_blocks(client, stdin.lock(), json)?
+				},
+			}
+		},
+		_ => return Ok(false),
+	}
+	Ok(true)
+}
+
+/// Start the network and the RPC servers on top of the given client and run the
+/// event loop until the process is killed. The network propagates transactions
+/// from the given pool, and `author` builds the handler of the `author` RPC
+/// namespace for each server, given the network to propagate the submitted
+/// transactions. A validator advertises the public key of `validator_key` to its
+/// peers, and `start_consensus` is given the started network to take part in the
+/// block authoring.
+pub fn run_node<B, E, P, A, F, C>(
+	mut core: reactor::Core,
+	client: Arc<Client<B, E>>,
+	matches: &clap::ArgMatches,
+	base_path: &Path,
+	boot_nodes: &[String],
+	system_info: SystemInfo,
+	role: network::Role,
+	on_demand: Option<Arc<network::OnDemand>>,
+	transaction_pool: Arc<P>,
+	author: F,
+	validator_key: Option<Arc<ed25519::Pair>>,
+	start_consensus: C,
+) -> error::Result<()> where
+	B: client::backend::Backend + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
+	P: network::TransactionPool + 'static,
+	A: AuthorApi<Metadata=rpc::Metadata>,
+	F: Fn(reactor::Remote, Arc<network::Service>) -> A,
+	C: FnOnce(&reactor::Handle, Arc<network::Service>),
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let node_name = matches.value_of("name").map(|name| name.to_owned());
+	if let Some(ref name) = node_name {
+		info!("Node name: {}", name);
+	}
+
+	let network = network::Service::new(network::Params {
+		config: network::ProtocolConfig {
+			roles: role,
+			validator_key,
+		},
+		network_config: network_config(matches, base_path, boot_nodes, &system_info, node_name)?,
+		chain: client.clone(),
+		on_demand,
+		transaction_pool,
+	})?;
+	network.start_network();
+	start_consensus(&core.handle(), network.clone());
+
+	let remote = core.remote();
+	let handler = || {
+		let state = State::new(client.clone(), Subscriptions::new(remote.clone()));
+		let chain = Chain::new(client.clone(), Subscriptions::new(remote.clone()));
+		let system = System::new(system_info.clone(), client.clone(), network.clone());
+		rpc::rpc_handler(state, chain, author(remote.clone(), network.clone()), system)
+	};
+
+	let rpc_interface: IpAddr = parse_value(matches, "rpc-interface", "127.0.0.1".parse().unwrap(), "Invalid RPC interface specified")?;
+	let http_address = SocketAddr::new(rpc_interface, parse_value(matches, "rpc-port", 9933, "Invalid RPC port specified")?);
+	let ws_address = SocketAddr::new(rpc_interface, parse_value(matches, "ws-port", 9944, "Invalid WS port specified")?);
+	let _http_server = rpc::start_http(&http_address, handler())?;
+	let _ws_server = rpc::start_ws(&ws_address, handler())?;
+
+	let _ = core.run(future::empty::<(), ()>());
+	Ok(())
+}
+
+/// Run the BFT agreement on top of each new best block with the given key, exchanging
+/// the consensus messages with the other authorities through the network. The
+/// agreements run on the given event loop.
+pub fn start_bft<B, E, P>(
+	handle: &reactor::Handle,
+	client: Arc<Client<B, E>>,
+	network: Arc<network::Service>,
+	key: Arc<ed25519::Pair>,
+	factory: P,
+) where
+	B: client::backend::Backend + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
+	P: bft::ProposerFactory + 'static,
+	P::Error: ::std::fmt::Display,
+	reactor::Handle: future::Executor<bft::BftFuture<
+		P::Proposer,
+		Client<B, E>,
+		network::BftMessageStream<<P::Proposer as bft::Proposer>::Error>,
+		network::BftSink<<P::Proposer as bft::Proposer>::Error>,
+	>>,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let bft_service = bft::BftService::new(client.clone(), key, factory, handle.clone());
+	let new_best = client.best_block_notification_stream();
+	handle.spawn(new_best.for_each(move |notification| {
+		let authorities = match client.authorities_at(&BlockId::Hash(notification.hash)) {
+			Ok(authorities) => authorities,
+			Err(e) => {
+				warn!(target: "bft", "Unable to get the authorities at {}: {}", notification.hash, e);
+				return Ok(());
+			},
+		};
+
+		let input = network.bft_messages(notification.hash, authorities);
+		let link: Weak<network::BftNetwork> = Arc::downgrade(&network);
+		let output = network::BftSink::new(link, notification.hash);
+		if let Err(e) = bft_service.build_upon(&notification.header, input, output) {
+			warn!(target: "bft", "Unable to start the agreement on top of {}: {}", notification.hash, e);
+		}
+		Ok(())
+	}));
+}
+
+/// Build the p2p network configuration from the command line, connecting to the
+/// given boot nodes as well as those given with `--bootnodes`.
+pub fn network_config(
+	matches: &clap::ArgMatches,
+	base_path: &Path,
+	boot_nodes: &[String],
+	system_info: &SystemInfo,
+	node_name: Option<String>,
+) -> error::Result<network::NetworkConfiguration> {
+	let mut config = network::NetworkConfiguration::new();
+
+	let net_config_path = base_path.join("network").to_string_lossy().into_owned();
+	config.config_path = Some(net_config_path.clone());
+	config.net_config_path = Some(net_config_path);
+
+	let mut listen_address: SocketAddr = parse_value(matches, "listen-addr", "0.0.0.0:30333".parse().unwrap(), "Invalid listen address specified")?;
+	if matches.is_present("port") {
+		listen_address.set_port(parse_value(matches, "port", 30333, "Invalid p2p port specified")?);
+	}
+	config.listen_address = Some(listen_address);
+	config.public_address = None;
+
+	config.boot_nodes = boot_nodes.to_vec();
+	if let Some(nodes) = matches.values_of("bootnodes") {
+		config.boot_nodes.extend(nodes.map(Into::into));
+	}
+	config.reserved_nodes = matches.values_of("reserved-nodes").map_or_else(Vec::new, |nodes| nodes.map(Into::into).collect());
+	config.client_version = match node_name {
+		Some(name) => format!("{}/v{}/{}", system_info.impl_name, system_info.impl_version, name),
+		None => format!("{}/v{}", system_info.impl_name, system_info.impl_version),
+	};
+
+	Ok(config)
+}
+
+/// Parse the value of an argument, falling back to the default if not given.
+pub fn parse_value<T: FromStr>(matches: &clap::ArgMatches, name: &str, default: T, message: &str) -> error::Result<T> {
+	match matches.value_of(name) {
+		Some(value) => value.parse().map_err(|_| error::ErrorKind::Input(message.to_owned()).into()),
+		None => Ok(default),
+	}
+}
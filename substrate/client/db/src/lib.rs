@@ -0,0 +1,983 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Client backend that uses RocksDB database as storage.
+
+#![warn(missing_docs)]
+
+extern crate substrate_client as client;
+extern crate substrate_codec as codec;
+extern crate substrate_primitives as primitives;
+extern crate substrate_runtime_support as runtime_support;
+extern crate substrate_state_machine as state_machine;
+extern crate ethcore_bigint;
+extern crate hashdb;
+extern crate kvdb;
+extern crate kvdb_rocksdb;
+extern crate memorydb;
+extern crate parking_lot;
+
+#[macro_use] extern crate log;
+
+#[cfg(test)] extern crate kvdb_memorydb;
+
+mod state_db;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::path::PathBuf;
+
+use codec::Slicable;
+use ethcore_bigint::hash::H256 as TrieH256;
+use kvdb::{KeyValueDB, DBTransaction, DBValue};
+use kvdb_rocksdb::{Database, DatabaseConfig};
+use parking_lot::RwLock;
+use primitives::H256;
+use primitives::block::{self, Id as BlockId, HeaderHash};
+use runtime_support::Hashable;
+use client::blockchain::Backend as BlockchainBackend;
+use client::cht;
+use state_machine::{CodeExecutor, TrieBackend};
+use state_machine::backend::Backend as StateBackend;
+use state_db::StateDb;
+
+pub use state_db::PruningMode;
+
+/// Database settings.
+pub struct DatabaseSettings {
+	/// Cache size in MiB. If `None` the database default is used.
+	pub cache_size: Option<usize>,
+	/// Path to the database.
+	pub path: PathBuf,
+	/// State pruning mode.
+	pub pruning: PruningMode,
+}
+
+/// Create an instance of db-backed client.
+pub fn new_client<E, F>(
+	settings: DatabaseSettings,
+	executor: E,
+	build_genesis: F
+) -> Result<client::Client<Backend, E>, client::error::Error>
+	where
+		E: CodeExecutor,
+		F: FnOnce() -> (block::Header, Vec<(Vec<u8>, Vec<u8>)>)
+{
+	let backend = Backend::new(&settings)?;
+	client::Client::new(backend, executor, build_genesis)
+}
+
+mod columns {
+	pub const META: Option<u32> = Some(0);
+	pub const STATE: Option<u32> = Some(1);
+	pub const STATE_ROOT: Option<u32> = Some(2);
+	pub const BLOCK_INDEX: Option<u32> = Some(3);
+	pub const HEADER: Option<u32> = Some(4);
+	pub const BODY: Option<u32> = Some(5);
+	pub const JUSTIFICATION: Option<u32> = Some(6);
+	pub const STATE_REFS: Option<u32> = Some(7);
+	pub const STATE_JOURNAL: Option<u32> = Some(8);
+	pub const CHANGES: Option<u32> = Some(9);
+	pub const CHT: Option<u32> = Some(10);
+	pub const NUM_COLUMNS: u32 = 11;
+}
+
+mod meta {
+	pub const BEST_BLOCK: &[u8; 4] = b"best";
+	pub const CANONICAL: &[u8; 9] = b"canonical";
+	pub const FINALIZED_BLOCK: &[u8; 9] = b"finalized";
+	pub const LEAVES: &[u8; 6] = b"leaves";
+	pub const PRUNING_MODE: &[u8; 7] = b"pruning";
+}
+
+struct PendingBlock {
+	header: block::Header,
+	justification: Option<primitives::bft::Justification>,
+	body: Option<block::Body>,
+	is_best: bool,
+}
+
+#[derive(Clone)]
+struct Meta {
+	best_hash: HeaderHash,
+	best_number: block::Number,
+	genesis_hash: HeaderHash,
+	finalized_hash: HeaderHash,
+	finalized_number: block::Number,
+}
+
+type BlockKey = [u8; 8];
+
+// Big endian, so that the index is ordered by block number.
+fn number_to_db_key(n: block::Number) -> BlockKey {
+	[
+		(n >> 56) as u8,
+		((n >> 48) & 0xff) as u8,
+		((n >> 40) & 0xff) as u8,
+		((n >> 32) & 0xff) as u8,
+		((n >> 24) & 0xff) as u8,
+		((n >> 16) & 0xff) as u8,
+		((n >> 8) & 0xff) as u8,
+		(n & 0xff) as u8,
+	]
+}
+
+// Maps database error to client error
+fn db_err(err: kvdb::Error) -> client::error::Error {
+	client::error::ErrorKind::Backend(format!("{}", err)).into()
+}
+
+fn header_hash(header: &block::Header) -> HeaderHash {
+	header.blake2_256().into()
+}
+
+/// Block database
+pub struct BlockchainDb {
+	db: Arc<KeyValueDB>,
+	meta: RwLock<Meta>,
+	leaves: RwLock<Vec<HeaderHash>>,
+}
+
+impl BlockchainDb {
+	fn new(db: Arc<KeyValueDB>) -> Result<Self, client::error::Error> {
+		let meta = Self::read_meta(&*db)?;
+		let leaves = match db.get(columns::META, meta::LEAVES).map_err(db_err)? {
+			Some(raw) => decode_or_err(&raw, "leaves")?,
+			None if meta.genesis_hash.is_zero() => Vec::new(),
+			None => vec![meta.best_hash],
+		};
+		Ok(BlockchainDb {
+			db,
+			meta: RwLock::new(meta),
+			leaves: RwLock::new(leaves),
+		})
+	}
+
+	fn read_meta(db: &KeyValueDB) -> Result<Meta, client::error::Error> {
+		let genesis_hash = match db.get(columns::BLOCK_INDEX, &number_to_db_key(0)).map_err(db_err)? {
+			Some(raw) => HeaderHash::from(&raw[..]),
+			None => return Ok(Meta {
+				best_hash: Default::default(),
+				best_number: 0,
+				genesis_hash: Default::default(),
+				finalized_hash: Default::default(),
+				finalized_number: 0,
+			}),
+		};
+
+		let best_hash = db.get(columns::META, meta::BEST_BLOCK).map_err(db_err)?
+			.map(|raw| HeaderHash::from(&raw[..]))
+			.unwrap_or(genesis_hash);
+		let best_number = Self::read_number(db, &best_hash, "best header")?;
+		let finalized_hash = db.get(columns::META, meta::FINALIZED_BLOCK).map_err(db_err)?
+			.map(|raw| HeaderHash::from(&raw[..]))
+			.unwrap_or(genesis_hash);
+		let finalized_number = Self::read_number(db, &finalized_hash, "finalized header")?;
+
+		debug!("Opened blockchain db, best {:?} ({}), finalized {:?} ({})", best_hash, best_number, finalized_hash, finalized_number);
+		Ok(Meta {
+			best_hash,
+			best_number,
+			genesis_hash,
+			finalized_hash,
+			finalized_number,
+		})
+	}
+
+	fn read_number(db: &KeyValueDB, hash: &HeaderHash, what: &str) -> Result<block::Number, client::error::Error> {
+		match db.get(columns::HEADER, hash).map_err(db_err)? {
+			Some(raw) => decode_or_err::<block::Header>(&raw, what).map(|header| header.number),
+			None => Ok(0),
+		}
+	}
+
+	fn update_meta(&self, hash: HeaderHash, number: block::Number, is_best: bool) {
+		let mut meta = self.meta.write();
+		if number == 0 {
+			meta.genesis_hash = hash;
+			meta.finalized_hash = hash;
+		}
+		if is_best {
+			meta.best_number = number;
+			meta.best_hash = hash;
+		}
+	}
+
+	fn id(&self, id: BlockId) -> Result<Option<HeaderHash>, client::error::Error> {
+		match id {
+			BlockId::Hash(h) => Ok(Some(h)),
+			BlockId::Number(n) => Ok(self.db.get(columns::BLOCK_INDEX, &number_to_db_key(n)).map_err(db_err)?
+				.map(|raw| HeaderHash::from(&raw[..]))),
+		}
+	}
+
+	fn read_db(&self, id: BlockId, column: Option<u32>) -> Result<Option<DBValue>, client::error::Error> {
+		match self.id(id)? {
+			Some(hash) => self.db.get(column, &hash).map_err(db_err),
+			None => Ok(None),
+		}
+	}
+
+	// Returns true if the block is an ancestor of the other one.
+	fn is_ancestor(&self, hash: HeaderHash, number: block::Number, of: HeaderHash) -> Result<bool, client::error::Error> {
+		let mut current = of;
+		loop {
+			let header = self.header(BlockId::Hash(current))?
+				.ok_or_else(|| client::error::ErrorKind::UnknownBlock(BlockId::Hash(current)))?;
+			if header.number <= number {
+				return Ok(current == hash);
+			}
+			current = header.parent_hash;
+		}
+	}
+}
+
+fn decode_or_err<T: Slicable>(raw: &[u8], what: &str) -> Result<T, client::error::Error> {
+	T::decode(&mut &raw[..])
+		.ok_or_else(|| client::error::ErrorKind::Backend(format!("Error decoding {}", what)).into())
+}
+
+impl client::blockchain::Backend for BlockchainDb {
+	fn header(&self, id: BlockId) -> Result<Option<block::Header>, client::error::Error> {
+		match self.read_db(id, columns::HEADER)? {
+			Some(header) => decode_or_err(&header, "header").map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn body(&self, id: BlockId) -> Result<Option<block::Body>, client::error::Error> {
+		match self.read_db(id, columns::BODY)? {
+			Some(body) => decode_or_err(&body, "body").map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn justification(&self, id: BlockId) -> Result<Option<primitives::bft::Justification>, client::error::Error> {
+		match self.read_db(id, columns::JUSTIFICATION)? {
+			Some(justification) => decode_or_err(&justification, "justification").map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn info(&self) -> Result<client::blockchain::Info, client::error::Error> {
+		let meta = self.meta.read();
+		Ok(client::blockchain::Info {
+			best_hash: meta.best_hash,
+			best_number: meta.best_number,
+			genesis_hash: meta.genesis_hash,
+			finalized_hash: meta.finalized_hash,
+			finalized_number: meta.finalized_number,
+		})
+	}
+
+	fn status(&self, id: BlockId) -> Result<client::blockchain::BlockStatus, client::error::Error> {
+		match self.read_db(id, columns::HEADER)?.is_some() {
+			true => Ok(client::blockchain::BlockStatus::InChain),
+			false => Ok(client::blockchain::BlockStatus::Unknown),
+		}
+	}
+
+	fn hash(&self, number: block::Number) -> Result<Option<block::HeaderHash>, client::error::Error> {
+		self.id(BlockId::Number(number))
+	}
+
+	fn changed_keys(&self, id: BlockId) -> Result<Option<Vec<Vec<u8>>>, client::error::Error> {
+		match self.read_db(id, columns::CHANGES)? {
+			Some(keys) => decode_or_err(&keys, "changed keys").map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn leaves(&self) -> Result<Vec<HeaderHash>, client::error::Error> {
+		Ok(self.leaves.read().clone())
+	}
+
+	fn cht_root(&self, cht_num: block::Number) -> Result<Option<H256>, client::error::Error> {
+		Ok(self.db.get(columns::CHT, &number_to_db_key(cht_num)).map_err(db_err)?
+			.map(|raw| H256::from(&raw[..])))
+	}
+}
+
+/// Database transaction
+pub struct BlockImportOperation {
+	storage: Arc<StorageDb>,
+	pending_state: TrieBackend,
+	pending_block: Option<PendingBlock>,
+	changed_keys: BTreeSet<Vec<u8>>,
+}
+
+impl client::backend::BlockImportOperation for BlockImportOperation {
+	type State = TrieBackend;
+
+	fn state(&self) -> Result<&Self::State, client::error::Error> {
+		Ok(&self.pending_state)
+	}
+
+	fn set_block_data(&mut self, header: block::Header, body: Option<block::Body>, justification: Option<primitives::bft::Justification>, is_best: bool) -> Result<(), client::error::Error> {
+		assert!(self.pending_block.is_none(), "Only one block per operation is allowed");
+		self.pending_block = Some(PendingBlock {
+			header,
+			body,
+			justification,
+			is_best,
+		});
+		Ok(())
+	}
+
+	fn set_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, changes: I) -> Result<(), client::error::Error> {
+		let changed_keys = &mut self.changed_keys;
+		self.pending_state.commit(changes.inspect(|&(ref key, _)| { changed_keys.insert(key.clone()); }))
+			.map_err(client::error::ErrorKind::Backend)?;
+		Ok(())
+	}
+
+	fn set_child_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, storage_key: Vec<u8>, changes: I) -> Result<(), client::error::Error> {
+		self.changed_keys.insert(storage_key.clone());
+		self.pending_state.commit_child(storage_key, changes)
+			.map_err(client::error::ErrorKind::Backend)?;
+		Ok(())
+	}
+
+	fn reset_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, iter: I) -> Result<(), client::error::Error> {
+		self.pending_state = TrieBackend::with_storage_for_genesis(self.storage.clone());
+		let changed_keys = &mut self.changed_keys;
+		changed_keys.clear();
+		self.pending_state.commit(iter.map(|(k, v)| {
+			changed_keys.insert(k.clone());
+			(k, Some(v))
+		})).map_err(client::error::ErrorKind::Backend)?;
+		Ok(())
+	}
+}
+
+/// Trie nodes storage, backed by the state column of the database.
+struct StorageDb {
+	db: Arc<KeyValueDB>,
+}
+
+impl state_machine::TrieStorage for StorageDb {
+	fn get(&self, key: &TrieH256) -> Result<Option<hashdb::DBValue>, String> {
+		self.db.get(columns::STATE, &key[..])
+			.map(|val| val.map(|val| hashdb::DBValue::from_slice(&val)))
+			.map_err(|e| format!("Database backend error: {}", e))
+	}
+}
+
+/// Disk backend. Keeps data in a key-value store. State is kept as trie nodes,
+/// with the root of each block's state trie indexed by block hash.
+pub struct Backend {
+	db: Arc<KeyValueDB>,
+	storage: Arc<StorageDb>,
+	blockchain: BlockchainDb,
+	state_db: StateDb,
+}
+
+impl Backend {
+	/// Create a new instance of database backend.
+	pub fn new(config: &DatabaseSettings) -> Result<Backend, client::error::Error> {
+		let mut db_config = DatabaseConfig::with_columns(Some(columns::NUM_COLUMNS));
+		db_config.memory_budget = config.cache_size;
+		db_config.wal = true;
+		let path = config.path.to_str().ok_or_else(|| client::error::ErrorKind::Backend("Invalid database path".into()))?;
+		let db = Arc::new(Database::open(&db_config, &path).map_err(db_err)?);
+
+		Backend::from_kvdb(db as Arc<_>, config.pruning)
+	}
+
+	#[cfg(test)]
+	fn new_test(pruning: PruningMode) -> Backend {
+		let db = Arc::new(::kvdb_memorydb::create(columns::NUM_COLUMNS));
+
+		Backend::from_kvdb(db as Arc<_>, pruning).expect("failed to create test-db")
+	}
+
+	fn from_kvdb(db: Arc<KeyValueDB>, pruning: PruningMode) -> Result<Backend, client::error::Error> {
+		let blockchain = BlockchainDb::new(db.clone())?;
+		let state_db = StateDb::new(pruning, &*db)?;
+
+		Ok(Backend {
+			storage: Arc::new(StorageDb { db: db.clone() }),
+			db,
+			blockchain,
+			state_db,
+		})
+	}
+}
+
+impl client::backend::Backend for Backend {
+	type BlockImportOperation = BlockImportOperation;
+	type Blockchain = BlockchainDb;
+	type State = TrieBackend;
+
+	fn begin_operation(&self, block: BlockId) -> Result<Self::BlockImportOperation, client::error::Error> {
+		let state = match block {
+			BlockId::Hash(h) if h.is_zero() => TrieBackend::with_storage_for_genesis(self.storage.clone()),
+			_ => self.state_at(block)?,
+		};
+
+		Ok(BlockImportOperation {
+			storage: self.storage.clone(),
+			pending_state: state,
+			pending_block: None,
+			changed_keys: BTreeSet::new(),
+		})
+	}
+
+	fn commit_operation(&self, mut operation: Self::BlockImportOperation) -> Result<(), client::error::Error> {
+		let mut transaction = DBTransaction::new();
+		if let Some(pending_block) = operation.pending_block {
+			let hash = header_hash(&pending_block.header);
+			let number = pending_block.header.number;
+			transaction.put(columns::HEADER, &hash, &pending_block.header.encode());
+			if let Some(body) = pending_block.body {
+				transaction.put(columns::BODY, &hash, &body.encode());
+			}
+			if let Some(justification) = pending_block.justification {
+				transaction.put(columns::JUSTIFICATION, &hash, &justification.encode());
+			}
+			if pending_block.is_best {
+				let (best_hash, parent_hash) = (self.blockchain.meta.read().best_hash, pending_block.header.parent_hash);
+				if number != 0 && parent_hash != best_hash {
+					// the best chain switches to another fork: re-canonicalise the number index.
+					let route = client::blockchain::tree_route(
+						|id| self.blockchain.header(id),
+						BlockId::Hash(best_hash),
+						BlockId::Hash(parent_hash),
+					)?;
+					for retracted in route.retracted() {
+						transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(retracted.number));
+					}
+					for enacted in route.enacted() {
+						transaction.put(columns::BLOCK_INDEX, &number_to_db_key(enacted.number), &enacted.hash);
+					}
+				}
+				transaction.put(columns::BLOCK_INDEX, &number_to_db_key(number), &hash);
+				transaction.put(columns::META, meta::BEST_BLOCK, &hash);
+			}
+
+			let mut leaves = self.blockchain.leaves.read().clone();
+			leaves.retain(|leaf| *leaf != pending_block.header.parent_hash && *leaf != hash);
+			leaves.push(hash);
+			transaction.put(columns::META, meta::LEAVES, &leaves.encode());
+
+			let root = *operation.pending_state.root();
+			transaction.put(columns::STATE_ROOT, &hash, &root[..]);
+			let changed_keys: Vec<_> = operation.changed_keys.into_iter().collect();
+			transaction.put(columns::CHANGES, &hash, &changed_keys.encode());
+
+			let (best_number, finalized_number) = {
+				let meta = self.blockchain.meta.read();
+				(if pending_block.is_best { number } else { meta.best_number }, meta.finalized_number)
+			};
+			let canonical = self.state_db.insert_block(
+				&*self.db,
+				&mut transaction,
+				&hash,
+				number,
+				operation.pending_state.drain_overlay(),
+				best_number,
+				finalized_number,
+				|n| self.blockchain.hash(n),
+			)?;
+
+			debug!("DB Commit {:?} ({})", hash, number);
+			self.db.write(transaction).map_err(db_err)?;
+			self.state_db.on_commit(canonical);
+			self.blockchain.update_meta(hash, number, pending_block.is_best);
+			*self.blockchain.leaves.write() = leaves;
+		}
+		Ok(())
+	}
+
+	fn finalize_block(&self, block: BlockId) -> Result<(), client::error::Error> {
+		let header = self.blockchain.header(block)?
+			.ok_or_else(|| client::error::ErrorKind::UnknownBlock(block))?;
+		let hash = header_hash(&header);
+		if self.blockchain.hash(header.number)? != Some(hash) {
+			return Err(client::error::ErrorKind::NotInBestChain(block).into());
+		}
+
+		let (best_number, finalized_number) = {
+			let meta = self.blockchain.meta.read();
+			(meta.best_number, meta.finalized_number)
+		};
+		if header.number <= finalized_number {
+			return Ok(());
+		}
+
+		let mut transaction = DBTransaction::new();
+		transaction.put(columns::META, meta::FINALIZED_BLOCK, &hash);
+		for cht_num in cht::completed_between(finalized_number, header.number) {
+			let hashes = (cht::start_number(cht_num)..cht::end_number(cht_num) + 1)
+				.map(|n| self.blockchain.hash(n)?.ok_or_else(|| client::error::ErrorKind::UnknownBlock(BlockId::Number(n)).into()))
+				.collect::<Result<Vec<_>, client::error::Error>>()?;
+			if let Some(root) = cht::compute_root(cht_num, hashes) {
+				transaction.put(columns::CHT, &number_to_db_key(cht_num), &root);
+			}
+		}
+		let canonical = self.state_db.finalize(
+			&*self.db,
+			&mut transaction,
+			best_number,
+			header.number,
+			|n| self.blockchain.hash(n),
+		)?;
+
+		debug!("DB Finalize {:?} ({})", hash, header.number);
+		self.db.write(transaction).map_err(db_err)?;
+		self.state_db.on_commit(canonical);
+		let mut meta = self.blockchain.meta.write();
+		meta.finalized_hash = hash;
+		meta.finalized_number = header.number;
+		Ok(())
+	}
+
+	fn blockchain(&self) -> &BlockchainDb {
+		&self.blockchain
+	}
+
+	fn state_at(&self, block: BlockId) -> Result<Self::State, client::error::Error> {
+		let header = match self.blockchain.header(block)? {
+			Some(header) => header,
+			None => return Err(client::error::ErrorKind::UnknownBlock(block).into()),
+		};
+		let number = header.number;
+		if self.state_db.is_pruned(number) {
+			return Err(client::error::ErrorKind::StatePruned(block).into());
+		}
+		if let Some(canonical) = self.state_db.canonical() {
+			// the state of a block is built upon the states of its ancestors, which
+			// are discarded when the fork they belong to is not canonicalized.
+			if number > canonical && self.blockchain.hash(number)? != Some(header_hash(&header)) {
+				let mut ancestor = header;
+				while ancestor.number > canonical {
+					let parent = BlockId::Hash(ancestor.parent_hash);
+					ancestor = self.blockchain.header(parent)?
+						.ok_or_else(|| client::error::ErrorKind::UnknownBlock(parent))?;
+				}
+				if self.blockchain.hash(canonical)? != Some(header_hash(&ancestor)) {
+					return Err(client::error::ErrorKind::StatePruned(block).into());
+				}
+			}
+		}
+
+		match self.blockchain.read_db(block, columns::STATE_ROOT)? {
+			Some(root) => Ok(TrieBackend::with_storage(self.storage.clone(), TrieH256::from(&root[..]))),
+			None if self.state_db.is_era_pruned(number) => Err(client::error::ErrorKind::StatePruned(block).into()),
+			None => Err(client::error::ErrorKind::UnknownBlock(block).into()),
+		}
+	}
+
+	fn is_light(&self) -> bool {
+		false
+	}
+
+	fn revert(&self, n: block::Number) -> Result<block::Number, client::error::Error> {
+		let mut reverted = 0;
+		while reverted < n {
+			let (best_hash, best_number, finalized_number) = {
+				let meta = self.blockchain.meta.read();
+				(meta.best_hash, meta.best_number, meta.finalized_number)
+			};
+			if best_number <= finalized_number {
+				break;
+			}
+			let parent_hash = match self.blockchain.header(BlockId::Hash(best_hash))? {
+				Some(header) => header.parent_hash,
+				None => return Err(client::error::ErrorKind::UnknownBlock(BlockId::Hash(best_hash)).into()),
+			};
+
+			let mut transaction = DBTransaction::new();
+			for column in &[columns::HEADER, columns::BODY, columns::JUSTIFICATION, columns::STATE_ROOT, columns::CHANGES] {
+				transaction.delete(*column, &best_hash);
+			}
+			transaction.delete(columns::BLOCK_INDEX, &number_to_db_key(best_number));
+			transaction.put(columns::META, meta::BEST_BLOCK, &parent_hash);
+
+			let mut leaves = self.blockchain.leaves.read().clone();
+			leaves.retain(|leaf| *leaf != best_hash);
+			let mut parent_is_leaf = true;
+			for leaf in &leaves {
+				if self.blockchain.is_ancestor(parent_hash, best_number - 1, *leaf)? {
+					parent_is_leaf = false;
+					break;
+				}
+			}
+			if parent_is_leaf {
+				leaves.push(parent_hash);
+			}
+			transaction.put(columns::META, meta::LEAVES, &leaves.encode());
+			self.state_db.revert_block(&*self.db, &mut transaction, &best_hash, best_number)?;
+
+			debug!("DB Revert {:?} ({})", best_hash, best_number);
+			self.db.write(transaction).map_err(db_err)?;
+			self.blockchain.update_meta(parent_hash, best_number - 1, true);
+			*self.blockchain.leaves.write() = leaves;
+			reverted += 1;
+		}
+		Ok(reverted)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use client::backend::Backend as BTrait;
+	use client::backend::BlockImportOperation as Op;
+
+	fn insert_block(db: &Backend, number: block::Number, parent_hash: HeaderHash) -> HeaderHash {
+		let header = block::Header {
+			number,
+			parent_hash,
+			state_root: Default::default(),
+			digest: Default::default(),
+			transaction_root: Default::default(),
+		};
+		let hash = header_hash(&header);
+
+		let parent = match number {
+			0 => BlockId::Hash(Default::default()),
+			_ => BlockId::Hash(parent_hash),
+		};
+		let mut op = db.begin_operation(parent).unwrap();
+		op.set_block_data(header, Some(vec![]), None, true).unwrap();
+		db.commit_operation(op).unwrap();
+		hash
+	}
+
+	fn insert_state_block(
+		db: &Backend,
+		number: block::Number,
+		parent_hash: HeaderHash,
+		fork: u8,
+		changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+		finalized: bool,
+		is_best: bool,
+	) -> HeaderHash {
+		let header = block::Header {
+			number,
+			parent_hash,
+			state_root: Default::default(),
+			digest: Default::default(),
+			transaction_root: [fork; 32].into(),
+		};
+		let hash = header_hash(&header);
+		let justification = primitives::bft::Justification { round_number: 0, hash, signatures: Vec::new() };
+
+		let mut op = match number {
+			0 => {
+				let mut op = db.begin_operation(BlockId::Hash(Default::default())).unwrap();
+				op.reset_storage(changes.into_iter().filter_map(|(k, v)| v.map(|v| (k, v)))).unwrap();
+				op
+			},
+			_ => {
+				let mut op = db.begin_operation(BlockId::Hash(parent_hash)).unwrap();
+				op.set_storage(changes.into_iter()).unwrap();
+				op
+			},
+		};
+		op.set_block_data(header, Some(vec![]), Some(justification), is_best).unwrap();
+		db.commit_operation(op).unwrap();
+		if finalized {
+			db.finalize_block(BlockId::Hash(hash)).unwrap();
+		}
+		hash
+	}
+
+	fn insert_state_chain(db: &Backend, len: block::Number, finalized: bool) -> Vec<HeaderHash> {
+		let mut hashes = Vec::new();
+		let mut parent = Default::default();
+		for i in 0..len {
+			let changes = match i {
+				0 => vec![(vec![1], Some(vec![0])), (vec![2], Some(vec![2; 64]))],
+				_ => vec![(vec![1], Some(vec![i as u8; 64]))],
+			};
+			parent = insert_state_block(db, i, parent, 0, changes, finalized, true);
+			hashes.push(parent);
+		}
+		hashes
+	}
+
+	fn is_pruned(result: Result<TrieBackend, client::error::Error>) -> bool {
+		match result {
+			Err(client::error::Error(client::error::ErrorKind::StatePruned(_), _)) => true,
+			_ => false,
+		}
+	}
+
+	#[test]
+	fn block_hash_inserted_correctly() {
+		let db = Backend::new_test(PruningMode::ArchiveAll);
+		let mut parent = Default::default();
+		for i in 0..10 {
+			assert!(db.blockchain().hash(i).unwrap().is_none());
+			parent = insert_block(&db, i, parent);
+			assert_eq!(db.blockchain().hash(i).unwrap().unwrap(), parent);
+			assert_eq!(db.blockchain().header(BlockId::Number(i)).unwrap().unwrap().number, i);
+		}
+
+		let info = db.blockchain().info().unwrap();
+		assert_eq!(info.best_number, 9);
+		assert_eq!(info.best_hash, parent);
+		assert_eq!(info.genesis_hash, db.blockchain().hash(0).unwrap().unwrap());
+	}
+
+	#[test]
+	fn set_state_data() {
+		let db = Backend::new_test(PruningMode::ArchiveAll);
+		let hash = {
+			let mut op = db.begin_operation(BlockId::Hash(Default::default())).unwrap();
+			let header = block::Header::from_block_number(0);
+			let hash = header_hash(&header);
+
+			let storage = vec![
+				(vec![1, 3, 5], vec![2, 4, 6]),
+				(vec![1, 2, 3], vec![9, 9, 9]),
+			];
+
+			op.reset_storage(storage.iter().cloned()).unwrap();
+			op.set_block_data(header, Some(vec![]), None, true).unwrap();
+			db.commit_operation(op).unwrap();
+			hash
+		};
+
+		let state = db.state_at(BlockId::Number(0)).unwrap();
+		assert_eq!(state.storage(&[1, 3, 5]).unwrap(), Some(vec![2, 4, 6]));
+		assert_eq!(state.storage(&[1, 2, 3]).unwrap(), Some(vec![9, 9, 9]));
+		assert_eq!(state.storage(&[5, 5, 5]).unwrap(), None);
+
+		let mut op = db.begin_operation(BlockId::Number(0)).unwrap();
+		let mut header = block::Header::from_block_number(1);
+		header.parent_hash = hash;
+		op.set_storage(vec![
+			(vec![1, 3, 5], None),
+			(vec![5, 5, 5], Some(vec![4, 5, 6])),
+		].into_iter()).unwrap();
+		op.set_block_data(header, Some(vec![]), None, true).unwrap();
+		db.commit_operation(op).unwrap();
+
+		let state = db.state_at(BlockId::Number(1)).unwrap();
+		assert_eq!(state.storage(&[1, 3, 5]).unwrap(), None);
+		assert_eq!(state.storage(&[1, 2, 3]).unwrap(), Some(vec![9, 9, 9]));
+		assert_eq!(state.storage(&[5, 5, 5]).unwrap(), Some(vec![4, 5, 6]));
+	}
+
+	#[test]
+	fn reopens_existing_database() {
+		let kvdb: Arc<KeyValueDB> = Arc::new(::kvdb_memorydb::create(columns::NUM_COLUMNS));
+		let best = {
+			let db = Backend::from_kvdb(kvdb.clone(), PruningMode::ArchiveAll).unwrap();
+			let genesis = insert_block(&db, 0, Default::default());
+			insert_block(&db, 1, genesis)
+		};
+
+		let db = Backend::from_kvdb(kvdb, PruningMode::ArchiveAll).unwrap();
+		let info = db.blockchain().info().unwrap();
+		assert_eq!(info.best_number, 1);
+		assert_eq!(info.best_hash, best);
+		assert!(db.state_at(BlockId::Number(1)).is_ok());
+	}
+
+	#[test]
+	fn old_finalized_state_is_pruned() {
+		let db = Backend::new_test(PruningMode::KeepLast(2));
+		insert_state_chain(&db, 6, true);
+
+		assert!(is_pruned(db.state_at(BlockId::Number(0))));
+		assert!(is_pruned(db.state_at(BlockId::Number(2))));
+		for i in 3..6 {
+			let state = db.state_at(BlockId::Number(i)).unwrap();
+			assert_eq!(state.storage(&[1]).unwrap(), Some(vec![i as u8; 64]));
+			// untouched since genesis, so shared by all states.
+			assert_eq!(state.storage(&[2]).unwrap(), Some(vec![2; 64]));
+		}
+	}
+
+	#[test]
+	fn unfinalized_state_is_not_pruned() {
+		let db = Backend::new_test(PruningMode::KeepLast(2));
+		insert_state_chain(&db, 6, false);
+
+		for i in 1..6 {
+			let state = db.state_at(BlockId::Number(i)).unwrap();
+			assert_eq!(state.storage(&[1]).unwrap(), Some(vec![i as u8; 64]));
+		}
+
+		db.finalize_block(BlockId::Number(1)).unwrap();
+		assert!(is_pruned(db.state_at(BlockId::Number(0))));
+		assert!(db.state_at(BlockId::Number(1)).is_ok());
+
+		db.finalize_block(BlockId::Number(5)).unwrap();
+		assert!(is_pruned(db.state_at(BlockId::Number(2))));
+		assert_eq!(db.state_at(BlockId::Number(3)).unwrap().storage(&[1]).unwrap(), Some(vec![3; 64]));
+	}
+
+	#[test]
+	fn archive_keeps_all_states() {
+		let db = Backend::new_test(PruningMode::ArchiveAll);
+		insert_state_chain(&db, 6, true);
+
+		for i in 1..6 {
+			let state = db.state_at(BlockId::Number(i)).unwrap();
+			assert_eq!(state.storage(&[1]).unwrap(), Some(vec![i as u8; 64]));
+		}
+	}
+
+	#[test]
+	fn non_canonical_state_is_discarded() {
+		let db = Backend::new_test(PruningMode::KeepLast(1));
+		let genesis = insert_state_block(&db, 0, Default::default(), 0, vec![(vec![1], Some(vec![0]))], true, true);
+		let fork = insert_state_block(&db, 1, genesis, 1, vec![(vec![1], Some(vec![20; 64]))], false, false);
+		let mut parent = insert_state_block(&db, 1, genesis, 0, vec![(vec![1], Some(vec![10; 64]))], true, true);
+		assert_eq!(db.state_at(BlockId::Hash(fork)).unwrap().storage(&[1]).unwrap(), Some(vec![20; 64]));
+
+		for i in 2..4 {
+			parent = insert_state_block(&db, i, parent, 0, vec![(vec![i as u8], Some(vec![i as u8; 64]))], true, true);
+		}
+
+		assert!(is_pruned(db.state_at(BlockId::Hash(fork))));
+		let state = db.state_at(BlockId::Number(3)).unwrap();
+		assert_eq!(state.storage(&[1]).unwrap(), Some(vec![10; 64]));
+	}
+
+	#[test]
+	fn descendants_of_discarded_fork_are_pruned() {
+		let db = Backend::new_test(PruningMode::KeepLast(1));
+		let genesis = insert_state_block(&db, 0, Default::default(), 0, vec![(vec![1], Some(vec![0]))], true, true);
+		let mut fork = genesis;
+		for i in 1..4 {
+			fork = insert_state_block(&db, i, fork, 1, vec![(vec![1], Some(vec![20 + i as u8; 64]))], false, false);
+		}
+		let mut parent = genesis;
+		for i in 1..4 {
+			parent = insert_state_block(&db, i, parent, 0, vec![(vec![1], Some(vec![i as u8; 64]))], true, true);
+		}
+
+		assert!(is_pruned(db.state_at(BlockId::Hash(fork))));
+		assert_eq!(db.state_at(BlockId::Number(3)).unwrap().storage(&[1]).unwrap(), Some(vec![3; 64]));
+	}
+
+	#[test]
+	fn changed_keys_are_recorded() {
+		let db = Backend::new_test(PruningMode::ArchiveAll);
+		let genesis = insert_state_block(&db, 0, Default::default(), 0, vec![(vec![1], Some(vec![0])), (vec![2], Some(vec![0]))], true, true);
+		let block1 = insert_state_block(&db, 1, genesis, 0, vec![(vec![3], Some(vec![1])), (vec![1], None)], true, true);
+
+		assert_eq!(db.blockchain().changed_keys(BlockId::Hash(genesis)).unwrap(), Some(vec![vec![1], vec![2]]));
+		assert_eq!(db.blockchain().changed_keys(BlockId::Hash(block1)).unwrap(), Some(vec![vec![1], vec![3]]));
+		assert_eq!(db.blockchain().changed_keys(BlockId::Number(2)).unwrap(), None);
+	}
+
+	#[test]
+	fn pruning_progress_survives_reopen() {
+		let kvdb: Arc<KeyValueDB> = Arc::new(::kvdb_memorydb::create(columns::NUM_COLUMNS));
+		{
+			let db = Backend::from_kvdb(kvdb.clone(), PruningMode::KeepLast(2)).unwrap();
+			insert_state_chain(&db, 6, true);
+		}
+
+		let db = Backend::from_kvdb(kvdb, PruningMode::KeepLast(2)).unwrap();
+		assert!(is_pruned(db.state_at(BlockId::Number(2))));
+		assert!(db.state_at(BlockId::Number(3)).is_ok());
+	}
+
+	#[test]
+	fn pruning_mode_is_checked_on_reopen() {
+		let kvdb: Arc<KeyValueDB> = Arc::new(::kvdb_memorydb::create(columns::NUM_COLUMNS));
+		assert!(Backend::from_kvdb(kvdb.clone(), PruningMode::KeepLast(0)).is_err());
+		{
+			let db = Backend::from_kvdb(kvdb.clone(), PruningMode::KeepLast(2)).unwrap();
+			insert_state_chain(&db, 2, true);
+		}
+
+		assert!(Backend::from_kvdb(kvdb.clone(), PruningMode::ArchiveAll).is_err());
+		assert!(Backend::from_kvdb(kvdb.clone(), PruningMode::KeepLast(4)).is_ok());
+		assert!(Backend::from_kvdb(kvdb, PruningMode::KeepLast(2)).is_ok());
+	}
+
+	#[test]
+	fn revert_removes_best_blocks() {
+		let db = Backend::new_test(PruningMode::ArchiveAll);
+		let hashes = insert_state_chain(&db, 6, false);
+
+		assert_eq!(db.revert(3).unwrap(), 3);
+		let info = db.blockchain().info().unwrap();
+		assert_eq!(info.best_number, 2);
+		assert_eq!(info.best_hash, hashes[2]);
+		assert_eq!(db.blockchain().hash(3).unwrap(), None);
+		assert!(db.blockchain().header(BlockId::Hash(hashes[3])).unwrap().is_none());
+		assert!(db.blockchain().justification(BlockId::Hash(hashes[4])).unwrap().is_none());
+		assert_eq!(db.state_at(BlockId::Number(2)).unwrap().storage(&[1]).unwrap(), Some(vec![2; 64]));
+
+		let block = insert_state_block(&db, 3, hashes[2], 1, vec![(vec![1], Some(vec![30; 64]))], true, true);
+		assert_eq!(db.blockchain().info().unwrap().best_hash, block);
+		assert_eq!(db.state_at(BlockId::Number(3)).unwrap().storage(&[1]).unwrap(), Some(vec![30; 64]));
+	}
+
+	#[test]
+	fn leaves_are_tracked() {
+		let kvdb: Arc<KeyValueDB> = Arc::new(::kvdb_memorydb::create(columns::NUM_COLUMNS));
+		let (a2, b2) = {
+			let db = Backend::from_kvdb(kvdb.clone(), PruningMode::ArchiveAll).unwrap();
+			let genesis = insert_state_block(&db, 0, Default::default(), 0, vec![], false, true);
+			let a1 = insert_state_block(&db, 1, genesis, 0, vec![], false, true);
+			let a2 = insert_state_block(&db, 2, a1, 0, vec![], false, true);
+			let b2 = insert_state_block(&db, 2, a1, 1, vec![], false, false);
+			assert_eq!(db.blockchain().leaves().unwrap(), vec![a2, b2]);
+			(a2, b2)
+		};
+
+		let db = Backend::from_kvdb(kvdb, PruningMode::ArchiveAll).unwrap();
+		assert_eq!(db.blockchain().leaves().unwrap(), vec![a2, b2]);
+		assert_eq!(db.revert(1).unwrap(), 1);
+		assert_eq!(db.blockchain().leaves().unwrap(), vec![b2]);
+	}
+
+	#[test]
+	fn revert_stops_at_finalized_block() {
+		let kvdb: Arc<KeyValueDB> = Arc::new(::kvdb_memorydb::create(columns::NUM_COLUMNS));
+		let hashes = {
+			let db = Backend::from_kvdb(kvdb.clone(), PruningMode::KeepLast(2)).unwrap();
+			let hashes = insert_state_chain(&db, 6, false);
+			db.finalize_block(BlockId::Number(3)).unwrap();
+			assert_eq!(db.revert(10).unwrap(), 2);
+			hashes
+		};
+
+		let db = Backend::from_kvdb(kvdb, PruningMode::KeepLast(2)).unwrap();
+		let info = db.blockchain().info().unwrap();
+		assert_eq!(info.best_number, 3);
+		assert_eq!(info.best_hash, hashes[3]);
+		assert_eq!(db.state_at(BlockId::Number(3)).unwrap().storage(&[1]).unwrap(), Some(vec![3; 64]));
+		assert_eq!(db.revert(1).unwrap(), 0);
+	}
+
+	#[test]
+	fn cht_root_is_stored_when_cht_is_finalized() {
+		let db = Backend::new_test(PruningMode::ArchiveAll);
+		let mut hashes = vec![insert_block(&db, 0, Default::default())];
+		for number in 1..cht::SIZE + 2 {
+			let parent = hashes[number as usize - 1];
+			hashes.push(insert_block(&db, number, parent));
+		}
+
+		db.finalize_block(BlockId::Number(cht::SIZE - 1)).unwrap();
+		assert_eq!(db.blockchain().cht_root(0).unwrap(), None);
+		db.finalize_block(BlockId::Number(cht::SIZE + 1)).unwrap();
+		assert_eq!(db.blockchain().cht_root(0).unwrap(), cht::compute_root(0, hashes[1..cht::SIZE as usize + 1].iter().cloned()));
+		assert_eq!(db.blockchain().cht_root(1).unwrap(), None);
+	}
+}
@@ -0,0 +1,378 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! State trie nodes maintenance.
+//!
+//! In archive mode every inserted node is kept forever. Otherwise each node has
+//! a reference count and each block has a journal of the nodes its state
+//! transition inserted and removed. Removals are deferred until the block's
+//! number falls out of the retention window and the block is finalized, at
+//! which point the journal is applied: removals of the canonical block are
+//! carried out, and insertions of its non-canonical siblings are reverted.
+//! Nodes are deleted from the database once their reference count drops to zero.
+//!
+//! The pruning mode is stored in the database, which can't be reopened in
+//! archive mode once pruned, and vice versa.
+
+use std::collections::HashMap;
+
+use codec::Slicable;
+use kvdb::{KeyValueDB, DBTransaction, DBValue};
+use memorydb::MemoryDB;
+use parking_lot::RwLock;
+use primitives::block::{self, HeaderHash};
+use primitives::hexdisplay::HexDisplay;
+
+use {columns, meta, db_err, number_to_db_key};
+
+/// State pruning mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PruningMode {
+	/// Keep the states of all blocks.
+	ArchiveAll,
+	/// Keep the state of the best block and of the given number of blocks
+	/// before it, plus the states of all blocks which are not yet finalized.
+	KeepLast(block::Number),
+}
+
+impl Default for PruningMode {
+	fn default() -> Self {
+		PruningMode::KeepLast(256)
+	}
+}
+
+/// Changes to the state trie nodes made by a single block.
+#[derive(Default)]
+struct Journal {
+	inserted: Vec<(Vec<u8>, u32)>,
+	deleted: Vec<(Vec<u8>, u32)>,
+}
+
+impl Slicable for Journal {
+	fn decode<I: ::codec::Input>(input: &mut I) -> Option<Self> {
+		<(Vec<(Vec<u8>, u32)>, Vec<(Vec<u8>, u32)>)>::decode(input)
+			.map(|(inserted, deleted)| Journal { inserted, deleted })
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		self.inserted.using_encoded(|s| v.extend(s));
+		self.deleted.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+fn encode_mode(mode: PruningMode) -> Vec<u8> {
+	match mode {
+		PruningMode::ArchiveAll => vec![0],
+		PruningMode::KeepLast(keep) => {
+			let mut v = vec![1];
+			keep.using_encoded(|s| v.extend(s));
+			v
+		},
+	}
+}
+
+fn decode_mode(raw: &[u8]) -> Option<PruningMode> {
+	match raw.split_first() {
+		Some((&0, _)) => Some(PruningMode::ArchiveAll),
+		Some((&1, mut rest)) => block::Number::decode(&mut rest).map(PruningMode::KeepLast),
+		_ => None,
+	}
+}
+
+/// Accumulated reference count changes, to be written in a single transaction.
+#[derive(Default)]
+struct RefChanges {
+	changes: HashMap<Vec<u8>, (Option<DBValue>, i64)>,
+}
+
+impl RefChanges {
+	fn insert(&mut self, key: Vec<u8>, value: DBValue, count: u32) {
+		let entry = self.changes.entry(key).or_insert((None, 0));
+		entry.0 = Some(value);
+		entry.1 += count as i64;
+	}
+
+	fn remove(&mut self, key: Vec<u8>, count: u32) {
+		self.changes.entry(key).or_insert((None, 0)).1 -= count as i64;
+	}
+
+	fn apply(self, db: &KeyValueDB, transaction: &mut DBTransaction) -> Result<(), ::client::error::Error> {
+		for (key, (value, delta)) in self.changes {
+			if delta == 0 {
+				continue;
+			}
+
+			let current = match db.get(columns::STATE_REFS, &key).map_err(db_err)? {
+				Some(raw) => u32::decode(&mut &raw[..]).unwrap_or(0) as i64,
+				None => 0,
+			};
+
+			let updated = current + delta;
+			if updated <= 0 {
+				trace!(target: "state-db", "Deleting node {}", HexDisplay::from(&key));
+				transaction.delete(columns::STATE, &key);
+				transaction.delete(columns::STATE_REFS, &key);
+			} else {
+				if current == 0 {
+					if let Some(value) = value {
+						transaction.put(columns::STATE, &key, &value);
+					}
+				}
+				transaction.put(columns::STATE_REFS, &key, &(updated as u32).encode());
+			}
+		}
+		Ok(())
+	}
+}
+
+fn journal_key(number: block::Number, hash: &HeaderHash) -> Vec<u8> {
+	let mut key = number_to_db_key(number).to_vec();
+	key.extend_from_slice(&hash[..]);
+	key
+}
+
+/// State database maintenance.
+pub struct StateDb {
+	mode: PruningMode,
+	/// All blocks up to and including this number have their journals applied.
+	canonical: RwLock<block::Number>,
+}
+
+impl StateDb {
+	/// Open state db maintenance for a database, reading the pruning progress.
+	/// Fails if the database was created with archive mode and `mode` prunes,
+	/// or the other way around.
+	pub fn new(mode: PruningMode, db: &KeyValueDB) -> Result<Self, ::client::error::Error> {
+		if let PruningMode::KeepLast(0) = mode {
+			return Err(::client::error::ErrorKind::Backend("Pruning must keep the state of at least one block".into()).into());
+		}
+
+		let stored = match db.get(columns::META, meta::PRUNING_MODE).map_err(db_err)? {
+			Some(raw) => Some(decode_mode(&raw)
+				.ok_or_else(|| ::client::error::ErrorKind::Backend("Error decoding pruning mode".into()))?),
+			None => None,
+		};
+		match stored {
+			Some(stored) if (stored == PruningMode::ArchiveAll) != (mode == PruningMode::ArchiveAll) => {
+				return Err(::client::error::ErrorKind::Backend(
+					format!("Database was created with pruning mode {:?} and can't be opened with {:?}", stored, mode)
+				).into());
+			},
+			Some(stored) if stored == mode => (),
+			_ => {
+				let mut transaction = DBTransaction::new();
+				transaction.put(columns::META, meta::PRUNING_MODE, &encode_mode(mode));
+				db.write(transaction).map_err(db_err)?;
+			},
+		}
+
+		let canonical = match db.get(columns::META, meta::CANONICAL).map_err(db_err)? {
+			Some(raw) => block::Number::decode(&mut &raw[..])
+				.ok_or_else(|| ::client::error::ErrorKind::Backend("Error decoding canonical block number".into()))?,
+			None => 0,
+		};
+
+		Ok(StateDb {
+			mode,
+			canonical: RwLock::new(canonical),
+		})
+	}
+
+	/// Returns true if the state of the canonical block with given number is no
+	/// longer available.
+	pub fn is_pruned(&self, number: block::Number) -> bool {
+		match self.mode {
+			PruningMode::ArchiveAll => false,
+			PruningMode::KeepLast(_) => number < *self.canonical.read(),
+		}
+	}
+
+	/// Returns true if the state of any block with given number may be missing.
+	pub fn is_era_pruned(&self, number: block::Number) -> bool {
+		match self.mode {
+			PruningMode::ArchiveAll => false,
+			PruningMode::KeepLast(_) => number <= *self.canonical.read(),
+		}
+	}
+
+	/// Returns the number of the highest canonicalized block, below which all
+	/// states are pruned. Returns `None` in archive mode.
+	pub fn canonical(&self) -> Option<block::Number> {
+		match self.mode {
+			PruningMode::ArchiveAll => None,
+			PruningMode::KeepLast(_) => Some(*self.canonical.read()),
+		}
+	}
+
+	/// Write trie node changes of a newly imported block into the transaction,
+	/// along with the pruning they allow. Returns the number of the highest
+	/// canonicalized block, which must be passed to `on_commit` once the
+	/// transaction is written.
+	pub fn insert_block<F>(
+		&self,
+		db: &KeyValueDB,
+		transaction: &mut DBTransaction,
+		hash: &HeaderHash,
+		number: block::Number,
+		mut changes: MemoryDB,
+		best_number: block::Number,
+		finalized_number: block::Number,
+		canonical_hash: F,
+	) -> Result<block::Number, ::client::error::Error>
+		where F: Fn(block::Number) -> Result<Option<HeaderHash>, ::client::error::Error>
+	{
+		if let PruningMode::ArchiveAll = self.mode {
+			for (key, (value, rc)) in changes.drain() {
+				if rc > 0 {
+					transaction.put(columns::STATE, &key[..], &value);
+				}
+			}
+			return Ok(0);
+		}
+
+		let mut refs = RefChanges::default();
+		let mut journal = Journal::default();
+		for (key, (value, rc)) in changes.drain() {
+			if rc > 0 {
+				refs.insert(key.to_vec(), value, rc as u32);
+				journal.inserted.push((key.to_vec(), rc as u32));
+			} else if rc < 0 {
+				journal.deleted.push((key.to_vec(), (-rc) as u32));
+			}
+		}
+
+		if number > 0 {
+			transaction.put(columns::STATE_JOURNAL, &journal_key(number, hash), &journal.encode());
+		}
+
+		self.canonicalize(db, transaction, refs, best_number, finalized_number, canonical_hash)
+	}
+
+	/// Write the pruning allowed by finalization of a block into the transaction.
+	/// Returns the number of the highest canonicalized block, which must be
+	/// passed to `on_commit` once the transaction is written.
+	pub fn finalize<F>(
+		&self,
+		db: &KeyValueDB,
+		transaction: &mut DBTransaction,
+		best_number: block::Number,
+		finalized_number: block::Number,
+		canonical_hash: F,
+	) -> Result<block::Number, ::client::error::Error>
+		where F: Fn(block::Number) -> Result<Option<HeaderHash>, ::client::error::Error>
+	{
+		self.canonicalize(db, transaction, RefChanges::default(), best_number, finalized_number, canonical_hash)
+	}
+
+	// Apply journals of the finalized blocks which fell out of the retention window.
+	// Reference count changes are accumulated in `refs`, so that they are not
+	// overwritten by changes written earlier in the same transaction.
+	fn canonicalize<F>(
+		&self,
+		db: &KeyValueDB,
+		transaction: &mut DBTransaction,
+		mut refs: RefChanges,
+		best_number: block::Number,
+		finalized_number: block::Number,
+		canonical_hash: F,
+	) -> Result<block::Number, ::client::error::Error>
+		where F: Fn(block::Number) -> Result<Option<HeaderHash>, ::client::error::Error>
+	{
+		let keep = match self.mode {
+			PruningMode::ArchiveAll => return Ok(0),
+			PruningMode::KeepLast(keep) => keep,
+		};
+
+		let mut canonical = *self.canonical.read();
+		while canonical + keep < best_number {
+			let era = canonical + 1;
+			if era > finalized_number {
+				trace!(target: "state-db", "Block #{} is not finalized, delaying pruning", era);
+				break;
+			}
+			let canonical_hash = match canonical_hash(era)? {
+				Some(hash) => hash,
+				None => break,
+			};
+
+			let prefix = number_to_db_key(era);
+			for (key, raw) in db.iter_from_prefix(columns::STATE_JOURNAL, &prefix) {
+				if !key.starts_with(&prefix) {
+					break;
+				}
+				let journal = Journal::decode(&mut &raw[..])
+					.ok_or_else(|| ::client::error::ErrorKind::Backend("Error decoding state journal".into()))?;
+				let journal_hash = HeaderHash::from(&key[prefix.len()..]);
+				if journal_hash == canonical_hash {
+					for (node, count) in journal.deleted {
+						refs.remove(node, count);
+					}
+				} else {
+					trace!(target: "state-db", "Discarding state of non-canonical block {:?}", journal_hash);
+					for (node, count) in journal.inserted {
+						refs.remove(node, count);
+					}
+					transaction.delete(columns::STATE_ROOT, &journal_hash);
+				}
+				transaction.delete(columns::STATE_JOURNAL, &key);
+			}
+
+			canonical = era;
+			debug!(target: "state-db", "Pruned state of block #{}", era - 1);
+		}
+
+		refs.apply(db, transaction)?;
+		transaction.put(columns::META, meta::CANONICAL, &canonical.encode());
+		Ok(canonical)
+	}
+
+	/// Write the removal of the trie nodes inserted by a block which is not yet
+	/// canonicalized into the transaction. In archive mode nodes are never removed.
+	pub fn revert_block(
+		&self,
+		db: &KeyValueDB,
+		transaction: &mut DBTransaction,
+		hash: &HeaderHash,
+		number: block::Number,
+	) -> Result<(), ::client::error::Error> {
+		if let PruningMode::ArchiveAll = self.mode {
+			return Ok(());
+		}
+
+		let key = journal_key(number, hash);
+		if let Some(raw) = db.get(columns::STATE_JOURNAL, &key).map_err(db_err)? {
+			let journal = Journal::decode(&mut &raw[..])
+				.ok_or_else(|| ::client::error::ErrorKind::Backend("Error decoding state journal".into()))?;
+			let mut refs = RefChanges::default();
+			for (node, count) in journal.inserted {
+				refs.remove(node, count);
+			}
+			refs.apply(db, transaction)?;
+			transaction.delete(columns::STATE_JOURNAL, &key);
+		}
+		Ok(())
+	}
+
+	/// Update pruning progress after the transaction created by `insert_block`
+	/// or `finalize` has been written.
+	pub fn on_commit(&self, canonical: block::Number) {
+		if let PruningMode::KeepLast(_) = self.mode {
+			*self.canonical.write() = canonical;
+		}
+	}
+}
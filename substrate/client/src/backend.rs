@@ -30,8 +30,11 @@ pub trait BlockImportOperation {
 	fn state(&self) -> error::Result<&Self::State>;
 	/// Append block data to the transaction.
 	fn set_block_data(&mut self, header: block::Header, body: Option<block::Body>, justification: Option<primitives::bft::Justification>, is_new_best: bool) -> error::Result<()>;
-	/// Inject storage data into the database.
+	/// Inject storage data into the database. Changed keys are recorded in the
+	/// per-block changes index.
 	fn set_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, changes: I) -> error::Result<()>;
+	/// Inject changes to the child storage trie placed under `storage_key` into the database.
+	fn set_child_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, storage_key: Vec<u8>, changes: I) -> error::Result<()>;
 	/// Inject storage data into the database.
 	fn reset_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, iter: I) -> error::Result<()>;
 }
@@ -43,14 +46,25 @@ pub trait Backend {
 	/// Associated blockchain backend type.
 	type Blockchain: ::blockchain::Backend;
 	/// Associated state backend type.
-	type State: state_machine::backend::Backend;
+	type State: state_machine::backend::Backend + state_machine::backend::TryIntoTrieBackend;
 
 	/// Begin a new block insertion transaction with given parent block id.
 	fn begin_operation(&self, block: BlockId) -> error::Result<Self::BlockImportOperation>;
 	/// Commit block insertion.
 	fn commit_operation(&self, transaction: Self::BlockImportOperation) -> error::Result<()>;
+	/// Mark a block of the best chain and all its ancestors as final. The state of
+	/// finalized blocks may be pruned and they can no longer be reverted.
+	fn finalize_block(&self, block: BlockId) -> error::Result<()>;
 	/// Returns reference to blockchain backend.
 	fn blockchain(&self) -> &Self::Blockchain;
 	/// Returns state backend for specified block.
 	fn state_at(&self, block: BlockId) -> error::Result<Self::State>;
+	/// Returns true if the backend keeps headers only and reads the state from remote
+	/// nodes. Blocks are imported into such backends without being executed.
+	fn is_light(&self) -> bool;
+	/// Attempts to revert the best chain by `n` blocks, removing their data and
+	/// making the parent of the last reverted block the best one. Returns the
+	/// number of blocks actually reverted, which may be fewer if an earlier block
+	/// is finalized.
+	fn revert(&self, n: block::Number) -> error::Result<block::Number>;
 }
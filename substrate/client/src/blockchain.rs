@@ -18,7 +18,7 @@
 
 use primitives::block::{self, Id as BlockId};
 use primitives;
-use error::Result;
+use error::{ErrorKind, Result};
 
 
 /// Blockchain database backend. Does not perform any validation.
@@ -35,6 +35,15 @@ pub trait Backend: Send + Sync {
 	fn status(&self, id: BlockId) -> Result<BlockStatus>;
 	/// Get block hash by number. Returns `None` if the header is not in the chain.
 	fn hash(&self, number: block::Number) -> Result<Option<block::HeaderHash>>;
+	/// Get the keys of the storage entries changed by the block, in lexicographic order.
+	/// Changes to child storage tries are recorded under the key of the child trie root.
+	/// Returns `None` if the block is not found.
+	fn changed_keys(&self, id: BlockId) -> Result<Option<Vec<Vec<u8>>>>;
+	/// Get the hashes of all blocks without children, in no particular order.
+	fn leaves(&self) -> Result<Vec<block::HeaderHash>>;
+	/// Get the root of the CHT with given number. Returns `None` if the CHT is not
+	/// complete on the finalized chain.
+	fn cht_root(&self, cht_num: block::Number) -> Result<Option<primitives::H256>>;
 }
 
 /// Block import outcome
@@ -58,6 +67,10 @@ pub struct Info {
 	pub best_number: block::Number,
 	/// Genesis block hash.
 	pub genesis_hash: block::HeaderHash,
+	/// Last finalized block hash.
+	pub finalized_hash: block::HeaderHash,
+	/// Last finalized block number.
+	pub finalized_number: block::Number,
 }
 
 /// Block status.
@@ -68,3 +81,106 @@ pub enum BlockStatus {
 	/// Not in the queue or the blockchain.
 	Unknown,
 }
+
+/// A block in a tree route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteEntry {
+	/// The number of the block.
+	pub number: block::Number,
+	/// The hash of the block.
+	pub hash: block::HeaderHash,
+}
+
+/// A tree-route from one block to another in the chain.
+///
+/// All blocks prior to the pivot in the route are the retracted blocks, the
+/// block at the pivot is the common ancestor and all blocks after it are the
+/// enacted blocks.
+///
+/// ```text
+/// G -> A1 -> A2 -> A3
+///        \
+///         -> B2 -> B3
+/// ```
+///
+/// The route from A3 to B3 is [A3, A2, A1, B2, B3] with pivot at A1: A3 and A2
+/// are retracted, B2 and B3 are enacted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRoute {
+	route: Vec<RouteEntry>,
+	pivot: usize,
+}
+
+impl TreeRoute {
+	/// Get the blocks which are retracted, starting with `from`.
+	pub fn retracted(&self) -> &[RouteEntry] {
+		&self.route[..self.pivot]
+	}
+
+	/// Get the common ancestor block.
+	pub fn common_block(&self) -> &RouteEntry {
+		&self.route[self.pivot]
+	}
+
+	/// Get the blocks which are enacted, ending with `to`.
+	pub fn enacted(&self) -> &[RouteEntry] {
+		&self.route[self.pivot + 1..]
+	}
+
+	/// Extend the route with a child of `to`, which becomes the new last enacted block.
+	pub fn push_enacted(&mut self, entry: RouteEntry) {
+		self.route.push(entry);
+	}
+}
+
+/// Compute a tree-route between two blocks, loading headers with the given function.
+pub fn tree_route<F>(load_header: F, from: BlockId, to: BlockId) -> Result<TreeRoute>
+	where F: Fn(BlockId) -> Result<Option<block::Header>>
+{
+	let load = |id: BlockId| -> Result<(block::HeaderHash, block::Header)> {
+		match load_header(id)? {
+			Some(header) => Ok((header.hash(), header)),
+			None => Err(ErrorKind::UnknownBlock(id).into()),
+		}
+	};
+	let entry = |&(hash, ref header): &(block::HeaderHash, block::Header)| RouteEntry {
+		number: header.number,
+		hash,
+	};
+
+	let mut from = load(from)?;
+	let mut to = load(to)?;
+
+	let mut from_branch = Vec::new();
+	let mut to_branch = Vec::new();
+
+	while to.1.number > from.1.number {
+		to_branch.push(entry(&to));
+		to = load(BlockId::Hash(to.1.parent_hash))?;
+	}
+
+	while from.1.number > to.1.number {
+		from_branch.push(entry(&from));
+		from = load(BlockId::Hash(from.1.parent_hash))?;
+	}
+
+	// numbers are equal now. walk backwards until the block is the same.
+	while from.0 != to.0 {
+		to_branch.push(entry(&to));
+		to = load(BlockId::Hash(to.1.parent_hash))?;
+
+		from_branch.push(entry(&from));
+		from = load(BlockId::Hash(from.1.parent_hash))?;
+	}
+
+	// add the pivot block and append the reversed to-branch
+	// (note that it's reverse order originally).
+	let pivot = from_branch.len();
+	from_branch.push(entry(&to));
+	from_branch.extend(to_branch.into_iter().rev());
+
+	Ok(TreeRoute {
+		route: from_branch,
+		pivot,
+	})
+}
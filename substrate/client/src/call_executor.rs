@@ -0,0 +1,102 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Execution of runtime calls on behalf of the client.
+
+use state_machine::{self, OverlayedChanges, Backend as StateBackend, CodeExecutor};
+use state_machine::backend::TryIntoTrieBackend;
+use primitives::block::Id as BlockId;
+
+use backend;
+use error;
+
+/// Information regarding the result of a call.
+pub struct CallResult {
+	/// The data that was returned from the call.
+	pub return_data: Vec<u8>,
+	/// The changes made to the state by the call.
+	pub changes: OverlayedChanges,
+}
+
+/// Method call executor. Full clients execute calls against their local state,
+/// light clients delegate them to full nodes.
+pub trait CallExecutor {
+	/// Execute a call to a contract on top of state in a block of given id.
+	///
+	/// No changes are made.
+	fn call<B: backend::Backend>(
+		&self,
+		backend: &B,
+		id: &BlockId,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<CallResult>;
+
+	/// Execute a call on top of the given state, gathering the changes in the overlay.
+	fn call_at_state<S: StateBackend>(
+		&self,
+		state: &S,
+		overlay: &mut OverlayedChanges,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<Vec<u8>>;
+
+	/// Execute a call on top of the given state, returning the result along with
+	/// a proof of execution.
+	fn prove_at_state<S: StateBackend + TryIntoTrieBackend>(
+		&self,
+		state: S,
+		overlay: &mut OverlayedChanges,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<(Vec<u8>, Vec<Vec<u8>>)>;
+}
+
+impl<E: CodeExecutor> CallExecutor for E {
+	fn call<B: backend::Backend>(
+		&self,
+		backend: &B,
+		id: &BlockId,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<CallResult> {
+		let mut changes = OverlayedChanges::default();
+		let return_data = self.call_at_state(&backend.state_at(*id)?, &mut changes, method, call_data)?;
+		Ok(CallResult { return_data, changes })
+	}
+
+	fn call_at_state<S: StateBackend>(
+		&self,
+		state: &S,
+		overlay: &mut OverlayedChanges,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<Vec<u8>> {
+		state_machine::execute(state, overlay, self, method, call_data).map_err(Into::into)
+	}
+
+	fn prove_at_state<S: StateBackend + TryIntoTrieBackend>(
+		&self,
+		state: S,
+		overlay: &mut OverlayedChanges,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<(Vec<u8>, Vec<Vec<u8>>)> {
+		let state = state.try_into_trie_backend()
+			.ok_or_else(|| error::ErrorKind::Backend("State backend doesn't support proofs".into()))?;
+		state_machine::prove_execution(state, overlay, self, method, call_data).map_err(Into::into)
+	}
+}
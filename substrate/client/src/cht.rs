@@ -0,0 +1,151 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Canonical hash trie definitions and helper functions.
+//!
+//! A CHT is a trie mapping the numbers of a range of `SIZE` blocks of the best
+//! chain to their hashes. Given the root of a CHT, the hash of any block in its
+//! range can be proven without the headers of the other blocks.
+
+use std::ops::Range;
+
+use codec::Slicable;
+use primitives::H256;
+use primitives::block::{self, HeaderHash};
+use state_machine::{self, TrieBackend};
+
+use error;
+
+/// The number of blocks in each CHT.
+pub const SIZE: block::Number = 2048;
+
+/// Get the number of the CHT which includes the given block. The genesis block
+/// is not included in any CHT.
+pub fn block_to_cht_number(block: block::Number) -> Option<block::Number> {
+	match block {
+		0 => None,
+		n => Some((n - 1) / SIZE),
+	}
+}
+
+/// Get the number of the first block included in the given CHT.
+pub fn start_number(cht_num: block::Number) -> block::Number {
+	cht_num * SIZE + 1
+}
+
+/// Get the number of the last block included in the given CHT.
+pub fn end_number(cht_num: block::Number) -> block::Number {
+	(cht_num + 1) * SIZE
+}
+
+/// Get the numbers of the CHTs completed when the last finalized block moves from
+/// `from` to `to`, i.e. of the CHTs whose last block is in the `(from, to]` range.
+pub fn completed_between(from: block::Number, to: block::Number) -> Range<block::Number> {
+	from / SIZE..to / SIZE
+}
+
+/// Compute the root of the given CHT from the hashes of all of its blocks, in
+/// ascending order. Returns `None` if not all hashes are given.
+pub fn compute_root<I>(cht_num: block::Number, hashes: I) -> Option<H256>
+	where I: IntoIterator<Item=HeaderHash>
+{
+	build_trie(cht_num, hashes).map(|trie| trie.root().0.into())
+}
+
+/// Build a proof of the hash of the given block against the root of the given
+/// CHT, from the hashes of all of its blocks in ascending order. Returns `None`
+/// if not all hashes are given or the block is not included in the CHT.
+pub fn build_proof<I>(cht_num: block::Number, block: block::Number, hashes: I) -> Option<Vec<Vec<u8>>>
+	where I: IntoIterator<Item=HeaderHash>
+{
+	if block_to_cht_number(block) != Some(cht_num) {
+		return None;
+	}
+
+	let trie = build_trie(cht_num, hashes)?;
+	state_machine::prove_read(trie, &[encode_key(block)]).ok().map(|(_, proof)| proof)
+}
+
+/// Check a proof of the hash of the given block against the CHT root.
+pub fn check_proof(cht_root: H256, block: block::Number, hash: HeaderHash, proof: Vec<Vec<u8>>) -> error::Result<()> {
+	let values = state_machine::read_proof_check(cht_root.0, proof, &[encode_key(block)])?;
+	match values.into_iter().next() {
+		Some(Some(ref value)) if *value == hash.encode() => Ok(()),
+		_ => Err(error::ErrorKind::InvalidHeaderProof(block).into()),
+	}
+}
+
+fn build_trie<I>(cht_num: block::Number, hashes: I) -> Option<TrieBackend>
+	where I: IntoIterator<Item=HeaderHash>
+{
+	let pairs: Vec<_> = (start_number(cht_num)..end_number(cht_num) + 1)
+		.zip(hashes)
+		.map(|(number, hash)| (encode_key(number), hash.encode()))
+		.collect();
+	if pairs.len() as block::Number != SIZE {
+		return None;
+	}
+
+	Some(TrieBackend::from_pairs(pairs))
+}
+
+fn encode_key(block: block::Number) -> Vec<u8> {
+	block.encode()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hashes(cht_num: block::Number) -> Vec<HeaderHash> {
+		(start_number(cht_num)..end_number(cht_num) + 1).map(|n| H256::from(n)).collect()
+	}
+
+	#[test]
+	fn blocks_are_mapped_to_cht_numbers() {
+		assert_eq!(block_to_cht_number(0), None);
+		assert_eq!(block_to_cht_number(1), Some(0));
+		assert_eq!(block_to_cht_number(SIZE), Some(0));
+		assert_eq!(block_to_cht_number(SIZE + 1), Some(1));
+		assert_eq!(start_number(1), SIZE + 1);
+		assert_eq!(end_number(1), SIZE * 2);
+	}
+
+	#[test]
+	fn completed_chts_are_found() {
+		assert_eq!(completed_between(0, SIZE - 1), 0..0);
+		assert_eq!(completed_between(0, SIZE), 0..1);
+		assert_eq!(completed_between(SIZE, SIZE * 3), 1..3);
+		assert_eq!(completed_between(SIZE - 1, SIZE + 1), 0..1);
+	}
+
+	#[test]
+	fn root_requires_complete_range() {
+		assert!(compute_root(0, hashes(0)).is_some());
+		assert!(compute_root(0, hashes(0).into_iter().skip(1)).is_none());
+	}
+
+	#[test]
+	fn proof_is_built_and_checked() {
+		let root = compute_root(1, hashes(1)).unwrap();
+		let block = SIZE + 42;
+		let proof = build_proof(1, block, hashes(1)).unwrap();
+		assert!(check_proof(root, block, H256::from(block), proof.clone()).is_ok());
+		assert!(check_proof(root, block, H256::from(block + 1), proof.clone()).is_err());
+		assert!(check_proof(root, block + 1, H256::from(block + 1), proof).is_err());
+		assert!(build_proof(0, block, hashes(1)).is_none());
+	}
+}
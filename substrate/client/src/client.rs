@@ -16,22 +16,63 @@
 
 //! Substrate Client
 
+use futures::sync::mpsc;
+use parking_lot::Mutex;
 use primitives::{self, block, AuthorityId};
 use primitives::block::Id as BlockId;
 use primitives::storage::{StorageKey, StorageData};
 use runtime_support::Hashable;
 use codec::{KeyedVec, Slicable};
 use state_machine::{self, Ext, OverlayedChanges, Backend as StateBackend, CodeExecutor};
+use state_machine::backend::TryIntoTrieBackend;
 
 use backend::{self, BlockImportOperation};
+use call_executor::{CallExecutor, CallResult};
 use blockchain::{self, Info as ChainInfo, Backend as ChainBackend};
-use {error, in_mem, block_builder, runtime_io, bft};
+use {error, in_mem, block_builder, cht, runtime_io, bft};
+
+/// Maximum number of blocks between the first and the last block of a `key_changes` range.
+pub const MAX_KEY_CHANGES_RANGE: block::Number = 4096;
 
 /// Polkadot Client
 #[derive(Debug)]
 pub struct Client<B, E> where B: backend::Backend {
 	backend: B,
 	executor: E,
+	import_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<BlockImportNotification>>>,
+	best_block_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<BlockImportNotification>>>,
+	finality_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<FinalityNotification>>>,
+}
+
+/// A stream of block import events.
+pub type ImportNotifications = mpsc::UnboundedReceiver<BlockImportNotification>;
+
+/// A stream of block finality events.
+pub type FinalityNotifications = mpsc::UnboundedReceiver<FinalityNotification>;
+
+/// Summary of an imported block.
+#[derive(Clone, Debug)]
+pub struct BlockImportNotification {
+	/// Imported block header hash.
+	pub hash: block::HeaderHash,
+	/// Imported block header.
+	pub header: block::Header,
+	/// Is this the new best block.
+	pub is_new_best: bool,
+	/// Route from the previous best block to the imported one. Only set for
+	/// the new best block.
+	pub tree_route: Option<blockchain::TreeRoute>,
+}
+
+/// Summary of a block which has been finalized by a justification.
+#[derive(Clone, Debug)]
+pub struct FinalityNotification {
+	/// Finalized block header hash.
+	pub hash: block::HeaderHash,
+	/// Finalized block header.
+	pub header: block::Header,
+	/// The justification finalizing the block.
+	pub justification: primitives::bft::Justification,
 }
 
 /// Client info
@@ -46,21 +87,11 @@ pub struct ClientInfo {
 	pub best_queued_hash: Option<block::HeaderHash>,
 }
 
-/// Information regarding the result of a call.
-pub struct CallResult {
-	/// The data that was returned from the call.
-	pub return_data: Vec<u8>,
-	/// The changes made to the state by the call.
-	pub changes: OverlayedChanges,
-}
-
 /// Block import result.
 #[derive(Debug)]
 pub enum ImportResult {
-	/// Added to the import queue.
-	Queued,
-	/// Already in the import queue.
-	AlreadyQueued,
+	/// Imported into the blockchain.
+	Imported,
 	/// Already in the blockchain.
 	AlreadyInChain,
 	/// Block or parent is known to be bad.
@@ -108,9 +139,19 @@ pub fn new_in_mem<E, F>(
 	Client::new(in_mem::Backend::new(), executor, build_genesis)
 }
 
+/// Check a storage read proof generated by `Client::read_proof` against the given
+/// state root, returning the proven values of the keys.
+pub fn check_read_proof(
+	state_root: primitives::H256,
+	proof: Vec<Vec<u8>>,
+	keys: &[Vec<u8>],
+) -> error::Result<Vec<Option<Vec<u8>>>> {
+	state_machine::read_proof_check(state_root.0, proof, keys).map_err(Into::into)
+}
+
 impl<B, E> Client<B, E> where
 	B: backend::Backend,
-	E: CodeExecutor,
+	E: CallExecutor,
 	error::Error: From<<<B as backend::Backend>::State as StateBackend>::Error>,
 {
 	/// Creates new Polkadot Client with given blockchain and code executor.
@@ -133,6 +174,9 @@ impl<B, E> Client<B, E> where
 		Ok(Client {
 			backend,
 			executor,
+			import_notification_sinks: Mutex::new(Vec::new()),
+			best_block_notification_sinks: Mutex::new(Vec::new()),
+			finality_notification_sinks: Mutex::new(Vec::new()),
 		})
 	}
 
@@ -150,8 +194,43 @@ impl<B, E> Client<B, E> where
 	pub fn storage(&self, id: &BlockId, key: &StorageKey) -> error::Result<StorageData> {
 		Ok(StorageData(self.state_at(id)?
 			.storage(&key.0)?
-			.ok_or_else(|| error::ErrorKind::NoValueForKey(key.0.clone()))?
-			.to_vec()))
+			.ok_or_else(|| error::ErrorKind::NoValueForKey(key.0.clone()))?))
+	}
+
+	/// Generate a storage read proof for the given keys in state at a block of given id.
+	pub fn read_proof(&self, id: &BlockId, keys: &[Vec<u8>]) -> error::Result<Vec<Vec<u8>>> {
+		let state = self.state_at(id)?
+			.try_into_trie_backend()
+			.ok_or_else(|| error::ErrorKind::Backend("State backend doesn't support proofs".into()))?;
+		state_machine::prove_read(state, keys)
+			.map(|(_, proof)| proof)
+			.map_err(Into::into)
+	}
+
+	/// Execute a call on top of state in a block of given id, generating a proof
+	/// of execution which can be checked by light clients.
+	pub fn execution_proof(&self, id: &BlockId, method: &str, call_data: &[u8]) -> error::Result<(Vec<u8>, Vec<Vec<u8>>)> {
+		self.executor.prove_at_state(self.state_at(id)?, &mut Default::default(), method, call_data)
+	}
+
+	/// Get the header of the block with given number, along with a proof of its
+	/// hash against the root of the CHT which includes it. Only blocks of CHTs
+	/// which are complete on the finalized chain can be proven.
+	pub fn header_proof(&self, number: block::Number) -> error::Result<(block::Header, Vec<Vec<u8>>)> {
+		let cht_num = cht::block_to_cht_number(number)
+			.ok_or_else(|| error::ErrorKind::HeaderProofUnavailable(number))?;
+		if self.backend.blockchain().cht_root(cht_num)?.is_none() {
+			return Err(error::ErrorKind::HeaderProofUnavailable(number).into());
+		}
+
+		let id = BlockId::Number(number);
+		let header = self.header(&id)?.ok_or_else(|| error::ErrorKind::UnknownBlock(id))?;
+		let hashes = (cht::start_number(cht_num)..cht::end_number(cht_num) + 1)
+			.map(|n| self.block_hash(n)?.ok_or_else(|| error::ErrorKind::UnknownBlock(BlockId::Number(n)).into()))
+			.collect::<error::Result<Vec<_>>>()?;
+		let proof = cht::build_proof(cht_num, number, hashes)
+			.ok_or_else(|| error::ErrorKind::HeaderProofUnavailable(number))?;
+		Ok((header, proof))
 	}
 
 	/// Get the code at a given block.
@@ -167,11 +246,11 @@ impl<B, E> Client<B, E> where
 	/// Get the current set of authorities from storage.
 	pub fn authorities_at(&self, id: &BlockId) -> error::Result<Vec<AuthorityId>> {
 		let state = self.state_at(id)?;
-		(0..u32::decode(&mut state.storage(b":auth:len")?.ok_or(error::ErrorKind::AuthLenEmpty)?).ok_or(error::ErrorKind::AuthLenInvalid)?)
+		(0..u32::decode(&mut &state.storage(b":auth:len")?.ok_or(error::ErrorKind::AuthLenEmpty)?[..]).ok_or(error::ErrorKind::AuthLenInvalid)?)
 			.map(|i| state.storage(&i.to_keyed_vec(b":auth:"))
-				.map_err(|_| error::ErrorKind::Backend)
+				.map_err(|_| error::ErrorKind::Backend("Error reading authorities".into()))
 				.and_then(|v| v.ok_or(error::ErrorKind::AuthEmpty(i)))
-				.and_then(|mut s| AuthorityId::decode(&mut s).ok_or(error::ErrorKind::AuthInvalid(i)))
+				.and_then(|s| AuthorityId::decode(&mut &s[..]).ok_or(error::ErrorKind::AuthInvalid(i)))
 				.map_err(Into::into)
 			).collect()
 	}
@@ -180,15 +259,7 @@ impl<B, E> Client<B, E> where
 	///
 	/// No changes are made.
 	pub fn call(&self, id: &BlockId, method: &str, call_data: &[u8]) -> error::Result<CallResult> {
-		let mut changes = OverlayedChanges::default();
-		let return_data = state_machine::execute(
-			&self.state_at(id)?,
-			&mut changes,
-			&self.executor,
-			method,
-			call_data,
-		)?;
-		Ok(CallResult { return_data, changes })
+		self.executor.call(&self.backend, id, method, call_data)
 	}
 
 	/// Set up the native execution environment to call into a native runtime code.
@@ -209,12 +280,12 @@ impl<B, E> Client<B, E> where
 	}
 
 	/// Create a new block, built on the head of the chain.
-	pub fn new_block(&self) -> error::Result<block_builder::BlockBuilder<B, E>> where E: Clone {
+	pub fn new_block(&self) -> error::Result<block_builder::BlockBuilder<B, E>> where E: CodeExecutor + Clone {
 		block_builder::BlockBuilder::new(self)
 	}
 
 	/// Create a new block, built on top of `parent`.
-	pub fn new_block_at(&self, parent: &BlockId) -> error::Result<block_builder::BlockBuilder<B, E>> where E: Clone {
+	pub fn new_block_at(&self, parent: &BlockId) -> error::Result<block_builder::BlockBuilder<B, E>> where E: CodeExecutor + Clone {
 		block_builder::BlockBuilder::at_block(parent, &self)
 	}
 
@@ -233,7 +304,7 @@ impl<B, E> Client<B, E> where
 		})
 	}
 
-	/// Queue a block for import.
+	/// Import a block. The block is executed and committed before this returns.
 	pub fn import_block(
 		&self,
 		header: JustifiedHeader,
@@ -243,6 +314,10 @@ impl<B, E> Client<B, E> where
 		// TODO: validate block
 		// TODO: import justification.
 		let (header, justification) = header.into_inner();
+		let hash = block::HeaderHash::from(header.blake2_256());
+		if let blockchain::BlockStatus::InChain = self.backend.blockchain().status(BlockId::Hash(hash))? {
+			return Ok(ImportResult::AlreadyInChain);
+		}
 		match self.backend.blockchain().status(BlockId::Hash(header.parent_hash))? {
 			blockchain::BlockStatus::InChain => (),
 			blockchain::BlockStatus::Unknown => return Ok(ImportResult::UnknownParent),
@@ -251,20 +326,113 @@ impl<B, E> Client<B, E> where
 		let mut transaction = self.backend.begin_operation(BlockId::Hash(header.parent_hash))?;
 		let mut overlay = OverlayedChanges::default();
 
-		state_machine::execute(
-			transaction.state()?,
-			&mut overlay,
-			&self.executor,
-			"execute_block",
-			&block::Block { header: header.clone(), transactions: body.clone().unwrap_or_default().clone() }.encode()
-		)?;
-
-		let is_new_best = header.number == self.backend.blockchain().info()?.best_number + 1;
-		trace!("Imported {}, (#{}), best={}", block::HeaderHash::from(header.blake2_256()), header.number, is_new_best);
-		transaction.set_block_data(header, body, Some(justification.uncheck().into()), is_new_best)?;
+		// light clients import headers only and have no state to execute the block on.
+		if !self.backend.is_light() {
+			let transactions = match body {
+				Some(ref transactions) => transactions.clone(),
+				None => return Err(error::ErrorKind::MissingBody(BlockId::Hash(header.hash())).into()),
+			};
+			self.executor.call_at_state(
+				transaction.state()?,
+				&mut overlay,
+				"execute_block",
+				&block::Block { header: header.clone(), transactions }.encode()
+			)?;
+		}
+
+		// the longest chain is the best one, unless it doesn't include the last finalized block.
+		// the route is computed before the commit, so the import can't fail after it.
+		let last_best = self.backend.blockchain().info()?;
+		let tree_route = match header.number > last_best.best_number {
+			true => {
+				let mut route = blockchain::tree_route(
+					|id| self.backend.blockchain().header(id),
+					BlockId::Hash(last_best.best_hash),
+					BlockId::Hash(header.parent_hash),
+				)?;
+				match route.common_block().number >= last_best.finalized_number {
+					true => {
+						route.push_enacted(blockchain::RouteEntry { number: header.number, hash });
+						Some(route)
+					},
+					false => None,
+				}
+			},
+			false => None,
+		};
+		let is_new_best = tree_route.is_some();
+		let justification: primitives::bft::Justification = justification.uncheck().into();
+		trace!("Imported {}, (#{}), best={}", hash, header.number, is_new_best);
+		transaction.set_block_data(header.clone(), body, Some(justification), is_new_best)?;
 		transaction.set_storage(overlay.drain())?;
+		for (storage_key, changes) in overlay.drain_children() {
+			transaction.set_child_storage(storage_key, changes.into_iter())?;
+		}
 		self.backend.commit_operation(transaction)?;
-		Ok(ImportResult::Queued)
+
+		let notification = BlockImportNotification {
+			hash,
+			header: header.clone(),
+			is_new_best,
+			tree_route,
+		};
+		if is_new_best {
+			Self::notify(&self.best_block_notification_sinks, &notification);
+		}
+		Self::notify(&self.import_notification_sinks, &notification);
+
+		Ok(ImportResult::Imported)
+	}
+
+	/// Mark a block of the best chain and all its ancestors as final. Their state
+	/// may be pruned and they can no longer be reverted. A finality notification is
+	/// sent for the block, unless it is already final.
+	pub fn finalize_block(&self, id: BlockId) -> error::Result<()> {
+		let header = self.header(&id)?.ok_or_else(|| error::ErrorKind::UnknownBlock(id))?;
+		if header.number <= self.backend.blockchain().info()?.finalized_number {
+			return Ok(());
+		}
+		let justification = self.justification(&id)?.ok_or_else(|| error::ErrorKind::BadJustification(id))?;
+
+		self.backend.finalize_block(id)?;
+		Self::notify(&self.finality_notification_sinks, &FinalityNotification {
+			hash: header.hash(),
+			header,
+			justification,
+		});
+		Ok(())
+	}
+
+	/// Attempts to revert the chain by `n` blocks. Returns the number of blocks that
+	/// were actually reverted.
+	pub fn revert(&self, n: block::Number) -> error::Result<block::Number> {
+		self.backend.revert(n)
+	}
+
+	/// Get a stream of all imported blocks.
+	pub fn import_notification_stream(&self) -> ImportNotifications {
+		let (sink, stream) = mpsc::unbounded();
+		self.import_notification_sinks.lock().push(sink);
+		stream
+	}
+
+	/// Get a stream of imported blocks which became the new best block.
+	pub fn best_block_notification_stream(&self) -> ImportNotifications {
+		let (sink, stream) = mpsc::unbounded();
+		self.best_block_notification_sinks.lock().push(sink);
+		stream
+	}
+
+	/// Get a stream of finalized blocks.
+	pub fn finality_notification_stream(&self) -> FinalityNotifications {
+		let (sink, stream) = mpsc::unbounded();
+		self.finality_notification_sinks.lock().push(sink);
+		stream
+	}
+
+	// send a notification to all subscribers, dropping the ones which went away.
+	fn notify<T: Clone>(sinks: &Mutex<Vec<mpsc::UnboundedSender<T>>>, notification: &T) {
+		sinks.lock().retain(|sink| sink.unbounded_send(notification.clone()).is_ok());
 	}
 
 	/// Get blockchain info.
@@ -321,28 +489,86 @@ impl<B, E> Client<B, E> where
 	pub fn justification(&self, id: &BlockId) -> error::Result<Option<primitives::bft::Justification>> {
 		self.backend.blockchain().justification(*id)
 	}
+
+	/// Get the blocks within the range `[first, last]` which changed any of the storage
+	/// entries under `keys`, as (number, hash, indices of the changed keys) in ascending
+	/// order. The range is taken along the chain ending at `last`; `first` must be an
+	/// ancestor of `last`, at most `MAX_KEY_CHANGES_RANGE` blocks back. Changes within
+	/// a child storage trie are recorded as changes of the key the trie is placed under.
+	pub fn key_changes(&self, first: &BlockId, last: &BlockId, keys: &[StorageKey]) -> error::Result<Vec<(block::Number, block::HeaderHash, Vec<usize>)>> {
+		let blockchain = self.backend.blockchain();
+		let first_header = blockchain.header(*first)?.ok_or_else(|| error::ErrorKind::UnknownBlock(*first))?;
+		let first_hash = block::HeaderHash::from(first_header.blake2_256());
+
+		let mut changes = Vec::new();
+		let mut current = blockchain.header(*last)?.ok_or_else(|| error::ErrorKind::UnknownBlock(*last))?;
+		let mut current_hash = block::HeaderHash::from(current.blake2_256());
+		if current.number < first_header.number || current.number - first_header.number > MAX_KEY_CHANGES_RANGE {
+			return Err(error::ErrorKind::InvalidBlockRange(*first, *last).into());
+		}
+		loop {
+			if current.number < first_header.number {
+				return Err(error::ErrorKind::InvalidBlockRange(*first, *last).into());
+			}
+
+			if let Some(changed_keys) = blockchain.changed_keys(BlockId::Hash(current_hash))? {
+				let changed: Vec<_> = keys.iter()
+					.enumerate()
+					.filter(|&(_, key)| changed_keys.binary_search(&key.0).is_ok())
+					.map(|(index, _)| index)
+					.collect();
+				if !changed.is_empty() {
+					changes.push((current.number, current_hash, changed));
+				}
+			}
+
+			if current.number == first_header.number {
+				if current_hash != first_hash {
+					return Err(error::ErrorKind::InvalidBlockRange(*first, *last).into());
+				}
+				break;
+			}
+
+			current_hash = current.parent_hash;
+			current = blockchain.header(BlockId::Hash(current_hash))?
+				.ok_or_else(|| error::ErrorKind::UnknownBlock(BlockId::Hash(current_hash)))?;
+		}
+
+		changes.reverse();
+		Ok(changes)
+	}
 }
 
 impl<B, E> bft::BlockImport for Client<B, E>
 	where
 		B: backend::Backend,
-		E: state_machine::CodeExecutor,
+		E: CallExecutor,
 		error::Error: From<<B::State as state_machine::backend::Backend>::Error>
 {
 	fn import_block(&self, block: block::Block, justification: bft::Justification) {
+		let hash = block.header.hash();
 		let justified_header = JustifiedHeader {
 			header: block.header,
 			justification,
 		};
 
-		let _ = self.import_block(justified_header, Some(block.transactions));
+		// blocks agreed upon by the consensus are final.
+		match self.import_block(justified_header, Some(block.transactions)) {
+			Ok(ImportResult::Imported) | Ok(ImportResult::AlreadyInChain) => {
+				if let Err(e) = self.finalize_block(BlockId::Hash(hash)) {
+					warn!("Error finalizing block {}: {}", hash, e);
+				}
+			},
+			Ok(result) => warn!("Block {} agreed upon was not imported: {:?}", hash, result),
+			Err(e) => warn!("Error importing block {}: {}", hash, e),
+		}
 	}
 }
 
 impl<B, E> bft::Authorities for Client<B, E>
 	where
 		B: backend::Backend,
-		E: state_machine::CodeExecutor,
+		E: CallExecutor,
 		error::Error: From<<B::State as state_machine::backend::Backend>::Error>
 {
 	fn authorities(&self, at: &BlockId) -> Result<Vec<AuthorityId>, bft::Error> {
@@ -461,6 +687,37 @@ mod tests {
 		assert_eq!(client.using_environment(|| test_runtime::system::latest_block_hash()).unwrap(), client.block_hash(1).unwrap().unwrap());
 	}
 
+	#[test]
+	fn block_without_body_is_rejected() {
+		let client = new_in_mem(Executor::new(), prepare_genesis).unwrap();
+		let block = client.new_block().unwrap().bake().unwrap();
+
+		let justification = justify(&block.header);
+		let justified = client.check_justification(block.header, justification).unwrap();
+		assert!(client.import_block(justified, None).is_err());
+		assert_eq!(client.info().unwrap().chain.best_number, 0);
+	}
+
+	#[test]
+	fn block_imported_twice_is_already_in_chain() {
+		let client = new_in_mem(Executor::new(), prepare_genesis).unwrap();
+		let block = client.new_block().unwrap().bake().unwrap();
+
+		let justification = justify(&block.header);
+		let justified = client.check_justification(block.header.clone(), justification.clone()).unwrap();
+		match client.import_block(justified, Some(block.transactions.clone())).unwrap() {
+			ImportResult::Imported => (),
+			result => panic!("Unexpected import result {:?}", result),
+		}
+
+		let justified = client.check_justification(block.header, justification).unwrap();
+		match client.import_block(justified, Some(block.transactions)).unwrap() {
+			ImportResult::AlreadyInChain => (),
+			result => panic!("Unexpected import result {:?}", result),
+		}
+		assert_eq!(client.info().unwrap().chain.best_number, 1);
+	}
+
 	trait Signable {
 		fn signed(self) -> PrimitiveTransaction;
 	}
@@ -506,4 +763,124 @@ mod tests {
 		assert_eq!(client.using_environment(|| test_runtime::system::balance_of(Keyring::Alice.to_raw_public())).unwrap(), 958);
 		assert_eq!(client.using_environment(|| test_runtime::system::balance_of(Keyring::Ferdie.to_raw_public())).unwrap(), 42);
 	}
+
+	#[test]
+	fn import_notifications_are_sent() {
+		use futures::{Future, Stream};
+
+		let client = new_in_mem(Executor::new(), prepare_genesis).unwrap();
+		let genesis_hash = client.block_hash(0).unwrap().unwrap();
+		let imported = client.import_notification_stream();
+		let best = client.best_block_notification_stream();
+		let finalized = client.finality_notification_stream();
+
+		let block = client.new_block().unwrap().bake().unwrap();
+		let hash = block.header.hash();
+		let justification = justify(&block.header);
+		let justified = client.check_justification(block.header, justification).unwrap();
+		client.import_block(justified, Some(block.transactions)).unwrap();
+
+		let notification = imported.into_future().wait().map_err(|_| ()).unwrap().0.unwrap();
+		assert_eq!(notification.hash, hash);
+		assert!(notification.is_new_best);
+		let tree_route = notification.tree_route.unwrap();
+		assert!(tree_route.retracted().is_empty());
+		assert_eq!(tree_route.common_block().hash, genesis_hash);
+		assert_eq!(tree_route.enacted().iter().map(|e| e.hash).collect::<Vec<_>>(), vec![hash]);
+
+		assert_eq!(best.into_future().wait().map_err(|_| ()).unwrap().0.unwrap().hash, hash);
+
+		client.finalize_block(BlockId::Hash(hash)).unwrap();
+		client.finalize_block(BlockId::Number(0)).unwrap();
+		let finality = finalized.into_future().wait().map_err(|_| ()).unwrap().0.unwrap();
+		assert_eq!(finality.hash, hash);
+		assert_eq!(finality.justification.hash, hash);
+		assert_eq!(client.backend().blockchain().info().unwrap().finalized_hash, hash);
+	}
+
+	#[test]
+	fn longest_fork_becomes_best() {
+		use futures::{Future, Stream};
+
+		let client = new_in_mem(Executor::new(), prepare_genesis).unwrap();
+		let genesis_hash = client.block_hash(0).unwrap().unwrap();
+		let import = |block: block::Block| {
+			let hash = block.header.hash();
+			let justification = justify(&block.header);
+			let justified = client.check_justification(block.header, justification).unwrap();
+			client.import_block(justified, Some(block.transactions)).unwrap();
+			hash
+		};
+
+		let a1 = import(client.new_block().unwrap().bake().unwrap());
+		let mut builder = client.new_block_at(&BlockId::Hash(genesis_hash)).unwrap();
+		builder.push(Transaction {
+			from: Keyring::Alice.to_raw_public(),
+			to: Keyring::Ferdie.to_raw_public(),
+			amount: 42,
+			nonce: 0
+		}.signed()).unwrap();
+		let b1 = import(builder.bake().unwrap());
+		assert_eq!(client.info().unwrap().chain.best_hash, a1);
+
+		let best = client.best_block_notification_stream();
+		let b2 = import(client.new_block_at(&BlockId::Hash(b1)).unwrap().bake().unwrap());
+		assert_eq!(client.info().unwrap().chain.best_hash, b2);
+		assert_eq!(client.block_hash(1).unwrap(), Some(b1));
+		let tree_route = best.into_future().wait().map_err(|_| ()).unwrap().0.unwrap().tree_route.unwrap();
+		assert_eq!(tree_route.retracted().iter().map(|e| e.hash).collect::<Vec<_>>(), vec![a1]);
+		assert_eq!(tree_route.enacted().iter().map(|e| e.hash).collect::<Vec<_>>(), vec![b1, b2]);
+
+		// a longer fork which doesn't include the finalized block is not chosen.
+		client.finalize_block(BlockId::Hash(b2)).unwrap();
+		let a2 = import(client.new_block_at(&BlockId::Hash(a1)).unwrap().bake().unwrap());
+		import(client.new_block_at(&BlockId::Hash(a2)).unwrap().bake().unwrap());
+		assert_eq!(client.info().unwrap().chain.best_hash, b2);
+	}
+
+	#[test]
+	fn key_changes_works() {
+		let client = new_in_mem(Executor::new(), prepare_genesis).unwrap();
+		let ferdie_balance = StorageKey(primitives::twox_128(&Keyring::Ferdie.to_raw_public().to_keyed_vec(b"balance:")).to_vec());
+		let keys = vec![StorageKey(b":nothing".to_vec()), ferdie_balance];
+
+		let mut builder = client.new_block().unwrap();
+		builder.push(Transaction {
+			from: Keyring::Alice.to_raw_public(),
+			to: Keyring::Ferdie.to_raw_public(),
+			amount: 42,
+			nonce: 0
+		}.signed()).unwrap();
+		let block = builder.bake().unwrap();
+		let hash1 = block.header.hash();
+		let justification = justify(&block.header);
+		let justified = client.check_justification(block.header, justification).unwrap();
+		client.import_block(justified, Some(block.transactions)).unwrap();
+
+		let block = client.new_block().unwrap().bake().unwrap();
+		let justification = justify(&block.header);
+		let justified = client.check_justification(block.header, justification).unwrap();
+		client.import_block(justified, Some(block.transactions)).unwrap();
+
+		assert_eq!(client.key_changes(&BlockId::Number(0), &BlockId::Number(2), &keys).unwrap(), vec![(1, hash1, vec![1])]);
+		assert!(client.key_changes(&BlockId::Number(2), &BlockId::Number(2), &keys).unwrap().is_empty());
+		assert!(client.key_changes(&BlockId::Number(2), &BlockId::Number(1), &keys).is_err());
+	}
+
+	#[test]
+	fn read_proof_generated_and_checked() {
+		let client = new_in_mem(Executor::new(), prepare_genesis).unwrap();
+
+		let block = client.new_block().unwrap().bake().unwrap();
+		let justification = justify(&block.header);
+		let justified = client.check_justification(block.header, justification).unwrap();
+		client.import_block(justified, Some(block.transactions)).unwrap();
+
+		let keys = vec![b":auth:len".to_vec(), b":nothing".to_vec()];
+		let state_root = client.header(&BlockId::Number(1)).unwrap().unwrap().state_root;
+		let proof = client.read_proof(&BlockId::Number(1), &keys).unwrap();
+
+		assert_eq!(check_read_proof(state_root, proof.clone(), &keys).unwrap(), vec![Some(vec![3, 0, 0, 0]), None]);
+		assert!(check_read_proof(Default::default(), proof, &keys).is_err());
+	}
 }
@@ -23,9 +23,9 @@ use primitives::hexdisplay::HexDisplay;
 error_chain! {
 	errors {
 		/// Backend error.
-		Backend {
+		Backend(s: String) {
 			description("Unrecoverable backend error"),
-			display("Backend error"),
+			display("Backend error: {}", s),
 		}
 
 		/// Unknown block.
@@ -34,6 +34,24 @@ error_chain! {
 			display("UnknownBlock: {}", h),
 		}
 
+		/// State of the block is no longer available.
+		StatePruned(h: ::primitives::block::Id) {
+			description("state has been pruned"),
+			display("State for block {} has been pruned", h),
+		}
+
+		/// Block is not in the best chain.
+		NotInBestChain(h: ::primitives::block::Id) {
+			description("block is not in the best chain"),
+			display("Block {} is not in the best chain", h),
+		}
+
+		/// Invalid block range.
+		InvalidBlockRange(first: ::primitives::block::Id, last: ::primitives::block::Id) {
+			description("invalid block range"),
+			display("Block {} is not an ancestor of block {} within the maximum range", first, last),
+		}
+
 		/// Execution error.
 		Execution(e: Box<state_machine::Error>) {
 			description("execution error"),
@@ -76,6 +94,48 @@ error_chain! {
 			display("Current state of blockchain has invalid authority value for index {}", i),
 		}
 
+		/// Operation requires state which light clients don't have.
+		NotAvailableOnLightClient {
+			description("not available on light client"),
+			display("This method is not currently available when running in light client mode"),
+		}
+
+		/// Remote data fetch has been cancelled.
+		RemoteFetchCancelled {
+			description("remote fetch cancelled"),
+			display("Remote data fetch has been cancelled"),
+		}
+
+		/// Remote data fetch has failed.
+		RemoteFetchFailed {
+			description("remote fetch failed"),
+			display("Remote data fetch has failed"),
+		}
+
+		/// Remote data fetch has not completed in time.
+		RemoteFetchTimedOut {
+			description("remote fetch timed out"),
+			display("Remote data fetch has timed out"),
+		}
+
+		/// Header proof is not available for the block.
+		HeaderProofUnavailable(n: ::primitives::block::Number) {
+			description("header proof unavailable"),
+			display("Header proof is not available for block #{}", n),
+		}
+
+		/// Invalid header proof.
+		InvalidHeaderProof(n: ::primitives::block::Number) {
+			description("invalid header proof"),
+			display("Invalid header proof for block #{}", n),
+		}
+
+		/// Block body is required to import the block.
+		MissingBody(h: ::primitives::block::Id) {
+			description("missing block body"),
+			display("Block {} has no body and can't be executed", h),
+		}
+
 		/// Bad justification for header.
 		BadJustification(h: ::primitives::block::Id) {
 			description("bad justification for header"),
@@ -16,15 +16,16 @@
 
 //! In memory client backend
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use parking_lot::RwLock;
 use state_machine;
 use error;
 use backend;
 use runtime_support::Hashable;
-use primitives;
+use primitives::{self, H256};
 use primitives::block::{self, Id as BlockId, HeaderHash};
 use blockchain::{self, BlockStatus};
+use cht;
 use state_machine::backend::Backend as StateBackend;
 
 fn header_hash(header: &block::Header) -> block::HeaderHash {
@@ -47,15 +48,21 @@ struct Block {
 pub struct BlockImportOperation {
 	pending_block: Option<PendingBlock>,
 	pending_state: state_machine::backend::InMemory,
+	changed_keys: BTreeSet<Vec<u8>>,
 }
 
 #[derive(Clone)]
 struct BlockchainStorage {
 	blocks: HashMap<HeaderHash, Block>,
+	changes: HashMap<HeaderHash, Vec<Vec<u8>>>,
 	hashes: HashMap<block::Number, HeaderHash>,
+	leaves: HashSet<HeaderHash>,
 	best_hash: HeaderHash,
 	best_number: block::Number,
 	genesis_hash: HeaderHash,
+	finalized_hash: HeaderHash,
+	finalized_number: block::Number,
+	cht_roots: HashMap<block::Number, H256>,
 }
 
 /// In-memory blockchain. Supports concurrent reads.
@@ -79,35 +86,150 @@ impl Blockchain {
 		}
 	}
 
-	fn new() -> Blockchain {
+	/// Create new in-memory blockchain storage.
+	pub fn new() -> Blockchain {
 		Blockchain {
 			storage: RwLock::new(
 				BlockchainStorage {
 					blocks: HashMap::new(),
+					changes: HashMap::new(),
 					hashes: HashMap::new(),
+					leaves: HashSet::new(),
 					best_hash: HeaderHash::default(),
 					best_number: 0,
 					genesis_hash: HeaderHash::default(),
+					finalized_hash: HeaderHash::default(),
+					finalized_number: 0,
+					cht_roots: HashMap::new(),
 				})
 		}
 	}
 
-	fn insert(&self, hash: HeaderHash, header: block::Header, justification: Option<primitives::bft::Justification>, body: Option<block::Body>, is_new_best: bool) {
+	/// Insert a block header and associated data, optionally making it the best block.
+	pub fn insert(&self, hash: HeaderHash, header: block::Header, justification: Option<primitives::bft::Justification>, body: Option<block::Body>, changed_keys: Vec<Vec<u8>>, is_new_best: bool) -> error::Result<()> {
 		let number = header.number;
+		let parent_hash = header.parent_hash;
 		let mut storage = self.storage.write();
+
+		// the route is computed first, so that nothing is changed if it fails.
+		let route = match is_new_best && number != 0 && parent_hash != storage.best_hash {
+			true => {
+				let blocks = &storage.blocks;
+				Some(blockchain::tree_route(
+					|id| Ok(match id {
+						BlockId::Hash(h) => blocks.get(&h).map(|b| b.header.clone()),
+						BlockId::Number(_) => None,
+					}),
+					BlockId::Hash(storage.best_hash),
+					BlockId::Hash(parent_hash),
+				)?)
+			},
+			false => None,
+		};
+
 		storage.blocks.insert(hash, Block {
 			header: header,
 			body: body,
 			justification: justification,
 		});
-		storage.hashes.insert(number, hash);
+		storage.changes.insert(hash, changed_keys);
+		storage.leaves.remove(&parent_hash);
+		storage.leaves.insert(hash);
+
 		if is_new_best {
+			// the best chain switches to another fork: re-canonicalise the number index.
+			if let Some(route) = route {
+				for retracted in route.retracted() {
+					storage.hashes.remove(&retracted.number);
+				}
+				for enacted in route.enacted() {
+					storage.hashes.insert(enacted.number, enacted.hash);
+				}
+			}
+
+			storage.hashes.insert(number, hash);
 			storage.best_hash = hash;
 			storage.best_number = number;
 		}
 		if number == 0 {
 			storage.genesis_hash = hash;
+			storage.finalized_hash = hash;
+		}
+		Ok(())
+	}
+
+	/// Mark a block of the best chain and all its ancestors as final.
+	pub fn finalize(&self, id: BlockId) -> error::Result<()> {
+		let mut storage = self.storage.write();
+		let hash = match id {
+			BlockId::Hash(h) => Some(h),
+			BlockId::Number(n) => storage.hashes.get(&n).cloned(),
+		};
+		let (hash, number) = match hash.and_then(|hash| storage.blocks.get(&hash).map(|b| (hash, b.header.number))) {
+			Some(block) => block,
+			None => return Err(error::ErrorKind::UnknownBlock(id).into()),
+		};
+		if storage.hashes.get(&number) != Some(&hash) {
+			return Err(error::ErrorKind::NotInBestChain(id).into());
+		}
+		if number > storage.finalized_number {
+			for cht_num in cht::completed_between(storage.finalized_number, number) {
+				let root = cht::compute_root(cht_num, (cht::start_number(cht_num)..cht::end_number(cht_num) + 1)
+					.filter_map(|n| storage.hashes.get(&n).cloned()));
+				if let Some(root) = root {
+					storage.cht_roots.insert(cht_num, root);
+				}
+			}
+			storage.finalized_hash = hash;
+			storage.finalized_number = number;
 		}
+		Ok(())
+	}
+
+	/// Remove the headers, bodies and justifications of the blocks included in a
+	/// complete CHT, keeping their hashes. The last finalized block is kept. Light
+	/// clients fetch the headers back from full nodes, proven against the CHT root.
+	pub fn prune_cht(&self, cht_num: block::Number) {
+		let mut storage = self.storage.write();
+		if !storage.cht_roots.contains_key(&cht_num) {
+			return;
+		}
+
+		for number in cht::start_number(cht_num)..cht::end_number(cht_num) + 1 {
+			let hash = match storage.hashes.get(&number) {
+				Some(hash) if *hash != storage.finalized_hash => *hash,
+				_ => continue,
+			};
+			storage.blocks.remove(&hash);
+			storage.changes.remove(&hash);
+		}
+	}
+
+	/// Remove the best block, making its parent the best one. Returns the hash of the
+	/// removed block, or `None` if the best block is finalized.
+	pub fn revert_best(&self) -> Option<HeaderHash> {
+		let mut storage = self.storage.write();
+		if storage.best_number <= storage.finalized_number {
+			return None;
+		}
+
+		let hash = storage.best_hash;
+		let header = storage.blocks.remove(&hash)?.header;
+		storage.changes.remove(&hash);
+		storage.hashes.remove(&header.number);
+		storage.leaves.remove(&hash);
+		if !storage.blocks.values().any(|b| b.header.parent_hash == header.parent_hash) {
+			storage.leaves.insert(header.parent_hash);
+		}
+		storage.best_hash = header.parent_hash;
+		storage.best_number = header.number - 1;
+		Some(hash)
+	}
+
+	/// Compute a tree-route between two blocks. See `blockchain::TreeRoute`.
+	pub fn tree_route(&self, from: BlockId, to: BlockId) -> error::Result<blockchain::TreeRoute> {
+		use blockchain::Backend;
+		blockchain::tree_route(|id| self.header(id), from, to)
 	}
 
 	/// Compare this blockchain with another in-mem blockchain
@@ -145,6 +267,8 @@ impl blockchain::Backend for Blockchain {
 			best_hash: storage.best_hash,
 			best_number: storage.best_number,
 			genesis_hash: storage.genesis_hash,
+			finalized_hash: storage.finalized_hash,
+			finalized_number: storage.finalized_number,
 		})
 	}
 
@@ -158,6 +282,18 @@ impl blockchain::Backend for Blockchain {
 	fn hash(&self, number: block::Number) -> error::Result<Option<block::HeaderHash>> {
 		Ok(self.id(BlockId::Number(number)))
 	}
+
+	fn changed_keys(&self, id: BlockId) -> error::Result<Option<Vec<Vec<u8>>>> {
+		Ok(self.id(id).and_then(|hash| self.storage.read().changes.get(&hash).cloned()))
+	}
+
+	fn leaves(&self) -> error::Result<Vec<HeaderHash>> {
+		Ok(self.storage.read().leaves.iter().cloned().collect())
+	}
+
+	fn cht_root(&self, cht_num: block::Number) -> error::Result<Option<H256>> {
+		Ok(self.storage.read().cht_roots.get(&cht_num).cloned())
+	}
 }
 
 impl backend::BlockImportOperation for BlockImportOperation {
@@ -181,12 +317,20 @@ impl backend::BlockImportOperation for BlockImportOperation {
 	}
 
 	fn set_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, changes: I) -> error::Result<()> {
-		self.pending_state.commit(changes);
+		let changed_keys = &mut self.changed_keys;
+		self.pending_state.commit(changes.inspect(|&(ref key, _)| { changed_keys.insert(key.clone()); }))?;
+		Ok(())
+	}
+
+	fn set_child_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, storage_key: Vec<u8>, changes: I) -> error::Result<()> {
+		self.changed_keys.insert(storage_key.clone());
+		self.pending_state.commit_child(storage_key, changes)?;
 		Ok(())
 	}
 
 	fn reset_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, iter: I) -> error::Result<()> {
-		self.pending_state = state_machine::backend::InMemory::from(iter.collect());
+		self.pending_state = iter.collect();
+		self.changed_keys = self.pending_state.keys().cloned().collect();
 		Ok(())
 	}
 }
@@ -221,6 +365,7 @@ impl backend::Backend for Backend {
 		Ok(BlockImportOperation {
 			pending_block: None,
 			pending_state: state,
+			changed_keys: BTreeSet::new(),
 		})
 	}
 
@@ -228,11 +373,16 @@ impl backend::Backend for Backend {
 		if let Some(pending_block) = operation.pending_block {
 			let hash = header_hash(&pending_block.block.header);
 			self.states.write().insert(hash, operation.pending_state);
-			self.blockchain.insert(hash, pending_block.block.header, pending_block.block.justification, pending_block.block.body, pending_block.is_best);
+			let changed_keys = operation.changed_keys.into_iter().collect();
+			self.blockchain.insert(hash, pending_block.block.header, pending_block.block.justification, pending_block.block.body, changed_keys, pending_block.is_best)?;
 		}
 		Ok(())
 	}
 
+	fn finalize_block(&self, block: BlockId) -> error::Result<()> {
+		self.blockchain.finalize(block)
+	}
+
 	fn blockchain(&self) -> &Blockchain {
 		&self.blockchain
 	}
@@ -243,4 +393,155 @@ impl backend::Backend for Backend {
 			None => Err(error::ErrorKind::UnknownBlock(block).into()),
 		}
 	}
+
+	fn is_light(&self) -> bool {
+		false
+	}
+
+	fn revert(&self, n: block::Number) -> error::Result<block::Number> {
+		let mut reverted = 0;
+		while reverted < n {
+			match self.blockchain.revert_best() {
+				Some(hash) => self.states.write().remove(&hash),
+				None => break,
+			};
+			reverted += 1;
+		}
+		Ok(reverted)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::{Backend as BackendT, BlockImportOperation as OperationT};
+	use blockchain::Backend as BlockchainT;
+
+	fn insert_block(backend: &Backend, number: block::Number, parent_hash: HeaderHash, fork: u8, is_best: bool) -> HeaderHash {
+		let mut header = block::Header::from_block_number(number);
+		header.parent_hash = parent_hash;
+		header.transaction_root = [fork; 32].into();
+		let hash = header_hash(&header);
+
+		let mut op = backend.begin_operation(BlockId::Hash(parent_hash)).unwrap();
+		op.set_block_data(header, None, None, is_best).unwrap();
+		backend.commit_operation(op).unwrap();
+		hash
+	}
+
+	// G -> A1 -> A2 -> A3
+	//        \
+	//         -> B2 -> B3 -> B4
+	fn forked_chain() -> (Backend, Vec<HeaderHash>, Vec<HeaderHash>) {
+		let backend = Backend::new();
+		let genesis = insert_block(&backend, 0, Default::default(), 0, true);
+		let a1 = insert_block(&backend, 1, genesis, 0, true);
+		let a2 = insert_block(&backend, 2, a1, 0, true);
+		let a3 = insert_block(&backend, 3, a2, 0, true);
+		let b2 = insert_block(&backend, 2, a1, 1, false);
+		let b3 = insert_block(&backend, 3, b2, 1, false);
+		(backend, vec![genesis, a1, a2, a3], vec![b2, b3])
+	}
+
+	#[test]
+	fn tree_route_between_forks() {
+		let (backend, a, b) = forked_chain();
+		let route = backend.blockchain().tree_route(BlockId::Hash(a[3]), BlockId::Hash(b[1])).unwrap();
+
+		assert_eq!(route.retracted().iter().map(|e| e.hash).collect::<Vec<_>>(), vec![a[3], a[2]]);
+		assert_eq!(route.common_block().hash, a[1]);
+		assert_eq!(route.enacted().iter().map(|e| e.hash).collect::<Vec<_>>(), vec![b[0], b[1]]);
+	}
+
+	#[test]
+	fn tree_route_along_one_chain() {
+		let (backend, a, _) = forked_chain();
+		let route = backend.blockchain().tree_route(BlockId::Hash(a[1]), BlockId::Hash(a[3])).unwrap();
+
+		assert!(route.retracted().is_empty());
+		assert_eq!(route.common_block().hash, a[1]);
+		assert_eq!(route.enacted().iter().map(|e| e.hash).collect::<Vec<_>>(), vec![a[2], a[3]]);
+
+		let route = backend.blockchain().tree_route(BlockId::Hash(a[2]), BlockId::Hash(a[2])).unwrap();
+		assert!(route.retracted().is_empty() && route.enacted().is_empty());
+		assert_eq!(route.common_block().hash, a[2]);
+	}
+
+	#[test]
+	fn leaves_are_tracked() {
+		let (backend, a, b) = forked_chain();
+		let mut leaves = backend.blockchain().leaves().unwrap();
+		leaves.sort();
+		let mut expected = vec![a[3], b[1]];
+		expected.sort();
+		assert_eq!(leaves, expected);
+	}
+
+	#[test]
+	fn number_index_follows_best_fork() {
+		let (backend, a, b) = forked_chain();
+		let chain = backend.blockchain();
+		assert_eq!(chain.hash(2).unwrap(), Some(a[2]));
+		assert_eq!(chain.hash(3).unwrap(), Some(a[3]));
+
+		let b4 = insert_block(&backend, 4, b[1], 1, true);
+		assert_eq!(chain.info().unwrap().best_hash, b4);
+		assert_eq!(chain.hash(1).unwrap(), Some(a[1]));
+		assert_eq!(chain.hash(2).unwrap(), Some(b[0]));
+		assert_eq!(chain.hash(3).unwrap(), Some(b[1]));
+		assert_eq!(chain.hash(4).unwrap(), Some(b4));
+		assert_eq!(chain.header(BlockId::Hash(a[3])).unwrap().unwrap().number, 3);
+	}
+
+	#[test]
+	fn revert_removes_best_blocks() {
+		let (backend, a, b) = forked_chain();
+		assert_eq!(backend.revert(2).unwrap(), 2);
+
+		let chain = backend.blockchain();
+		assert_eq!(chain.info().unwrap().best_hash, a[1]);
+		assert_eq!(chain.info().unwrap().best_number, 1);
+		assert_eq!(chain.hash(2).unwrap(), None);
+		assert_eq!(chain.status(BlockId::Hash(a[2])).unwrap(), BlockStatus::Unknown);
+		assert!(backend.state_at(BlockId::Hash(a[3])).is_err());
+		assert!(backend.state_at(BlockId::Hash(a[1])).is_ok());
+		assert_eq!(chain.leaves().unwrap(), vec![b[1]]);
+	}
+
+	#[test]
+	fn finalized_blocks_are_not_reverted() {
+		let (backend, a, b) = forked_chain();
+		assert!(backend.finalize_block(BlockId::Hash(b[0])).is_err());
+		backend.finalize_block(BlockId::Hash(a[2])).unwrap();
+		backend.finalize_block(BlockId::Hash(a[1])).unwrap();
+
+		let info = backend.blockchain().info().unwrap();
+		assert_eq!(info.finalized_hash, a[2]);
+		assert_eq!(info.finalized_number, 2);
+		assert_eq!(backend.revert(3).unwrap(), 1);
+		assert_eq!(backend.blockchain().info().unwrap().best_hash, a[2]);
+	}
+
+	#[test]
+	fn cht_root_is_stored_when_cht_is_finalized() {
+		let backend = Backend::new();
+		let mut hashes = vec![insert_block(&backend, 0, Default::default(), 0, true)];
+		for number in 1..cht::SIZE + 2 {
+			let parent = hashes[number as usize - 1];
+			hashes.push(insert_block(&backend, number, parent, 0, true));
+		}
+
+		let chain = backend.blockchain();
+		backend.finalize_block(BlockId::Number(cht::SIZE - 1)).unwrap();
+		assert_eq!(chain.cht_root(0).unwrap(), None);
+		backend.finalize_block(BlockId::Number(cht::SIZE + 1)).unwrap();
+		assert_eq!(chain.cht_root(0).unwrap(), cht::compute_root(0, hashes[1..cht::SIZE as usize + 1].iter().cloned()));
+		assert_eq!(chain.cht_root(1).unwrap(), None);
+
+		chain.prune_cht(0);
+		assert!(chain.header(BlockId::Number(1)).unwrap().is_none());
+		assert!(chain.header(BlockId::Number(cht::SIZE)).unwrap().is_none());
+		assert!(chain.header(BlockId::Number(cht::SIZE + 1)).unwrap().is_some());
+		assert_eq!(chain.hash(1).unwrap(), Some(hashes[1]));
+	}
 }
@@ -31,6 +31,7 @@ extern crate ed25519;
 
 extern crate triehash;
 extern crate parking_lot;
+extern crate futures;
 #[cfg(test)] #[macro_use] extern crate hex_literal;
 #[macro_use] extern crate error_chain;
 #[macro_use] extern crate log;
@@ -41,7 +42,15 @@ pub mod backend;
 pub mod in_mem;
 pub mod genesis;
 pub mod block_builder;
+pub mod cht;
+pub mod light;
+mod call_executor;
 mod client;
 
-pub use client::{Client, ClientInfo, CallResult, ImportResult, BlockStatus, new_in_mem};
+pub use client::{
+	Client, ClientInfo, ImportResult, BlockStatus, BlockImportNotification,
+	FinalityNotification, ImportNotifications, FinalityNotifications, new_in_mem, check_read_proof,
+	MAX_KEY_CHANGES_RANGE,
+};
+pub use call_executor::{CallExecutor, CallResult};
 pub use blockchain::Info as ChainInfo;
@@ -0,0 +1,699 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Light client. Keeps headers and justifications only and fetches everything
+//! else from full nodes, checking the returned proofs against the state roots
+//! of the local headers.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use futures::{executor, Async, IntoFuture};
+use parking_lot::{Condvar, Mutex};
+use primitives::{self, H256};
+use primitives::block::{self, Id as BlockId, HeaderHash};
+use runtime_support::Hashable;
+use state_machine::{self, CodeExecutor, OverlayedChanges, Backend as StateBackend, TrieBackend};
+use state_machine::backend::TryIntoTrieBackend;
+
+use backend;
+use blockchain::{self, Backend as ChainBackend};
+use call_executor::{CallExecutor, CallResult};
+use client::Client;
+use {cht, error, in_mem};
+
+/// Time to wait for a response from remote nodes before failing the request.
+const REMOTE_REQUEST_TIMEOUT_SEC: u64 = 30;
+
+/// Remote storage read request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteReadRequest {
+	/// Read at state of given block.
+	pub block: HeaderHash,
+	/// Header of the block at which the read is performed.
+	pub header: block::Header,
+	/// Storage key to read.
+	pub key: Vec<u8>,
+}
+
+/// Remote call request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteCallRequest {
+	/// Call at state of given block.
+	pub block: HeaderHash,
+	/// Header of the block at which the call is performed.
+	pub header: block::Header,
+	/// Method to call.
+	pub method: String,
+	/// Call data.
+	pub call_data: Vec<u8>,
+}
+
+/// Remote header request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteHeaderRequest {
+	/// Root of the CHT which includes the block.
+	pub cht_root: H256,
+	/// Number of the block to fetch the header of.
+	pub block: block::Number,
+}
+
+/// Light client data fetcher. Implementations must check the data they receive
+/// from remote nodes (see `FetchChecker`) and fail the request if it is invalid.
+pub trait Fetcher: Send + Sync {
+	/// Remote storage read result.
+	type RemoteReadResult: IntoFuture<Item=Option<Vec<u8>>, Error=error::Error>;
+	/// Remote call result.
+	type RemoteCallResult: IntoFuture<Item=CallResult, Error=error::Error>;
+	/// Remote header result.
+	type RemoteHeaderResult: IntoFuture<Item=block::Header, Error=error::Error>;
+
+	/// Fetch remote storage value.
+	fn remote_read(&self, request: RemoteReadRequest) -> Self::RemoteReadResult;
+	/// Fetch remote call result.
+	fn remote_call(&self, request: RemoteCallRequest) -> Self::RemoteCallResult;
+	/// Fetch remote header.
+	fn remote_header(&self, request: RemoteHeaderRequest) -> Self::RemoteHeaderResult;
+}
+
+/// Light client remote data checker.
+pub trait FetchChecker: Send + Sync {
+	/// Check remote storage read proof.
+	fn check_read_proof(&self, request: &RemoteReadRequest, remote_proof: Vec<Vec<u8>>) -> error::Result<Option<Vec<u8>>>;
+	/// Check remote method execution proof.
+	fn check_execution_proof(&self, request: &RemoteCallRequest, remote_proof: Vec<Vec<u8>>) -> error::Result<CallResult>;
+	/// Check remote header and its CHT proof.
+	fn check_header_proof(&self, request: &RemoteHeaderRequest, header: Option<block::Header>, remote_proof: Vec<Vec<u8>>) -> error::Result<block::Header>;
+}
+
+/// Remote data checker which re-executes remote calls with the given executor.
+pub struct LightDataChecker<E> {
+	executor: E,
+}
+
+impl<E> LightDataChecker<E> {
+	/// Create new light data checker.
+	pub fn new(executor: E) -> Self {
+		LightDataChecker { executor }
+	}
+}
+
+impl<E: CodeExecutor> FetchChecker for LightDataChecker<E> {
+	fn check_read_proof(&self, request: &RemoteReadRequest, remote_proof: Vec<Vec<u8>>) -> error::Result<Option<Vec<u8>>> {
+		let mut values = state_machine::read_proof_check(request.header.state_root.0, remote_proof, &[request.key.clone()])?;
+		Ok(values.pop().expect("a value is returned for every requested key; qed"))
+	}
+
+	fn check_execution_proof(&self, request: &RemoteCallRequest, remote_proof: Vec<Vec<u8>>) -> error::Result<CallResult> {
+		let mut changes = OverlayedChanges::default();
+		let return_data = state_machine::execution_proof_check(
+			request.header.state_root.0,
+			remote_proof,
+			&mut changes,
+			&self.executor,
+			&request.method,
+			&request.call_data,
+		)?;
+		Ok(CallResult { return_data, changes })
+	}
+
+	fn check_header_proof(&self, request: &RemoteHeaderRequest, header: Option<block::Header>, remote_proof: Vec<Vec<u8>>) -> error::Result<block::Header> {
+		let header = match header {
+			Some(ref header) if header.number == request.block => header.clone(),
+			_ => return Err(error::ErrorKind::InvalidHeaderProof(request.block).into()),
+		};
+		cht::check_proof(request.cht_root, request.block, header.blake2_256().into(), remote_proof)?;
+		Ok(header)
+	}
+}
+
+/// Light client blockchain. Keeps the headers of recent blocks in memory. Once a
+/// CHT is finalized, the headers of its blocks are dropped and fetched from remote
+/// nodes by number when needed, with a proof against the CHT root.
+pub struct Blockchain<F> {
+	storage: in_mem::Blockchain,
+	fetcher: Arc<F>,
+}
+
+impl<F: Fetcher> Blockchain<F> {
+	// Mark a block as final, dropping the headers of the CHTs it completes.
+	fn finalize(&self, block: BlockId) -> error::Result<()> {
+		let finalized_number = self.storage.info()?.finalized_number;
+		self.storage.finalize(block)?;
+		for cht_num in cht::completed_between(finalized_number, self.storage.info()?.finalized_number) {
+			self.storage.prune_cht(cht_num);
+		}
+		Ok(())
+	}
+}
+
+impl<F: Fetcher> ChainBackend for Blockchain<F> {
+	fn header(&self, id: BlockId) -> error::Result<Option<block::Header>> {
+		if let Some(header) = self.storage.header(id)? {
+			return Ok(Some(header));
+		}
+
+		let number = match id {
+			BlockId::Number(number) => number,
+			BlockId::Hash(_) => return Ok(None),
+		};
+		let cht_root = match cht::block_to_cht_number(number) {
+			Some(cht_num) => self.storage.cht_root(cht_num)?,
+			None => None,
+		};
+		match cht_root {
+			Some(cht_root) => wait_remote(self.fetcher.remote_header(RemoteHeaderRequest {
+				cht_root,
+				block: number,
+			}), Duration::from_secs(REMOTE_REQUEST_TIMEOUT_SEC)).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	fn body(&self, id: BlockId) -> error::Result<Option<block::Body>> {
+		self.storage.body(id)
+	}
+
+	fn justification(&self, id: BlockId) -> error::Result<Option<primitives::bft::Justification>> {
+		self.storage.justification(id)
+	}
+
+	fn info(&self) -> error::Result<blockchain::Info> {
+		self.storage.info()
+	}
+
+	fn status(&self, id: BlockId) -> error::Result<blockchain::BlockStatus> {
+		self.storage.status(id)
+	}
+
+	fn hash(&self, number: block::Number) -> error::Result<Option<HeaderHash>> {
+		self.storage.hash(number)
+	}
+
+	fn changed_keys(&self, id: BlockId) -> error::Result<Option<Vec<Vec<u8>>>> {
+		self.storage.changed_keys(id)
+	}
+
+	fn leaves(&self) -> error::Result<Vec<HeaderHash>> {
+		self.storage.leaves()
+	}
+
+	fn cht_root(&self, cht_num: block::Number) -> error::Result<Option<H256>> {
+		self.storage.cht_root(cht_num)
+	}
+}
+
+/// Light client backend. Keeps headers and justifications in memory and reads
+/// the state from remote nodes.
+pub struct Backend<F> {
+	blockchain: Blockchain<F>,
+	fetcher: Arc<F>,
+}
+
+impl<F: Fetcher> Backend<F> {
+	/// Create new light backend.
+	pub fn new(fetcher: Arc<F>) -> Self {
+		Backend {
+			blockchain: Blockchain {
+				storage: in_mem::Blockchain::new(),
+				fetcher: fetcher.clone(),
+			},
+			fetcher,
+		}
+	}
+}
+
+/// Light block import operation. Only the header and justification are kept.
+pub struct BlockImportOperation<F> {
+	pending_block: Option<(block::Header, Option<primitives::bft::Justification>, bool)>,
+	_fetcher: ::std::marker::PhantomData<F>,
+}
+
+impl<F: Fetcher> backend::BlockImportOperation for BlockImportOperation<F> {
+	type State = OnDemandState<F>;
+
+	fn state(&self) -> error::Result<&Self::State> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn set_block_data(&mut self, header: block::Header, _body: Option<block::Body>, justification: Option<primitives::bft::Justification>, is_new_best: bool) -> error::Result<()> {
+		assert!(self.pending_block.is_none(), "Only one block per operation is allowed");
+		self.pending_block = Some((header, justification, is_new_best));
+		Ok(())
+	}
+
+	fn set_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, mut changes: I) -> error::Result<()> {
+		// blocks are never executed by light clients, so there are no changes to keep.
+		match changes.next() {
+			Some(_) => Err(error::ErrorKind::NotAvailableOnLightClient.into()),
+			None => Ok(()),
+		}
+	}
+
+	fn set_child_storage<I: Iterator<Item=(Vec<u8>, Option<Vec<u8>>)>>(&mut self, _storage_key: Vec<u8>, _changes: I) -> error::Result<()> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn reset_storage<I: Iterator<Item=(Vec<u8>, Vec<u8>)>>(&mut self, _iter: I) -> error::Result<()> {
+		// light clients don't keep the genesis state either.
+		Ok(())
+	}
+}
+
+impl<F: Fetcher> backend::Backend for Backend<F> {
+	type BlockImportOperation = BlockImportOperation<F>;
+	type Blockchain = Blockchain<F>;
+	type State = OnDemandState<F>;
+
+	fn begin_operation(&self, _block: BlockId) -> error::Result<Self::BlockImportOperation> {
+		Ok(BlockImportOperation {
+			pending_block: None,
+			_fetcher: Default::default(),
+		})
+	}
+
+	fn commit_operation(&self, operation: Self::BlockImportOperation) -> error::Result<()> {
+		if let Some((header, justification, is_new_best)) = operation.pending_block {
+			let hash = header.blake2_256().into();
+			self.blockchain.storage.insert(hash, header, justification, None, Vec::new(), is_new_best)?;
+		}
+		Ok(())
+	}
+
+	fn finalize_block(&self, block: BlockId) -> error::Result<()> {
+		self.blockchain.finalize(block)
+	}
+
+	fn blockchain(&self) -> &Blockchain<F> {
+		&self.blockchain
+	}
+
+	fn state_at(&self, block: BlockId) -> error::Result<Self::State> {
+		let header = self.blockchain.header(block)?.ok_or_else(|| error::ErrorKind::UnknownBlock(block))?;
+		Ok(OnDemandState {
+			fetcher: self.fetcher.clone(),
+			block: header.blake2_256().into(),
+			header,
+		})
+	}
+
+	fn is_light(&self) -> bool {
+		true
+	}
+
+	fn revert(&self, n: block::Number) -> error::Result<block::Number> {
+		let mut reverted = 0;
+		while reverted < n && self.blockchain.storage.revert_best().is_some() {
+			reverted += 1;
+		}
+		Ok(reverted)
+	}
+}
+
+/// State of a block which is read from remote nodes on demand. Only storage
+/// reads are supported.
+pub struct OnDemandState<F> {
+	fetcher: Arc<F>,
+	block: HeaderHash,
+	header: block::Header,
+}
+
+impl<F: Fetcher> StateBackend for OnDemandState<F> {
+	type Error = error::Error;
+
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		wait_remote(self.fetcher.remote_read(RemoteReadRequest {
+			block: self.block,
+			header: self.header.clone(),
+			key: key.to_vec(),
+		}), Duration::from_secs(REMOTE_REQUEST_TIMEOUT_SEC))
+	}
+
+	fn child_storage(&self, _storage_key: &[u8], _key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn child_keys(&self, _storage_key: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn keys_with_prefix(&self, _prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn next_key(&self, _key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn commit<I>(&mut self, _changes: I) -> Result<(), Self::Error> where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
+		// the state is never changed locally.
+		Ok(())
+	}
+
+	fn commit_child<I>(&mut self, _storage_key: Vec<u8>, _changes: I) -> Result<(), Self::Error> where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
+		Ok(())
+	}
+
+	fn pairs(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error> {
+		// whole state is not available on light node.
+		Ok(Vec::new())
+	}
+
+	fn storage_root<I>(&self, _delta: I) -> Result<[u8; 32], Self::Error> where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
+		// the root can't be recomputed without the full state, so changes are ignored.
+		Ok(self.header.state_root.0)
+	}
+
+	fn child_storage_root<I>(&self, _storage_key: &[u8], _delta: I) -> Result<Option<[u8; 32]>, Self::Error> where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)> {
+		Ok(None)
+	}
+}
+
+impl<F> TryIntoTrieBackend for OnDemandState<F> {
+	fn try_into_trie_backend(self) -> Option<TrieBackend> {
+		None
+	}
+}
+
+/// Call executor which delegates all calls to remote nodes.
+pub struct RemoteCallExecutor<F> {
+	fetcher: Arc<F>,
+}
+
+impl<F: Fetcher> RemoteCallExecutor<F> {
+	/// Create new remote call executor.
+	pub fn new(fetcher: Arc<F>) -> Self {
+		RemoteCallExecutor { fetcher }
+	}
+}
+
+impl<F: Fetcher> CallExecutor for RemoteCallExecutor<F> {
+	fn call<B: backend::Backend>(
+		&self,
+		backend: &B,
+		id: &BlockId,
+		method: &str,
+		call_data: &[u8],
+	) -> error::Result<CallResult> {
+		let header = backend.blockchain().header(*id)?.ok_or_else(|| error::ErrorKind::UnknownBlock(*id))?;
+		wait_remote(self.fetcher.remote_call(RemoteCallRequest {
+			block: header.blake2_256().into(),
+			header,
+			method: method.into(),
+			call_data: call_data.to_vec(),
+		}), Duration::from_secs(REMOTE_REQUEST_TIMEOUT_SEC))
+	}
+
+	fn call_at_state<S: StateBackend>(
+		&self,
+		_state: &S,
+		_overlay: &mut OverlayedChanges,
+		_method: &str,
+		_call_data: &[u8],
+	) -> error::Result<Vec<u8>> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+
+	fn prove_at_state<S: StateBackend + TryIntoTrieBackend>(
+		&self,
+		_state: S,
+		_overlay: &mut OverlayedChanges,
+		_method: &str,
+		_call_data: &[u8],
+	) -> error::Result<(Vec<u8>, Vec<Vec<u8>>)> {
+		Err(error::ErrorKind::NotAvailableOnLightClient.into())
+	}
+}
+
+// Wakes up the thread waiting for a remote response.
+#[derive(Default)]
+struct WaitNotify {
+	notified: Mutex<bool>,
+	condvar: Condvar,
+}
+
+impl executor::Notify for WaitNotify {
+	fn notify(&self, _id: usize) {
+		*self.notified.lock() = true;
+		self.condvar.notify_one();
+	}
+}
+
+// Block on the remote response, failing if it doesn't arrive within the timeout.
+// The storage and call APIs of the client are synchronous, but the wait must be
+// bounded since nothing guarantees that a remote node ever answers.
+fn wait_remote<F: IntoFuture<Error=error::Error>>(response: F, timeout: Duration) -> error::Result<F::Item> {
+	let deadline = Instant::now() + timeout;
+	let notify = Arc::new(WaitNotify::default());
+	let mut response = executor::spawn(response.into_future());
+	loop {
+		if let Async::Ready(item) = response.poll_future_notify(&notify, 0)? {
+			return Ok(item);
+		}
+
+		let now = Instant::now();
+		if now >= deadline {
+			return Err(error::ErrorKind::RemoteFetchTimedOut.into());
+		}
+
+		let mut notified = notify.notified.lock();
+		if !*notified {
+			notify.condvar.wait_for(&mut notified, deadline - now);
+		}
+		*notified = false;
+	}
+}
+
+/// Create an instance of light client.
+pub fn new_light<F, G>(
+	fetcher: Arc<F>,
+	build_genesis: G,
+) -> error::Result<Client<Backend<F>, RemoteCallExecutor<F>>>
+	where
+		F: Fetcher,
+		G: FnOnce() -> (block::Header, Vec<(Vec<u8>, Vec<u8>)>)
+{
+	Client::new(Backend::new(fetcher.clone()), RemoteCallExecutor::new(fetcher), build_genesis)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::thread;
+	use futures::{future, Future};
+	use futures::sync::oneshot;
+	use backend::{Backend as BackendT, BlockImportOperation as OperationT};
+	use codec::{Joiner, Slicable};
+	use keyring::Keyring;
+	use test_runtime::genesismap::{GenesisConfig, additional_storage_with_genesis};
+	use test_runtime;
+	use {genesis, new_in_mem};
+	use executor::NativeExecutor;
+	use primitives::storage::StorageKey;
+
+	native_executor_instance!(Executor, test_runtime::api::dispatch, include_bytes!("../../test-runtime/wasm/target/wasm32-unknown-unknown/release/substrate_test_runtime.compact.wasm"));
+
+	type FullClient = Client<in_mem::Backend, NativeExecutor<Executor>>;
+
+	fn prepare_genesis() -> (block::Header, Vec<(Vec<u8>, Vec<u8>)>) {
+		let mut storage = GenesisConfig::new_simple(vec![
+			Keyring::Alice.to_raw_public(),
+			Keyring::Bob.to_raw_public(),
+		], 1000).genesis_map();
+		let block = genesis::construct_genesis_block(&storage);
+		storage.extend(additional_storage_with_genesis(&block));
+		(block::Header::decode(&mut block.header.encode().as_ref()).expect("to_vec() always gives a valid serialisation; qed"), storage.into_iter().collect())
+	}
+
+	// answers the requests from a local full client.
+	struct LocalFetcher {
+		full: Arc<FullClient>,
+		checker: LightDataChecker<NativeExecutor<Executor>>,
+	}
+
+	impl Fetcher for LocalFetcher {
+		type RemoteReadResult = error::Result<Option<Vec<u8>>>;
+		type RemoteCallResult = error::Result<CallResult>;
+		type RemoteHeaderResult = error::Result<block::Header>;
+
+		fn remote_read(&self, request: RemoteReadRequest) -> Self::RemoteReadResult {
+			let proof = self.full.read_proof(&BlockId::Hash(request.block), &[request.key.clone()])?;
+			self.checker.check_read_proof(&request, proof)
+		}
+
+		fn remote_call(&self, request: RemoteCallRequest) -> Self::RemoteCallResult {
+			let (_, proof) = self.full.execution_proof(&BlockId::Hash(request.block), &request.method, &request.call_data)?;
+			self.checker.check_execution_proof(&request, proof)
+		}
+
+		fn remote_header(&self, request: RemoteHeaderRequest) -> Self::RemoteHeaderResult {
+			let (header, proof) = self.full.header_proof(request.block)?;
+			self.checker.check_header_proof(&request, Some(header), proof)
+		}
+	}
+
+	fn light_and_full() -> (Client<Backend<LocalFetcher>, RemoteCallExecutor<LocalFetcher>>, Arc<FullClient>) {
+		let full = Arc::new(new_in_mem(Executor::new(), prepare_genesis).unwrap());
+		let fetcher = Arc::new(LocalFetcher {
+			full: full.clone(),
+			checker: LightDataChecker::new(Executor::new()),
+		});
+		(new_light(fetcher, prepare_genesis).unwrap(), full)
+	}
+
+	#[test]
+	fn light_client_has_genesis_header_only() {
+		let (light, full) = light_and_full();
+		assert_eq!(light.info().unwrap().chain.genesis_hash, full.info().unwrap().chain.genesis_hash);
+		assert_eq!(light.header(&BlockId::Number(0)).unwrap(), full.header(&BlockId::Number(0)).unwrap());
+		assert!(light.body(&BlockId::Number(0)).unwrap().is_none());
+	}
+
+	#[test]
+	fn light_client_reads_remote_storage() {
+		let (light, full) = light_and_full();
+		let key = StorageKey(b":auth:len".to_vec());
+		assert_eq!(light.storage(&BlockId::Number(0), &key).unwrap(), full.storage(&BlockId::Number(0), &key).unwrap());
+		assert_eq!(light.authorities_at(&BlockId::Number(0)).unwrap(), full.authorities_at(&BlockId::Number(0)).unwrap());
+	}
+
+	#[test]
+	fn light_client_executes_remote_calls() {
+		let (light, full) = light_and_full();
+		let tx = test_runtime::Transaction {
+			from: Keyring::Alice.to_raw_public(),
+			to: Keyring::Bob.to_raw_public(),
+			amount: 42,
+			nonce: 0,
+		};
+		let signature = Keyring::Alice.sign(&tx.encode());
+		let header = full.header(&BlockId::Number(0)).unwrap().unwrap();
+		let call_data = vec![].and(&header).and(&test_runtime::UncheckedTransaction { signature, tx });
+		assert_eq!(
+			light.call(&BlockId::Number(0), "execute_transaction", &call_data).unwrap().return_data,
+			full.call(&BlockId::Number(0), "execute_transaction", &call_data).unwrap().return_data
+		);
+	}
+
+	#[test]
+	fn bad_read_proof_is_rejected() {
+		let (_, full) = light_and_full();
+		let checker = LightDataChecker::new(Executor::new());
+		let header = full.header(&BlockId::Number(0)).unwrap().unwrap();
+		let request = RemoteReadRequest {
+			block: header.blake2_256().into(),
+			header,
+			key: b":auth:len".to_vec(),
+		};
+		assert!(checker.check_read_proof(&request, vec![vec![42]]).is_err());
+	}
+
+	#[test]
+	fn header_proof_is_checked_against_cht_root() {
+		let checker = LightDataChecker::new(Executor::new());
+		let (header, _) = prepare_genesis();
+		let header = block::Header { number: 1, ..header };
+		let mut hashes: Vec<HeaderHash> = (0..cht::SIZE).map(|n| H256::from(n)).collect();
+		hashes[0] = header.blake2_256().into();
+		let request = RemoteHeaderRequest {
+			cht_root: cht::compute_root(0, hashes.clone()).unwrap(),
+			block: 1,
+		};
+		let proof = cht::build_proof(0, 1, hashes).unwrap();
+		assert_eq!(checker.check_header_proof(&request, Some(header.clone()), proof.clone()).unwrap(), header);
+		assert!(checker.check_header_proof(&request, None, proof.clone()).is_err());
+		let other_header = block::Header { parent_hash: H256::from(42), ..header };
+		assert!(checker.check_header_proof(&request, Some(other_header), proof).is_err());
+	}
+
+	// serves the headers of a chain which is known in advance.
+	struct HeaderFetcher {
+		headers: Vec<block::Header>,
+		checker: LightDataChecker<NativeExecutor<Executor>>,
+	}
+
+	impl Fetcher for HeaderFetcher {
+		type RemoteReadResult = error::Result<Option<Vec<u8>>>;
+		type RemoteCallResult = error::Result<CallResult>;
+		type RemoteHeaderResult = error::Result<block::Header>;
+
+		fn remote_read(&self, _request: RemoteReadRequest) -> Self::RemoteReadResult {
+			Err(error::ErrorKind::RemoteFetchFailed.into())
+		}
+
+		fn remote_call(&self, _request: RemoteCallRequest) -> Self::RemoteCallResult {
+			Err(error::ErrorKind::RemoteFetchFailed.into())
+		}
+
+		fn remote_header(&self, request: RemoteHeaderRequest) -> Self::RemoteHeaderResult {
+			let cht_num = cht::block_to_cht_number(request.block).unwrap();
+			let hashes = self.headers[cht::start_number(cht_num) as usize..cht::end_number(cht_num) as usize + 1].iter()
+				.map(|header| header.blake2_256().into());
+			let proof = cht::build_proof(cht_num, request.block, hashes).unwrap();
+			self.checker.check_header_proof(&request, Some(self.headers[request.block as usize].clone()), proof)
+		}
+	}
+
+	#[test]
+	fn headers_of_finalized_chts_are_fetched_with_proof() {
+		let mut headers = vec![block::Header::from_block_number(0)];
+		for number in 1..cht::SIZE + 2 {
+			let mut header = block::Header::from_block_number(number);
+			header.parent_hash = headers[number as usize - 1].blake2_256().into();
+			headers.push(header);
+		}
+		let backend = Backend::new(Arc::new(HeaderFetcher {
+			headers: headers.clone(),
+			checker: LightDataChecker::new(Executor::new()),
+		}));
+		for header in &headers {
+			let parent = match header.number {
+				0 => BlockId::Hash(Default::default()),
+				_ => BlockId::Hash(header.parent_hash),
+			};
+			let mut op = backend.begin_operation(parent).unwrap();
+			op.set_block_data(header.clone(), None, None, true).unwrap();
+			backend.commit_operation(op).unwrap();
+		}
+
+		backend.finalize_block(BlockId::Number(cht::SIZE + 1)).unwrap();
+		let chain = backend.blockchain();
+		assert!(chain.storage.header(BlockId::Number(1)).unwrap().is_none());
+		assert_eq!(chain.header(BlockId::Number(1)).unwrap(), Some(headers[1].clone()));
+		assert_eq!(chain.header(BlockId::Number(cht::SIZE)).unwrap(), Some(headers[cht::SIZE as usize].clone()));
+		assert_eq!(chain.header(BlockId::Number(cht::SIZE + 1)).unwrap(), Some(headers[cht::SIZE as usize + 1].clone()));
+		assert_eq!(chain.header(BlockId::Number(cht::SIZE + 2)).unwrap(), None);
+	}
+
+	#[test]
+	fn remote_request_times_out() {
+		let error = wait_remote(future::empty::<(), error::Error>(), Duration::from_millis(10)).unwrap_err();
+		assert!(match *error.kind() {
+			error::ErrorKind::RemoteFetchTimedOut => true,
+			_ => false,
+		});
+	}
+
+	#[test]
+	fn remote_response_wakes_up_waiting_thread() {
+		let (sender, receiver) = oneshot::channel();
+		thread::spawn(move || {
+			thread::sleep(Duration::from_millis(10));
+			sender.send(42).unwrap();
+		});
+		let response = receiver.map_err(|_| error::Error::from(error::ErrorKind::RemoteFetchCancelled));
+		assert_eq!(wait_remote(response, Duration::from_secs(10)).unwrap(), 42);
+	}
+}
@@ -20,6 +20,15 @@ use rstd::prelude::*;
 use rstd::{mem, slice};
 use super::joiner::Joiner;
 
+// Decoded vectors reserve at most this many bytes up front: the length prefix is
+// untrusted, so they grow further only as their items are actually read.
+const MAX_PREALLOCATION: usize = 4 * 1024;
+
+// Capacity to reserve for a vector with the given decoded length.
+fn capacity_for<T>(len: u32) -> usize {
+	::rstd::cmp::min(len as usize, MAX_PREALLOCATION / ::rstd::cmp::max(mem::size_of::<T>(), 1))
+}
+
 /// Trait that allows reading of data into a slice.
 pub trait Input {
 	/// Read into the provided input slice. Returns the number of bytes read.
@@ -89,12 +98,16 @@ impl Slicable for Vec<u8> {
 	fn decode<I: Input>(input: &mut I) -> Option<Self> {
 		u32::decode(input).and_then(move |len| {
 			let len = len as usize;
-			let mut vec = vec![0; len];
-			if input.read(&mut vec[..len]) != len {
-				None
-			} else {
-				Some(vec)
+			let mut vec = Vec::new();
+			while vec.len() < len {
+				let read = vec.len();
+				let chunk = ::rstd::cmp::min(len - read, MAX_PREALLOCATION);
+				vec.resize(read + chunk, 0);
+				if input.read(&mut vec[read..]) != chunk {
+					return None;
+				}
 			}
+			Some(vec)
 		})
 	}
 
@@ -112,8 +125,7 @@ impl Slicable for Vec<u8> {
 impl Slicable for Vec<u64> {
 	fn decode<I: Input>(input: &mut I) -> Option<Self> {
 		u32::decode(input).and_then(move |len| {
-			let len = len as usize;
-			let mut vec = Vec::with_capacity(len);
+			let mut vec = Vec::with_capacity(capacity_for::<u64>(len));
 			for _ in 0..len {
 				vec.push(u64::decode(input)?);
 			}
@@ -153,7 +165,7 @@ macro_rules! impl_vec_simple_array {
 			{
 				fn decode<I: Input>(input: &mut I) -> Option<Self> {
 					u32::decode(input).and_then(move |len| {
-						let mut r = Vec::with_capacity(len as usize);
+						let mut r = Vec::with_capacity(capacity_for::<[T; $size]>(len));
 						for _ in 0..len {
 							r.push(match Slicable::decode(input) {
 								Some(x) => x,
@@ -193,7 +205,7 @@ impl<T: Slicable> NonTrivialSlicable for Vec<T> where Vec<T>: Slicable {}
 impl<T: NonTrivialSlicable> Slicable for Vec<T> {
 	fn decode<I: Input>(input: &mut I) -> Option<Self> {
 		u32::decode(input).and_then(move |len| {
-			let mut r = Vec::with_capacity(len as usize);
+			let mut r = Vec::with_capacity(capacity_for::<T>(len));
 			for _ in 0..len {
 				r.push(match T::decode(input) {
 					Some(x) => x,
@@ -415,4 +427,19 @@ mod tests {
 			assert_eq!(slice, &b"\x0b\0\0\0Hello world")
 		);
 	}
+
+	#[test]
+	fn vec_longer_than_input_is_rejected() {
+		let mut encoded = u32::max_value().encode();
+		encoded.extend(&[1, 2, 3]);
+		assert_eq!(<Vec<u8>>::decode(&mut &encoded[..]), None);
+		assert_eq!(<Vec<u64>>::decode(&mut &encoded[..]), None);
+		assert_eq!(<Vec<Vec<u8>>>::decode(&mut &encoded[..]), None);
+	}
+
+	#[test]
+	fn long_vec_is_decoded() {
+		let v: Vec<u8> = (0..3 * MAX_PREALLOCATION + 1).map(|i| i as u8).collect();
+		assert_eq!(<Vec<u8>>::decode(&mut &v.encode()[..]), Some(v));
+	}
 }
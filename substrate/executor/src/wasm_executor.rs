@@ -23,6 +23,7 @@ use parity_wasm::{deserialize_buffer, ModuleInstanceInterface, ProgramInstance};
 use parity_wasm::interpreter::{ItemIndex, DummyUserError};
 use parity_wasm::RuntimeValue::{I32, I64};
 use state_machine::{Externalities, CodeExecutor};
+use codec::Slicable;
 use error::{Error, ErrorKind, Result};
 use wasm_utils::{MemoryInstance, UserDefinedElements,
 	AddModuleWithoutFullDependentInstance};
@@ -193,6 +194,33 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 		}
 		this.ext.clear_storage(&key);
 	},
+	ext_clear_prefix(prefix_data: *const u8, prefix_len: u32) => {
+		let prefix = this.memory.get(prefix_data, prefix_len as usize).map_err(|_| DummyUserError)?;
+		info!(target: "wasm-trace", "*** Clearing prefix:  {}   [k={}]", ascii_format(&prefix), HexDisplay::from(&prefix));
+		this.ext.clear_prefix(&prefix);
+	},
+	// return 0 and place u32::max_value() into written_out if there is no key after the given one.
+	ext_next_key(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8 => {
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| DummyUserError)?;
+		if let Some(next) = this.ext.next_key(&key) {
+			let offset = this.heap.allocate(next.len() as u32) as u32;
+			this.memory.set(offset, &next).map_err(|_| DummyUserError)?;
+			this.memory.write_primitive(written_out, next.len() as u32)?;
+			offset
+		} else {
+			this.memory.write_primitive(written_out, u32::max_value())?;
+			0
+		}
+	},
+	// return the encoded `Vec<Vec<u8>>` of the keys starting with the prefix, placing its length into written_out.
+	ext_keys_with_prefix(prefix_data: *const u8, prefix_len: u32, written_out: *mut u32) -> *mut u8 => {
+		let prefix = this.memory.get(prefix_data, prefix_len as usize).map_err(|_| DummyUserError)?;
+		let keys = this.ext.keys_with_prefix(&prefix).encode();
+		let offset = this.heap.allocate(keys.len() as u32) as u32;
+		this.memory.set(offset, &keys).map_err(|_| DummyUserError)?;
+		this.memory.write_primitive(written_out, keys.len() as u32)?;
+		offset
+	},
 	// return 0 and place u32::max_value() into written_out if no value exists for the key.
 	ext_get_allocated_storage(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8 => {
 		let key = this.memory.get(key_data, key_len as usize).map_err(|_| DummyUserError)?;
@@ -214,6 +242,52 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 			0
 		}
 	},
+	// return 0 and place u32::max_value() into written_out if no value exists for the key.
+	ext_get_allocated_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8 => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| DummyUserError)?;
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| DummyUserError)?;
+		let maybe_value = this.ext.child_storage(&storage_key, &key);
+		info!(target: "wasm-trace", "    Getting child storage: {} / {} == {}   [k={}]", ascii_format(&storage_key), ascii_format(&key), if let Some(ref b) = maybe_value { format!("{}", HexDisplay::from(b)) } else { "<empty>".to_owned() }, HexDisplay::from(&key));
+
+		if let Some(value) = maybe_value {
+			let offset = this.heap.allocate(value.len() as u32) as u32;
+			this.memory.set(offset, &value).map_err(|_| DummyUserError)?;
+			this.memory.write_primitive(written_out, value.len() as u32)?;
+			offset
+		} else {
+			this.memory.write_primitive(written_out, u32::max_value())?;
+			0
+		}
+	},
+	ext_set_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32) => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| DummyUserError)?;
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| DummyUserError)?;
+		let value = this.memory.get(value_data, value_len as usize).map_err(|_| DummyUserError)?;
+		info!(target: "wasm-trace", "*** Setting child storage: {} / {} -> {}   [k={}]", ascii_format(&storage_key), ascii_format(&key), HexDisplay::from(&value), HexDisplay::from(&key));
+		this.ext.set_child_storage(storage_key, key, value);
+	},
+	ext_clear_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32) => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| DummyUserError)?;
+		let key = this.memory.get(key_data, key_len as usize).map_err(|_| DummyUserError)?;
+		info!(target: "wasm-trace", "*** Clearing child storage: {} / {}   [k={}]", ascii_format(&storage_key), ascii_format(&key), HexDisplay::from(&key));
+		this.ext.clear_child_storage(&storage_key, &key);
+	},
+	ext_kill_child_storage(storage_key_data: *const u8, storage_key_len: u32) => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| DummyUserError)?;
+		info!(target: "wasm-trace", "*** Killing child storage: {}", ascii_format(&storage_key));
+		this.ext.kill_child_storage(&storage_key);
+	},
+	// return 0 if the child trie is empty, in which case nothing is written into result.
+	ext_child_storage_root(storage_key_data: *const u8, storage_key_len: u32, result: *mut u8) -> u32 => {
+		let storage_key = this.memory.get(storage_key_data, storage_key_len as usize).map_err(|_| DummyUserError)?;
+		match this.ext.child_storage_root(&storage_key) {
+			Some(r) => {
+				this.memory.set(result, &r[..]).map_err(|_| DummyUserError)?;
+				1
+			},
+			None => 0,
+		}
+	},
 	// return u32::max_value() if no value exists for the key.
 	ext_get_storage_into(key_data: *const u8, key_len: u32, value_data: *mut u8, value_len: u32, value_offset: u32) -> u32 => {
 		let key = this.memory.get(key_data, key_len as usize).map_err(|_| DummyUserError)?;
@@ -253,6 +327,15 @@ impl_function_executor!(this: FunctionExecutor<'e, E>,
 	ext_chain_id() -> u64 => {
 		this.ext.chain_id()
 	},
+	ext_start_transaction() => {
+		this.ext.start_transaction();
+	},
+	ext_commit_transaction() => {
+		this.ext.commit_transaction().map_err(|_| DummyUserError)?;
+	},
+	ext_rollback_transaction() => {
+		this.ext.rollback_transaction().map_err(|_| DummyUserError)?;
+	},
 	ext_twox_128(data: *const u8, len: u32, out: *mut u8) => {
 		let result = if len == 0 {
 			let hashed = twox_128(&[0u8; 0]);
@@ -401,7 +484,7 @@ mod tests {
 			b"foo".to_vec() => b"bar".to_vec(),
 			b"baz".to_vec() => b"bar".to_vec()
 		];
-		assert_eq!(expected, ext);
+		assert_eq!(expected, *ext);
 	}
 
 	#[test]
@@ -16,7 +16,9 @@
 
 //! Blockchain access trait
 
-use client::{self, Client as PolkadotClient, ImportResult, ClientInfo, BlockStatus};
+use client::{self, Client as PolkadotClient, ImportResult, ClientInfo, BlockStatus, ImportNotifications};
+use client::backend::Backend;
+use client::blockchain::{self, Backend as ChainBackend};
 use client::error::Error;
 use state_machine;
 use primitives::block::{self, Id as BlockId};
@@ -43,17 +45,37 @@ pub trait Client: Send + Sync {
 
 	/// Get block justification.
 	fn justification(&self, id: &BlockId) -> Result<Option<Justification>, Error>;
+
+	/// Get a stream of blocks which became the new best block.
+	fn best_block_notification_stream(&self) -> ImportNotifications;
+
+	/// Get storage read proof for the key at the given block.
+	fn read_proof(&self, block: &block::HeaderHash, key: &[u8]) -> Result<Vec<Vec<u8>>, Error>;
+
+	/// Get method execution proof at the given block.
+	fn execution_proof(&self, block: &block::HeaderHash, method: &str, data: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>), Error>;
+
+	/// Get header of the block with given number along with its CHT proof.
+	fn header_proof(&self, block: block::Number) -> Result<(block::Header, Vec<Vec<u8>>), Error>;
 }
 
 impl<B, E> Client for PolkadotClient<B, E> where
 	B: client::backend::Backend + Send + Sync + 'static,
-	E: state_machine::CodeExecutor + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
 	Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>, {
 
 	fn import(&self, header: block::Header, justification: Justification, body: Option<block::Body>) -> Result<ImportResult, Error> {
 		// TODO: defer justification check.
+		let (hash, number) = (header.hash(), header.number);
 		let justified_header = self.check_justification(header, justification.into())?;
-		(self as &PolkadotClient<B, E>).import_block(justified_header, body)
+		let result = (self as &PolkadotClient<B, E>).import_block(justified_header, body)?;
+		// justified blocks of the best chain are final.
+		if let ImportResult::Imported = result {
+			if (self as &PolkadotClient<B, E>).block_hash(number)? == Some(hash) {
+				self.finalize_block(BlockId::Hash(hash))?;
+			}
+		}
+		Ok(result)
 	}
 
 	fn info(&self) -> Result<ClientInfo, Error> {
@@ -69,7 +91,13 @@ impl<B, E> Client for PolkadotClient<B, E> where
 	}
 
 	fn header(&self, id: &BlockId) -> Result<Option<block::Header>, Error> {
-		(self as &PolkadotClient<B, E>).header(id)
+		let client = self as &PolkadotClient<B, E>;
+		// light clients fetch the headers they have pruned from remote nodes, which must
+		// never happen on the network thread. Only the headers they keep are served.
+		if client.backend().is_light() && client.backend().blockchain().status(*id)? == blockchain::BlockStatus::Unknown {
+			return Ok(None);
+		}
+		client.header(id)
 	}
 
 	fn body(&self, id: &BlockId) -> Result<Option<block::Body>, Error> {
@@ -79,4 +107,20 @@ impl<B, E> Client for PolkadotClient<B, E> where
 	fn justification(&self, id: &BlockId) -> Result<Option<Justification>, Error> {
 		(self as &PolkadotClient<B, E>).justification(id)
 	}
+
+	fn best_block_notification_stream(&self) -> ImportNotifications {
+		(self as &PolkadotClient<B, E>).best_block_notification_stream()
+	}
+
+	fn read_proof(&self, block: &block::HeaderHash, key: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+		(self as &PolkadotClient<B, E>).read_proof(&BlockId::Hash(*block), &[key.to_vec()])
+	}
+
+	fn execution_proof(&self, block: &block::HeaderHash, method: &str, data: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>), Error> {
+		(self as &PolkadotClient<B, E>).execution_proof(&BlockId::Hash(*block), method, data)
+	}
+
+	fn header_proof(&self, block: block::Number) -> Result<(block::Header, Vec<Vec<u8>>), Error> {
+		(self as &PolkadotClient<B, E>).header_proof(block)
+	}
 }
@@ -14,18 +14,24 @@
 // You should have received a copy of the GNU General Public License
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
 
+use std::sync::Arc;
+use ed25519;
 use service::Role;
 
 /// Protocol configuration
 #[derive(Clone)]
 pub struct ProtocolConfig {
 	pub roles: Role,
+	/// Authority key of the local validator. Its public key is advertised to the
+	/// peers in a signed `Status` so that consensus messages are routed to it.
+	pub validator_key: Option<Arc<ed25519::Pair>>,
 }
 
 impl Default for ProtocolConfig {
 	fn default() -> ProtocolConfig {
 		ProtocolConfig {
 			roles: Role::FULL,
+			validator_key: None,
 		}
 	}
 }
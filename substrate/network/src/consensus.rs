@@ -0,0 +1,399 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
+
+//! BFT consensus messages gossip. Relays the messages of the current round to
+//! the authorities and delivers the checked ones to the local agreement.
+
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::Weak;
+use std::time::Instant;
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use futures::sync::mpsc;
+use bft;
+use codec::Slicable;
+use ed25519;
+use primitives::AuthorityId;
+use primitives::block::HeaderHash;
+use primitives::hashing::blake2_256;
+use network::PeerId;
+
+use io::SyncIo;
+use message::{self, Message};
+use protocol::Protocol;
+use reputation::Misbehavior;
+use service::Role;
+
+// Messages of the past rounds are kept this long to answer the late peers.
+const MESSAGE_LIFETIME_SEC: u64 = 60;
+// Messages of the other rounds are only kept up to this many per peer until their round is started.
+pub const MAX_PENDING_MESSAGES_PER_PEER: usize = 256;
+
+/// Network link used to gossip the local BFT messages.
+pub trait BftNetwork: Send + Sync {
+	/// Gossip a local message of the round built on top of the given block.
+	fn send_bft_message(&self, parent_hash: HeaderHash, message: bft::Communication);
+}
+
+/// Stream of checked BFT messages of a round. Ends when another round is started.
+pub struct BftMessageStream<E> {
+	inner: mpsc::UnboundedReceiver<bft::Communication>,
+	_marker: PhantomData<E>,
+}
+
+/// Sink gossiping the local BFT messages of a round.
+pub struct BftSink<E> {
+	network: Weak<BftNetwork>,
+	parent_hash: HeaderHash,
+	_marker: PhantomData<E>,
+}
+
+struct PeerConsensus {
+	validator_id: Option<AuthorityId>,
+	known_messages: HashSet<HeaderHash>,
+}
+
+// the round the local node takes part in.
+struct Round {
+	parent_hash: HeaderHash,
+	authorities: Vec<AuthorityId>,
+	sink: mpsc::UnboundedSender<bft::Communication>,
+}
+
+struct StoredMessage {
+	hash: HeaderHash,
+	timestamp: Instant,
+	// the peer the message was received from, `None` for the local messages.
+	from: Option<PeerId>,
+	message: message::LocalizedBftMessage,
+}
+
+/// BFT messages gossip. Only validators take part in it.
+pub struct Consensus {
+	peers: HashMap<PeerId, PeerConsensus>,
+	round: Option<Round>,
+	messages: Vec<StoredMessage>,
+	message_hashes: HashSet<HeaderHash>,
+}
+
+impl<E> BftMessageStream<E> {
+	fn new(inner: mpsc::UnboundedReceiver<bft::Communication>) -> Self {
+		BftMessageStream {
+			inner,
+			_marker: PhantomData,
+		}
+	}
+}
+
+impl<E> Stream for BftMessageStream<E> {
+	type Item = bft::Communication;
+	type Error = E;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		match self.inner.poll() {
+			Ok(result) => Ok(result),
+			// the receiver never fails.
+			Err(_) => Ok(Async::Ready(None)),
+		}
+	}
+}
+
+impl<E> BftSink<E> {
+	/// Create a sink for the local messages of the round built on top of the given block.
+	pub fn new(network: Weak<BftNetwork>, parent_hash: HeaderHash) -> Self {
+		BftSink {
+			network,
+			parent_hash,
+			_marker: PhantomData,
+		}
+	}
+}
+
+impl<E> Sink for BftSink<E> {
+	type SinkItem = bft::Communication;
+	type SinkError = E;
+
+	fn start_send(&mut self, message: bft::Communication) -> StartSend<bft::Communication, E> {
+		// messages are dropped once the network is gone.
+		if let Some(network) = self.network.upgrade() {
+			network.send_bft_message(self.parent_hash, message);
+		}
+		Ok(AsyncSink::Ready)
+	}
+
+	fn poll_complete(&mut self) -> Poll<(), E> {
+		Ok(Async::Ready(()))
+	}
+}
+
+impl Consensus {
+	/// Create a new instance.
+	pub fn new() -> Consensus {
+		Consensus {
+			peers: HashMap::new(),
+			round: None,
+			messages: Vec::new(),
+			message_hashes: HashSet::new(),
+		}
+	}
+
+	/// Handle new connected peer. Validators are sent the messages of the current round.
+	pub fn new_peer(&mut self, io: &mut SyncIo, protocol: &Protocol, peer_id: PeerId, roles: Role, validator_id: Option<AuthorityId>) {
+		if !roles.contains(Role::VALIDATOR) {
+			return;
+		}
+
+		trace!(target: "bft", "Registering validator {}", peer_id);
+		let mut peer = PeerConsensus {
+			validator_id,
+			known_messages: HashSet::new(),
+		};
+		if let Some(ref round) = self.round {
+			if is_round_authority(round, &peer) {
+				for stored in self.messages.iter().filter(|m| m.message.parent_hash == round.parent_hash) {
+					peer.known_messages.insert(stored.hash);
+					protocol.send_message(io, peer_id, Message::BftMessage(stored.message.clone()));
+				}
+			}
+		}
+		self.peers.insert(peer_id, peer);
+	}
+
+	/// Handle disconnected peer.
+	pub fn peer_disconnected(&mut self, peer_id: PeerId) {
+		self.peers.remove(&peer_id);
+	}
+
+	/// Handle received BFT message. Messages of the current round are checked,
+	/// delivered and relayed; messages of the other rounds are kept until their
+	/// round is started, up to a limit per peer.
+	pub fn on_bft_message(&mut self, io: &mut SyncIo, protocol: &Protocol, peer_id: PeerId, message: message::LocalizedBftMessage) {
+		let hash = hash_message(&message);
+		match self.peers.get_mut(&peer_id) {
+			Some(peer) => {
+				peer.known_messages.insert(hash);
+			},
+			None => {
+				trace!(target: "bft", "Ignoring BFT message from unregistered peer {}", peer_id);
+				return;
+			},
+		}
+
+		if !self.message_hashes.insert(hash) {
+			trace!(target: "bft", "Ignoring already known BFT message from {}", peer_id);
+			return;
+		}
+
+		let is_current_round = self.round.as_ref().map_or(false, |round| round.parent_hash == message.parent_hash);
+		if is_current_round {
+			if let Err(e) = self.deliver(&message) {
+				trace!(target: "bft", "Invalid BFT message from {}: {}", peer_id, e);
+				protocol.report_peer(io, peer_id, Misbehavior::InvalidConsensusMessage);
+				self.message_hashes.remove(&hash);
+				return;
+			}
+
+			self.propagate(io, protocol, hash, &message);
+		} else if self.pending_messages_from(peer_id) >= MAX_PENDING_MESSAGES_PER_PEER {
+			trace!(target: "bft", "Dropping BFT message ahead of round from {}: too many pending messages", peer_id);
+			self.message_hashes.remove(&hash);
+			return;
+		}
+
+		self.messages.push(StoredMessage {
+			hash,
+			timestamp: Instant::now(),
+			from: Some(peer_id),
+			message,
+		});
+	}
+
+	/// Start the round built on top of the given block with the given authorities,
+	/// replacing the current one. Returns the stream of checked messages of the
+	/// round. Messages received ahead of the round are delivered as well, but not
+	/// relayed.
+	pub fn start_round<E>(&mut self, parent_hash: HeaderHash, authorities: Vec<AuthorityId>) -> BftMessageStream<E> {
+		let (sink, stream) = mpsc::unbounded();
+		self.round = Some(Round {
+			parent_hash,
+			authorities,
+			sink,
+		});
+
+		for stored in mem::replace(&mut self.messages, Vec::new()) {
+			if stored.message.parent_hash == parent_hash {
+				if let Err(e) = self.deliver(&stored.message) {
+					trace!(target: "bft", "Dropping invalid BFT message: {}", e);
+					self.message_hashes.remove(&stored.hash);
+					continue;
+				}
+			}
+			self.messages.push(stored);
+		}
+
+		BftMessageStream::new(stream)
+	}
+
+	/// Gossip a local message of the round built on top of the given block.
+	pub fn send_bft_message(&mut self, io: &mut SyncIo, protocol: &Protocol, parent_hash: HeaderHash, message: bft::Communication) {
+		let message = message::LocalizedBftMessage {
+			parent_hash,
+			message: to_wire_message(message),
+		};
+		let hash = hash_message(&message);
+		if !self.message_hashes.insert(hash) {
+			return;
+		}
+
+		self.propagate(io, protocol, hash, &message);
+		self.messages.push(StoredMessage {
+			hash,
+			timestamp: Instant::now(),
+			from: None,
+			message,
+		});
+	}
+
+	/// Drop the outdated messages. Messages of the current round are kept.
+	pub fn collect_garbage(&mut self) {
+		let now = Instant::now();
+		let current_parent = self.round.as_ref().map(|round| round.parent_hash);
+		self.messages.retain(|stored| Some(stored.message.parent_hash) == current_parent
+			|| now.duration_since(stored.timestamp).as_secs() < MESSAGE_LIFETIME_SEC);
+		self.message_hashes = self.messages.iter().map(|stored| stored.hash).collect();
+
+		let message_hashes = &self.message_hashes;
+		for peer in self.peers.values_mut() {
+			peer.known_messages.retain(|hash| message_hashes.contains(hash));
+		}
+	}
+
+	/// Forget all peers.
+	pub fn clear(&mut self) {
+		self.peers.clear();
+	}
+
+	// number of the stored messages of the other rounds received from the peer.
+	fn pending_messages_from(&self, peer_id: PeerId) -> usize {
+		let current_parent = self.round.as_ref().map(|round| round.parent_hash);
+		self.messages.iter()
+			.filter(|stored| stored.from == Some(peer_id) && Some(stored.message.parent_hash) != current_parent)
+			.count()
+	}
+
+	// check the message of the current round and pass it to the local agreement.
+	fn deliver(&self, message: &message::LocalizedBftMessage) -> Result<(), bft::Error> {
+		let round = self.round.as_ref().expect("only called for the messages of the current round; qed");
+		let communication = check_message(&message.message, &round.parent_hash, &round.authorities)?;
+		// the agreement may have concluded already.
+		let _ = round.sink.unbounded_send(communication);
+		Ok(())
+	}
+
+	// send the message to the validators which don't know it yet. Messages of the
+	// current round are only sent to its authorities.
+	fn propagate(&mut self, io: &mut SyncIo, protocol: &Protocol, hash: HeaderHash, message: &message::LocalizedBftMessage) {
+		let round = match self.round {
+			Some(ref round) if round.parent_hash == message.parent_hash => Some(round),
+			_ => None,
+		};
+		for (peer_id, peer) in self.peers.iter_mut() {
+			let is_target = round.map_or(true, |round| is_round_authority(round, peer));
+			if is_target && peer.known_messages.insert(hash) {
+				trace!(target: "bft", "Sending BFT message to {}", peer_id);
+				protocol.send_message(io, *peer_id, Message::BftMessage(message.clone()));
+			}
+		}
+	}
+}
+
+fn is_round_authority(round: &Round, peer: &PeerConsensus) -> bool {
+	peer.validator_id.map_or(false, |id| round.authorities.contains(&id))
+}
+
+fn hash_message(message: &message::LocalizedBftMessage) -> HeaderHash {
+	blake2_256(&message.encode()).into()
+}
+
+fn localized_signature(sender: AuthorityId, signature: ::primitives::Signature) -> ed25519::LocalizedSignature {
+	ed25519::LocalizedSignature {
+		signer: ed25519::Public(sender),
+		signature,
+	}
+}
+
+// check the signatures of a received message and convert it for the agreement.
+fn check_message(message: &message::BftMessage, parent_hash: &HeaderHash, authorities: &[AuthorityId]) -> Result<bft::Communication, bft::Error> {
+	match *message {
+		message::BftMessage::Propose(ref propose) => {
+			let proposal = bft::LocalizedProposal {
+				round_number: propose.round_number as usize,
+				proposal: propose.proposal.clone(),
+				digest: propose.digest,
+				sender: propose.sender,
+				digest_signature: localized_signature(propose.sender, propose.digest_signature),
+				full_signature: localized_signature(propose.sender, propose.full_signature),
+			};
+			bft::check_proposal(authorities, parent_hash, &proposal)?;
+			Ok(bft::generic::Communication::Consensus(bft::generic::LocalizedMessage::Propose(proposal)))
+		},
+		message::BftMessage::Vote(ref vote) => {
+			let vote = bft::LocalizedVote {
+				vote: match vote.vote {
+					message::BftVote::Prepare(r, h) => bft::generic::Vote::Prepare(r as usize, h),
+					message::BftVote::Commit(r, h) => bft::generic::Vote::Commit(r as usize, h),
+					message::BftVote::AdvanceRound(r) => bft::generic::Vote::AdvanceRound(r as usize),
+				},
+				sender: vote.sender,
+				signature: localized_signature(vote.sender, vote.signature),
+			};
+			bft::check_vote(authorities, parent_hash, &vote)?;
+			Ok(bft::generic::Communication::Consensus(bft::generic::LocalizedMessage::Vote(vote)))
+		},
+		message::BftMessage::Auxiliary(ref justification) => {
+			let justification = bft::check_prepare_justification(authorities, *parent_hash, justification.clone().into())
+				.map_err(|_| bft::Error::from(bft::ErrorKind::InvalidJustification))?;
+			Ok(bft::generic::Communication::Auxiliary(justification))
+		},
+	}
+}
+
+fn to_wire_message(message: bft::Communication) -> message::BftMessage {
+	match message {
+		bft::generic::Communication::Consensus(bft::generic::LocalizedMessage::Propose(proposal)) =>
+			message::BftMessage::Propose(message::SignedBftProposal {
+				round_number: proposal.round_number as u32,
+				proposal: proposal.proposal,
+				digest: proposal.digest,
+				sender: proposal.sender,
+				digest_signature: proposal.digest_signature.signature,
+				full_signature: proposal.full_signature.signature,
+			}),
+		bft::generic::Communication::Consensus(bft::generic::LocalizedMessage::Vote(vote)) =>
+			message::BftMessage::Vote(message::SignedBftVote {
+				vote: match vote.vote {
+					bft::generic::Vote::Prepare(r, h) => message::BftVote::Prepare(r as u32, h),
+					bft::generic::Vote::Commit(r, h) => message::BftVote::Commit(r as u32, h),
+					bft::generic::Vote::AdvanceRound(r) => message::BftVote::AdvanceRound(r as u32),
+				},
+				sender: vote.sender,
+				signature: vote.signature.signature,
+			}),
+		bft::generic::Communication::Auxiliary(justification) =>
+			message::BftMessage::Auxiliary(justification.uncheck().into()),
+	}
+}
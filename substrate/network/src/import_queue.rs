@@ -0,0 +1,274 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Blocks import queue. Downloaded blocks are verified and imported into the
+//! chain apart from the sync, which keeps downloading meanwhile. The outcome of
+//! each import is reported back to the sync.
+
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::Arc;
+use std::thread;
+use parking_lot::{Condvar, Mutex};
+use client::ImportResult;
+use client::error::{Error as ClientError, ErrorKind as ClientErrorKind};
+use network::PeerId;
+use primitives::block::{HeaderHash, Number as BlockNumber};
+
+use blocks::BlockData;
+use chain::Client;
+
+/// Outcome of a block import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportOutcome {
+	/// The block has been imported.
+	Imported,
+	/// The block is already known.
+	Known,
+	/// The block is incomplete or known to be bad.
+	Bad,
+	/// The block parent is unknown.
+	UnknownParent,
+	/// The block could not be imported because of a local error.
+	Error,
+}
+
+/// Report of a block import.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+	/// The peer the block was downloaded from.
+	pub origin: PeerId,
+	/// Block hash.
+	pub hash: HeaderHash,
+	/// Block number.
+	pub number: BlockNumber,
+	/// Import outcome.
+	pub outcome: ImportOutcome,
+}
+
+/// Import queue status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImportQueueStatus {
+	/// Number of blocks waiting for import.
+	pub importing_count: usize,
+}
+
+/// Blocks import queue.
+pub trait ImportQueue: Send + Sync {
+	/// Queue blocks for import. The blocks must be in ascending order.
+	fn import_blocks(&self, blocks: Vec<BlockData>);
+	/// Take the reports of the imports completed since the last call.
+	fn take_reports(&self) -> Vec<ImportReport>;
+	/// Drop the blocks waiting for import.
+	fn clear(&self);
+	/// Check if the block is waiting for import.
+	fn is_importing(&self, hash: &HeaderHash) -> bool;
+	/// Get the queue status.
+	fn status(&self) -> ImportQueueStatus;
+}
+
+#[derive(Default)]
+struct QueueState {
+	blocks: VecDeque<BlockData>,
+	importing: Option<HeaderHash>,
+	reports: Vec<ImportReport>,
+	stopping: bool,
+}
+
+struct QueueData {
+	state: Mutex<QueueState>,
+	signal: Condvar,
+}
+
+/// Import queue which imports the blocks in a background thread.
+pub struct AsyncImportQueue {
+	data: Arc<QueueData>,
+	handle: Option<thread::JoinHandle<()>>,
+}
+
+impl AsyncImportQueue {
+	/// Create a new instance and start the import thread.
+	pub fn new(chain: Arc<Client>) -> Self {
+		let data = Arc::new(QueueData {
+			state: Mutex::new(QueueState::default()),
+			signal: Condvar::new(),
+		});
+		let thread_data = data.clone();
+		let handle = thread::Builder::new()
+			.name("ImportQueue".into())
+			.spawn(move || import_thread(chain, thread_data))
+			.expect("Error starting import queue thread");
+		AsyncImportQueue {
+			data,
+			handle: Some(handle),
+		}
+	}
+}
+
+#[cfg(test)]
+impl AsyncImportQueue {
+	/// Block until all the queued blocks have been imported.
+	pub fn wait_idle(&self) {
+		let mut state = self.data.state.lock();
+		while !state.blocks.is_empty() || state.importing.is_some() {
+			self.data.signal.wait(&mut state);
+		}
+	}
+}
+
+impl ImportQueue for AsyncImportQueue {
+	fn import_blocks(&self, blocks: Vec<BlockData>) {
+		self.data.state.lock().blocks.extend(blocks);
+		self.data.signal.notify_all();
+	}
+
+	fn take_reports(&self) -> Vec<ImportReport> {
+		mem::replace(&mut self.data.state.lock().reports, Vec::new())
+	}
+
+	fn clear(&self) {
+		self.data.state.lock().blocks.clear();
+	}
+
+	fn is_importing(&self, hash: &HeaderHash) -> bool {
+		let state = self.data.state.lock();
+		state.importing.as_ref() == Some(hash) || state.blocks.iter().any(|b| b.block.hash == *hash)
+	}
+
+	fn status(&self) -> ImportQueueStatus {
+		let state = self.data.state.lock();
+		ImportQueueStatus {
+			importing_count: state.blocks.len() + if state.importing.is_some() { 1 } else { 0 },
+		}
+	}
+}
+
+impl Drop for AsyncImportQueue {
+	fn drop(&mut self) {
+		self.data.state.lock().stopping = true;
+		self.data.signal.notify_all();
+		if let Some(handle) = self.handle.take() {
+			let _ = handle.join();
+		}
+	}
+}
+
+fn import_thread(chain: Arc<Client>, data: Arc<QueueData>) {
+	loop {
+		let block = {
+			let mut state = data.state.lock();
+			loop {
+				if state.stopping {
+					return;
+				}
+				if let Some(block) = state.blocks.pop_front() {
+					state.importing = Some(block.block.hash);
+					break block;
+				}
+				data.signal.wait(&mut state);
+			}
+		};
+
+		let report = import_block(&*chain, block);
+		let mut state = data.state.lock();
+		state.importing = None;
+		if !is_success(report.outcome) {
+			// the descendants of the block can't be imported either.
+			state.blocks.clear();
+		}
+		state.reports.push(report);
+		data.signal.notify_all();
+	}
+}
+
+/// Import queue which imports the blocks right away.
+#[cfg(test)]
+pub struct SyncImportQueue {
+	chain: Arc<Client>,
+	reports: Mutex<Vec<ImportReport>>,
+}
+
+#[cfg(test)]
+impl SyncImportQueue {
+	/// Create a new instance.
+	pub fn new(chain: Arc<Client>) -> Self {
+		SyncImportQueue {
+			chain,
+			reports: Mutex::new(Vec::new()),
+		}
+	}
+}
+
+#[cfg(test)]
+impl ImportQueue for SyncImportQueue {
+	fn import_blocks(&self, blocks: Vec<BlockData>) {
+		let mut reports = self.reports.lock();
+		for block in blocks {
+			let report = import_block(&*self.chain, block);
+			let outcome = report.outcome;
+			reports.push(report);
+			if !is_success(outcome) {
+				break;
+			}
+		}
+	}
+
+	fn take_reports(&self) -> Vec<ImportReport> {
+		mem::replace(&mut *self.reports.lock(), Vec::new())
+	}
+
+	fn clear(&self) {}
+
+	fn is_importing(&self, _hash: &HeaderHash) -> bool {
+		false
+	}
+
+	fn status(&self) -> ImportQueueStatus {
+		ImportQueueStatus {
+			importing_count: 0,
+		}
+	}
+}
+
+fn is_success(outcome: ImportOutcome) -> bool {
+	outcome == ImportOutcome::Imported || outcome == ImportOutcome::Known
+}
+
+fn import_block(chain: &Client, block: BlockData) -> ImportReport {
+	let origin = block.origin;
+	let hash = block.block.hash;
+	let (header, justification) = match (block.block.header, block.block.justification) {
+		(Some(header), Some(justification)) => (header, justification),
+		_ => return ImportReport { origin, hash, number: 0, outcome: ImportOutcome::Bad },
+	};
+
+	let number = header.number;
+	let outcome = match chain.import(header, justification, block.block.body) {
+		Ok(ImportResult::Imported) => ImportOutcome::Imported,
+		Ok(ImportResult::AlreadyInChain) => ImportOutcome::Known,
+		Ok(ImportResult::KnownBad) => ImportOutcome::Bad,
+		Ok(ImportResult::UnknownParent) => ImportOutcome::UnknownParent,
+		Err(ClientError(ClientErrorKind::BadJustification(_), _)) => {
+			debug!(target: "sync", "Bad justification for block {}: {:?}", number, hash);
+			ImportOutcome::Bad
+		},
+		Err(e) => {
+			debug!(target: "sync", "Error importing block {}: {:?}: {:?}", number, hash, e);
+			ImportOutcome::Error
+		},
+	};
+	ImportReport { origin, hash, number, outcome }
+}
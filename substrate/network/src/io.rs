@@ -14,7 +14,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
 
-use network::{NetworkContext, PeerId, Error as NetworkError, SessionInfo};
+use network::{NetworkContext, PeerId, NodeId, Error as NetworkError, SessionInfo};
 
 /// IO interface for the syncing handler.
 /// Provides peer connection management and an interface to the blockchain client.
@@ -31,6 +31,10 @@ pub trait SyncIo {
 	}
 	/// Returns information on p2p session
 	fn peer_session_info(&self, peer_id: PeerId) -> Option<SessionInfo>;
+	/// Returns the persistent node id of the peer if known
+	fn peer_node_id(&self, peer_id: PeerId) -> Option<NodeId> {
+		self.peer_session_info(peer_id).and_then(|info| info.id)
+	}
 	/// Check if the session is expired
 	fn is_expired(&self) -> bool;
 }
@@ -22,12 +22,16 @@
 extern crate ethcore_network as network;
 extern crate ethcore_io as core_io;
 extern crate rand;
+extern crate futures;
 extern crate parking_lot;
 extern crate substrate_primitives as primitives;
 extern crate substrate_state_machine as state_machine;
 extern crate substrate_serializer as ser;
 extern crate substrate_client as client;
+extern crate substrate_codec as codec;
 extern crate substrate_runtime_support as runtime_support;
+extern crate substrate_bft as bft;
+extern crate ed25519;
 extern crate serde;
 extern crate serde_json;
 #[macro_use] extern crate serde_derive;
@@ -39,23 +43,29 @@ extern crate serde_json;
 #[cfg(test)] extern crate substrate_test_runtime as test_runtime;
 #[cfg(test)] extern crate substrate_keyring as keyring;
 #[cfg(test)] #[macro_use] extern crate substrate_executor as executor;
-#[cfg(test)] extern crate substrate_codec as codec;
-#[cfg(test)] extern crate substrate_bft as bft;
 
 mod service;
 mod sync;
 mod protocol;
 mod io;
 mod message;
-mod error;
+pub mod error;
 mod config;
 mod chain;
 mod blocks;
+mod import_queue;
+mod on_demand;
+mod consensus;
+mod reputation;
 
 #[cfg(test)] mod test;
 
-pub use service::Service;
-pub use protocol::{ProtocolStatus};
+pub use service::{Service, Params, SyncProvider, ManageNetwork, PeerInfo, Role, TransactionPool};
+pub use protocol::{ProtocolStatus, PeerInfo as ProtocolPeerInfo, TransactionStats};
+pub use config::ProtocolConfig;
+pub use on_demand::{OnDemand, OnDemandNetwork, RemoteResponse};
+pub use consensus::{BftNetwork, BftMessageStream, BftSink};
+pub use reputation::Misbehavior;
 pub use sync::{Status as SyncStatus, SyncState};
 pub use network::{NonReservedPeerMode, ConnectionFilter, ConnectionDirection, NetworkConfiguration};
 
@@ -12,20 +12,24 @@
 // GNU General Public License for more details.
 
 // You should have received a copy of the GNU General Public License
-// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
 
 //! Network packet message types. These get serialized and put into the lower level protocol payload.
+//!
+//! Peers speaking protocol version 1 and above exchange messages in the binary `Slicable`
+//! encoding. The `Status` handshake and all messages to older peers are encoded as JSON.
 
 use std::borrow::Borrow;
+use codec::{Slicable, NonTrivialSlicable, Input};
 use primitives::AuthorityId;
-use primitives::block::{Number as BlockNumber, HeaderHash, Header, Body};
+use primitives::block::{Number as BlockNumber, HeaderHash, Header, Body, Block};
 use primitives::bft::Justification;
 use service::Role as RoleFlags;
 
 pub type RequestId = u64;
 type Bytes = Vec<u8>;
 
-type Signature = ::primitives::hash::H256; //TODO:
+type Signature = ::primitives::Signature;
 
 /// Configured node role.
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -137,6 +141,22 @@ pub enum Message {
 	BlockResponse(BlockResponse),
 	/// Block announce.
 	BlockAnnounce(BlockAnnounce),
+	/// Remote storage read request.
+	RemoteReadRequest(RemoteReadRequest),
+	/// Remote storage read response.
+	RemoteReadResponse(RemoteReadResponse),
+	/// Remote method call request.
+	RemoteCallRequest(RemoteCallRequest),
+	/// Remote method call response.
+	RemoteCallResponse(RemoteCallResponse),
+	/// Remote header request.
+	RemoteHeaderRequest(RemoteHeaderRequest),
+	/// Remote header response.
+	RemoteHeaderResponse(RemoteHeaderResponse),
+	/// BFT consensus message.
+	BftMessage(LocalizedBftMessage),
+	/// Encoded transactions.
+	Transactions(Vec<Bytes>),
 }
 
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -151,7 +171,7 @@ pub struct Status {
 	pub best_hash: HeaderHash,
 	/// Genesis block hash.
 	pub genesis_hash: HeaderHash,
-	/// Signatue of `best_hash` made with validator address. Required for the validator role.
+	/// Signature of the sender node id made with the validator key. Required for the validator role.
 	pub validator_signature: Option<Signature>,
 	/// Validator address. Required for the validator role.
 	pub validator_id: Option<AuthorityId>,
@@ -185,9 +205,670 @@ pub struct BlockResponse {
 	pub blocks: Vec<BlockData>,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 /// Announce a new complete relay chain block on the network.
 pub struct BlockAnnounce {
 	/// New block header.
 	pub header: Header,
 }
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+/// Remote storage read request.
+pub struct RemoteReadRequest {
+	/// Unique request id.
+	pub id: RequestId,
+	/// Block at which to perform the read.
+	pub block: HeaderHash,
+	/// Storage key.
+	pub key: Bytes,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Remote storage read response.
+pub struct RemoteReadResponse {
+	/// Id of a request this response was made for.
+	pub id: RequestId,
+	/// Read proof.
+	pub proof: Vec<Bytes>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+/// Remote method call request.
+pub struct RemoteCallRequest {
+	/// Unique request id.
+	pub id: RequestId,
+	/// Block at which to perform the call.
+	pub block: HeaderHash,
+	/// Method name.
+	pub method: String,
+	/// Call data.
+	pub data: Bytes,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Remote method call response.
+pub struct RemoteCallResponse {
+	/// Id of a request this response was made for.
+	pub id: RequestId,
+	/// Execution proof.
+	pub proof: Vec<Bytes>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+/// Remote header request.
+pub struct RemoteHeaderRequest {
+	/// Unique request id.
+	pub id: RequestId,
+	/// Number of the block to fetch the header of.
+	pub block: BlockNumber,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Remote header response.
+pub struct RemoteHeaderResponse {
+	/// Id of a request this response was made for.
+	pub id: RequestId,
+	/// Header. None if the proof can't be generated.
+	pub header: Option<Header>,
+	/// Proof of the header hash against the CHT root.
+	pub proof: Vec<Bytes>,
+}
+
+/// BFT consensus vote.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum BftVote {
+	/// Prepare to vote for the proposal with given digest.
+	Prepare(u32, HeaderHash),
+	/// Commit to the proposal with given digest.
+	Commit(u32, HeaderHash),
+	/// Propose advancement to a new round.
+	AdvanceRound(u32),
+}
+
+/// Signed BFT proposal.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SignedBftProposal {
+	/// The round number.
+	pub round_number: u32,
+	/// The proposed block.
+	pub proposal: Block,
+	/// The digest of the proposal.
+	pub digest: HeaderHash,
+	/// The sender of the proposal.
+	pub sender: AuthorityId,
+	/// The signature on the message (propose, round number, digest).
+	pub digest_signature: ::primitives::Signature,
+	/// The signature on the message (propose, round number, proposal).
+	pub full_signature: ::primitives::Signature,
+}
+
+/// Signed BFT vote.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SignedBftVote {
+	/// The vote.
+	pub vote: BftVote,
+	/// The sender of the vote.
+	pub sender: AuthorityId,
+	/// The signature of the vote.
+	pub signature: ::primitives::Signature,
+}
+
+/// BFT consensus message.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum BftMessage {
+	/// A proposal.
+	Propose(SignedBftProposal),
+	/// A vote.
+	Vote(SignedBftVote),
+	/// Proof-of-lock for a prepared proposal.
+	Auxiliary(Justification),
+}
+
+/// BFT consensus message of the round built on top of the given block.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LocalizedBftMessage {
+	/// Hash of the block the round is building on.
+	pub parent_hash: HeaderHash,
+	/// The message.
+	pub message: BftMessage,
+}
+
+fn encode_option<T: Slicable>(v: &mut Vec<u8>, value: &Option<T>) {
+	match *value {
+		Some(ref value) => {
+			v.push(1);
+			value.using_encoded(|s| v.extend(s));
+		}
+		None => v.push(0),
+	}
+}
+
+fn decode_option<T: Slicable, I: Input>(input: &mut I) -> Option<Option<T>> {
+	match u8::decode(input)? {
+		0 => Some(None),
+		1 => T::decode(input).map(Some),
+		_ => None,
+	}
+}
+
+impl Slicable for Role {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => Some(Role::Full),
+			1 => Some(Role::Light),
+			2 => Some(Role::Validator),
+			3 => Some(Role::Collator),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		vec![match *self {
+			Role::Full => 0,
+			Role::Light => 1,
+			Role::Validator => 2,
+			Role::Collator => 3,
+		}]
+	}
+}
+
+impl NonTrivialSlicable for Role {}
+
+impl Slicable for BlockAttribute {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => Some(BlockAttribute::Header),
+			1 => Some(BlockAttribute::Body),
+			2 => Some(BlockAttribute::Receipt),
+			3 => Some(BlockAttribute::MessageQueue),
+			4 => Some(BlockAttribute::Justification),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		vec![match *self {
+			BlockAttribute::Header => 0,
+			BlockAttribute::Body => 1,
+			BlockAttribute::Receipt => 2,
+			BlockAttribute::MessageQueue => 3,
+			BlockAttribute::Justification => 4,
+		}]
+	}
+}
+
+impl NonTrivialSlicable for BlockAttribute {}
+
+impl Slicable for BlockData {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(BlockData {
+			hash: Slicable::decode(input)?,
+			header: decode_option(input)?,
+			body: decode_option(input)?,
+			receipt: decode_option(input)?,
+			message_queue: decode_option(input)?,
+			justification: decode_option(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.hash.encode();
+		encode_option(&mut v, &self.header);
+		encode_option(&mut v, &self.body);
+		encode_option(&mut v, &self.receipt);
+		encode_option(&mut v, &self.message_queue);
+		encode_option(&mut v, &self.justification);
+		v
+	}
+}
+
+impl NonTrivialSlicable for BlockData {}
+
+impl Slicable for FromBlock {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => Slicable::decode(input).map(FromBlock::Hash),
+			1 => Slicable::decode(input).map(FromBlock::Number),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		match *self {
+			FromBlock::Hash(ref hash) => {
+				v.push(0);
+				hash.using_encoded(|s| v.extend(s));
+			}
+			FromBlock::Number(ref number) => {
+				v.push(1);
+				number.using_encoded(|s| v.extend(s));
+			}
+		}
+		v
+	}
+}
+
+impl Slicable for Direction {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => Some(Direction::Ascending),
+			1 => Some(Direction::Descending),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		vec![match *self {
+			Direction::Ascending => 0,
+			Direction::Descending => 1,
+		}]
+	}
+}
+
+impl Slicable for Message {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => Slicable::decode(input).map(Message::Status),
+			1 => Slicable::decode(input).map(Message::BlockRequest),
+			2 => Slicable::decode(input).map(Message::BlockResponse),
+			3 => Slicable::decode(input).map(Message::BlockAnnounce),
+			4 => Slicable::decode(input).map(Message::RemoteReadRequest),
+			5 => Slicable::decode(input).map(Message::RemoteReadResponse),
+			6 => Slicable::decode(input).map(Message::RemoteCallRequest),
+			7 => Slicable::decode(input).map(Message::RemoteCallResponse),
+			8 => Slicable::decode(input).map(Message::RemoteHeaderRequest),
+			9 => Slicable::decode(input).map(Message::RemoteHeaderResponse),
+			10 => Slicable::decode(input).map(Message::BftMessage),
+			11 => Slicable::decode(input).map(Message::Transactions),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let (kind, data) = match *self {
+			Message::Status(ref m) => (0u8, m.encode()),
+			Message::BlockRequest(ref m) => (1, m.encode()),
+			Message::BlockResponse(ref m) => (2, m.encode()),
+			Message::BlockAnnounce(ref m) => (3, m.encode()),
+			Message::RemoteReadRequest(ref m) => (4, m.encode()),
+			Message::RemoteReadResponse(ref m) => (5, m.encode()),
+			Message::RemoteCallRequest(ref m) => (6, m.encode()),
+			Message::RemoteCallResponse(ref m) => (7, m.encode()),
+			Message::RemoteHeaderRequest(ref m) => (8, m.encode()),
+			Message::RemoteHeaderResponse(ref m) => (9, m.encode()),
+			Message::BftMessage(ref m) => (10, m.encode()),
+			Message::Transactions(ref m) => (11, m.encode()),
+		};
+		let mut v = Vec::with_capacity(data.len() + 1);
+		v.push(kind);
+		v.extend(data);
+		v
+	}
+}
+
+impl Slicable for Status {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(Status {
+			version: Slicable::decode(input)?,
+			roles: Slicable::decode(input)?,
+			best_number: Slicable::decode(input)?,
+			best_hash: Slicable::decode(input)?,
+			genesis_hash: Slicable::decode(input)?,
+			validator_signature: decode_option(input)?,
+			validator_id: decode_option(input)?,
+			parachain_id: decode_option(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		self.version.using_encoded(|s| v.extend(s));
+		self.roles.using_encoded(|s| v.extend(s));
+		self.best_number.using_encoded(|s| v.extend(s));
+		self.best_hash.using_encoded(|s| v.extend(s));
+		self.genesis_hash.using_encoded(|s| v.extend(s));
+		encode_option(&mut v, &self.validator_signature);
+		encode_option(&mut v, &self.validator_id);
+		encode_option(&mut v, &self.parachain_id);
+		v
+	}
+}
+
+impl Slicable for BlockRequest {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(BlockRequest {
+			id: Slicable::decode(input)?,
+			fields: Slicable::decode(input)?,
+			from: Slicable::decode(input)?,
+			to: decode_option(input)?,
+			direction: Slicable::decode(input)?,
+			max: decode_option(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		self.id.using_encoded(|s| v.extend(s));
+		self.fields.using_encoded(|s| v.extend(s));
+		self.from.using_encoded(|s| v.extend(s));
+		encode_option(&mut v, &self.to);
+		self.direction.using_encoded(|s| v.extend(s));
+		encode_option(&mut v, &self.max);
+		v
+	}
+}
+
+impl Slicable for BlockResponse {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(BlockResponse {
+			id: Slicable::decode(input)?,
+			blocks: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		self.blocks.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for BlockAnnounce {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Slicable::decode(input).map(|header| BlockAnnounce { header })
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		self.header.encode()
+	}
+}
+
+impl Slicable for RemoteReadRequest {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(RemoteReadRequest {
+			id: Slicable::decode(input)?,
+			block: Slicable::decode(input)?,
+			key: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		self.block.using_encoded(|s| v.extend(s));
+		self.key.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for RemoteReadResponse {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(RemoteReadResponse {
+			id: Slicable::decode(input)?,
+			proof: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		self.proof.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for RemoteCallRequest {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(RemoteCallRequest {
+			id: Slicable::decode(input)?,
+			block: Slicable::decode(input)?,
+			method: String::from_utf8(Slicable::decode(input)?).ok()?,
+			data: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		self.block.using_encoded(|s| v.extend(s));
+		self.method.as_bytes().to_vec().using_encoded(|s| v.extend(s));
+		self.data.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for RemoteCallResponse {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(RemoteCallResponse {
+			id: Slicable::decode(input)?,
+			proof: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		self.proof.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for RemoteHeaderRequest {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(RemoteHeaderRequest {
+			id: Slicable::decode(input)?,
+			block: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		self.block.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for RemoteHeaderResponse {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(RemoteHeaderResponse {
+			id: Slicable::decode(input)?,
+			header: decode_option(input)?,
+			proof: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.id.encode();
+		encode_option(&mut v, &self.header);
+		self.proof.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for BftVote {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => {
+				let (round, hash) = Slicable::decode(input)?;
+				Some(BftVote::Prepare(round, hash))
+			}
+			1 => {
+				let (round, hash) = Slicable::decode(input)?;
+				Some(BftVote::Commit(round, hash))
+			}
+			2 => Slicable::decode(input).map(BftVote::AdvanceRound),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = Vec::new();
+		match *self {
+			BftVote::Prepare(ref round, ref hash) => {
+				v.push(0);
+				round.using_encoded(|s| v.extend(s));
+				hash.using_encoded(|s| v.extend(s));
+			}
+			BftVote::Commit(ref round, ref hash) => {
+				v.push(1);
+				round.using_encoded(|s| v.extend(s));
+				hash.using_encoded(|s| v.extend(s));
+			}
+			BftVote::AdvanceRound(ref round) => {
+				v.push(2);
+				round.using_encoded(|s| v.extend(s));
+			}
+		}
+		v
+	}
+}
+
+impl Slicable for SignedBftProposal {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(SignedBftProposal {
+			round_number: Slicable::decode(input)?,
+			proposal: Slicable::decode(input)?,
+			digest: Slicable::decode(input)?,
+			sender: Slicable::decode(input)?,
+			digest_signature: Slicable::decode(input)?,
+			full_signature: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.round_number.encode();
+		self.proposal.using_encoded(|s| v.extend(s));
+		self.digest.using_encoded(|s| v.extend(s));
+		self.sender.using_encoded(|s| v.extend(s));
+		self.digest_signature.using_encoded(|s| v.extend(s));
+		self.full_signature.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for SignedBftVote {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(SignedBftVote {
+			vote: Slicable::decode(input)?,
+			sender: Slicable::decode(input)?,
+			signature: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.vote.encode();
+		self.sender.using_encoded(|s| v.extend(s));
+		self.signature.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+impl Slicable for BftMessage {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		match u8::decode(input)? {
+			0 => Slicable::decode(input).map(BftMessage::Propose),
+			1 => Slicable::decode(input).map(BftMessage::Vote),
+			2 => Slicable::decode(input).map(BftMessage::Auxiliary),
+			_ => None,
+		}
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let (kind, data) = match *self {
+			BftMessage::Propose(ref m) => (0u8, m.encode()),
+			BftMessage::Vote(ref m) => (1, m.encode()),
+			BftMessage::Auxiliary(ref m) => (2, m.encode()),
+		};
+		let mut v = Vec::with_capacity(data.len() + 1);
+		v.push(kind);
+		v.extend(data);
+		v
+	}
+}
+
+impl Slicable for LocalizedBftMessage {
+	fn decode<I: Input>(input: &mut I) -> Option<Self> {
+		Some(LocalizedBftMessage {
+			parent_hash: Slicable::decode(input)?,
+			message: Slicable::decode(input)?,
+		})
+	}
+
+	fn encode(&self) -> Vec<u8> {
+		let mut v = self.parent_hash.encode();
+		self.message.using_encoded(|s| v.extend(s));
+		v
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use primitives::block::Transaction;
+
+	fn roundtrip(message: Message) {
+		let encoded = message.encode();
+		assert_eq!(Message::decode(&mut &encoded[..]), Some(message));
+	}
+
+	#[test]
+	fn status_roundtrip() {
+		roundtrip(Message::Status(Status {
+			version: 1,
+			roles: vec![Role::Full, Role::Validator],
+			best_number: 42,
+			best_hash: 5.into(),
+			genesis_hash: 6.into(),
+			validator_signature: Some([2; 64].into()),
+			validator_id: Some([1; 32]),
+			parachain_id: Some(100),
+		}));
+	}
+
+	#[test]
+	fn block_messages_roundtrip() {
+		roundtrip(Message::BlockRequest(BlockRequest {
+			id: 1,
+			fields: vec![BlockAttribute::Header, BlockAttribute::Justification],
+			from: FromBlock::Number(10),
+			to: Some(5.into()),
+			direction: Direction::Descending,
+			max: None,
+		}));
+		roundtrip(Message::BlockResponse(BlockResponse {
+			id: 1,
+			blocks: vec![BlockData {
+				hash: 5.into(),
+				header: Some(Header::from_block_number(10)),
+				body: Some(vec![Transaction(vec![1, 2, 3])]),
+				receipt: None,
+				message_queue: None,
+				justification: Some(Justification {
+					round_number: 2,
+					hash: 5.into(),
+					signatures: vec![([2; 32], 7.into())],
+				}),
+			}],
+		}));
+	}
+
+	#[test]
+	fn remote_messages_roundtrip() {
+		roundtrip(Message::RemoteCallRequest(RemoteCallRequest {
+			id: 2,
+			block: 5.into(),
+			method: "authorities".into(),
+			data: vec![1, 2],
+		}));
+		roundtrip(Message::RemoteHeaderResponse(RemoteHeaderResponse {
+			id: 3,
+			header: None,
+			proof: vec![vec![1], vec![2, 3]],
+		}));
+	}
+
+	#[test]
+	fn invalid_data_is_rejected() {
+		assert_eq!(Message::decode(&mut &[12u8][..]), None);
+		let mut encoded = Message::Transactions(vec![vec![1, 2, 3]]).encode();
+		encoded.pop();
+		assert_eq!(Message::decode(&mut &encoded[..]), None);
+	}
+}
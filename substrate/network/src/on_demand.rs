@@ -0,0 +1,599 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
+
+//! On-demand requests service. Dispatches the remote data requests of the light
+//! client to full nodes and checks the responses.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Weak};
+use std::time::Instant;
+use futures::{Async, Future, Poll};
+use futures::sync::oneshot::{channel, Receiver, Sender};
+use parking_lot::Mutex;
+use client;
+use client::light::{Fetcher, FetchChecker, RemoteReadRequest, RemoteCallRequest, RemoteHeaderRequest};
+use network::PeerId;
+use primitives::block;
+
+use message::{self, Message};
+use reputation::Misbehavior;
+use service::Role;
+
+/// Remote request timeout.
+const REQUEST_TIMEOUT_SEC: u64 = 15;
+/// Default request retry count.
+const RETRY_COUNT: usize = 1;
+
+/// Network link used by the on-demand service to send requests.
+pub trait OnDemandNetwork: Send + Sync {
+	/// Send request message to the peer.
+	fn send_request(&self, peer: PeerId, message: Message);
+}
+
+/// On-demand requests service. Implements the light client `Fetcher`.
+pub struct OnDemand {
+	core: Mutex<OnDemandCore>,
+	checker: Arc<FetchChecker>,
+}
+
+/// On-demand remote request response.
+pub struct RemoteResponse<T> {
+	receiver: Receiver<Result<T, client::error::Error>>,
+}
+
+struct OnDemandCore {
+	service: Option<Weak<OnDemandNetwork>>,
+	next_request_id: u64,
+	pending_requests: VecDeque<Request>,
+	active_peers: HashMap<PeerId, Request>,
+	idle_peers: VecDeque<PeerId>,
+}
+
+struct Request {
+	id: u64,
+	timestamp: Instant,
+	retry_count: usize,
+	data: RequestData,
+}
+
+enum RequestData {
+	RemoteRead(RemoteReadRequest, Sender<Result<Option<Vec<u8>>, client::error::Error>>),
+	RemoteCall(RemoteCallRequest, Sender<Result<client::CallResult, client::error::Error>>),
+	RemoteHeader(RemoteHeaderRequest, Sender<Result<block::Header, client::error::Error>>),
+}
+
+impl<T> Future for RemoteResponse<T> {
+	type Item = T;
+	type Error = client::error::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		match self.receiver.poll() {
+			Ok(Async::Ready(Ok(response))) => Ok(Async::Ready(response)),
+			Ok(Async::Ready(Err(error))) => Err(error),
+			Ok(Async::NotReady) => Ok(Async::NotReady),
+			Err(_) => Err(client::error::ErrorKind::RemoteFetchCancelled.into()),
+		}
+	}
+}
+
+impl OnDemand {
+	/// Creates new on-demand service.
+	pub fn new(checker: Arc<FetchChecker>) -> Self {
+		OnDemand {
+			checker,
+			core: Mutex::new(OnDemandCore {
+				service: None,
+				next_request_id: 0,
+				pending_requests: VecDeque::new(),
+				active_peers: HashMap::new(),
+				idle_peers: VecDeque::new(),
+			}),
+		}
+	}
+
+	/// Sets the network link used to send requests. Requests are queued until
+	/// the link is set.
+	pub fn set_service_link(&self, service: Weak<OnDemandNetwork>) {
+		let mut core = self.core.lock();
+		core.service = Some(service);
+		core.dispatch();
+	}
+
+	/// Called when a new peer has completed the handshake. Only full nodes are
+	/// asked for remote data.
+	pub fn on_connect(&self, peer: PeerId, roles: Role) {
+		if !roles.contains(Role::FULL) {
+			return;
+		}
+
+		let mut core = self.core.lock();
+		core.idle_peers.push_back(peer);
+		core.dispatch();
+	}
+
+	/// Called when a peer is disconnected. Its active request is sent to another peer.
+	pub fn on_disconnect(&self, peer: PeerId) {
+		let mut core = self.core.lock();
+		core.remove_peer(peer);
+		core.dispatch();
+	}
+
+	/// Called when a remote read response is received. Returns the peer
+	/// misbehavior if the response is not valid.
+	pub fn on_remote_read_response(&self, peer: PeerId, response: message::RemoteReadResponse) -> Result<(), Misbehavior> {
+		self.accept_response("read", peer, response.id, |request| match request.data {
+			RequestData::RemoteRead(request, sender) => match self.checker.check_read_proof(&request, response.proof) {
+				Ok(response) => {
+					// the requester may have dropped the receiver.
+					let _ = sender.send(Ok(response));
+					Accept::Ok
+				},
+				Err(error) => Accept::CheckFailed(error, RequestData::RemoteRead(request, sender)),
+			},
+			data => Accept::Unexpected(data),
+		})
+	}
+
+	/// Called when a remote call response is received. Returns the peer
+	/// misbehavior if the response is not valid.
+	pub fn on_remote_call_response(&self, peer: PeerId, response: message::RemoteCallResponse) -> Result<(), Misbehavior> {
+		self.accept_response("call", peer, response.id, |request| match request.data {
+			RequestData::RemoteCall(request, sender) => match self.checker.check_execution_proof(&request, response.proof) {
+				Ok(response) => {
+					// the requester may have dropped the receiver.
+					let _ = sender.send(Ok(response));
+					Accept::Ok
+				},
+				Err(error) => Accept::CheckFailed(error, RequestData::RemoteCall(request, sender)),
+			},
+			data => Accept::Unexpected(data),
+		})
+	}
+
+	/// Called when a remote header response is received. Returns the peer
+	/// misbehavior if the response is not valid.
+	pub fn on_remote_header_response(&self, peer: PeerId, response: message::RemoteHeaderResponse) -> Result<(), Misbehavior> {
+		self.accept_response("header", peer, response.id, |request| match request.data {
+			RequestData::RemoteHeader(request, sender) => match self.checker.check_header_proof(&request, response.header, response.proof) {
+				Ok(response) => {
+					// the requester may have dropped the receiver.
+					let _ = sender.send(Ok(response));
+					Accept::Ok
+				},
+				Err(error) => Accept::CheckFailed(error, RequestData::RemoteHeader(request, sender)),
+			},
+			data => Accept::Unexpected(data),
+		})
+	}
+
+	/// Returns the peers which haven't answered their requests in time.
+	/// Their requests are sent to other peers. Requests which couldn't be sent
+	/// to any peer in time, e.g. because no full node is connected, are failed.
+	pub fn maintain_peers(&self) -> Vec<PeerId> {
+		let now = Instant::now();
+		let mut core = self.core.lock();
+		core.fail_expired(now);
+
+		let timed_out: Vec<_> = core.active_peers.iter()
+			.filter(|&(_, request)| now.duration_since(request.timestamp).as_secs() > REQUEST_TIMEOUT_SEC)
+			.map(|(peer, _)| *peer)
+			.collect();
+		for peer in &timed_out {
+			trace!(target: "sync", "Remote request timeout for peer {}", peer);
+			if let Some(request) = core.active_peers.remove(peer) {
+				core.retry(request);
+			}
+		}
+
+		core.dispatch();
+		timed_out
+	}
+
+	fn accept_response<F>(&self, rtype: &str, peer: PeerId, request_id: u64, try_accept: F) -> Result<(), Misbehavior>
+		where F: FnOnce(Request) -> Accept
+	{
+		let mut core = self.core.lock();
+		let request = match core.remove(peer, request_id) {
+			Some(request) => request,
+			None => {
+				trace!(target: "sync", "Invalid remote {} response from peer {}", rtype, peer);
+				core.remove_peer(peer);
+				return Err(Misbehavior::UnexpectedResponse);
+			},
+		};
+
+		let retry_count = request.retry_count;
+		let result = match try_accept(request) {
+			Accept::Ok => Ok(()),
+			Accept::CheckFailed(error, retry_request_data) => {
+				trace!(target: "sync", "Failed to check remote {} response from peer {}: {}", rtype, peer, error);
+				Err((Misbehavior::InvalidResponse, retry_request_data))
+			},
+			Accept::Unexpected(retry_request_data) => {
+				trace!(target: "sync", "Unexpected response to remote {} from peer {}", rtype, peer);
+				Err((Misbehavior::UnexpectedResponse, retry_request_data))
+			},
+		};
+
+		let result = result.map_err(|(misbehavior, request_data)| {
+			core.remove_peer(peer);
+			core.retry(Request {
+				id: request_id,
+				timestamp: Instant::now(),
+				retry_count,
+				data: request_data,
+			});
+			misbehavior
+		});
+
+		core.dispatch();
+		result
+	}
+
+	fn schedule_request(&self, data: RequestData) {
+		let mut core = self.core.lock();
+		let request_id = core.next_request_id;
+		core.next_request_id += 1;
+		core.insert(request_id, data);
+		core.dispatch();
+	}
+}
+
+impl Fetcher for OnDemand {
+	type RemoteReadResult = RemoteResponse<Option<Vec<u8>>>;
+	type RemoteCallResult = RemoteResponse<client::CallResult>;
+	type RemoteHeaderResult = RemoteResponse<block::Header>;
+
+	fn remote_read(&self, request: RemoteReadRequest) -> Self::RemoteReadResult {
+		let (sender, receiver) = channel();
+		self.schedule_request(RequestData::RemoteRead(request, sender));
+		RemoteResponse { receiver }
+	}
+
+	fn remote_call(&self, request: RemoteCallRequest) -> Self::RemoteCallResult {
+		let (sender, receiver) = channel();
+		self.schedule_request(RequestData::RemoteCall(request, sender));
+		RemoteResponse { receiver }
+	}
+
+	fn remote_header(&self, request: RemoteHeaderRequest) -> Self::RemoteHeaderResult {
+		let (sender, receiver) = channel();
+		self.schedule_request(RequestData::RemoteHeader(request, sender));
+		RemoteResponse { receiver }
+	}
+}
+
+// outcome of checking a response.
+enum Accept {
+	Ok,
+	CheckFailed(client::error::Error, RequestData),
+	Unexpected(RequestData),
+}
+
+impl OnDemandCore {
+	fn insert(&mut self, id: u64, data: RequestData) {
+		self.pending_requests.push_back(Request {
+			id,
+			timestamp: Instant::now(),
+			retry_count: RETRY_COUNT,
+			data,
+		});
+	}
+
+	// send the failed request to another peer, unless it has been retried too many times.
+	fn retry(&mut self, mut request: Request) {
+		match request.retry_count.checked_sub(1) {
+			Some(retry_count) => {
+				request.retry_count = retry_count;
+				request.timestamp = Instant::now();
+				self.pending_requests.push_front(request);
+			},
+			None => {
+				trace!(target: "sync", "Remote request {} has failed too many times", request.id);
+				request.data.fail(client::error::ErrorKind::RemoteFetchFailed.into());
+			},
+		}
+	}
+
+	// take the active request of the peer if it matches the response id.
+	fn remove(&mut self, peer: PeerId, id: u64) -> Option<Request> {
+		match self.active_peers.remove(&peer) {
+			Some(request) => match request.id == id {
+				true => {
+					self.idle_peers.push_back(peer);
+					Some(request)
+				},
+				false => {
+					self.active_peers.insert(peer, request);
+					None
+				},
+			},
+			None => None,
+		}
+	}
+
+	fn remove_peer(&mut self, peer: PeerId) {
+		if let Some(mut request) = self.active_peers.remove(&peer) {
+			request.timestamp = Instant::now();
+			self.pending_requests.push_front(request);
+			return;
+		}
+
+		if let Some(idle_index) = self.idle_peers.iter().position(|i| *i == peer) {
+			self.idle_peers.swap_remove_back(idle_index);
+		}
+	}
+
+	// fail the requests which have been waiting for a peer for too long.
+	fn fail_expired(&mut self, now: Instant) {
+		let (expired, pending): (Vec<_>, VecDeque<_>) = self.pending_requests.drain(..)
+			.partition(|request| now.duration_since(request.timestamp).as_secs() > REQUEST_TIMEOUT_SEC);
+		self.pending_requests = pending;
+		for request in expired {
+			trace!(target: "sync", "Remote request {} has not been sent to any peer in time", request.id);
+			request.data.fail(client::error::ErrorKind::RemoteFetchTimedOut.into());
+		}
+	}
+
+	// send the pending requests to the idle peers.
+	fn dispatch(&mut self) {
+		let service = match self.service.as_ref().and_then(|service| service.upgrade()) {
+			Some(service) => service,
+			None => return,
+		};
+
+		while !self.pending_requests.is_empty() {
+			let peer = match self.idle_peers.pop_front() {
+				Some(peer) => peer,
+				None => return,
+			};
+
+			let mut request = self.pending_requests.pop_front().expect("checked in loop condition; qed");
+			request.timestamp = Instant::now();
+			trace!(target: "sync", "Dispatching remote request {} to peer {}", request.id, peer);
+			service.send_request(peer, request.message());
+			self.active_peers.insert(peer, request);
+		}
+	}
+}
+
+impl Request {
+	fn message(&self) -> Message {
+		match self.data {
+			RequestData::RemoteRead(ref data, _) => Message::RemoteReadRequest(message::RemoteReadRequest {
+				id: self.id,
+				block: data.block,
+				key: data.key.clone(),
+			}),
+			RequestData::RemoteCall(ref data, _) => Message::RemoteCallRequest(message::RemoteCallRequest {
+				id: self.id,
+				block: data.block,
+				method: data.method.clone(),
+				data: data.call_data.clone(),
+			}),
+			RequestData::RemoteHeader(ref data, _) => Message::RemoteHeaderRequest(message::RemoteHeaderRequest {
+				id: self.id,
+				block: data.block,
+			}),
+		}
+	}
+}
+
+impl RequestData {
+	fn fail(self, error: client::error::Error) {
+		// the requester may have dropped the receiver.
+		match self {
+			RequestData::RemoteRead(_, sender) => { let _ = sender.send(Err(error)); },
+			RequestData::RemoteCall(_, sender) => { let _ = sender.send(Err(error)); },
+			RequestData::RemoteHeader(_, sender) => { let _ = sender.send(Err(error)); },
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use std::time::{Duration, Instant};
+	use futures::Future;
+	use parking_lot::Mutex;
+	use client;
+	use client::light::{Fetcher, FetchChecker, RemoteReadRequest, RemoteCallRequest, RemoteHeaderRequest};
+	use message::{self, Message};
+	use network::PeerId;
+	use primitives::block;
+	use reputation::Misbehavior;
+	use service::Role;
+	use super::{OnDemand, OnDemandNetwork, REQUEST_TIMEOUT_SEC};
+
+	struct DummyFetchChecker { ok: bool }
+
+	impl FetchChecker for DummyFetchChecker {
+		fn check_read_proof(&self, _request: &RemoteReadRequest, remote_proof: Vec<Vec<u8>>) -> client::error::Result<Option<Vec<u8>>> {
+			match self.ok {
+				true => Ok(remote_proof.into_iter().next()),
+				false => Err(client::error::ErrorKind::Backend("Test error".into()).into()),
+			}
+		}
+
+		fn check_execution_proof(&self, _request: &RemoteCallRequest, remote_proof: Vec<Vec<u8>>) -> client::error::Result<client::CallResult> {
+			match self.ok {
+				true => Ok(client::CallResult { return_data: remote_proof.into_iter().next().unwrap_or_default(), changes: Default::default() }),
+				false => Err(client::error::ErrorKind::Backend("Test error".into()).into()),
+			}
+		}
+
+		fn check_header_proof(&self, request: &RemoteHeaderRequest, header: Option<block::Header>, _remote_proof: Vec<Vec<u8>>) -> client::error::Result<block::Header> {
+			match (self.ok, header) {
+				(true, Some(header)) => Ok(header),
+				_ => Err(client::error::ErrorKind::InvalidHeaderProof(request.block).into()),
+			}
+		}
+	}
+
+	#[derive(Default)]
+	struct TestNetwork {
+		sent: Mutex<Vec<(PeerId, Message)>>,
+	}
+
+	impl OnDemandNetwork for TestNetwork {
+		fn send_request(&self, peer: PeerId, message: Message) {
+			self.sent.lock().push((peer, message));
+		}
+	}
+
+	fn dummy(ok: bool) -> (Arc<TestNetwork>, Arc<OnDemand>) {
+		let network = Arc::new(TestNetwork::default());
+		let on_demand = Arc::new(OnDemand::new(Arc::new(DummyFetchChecker { ok })));
+		on_demand.set_service_link(Arc::downgrade(&network));
+		(network, on_demand)
+	}
+
+	fn read_request() -> RemoteReadRequest {
+		RemoteReadRequest {
+			block: Default::default(),
+			header: block::Header::from_block_number(0),
+			key: b":key".to_vec(),
+		}
+	}
+
+	fn read_response(id: u64) -> message::RemoteReadResponse {
+		message::RemoteReadResponse { id, proof: vec![vec![42]] }
+	}
+
+	#[test]
+	fn requests_are_sent_to_full_peers_only() {
+		let (network, on_demand) = dummy(true);
+		on_demand.on_connect(0, Role::LIGHT);
+		let _ = on_demand.remote_read(read_request());
+		assert!(network.sent.lock().is_empty());
+
+		on_demand.on_connect(1, Role::FULL);
+		let sent = network.sent.lock();
+		assert_eq!(sent.len(), 1);
+		assert_eq!(sent[0].0, 1);
+	}
+
+	#[test]
+	fn read_response_is_checked_and_returned() {
+		let (_network, on_demand) = dummy(true);
+		on_demand.on_connect(0, Role::FULL);
+		let response = on_demand.remote_read(read_request());
+		assert_eq!(on_demand.on_remote_read_response(0, read_response(0)), Ok(()));
+		assert_eq!(response.wait().unwrap(), Some(vec![42]));
+	}
+
+	#[test]
+	fn bad_response_is_reported_and_request_is_retried() {
+		let (network, on_demand) = dummy(false);
+		on_demand.on_connect(0, Role::FULL);
+		on_demand.on_connect(1, Role::FULL);
+		let _ = on_demand.remote_read(read_request());
+		assert_eq!(on_demand.on_remote_read_response(0, read_response(0)), Err(Misbehavior::InvalidResponse));
+
+		let sent = network.sent.lock();
+		assert_eq!(sent.len(), 2);
+		assert_eq!(sent[1].0, 1);
+	}
+
+	#[test]
+	fn response_with_unknown_id_is_reported() {
+		let (_network, on_demand) = dummy(true);
+		on_demand.on_connect(0, Role::FULL);
+		let _ = on_demand.remote_read(read_request());
+		assert_eq!(on_demand.on_remote_read_response(0, read_response(1)), Err(Misbehavior::UnexpectedResponse));
+	}
+
+	#[test]
+	fn request_is_resent_when_peer_disconnects() {
+		let (network, on_demand) = dummy(true);
+		on_demand.on_connect(0, Role::FULL);
+		let _ = on_demand.remote_call(RemoteCallRequest {
+			block: Default::default(),
+			header: block::Header::from_block_number(0),
+			method: "test".into(),
+			call_data: vec![],
+		});
+		on_demand.on_disconnect(0);
+		on_demand.on_connect(1, Role::FULL);
+
+		let sent = network.sent.lock();
+		assert_eq!(sent.len(), 2);
+		assert_eq!(sent[1].0, 1);
+	}
+
+	#[test]
+	fn request_fails_when_retries_are_exhausted() {
+		let (network, on_demand) = dummy(false);
+		on_demand.on_connect(0, Role::FULL);
+		on_demand.on_connect(1, Role::FULL);
+		on_demand.on_connect(2, Role::FULL);
+		let response = on_demand.remote_read(read_request());
+		assert!(on_demand.on_remote_read_response(0, read_response(0)).is_err());
+		assert!(on_demand.on_remote_read_response(1, read_response(0)).is_err());
+		assert!(response.wait().is_err());
+		assert_eq!(network.sent.lock().len(), 2);
+	}
+
+	#[test]
+	fn timed_out_peer_is_reported_and_request_is_retried() {
+		let (network, on_demand) = dummy(true);
+		on_demand.on_connect(0, Role::FULL);
+		on_demand.on_connect(1, Role::FULL);
+		let _ = on_demand.remote_read(read_request());
+
+		assert!(on_demand.maintain_peers().is_empty());
+
+		on_demand.core.lock().active_peers.get_mut(&0).unwrap().timestamp =
+			Instant::now() - Duration::from_secs(REQUEST_TIMEOUT_SEC + 1);
+		assert_eq!(on_demand.maintain_peers(), vec![0]);
+
+		let sent = network.sent.lock();
+		assert_eq!(sent.len(), 2);
+		assert_eq!(sent[1].0, 1);
+	}
+
+	#[test]
+	fn request_fails_when_no_peer_answers_in_time() {
+		let (network, on_demand) = dummy(true);
+		let response = on_demand.remote_read(read_request());
+		assert!(on_demand.maintain_peers().is_empty());
+
+		on_demand.core.lock().pending_requests[0].timestamp =
+			Instant::now() - Duration::from_secs(REQUEST_TIMEOUT_SEC + 1);
+		assert!(on_demand.maintain_peers().is_empty());
+		assert!(on_demand.core.lock().pending_requests.is_empty());
+		assert!(response.wait().is_err());
+
+		on_demand.on_connect(0, Role::FULL);
+		assert!(network.sent.lock().is_empty());
+	}
+
+	#[test]
+	fn header_response_is_checked_and_returned() {
+		let (_network, on_demand) = dummy(true);
+		on_demand.on_connect(0, Role::FULL);
+		let response = on_demand.remote_header(RemoteHeaderRequest {
+			cht_root: Default::default(),
+			block: 1,
+		});
+		assert_eq!(on_demand.on_remote_header_response(0, message::RemoteHeaderResponse {
+			id: 0,
+			header: Some(block::Header::from_block_number(1)),
+			proof: vec![],
+		}), Ok(()));
+		assert_eq!(response.wait().unwrap(), block::Header::from_block_number(1));
+	}
+}
@@ -18,36 +18,63 @@ use std::collections::{HashMap, HashSet, BTreeMap};
 use std::{mem, cmp};
 use std::sync::Arc;
 use std::time;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serde_json;
+use codec::Slicable;
+use primitives::AuthorityId;
+use primitives::hexdisplay::HexDisplay;
+use primitives::hashing::blake2_256;
 use primitives::block::{HeaderHash, TransactionHash, Number as BlockNumber, Header, Id as BlockId};
 use network::{PeerId, NodeId};
 
 use message::{self, Message};
-use sync::{ChainSync, Status as SyncStatus};
-use service::Role;
+use import_queue::ImportQueue;
+use sync::{ChainSync, Status as SyncStatus, SyncState};
+use service::{Role, TransactionPool};
+use on_demand::OnDemand;
+use consensus::{Consensus, BftMessageStream};
+use reputation::{Reputation, Misbehavior, Reward};
 use config::ProtocolConfig;
 use chain::Client;
 use io::SyncIo;
 use error;
+use bft;
+use ed25519;
 use super::header_hash;
 
 const REQUEST_TIMEOUT_SEC: u64 = 15;
-const PROTOCOL_VERSION: u32 = 0;
+const PROTOCOL_VERSION: u32 = 1;
+// Messages are exchanged in the binary encoding with the peers supporting this version.
+// Older peers use JSON and only sync blocks: they are never sent transactions, BFT
+// messages or remote data requests, which they are unable to decode.
+const BINARY_ENCODING_VERSION: u32 = 1;
+// Maximum packet size accepted from or sent to a peer.
+const MAX_PACKET_SIZE: usize = 16 * 1024 * 1024;
 
 // Maximum allowed entries in `BlockResponse`
 const MAX_BLOCK_DATA_RESPONSE: u32 = 128;
+// Transactions known to a peer are forgotten past this many.
+const MAX_KNOWN_TRANSACTIONS: usize = 4096;
 
 // Lock must always be taken in order declared here.
 pub struct Protocol {
 	config: ProtocolConfig,
 	chain: Arc<Client>,
+	on_demand: Option<Arc<OnDemand>>,
+	transaction_pool: Arc<TransactionPool>,
 	genesis_hash: HeaderHash,
 	sync: RwLock<ChainSync>,
+	consensus: Mutex<Consensus>,
 	/// All connected peers
 	peers: RwLock<HashMap<PeerId, Peer>>,
 	/// Connected peers pending Status message.
 	handshaking_peers: RwLock<HashMap<PeerId, time::Instant>>,
+	/// Propagation statistics of the pool transactions.
+	transaction_stats: RwLock<BTreeMap<TransactionHash, TransactionStats>>,
+	/// Reputation of the known nodes.
+	reputation: Mutex<Reputation>,
+	/// Node id of the local node, signed in the `Status` of a validator.
+	local_node_id: RwLock<Option<NodeId>>,
 }
 
 /// Syncing status and statistics
@@ -67,6 +94,8 @@ struct Peer {
 	protocol_version: u32,
 	/// Roles
 	roles: Role,
+	/// Persistent node ID if known
+	node_id: Option<NodeId>,
 	/// Peer best block hash
 	best_hash: HeaderHash,
 	/// Peer best block number
@@ -75,8 +104,8 @@ struct Peer {
 	block_request: Option<message::BlockRequest>,
 	/// Request timestamp
 	request_timestamp: Option<time::Instant>,
-	/// Holds a set of transactions recently sent to this peer to avoid spamming.
-	_last_sent_transactions: HashSet<TransactionHash>,
+	/// Holds a set of transactions known to this peer to avoid spamming.
+	known_transactions: HashSet<TransactionHash>,
 	/// Request counter,
 	next_request_id: message::RequestId,
 }
@@ -91,10 +120,12 @@ pub struct PeerInfo {
 	pub best_hash: HeaderHash,
 	/// Peer best block number
 	pub best_number: BlockNumber,
+	/// Reputation of the peer node
+	pub reputation: i32,
 }
 
 /// Transaction stats
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TransactionStats {
 	/// Block number where this TX was first seen.
 	pub first_seen: u64,
@@ -104,19 +135,31 @@ pub struct TransactionStats {
 
 impl Protocol {
 	/// Create a new instance.
-	pub fn new(config: ProtocolConfig, chain: Arc<Client>) -> error::Result<Protocol>  {
+	pub fn new(config: ProtocolConfig, chain: Arc<Client>, import_queue: Arc<ImportQueue>, on_demand: Option<Arc<OnDemand>>, transaction_pool: Arc<TransactionPool>) -> error::Result<Protocol>  {
 		let info = chain.info()?;
+		let sync = ChainSync::new(config.roles, &info, import_queue);
 		let protocol = Protocol {
 			config: config,
 			chain: chain,
+			on_demand: on_demand,
+			transaction_pool: transaction_pool,
 			genesis_hash: info.chain.genesis_hash,
-			sync: RwLock::new(ChainSync::new(&info)),
+			sync: RwLock::new(sync),
+			consensus: Mutex::new(Consensus::new()),
 			peers: RwLock::new(HashMap::new()),
 			handshaking_peers: RwLock::new(HashMap::new()),
+			transaction_stats: RwLock::new(BTreeMap::new()),
+			reputation: Mutex::new(Reputation::new()),
+			local_node_id: RwLock::new(None),
 		};
 		Ok(protocol)
 	}
 
+	/// Set the node id of the local node once the network is started.
+	pub fn set_local_node_id(&self, node_id: NodeId) {
+		*self.local_node_id.write() = Some(node_id);
+	}
+
 	/// Returns protocol status
 	pub fn status(&self) -> ProtocolStatus {
 		let sync = self.sync.read();
@@ -129,12 +172,30 @@ impl Protocol {
 	}
 
 	pub fn handle_packet(&self, io: &mut SyncIo, peer_id: PeerId, data: &[u8]) {
-		let message: Message = match serde_json::from_slice(data) {
-			Ok(m) => m,
-			Err(e) => {
-				debug!("Invalid packet from {}: {}", peer_id, e);
-				io.disable_peer(peer_id);
-				return;
+		if data.len() > MAX_PACKET_SIZE {
+			debug!("Oversized packet from {}: {} bytes", peer_id, data.len());
+			self.report_peer(io, peer_id, Misbehavior::InvalidPacket);
+			return;
+		}
+
+		let binary = self.peers.read().get(&peer_id).map_or(false, |peer| peer.protocol_version >= BINARY_ENCODING_VERSION);
+		let message: Message = if binary {
+			match Message::decode(&mut &data[..]) {
+				Some(m) => m,
+				None => {
+					debug!("Invalid packet from {}", peer_id);
+					self.report_peer(io, peer_id, Misbehavior::InvalidPacket);
+					return;
+				}
+			}
+		} else {
+			match serde_json::from_slice(data) {
+				Ok(m) => m,
+				Err(e) => {
+					debug!("Invalid packet from {}: {}", peer_id, e);
+					self.report_peer(io, peer_id, Misbehavior::InvalidPacket);
+					return;
+				}
 			}
 		};
 
@@ -145,35 +206,47 @@ impl Protocol {
 				let request = {
 					let mut peers = self.peers.write();
 					if let Some(ref mut peer) = peers.get_mut(&peer_id) {
-						peer.request_timestamp = None;
-						match mem::replace(&mut peer.block_request, None) {
-							Some(r) => r,
+						match peer.block_request.as_ref().map(|request| request.id) {
+							Some(id) if id != r.id => {
+								// A late response to an earlier request. The outstanding one is kept.
+								trace!(target: "sync", "Ignoring mismatched response packet from {} (expected {} got {})", peer_id, id, r.id);
+								return;
+							},
+							Some(_) => (),
 							None => {
 								debug!("Unexpected response packet from {}", peer_id);
-								io.disable_peer(peer_id);
+								self.report_peer(io, peer_id, Misbehavior::UnexpectedResponse);
 								return;
 							}
 						}
+						peer.request_timestamp = None;
+						mem::replace(&mut peer.block_request, None).expect("block_request checked to be Some above; qed")
 					} else {
 						debug!("Unexpected packet from {}", peer_id);
-						io.disable_peer(peer_id);
+						self.report_peer(io, peer_id, Misbehavior::UnexpectedResponse);
 						return;
 					}
 				};
-				if request.id != r.id {
-					trace!(target: "sync", "Ignoring mismatched response packet from {} (expected {} got {})", peer_id, request.id, r.id);
-					return;
-				}
 				self.on_block_response(io, peer_id, request, r);
 			},
 			Message::BlockAnnounce(announce) => {
 				self.on_block_announce(io, peer_id, announce);
-			}
+			},
+			Message::RemoteReadRequest(request) => self.on_remote_read_request(io, peer_id, request),
+			Message::RemoteReadResponse(response) => self.on_remote_read_response(io, peer_id, response),
+			Message::RemoteCallRequest(request) => self.on_remote_call_request(io, peer_id, request),
+			Message::RemoteCallResponse(response) => self.on_remote_call_response(io, peer_id, response),
+			Message::RemoteHeaderRequest(request) => self.on_remote_header_request(io, peer_id, request),
+			Message::RemoteHeaderResponse(response) => self.on_remote_header_response(io, peer_id, response),
+			Message::BftMessage(m) => self.on_bft_message(io, peer_id, m),
+			Message::Transactions(m) => self.on_transactions(io, peer_id, m),
 		}
 	}
 
 	pub fn send_message(&self, io: &mut SyncIo, peer_id: PeerId, mut message: Message) {
 		let mut peers = self.peers.write();
+		// The status is sent to handshaking peers and is always JSON.
+		let mut binary = false;
 		if let Some(ref mut peer) = peers.get_mut(&peer_id) {
 			match &mut message {
 				&mut Message::BlockRequest(ref mut r) => {
@@ -184,17 +257,59 @@ impl Protocol {
 				},
 				_ => (),
 			}
+			binary = peer.protocol_version >= BINARY_ENCODING_VERSION;
+		}
+		if !binary && !is_block_sync_message(&message) {
+			trace!(target:"sync", "Not sending a message unsupported by {}", peer_id);
+			return;
+		}
+		let data = if binary {
+			message.encode()
+		} else {
+			serde_json::to_vec(&message).expect("Serializer is infallible; qed")
+		};
+		if data.len() > MAX_PACKET_SIZE {
+			warn!(target:"sync", "Dropping oversized message to {}: {} bytes", peer_id, data.len());
+			return;
 		}
-		let data = serde_json::to_vec(&message).expect("Serializer is infallible; qed");
 		if let Err(e) = io.send(peer_id, data) {
 			debug!(target:"sync", "Error sending message: {:?}", e);
 			io.disconnect_peer(peer_id);
 		}
 	}
 
+	/// Penalize the peer for misbehavior and disconnect it, disabling it for the
+	/// fatal faults. The peer node is banned for a while once its reputation drops too low.
+	pub fn report_peer(&self, io: &mut SyncIo, peer_id: PeerId, misbehavior: Misbehavior) {
+		let banned = match io.peer_node_id(peer_id) {
+			Some(node_id) => self.reputation.lock().report(&node_id, misbehavior),
+			None => false,
+		};
+		debug!(target: "sync", "Peer {} misbehaved: {:?}{}", peer_id, misbehavior, if banned { ", banned" } else { "" });
+		if misbehavior.is_fatal() {
+			io.disable_peer(peer_id);
+		} else {
+			io.disconnect_peer(peer_id);
+		}
+	}
+
+	/// Reward the peer for useful behavior.
+	pub fn reward_peer(&self, io: &mut SyncIo, peer_id: PeerId, reward: Reward) {
+		if let Some(node_id) = io.peer_node_id(peer_id) {
+			self.reputation.lock().reward(&node_id, reward);
+		}
+	}
+
 	/// Called when a new peer is connected
 	pub fn on_peer_connected(&self, io: &mut SyncIo, peer_id: PeerId) {
 		trace!(target: "sync", "Connected {}: {}", peer_id, io.peer_info(peer_id));
+		if let Some(node_id) = io.peer_node_id(peer_id) {
+			if self.reputation.lock().is_banned(&node_id) {
+				trace!(target: "sync", "Rejecting banned peer {}", peer_id);
+				io.disconnect_peer(peer_id);
+				return;
+			}
+		}
 		self.handshaking_peers.write().insert(peer_id, time::Instant::now());
 		self.send_status(io, peer_id);
 	}
@@ -210,6 +325,10 @@ impl Protocol {
 		};
 		if removed {
 			self.sync.write().peer_disconnected(io, self, peer);
+			self.consensus.lock().peer_disconnected(peer);
+			if let Some(ref on_demand) = self.on_demand {
+				on_demand.on_disconnect(peer);
+			}
 		}
 	}
 
@@ -272,25 +391,57 @@ impl Protocol {
 
 	pub fn tick(&self, io: &mut SyncIo) {
 		self.maintain_peers(io);
+		self.sync.write().maintain_import(io, self);
+		self.consensus.lock().collect_garbage();
+		if let Some(ref on_demand) = self.on_demand {
+			for peer_id in on_demand.maintain_peers() {
+				self.report_peer(io, peer_id, Misbehavior::Timeout);
+			}
+		}
 	}
 
 	fn maintain_peers(&self, io: &mut SyncIo) {
-		let tick = time::Instant::now();
+		self.maintain_peers_at(io, time::Instant::now());
+	}
+
+	/// Time out the pending requests as if their peers took too long to respond.
+	#[cfg(test)]
+	pub fn expire_requests(&self, io: &mut SyncIo) {
+		self.maintain_peers_at(io, time::Instant::now() + time::Duration::from_secs(REQUEST_TIMEOUT_SEC + 1));
+	}
+
+	fn maintain_peers_at(&self, io: &mut SyncIo, tick: time::Instant) {
 		let mut aborting = Vec::new();
+		let mut timed_out = Vec::new();
 		{
-			let peers = self.peers.read();
+			let mut peers = self.peers.write();
 			let handshaking_peers = self.handshaking_peers.read();
-			for (peer_id, timestamp) in peers.iter()
-				.filter_map(|(id, peer)| peer.request_timestamp.as_ref().map(|r| (id, r)))
-				.chain(handshaking_peers.iter()) {
+			for (peer_id, timestamp) in handshaking_peers.iter() {
 				if (tick - *timestamp).as_secs() > REQUEST_TIMEOUT_SEC {
-					trace!(target: "sync", "Timeout {}", peer_id);
-					io.disconnect_peer(*peer_id);
+					trace!(target: "sync", "Handshake timeout {}", peer_id);
 					aborting.push(*peer_id);
 				}
 			}
+			for (peer_id, peer) in peers.iter_mut() {
+				let expired = peer.request_timestamp.map_or(false, |t| (tick - t).as_secs() > REQUEST_TIMEOUT_SEC);
+				if expired {
+					trace!(target: "sync", "Request timeout {}", peer_id);
+					// The request is kept so that a late response is not treated as unexpected.
+					// It is dropped by the id check if a new request has been sent to the peer
+					// since, or by the sync which no longer expects blocks from the peer.
+					peer.request_timestamp = None;
+					timed_out.push(*peer_id);
+				}
+			}
+		}
+		for p in timed_out {
+			let keep = self.sync.write().on_request_timeout(io, self, p);
+			if !keep {
+				aborting.push(p);
+			}
 		}
 		for p in aborting {
+			self.report_peer(io, p, Misbehavior::Timeout);
 			self.on_peer_disconnected(io, p);
 		}
 	}
@@ -302,6 +453,7 @@ impl Protocol {
 				protocol_version: p.protocol_version,
 				best_hash: p.best_hash,
 				best_number: p.best_number,
+				reputation: p.node_id.map_or(0, |id| self.reputation.lock().reputation(&id)),
 			}
 		})
 	}
@@ -314,6 +466,12 @@ impl Protocol {
 			return;
 		}
 
+		let roles: Role = status.roles.into();
+		// the validator key is only trusted when it signed the node id of this session.
+		let validator_id = match (status.validator_id, status.validator_signature, io.peer_node_id(peer_id)) {
+			(Some(id), Some(signature), Some(node_id)) if ed25519::verify_strong(&signature, &node_id[..], ed25519::Public(id)) => Some(id),
+			_ => None,
+		};
 		{
 			let mut peers = self.peers.write();
 			let mut handshaking_peers = self.handshaking_peers.write();
@@ -322,24 +480,26 @@ impl Protocol {
 				return;
 			}
 			if status.genesis_hash != self.genesis_hash {
-				io.disable_peer(peer_id);
+				self.report_peer(io, peer_id, Misbehavior::GenesisMismatch);
 				trace!(target: "sync", "Peer {} genesis hash mismatch (ours: {}, theirs: {})", peer_id, self.genesis_hash, status.genesis_hash);
 				return;
 			}
-			if status.version != PROTOCOL_VERSION {
-				io.disable_peer(peer_id);
-				trace!(target: "sync", "Peer {} unsupported eth protocol ({})", peer_id, status.version);
+			if roles.contains(Role::VALIDATOR) && validator_id.is_none() {
+				self.report_peer(io, peer_id, Misbehavior::InvalidStatusSignature);
+				trace!(target: "sync", "Peer {} validator status signature is missing or invalid", peer_id);
 				return;
 			}
 
+			// Both sides settle on the lower of the two versions.
 			let peer = Peer {
-				protocol_version: status.version,
-				roles: status.roles.into(),
+				protocol_version: cmp::min(status.version, PROTOCOL_VERSION),
+				roles: roles,
+				node_id: io.peer_node_id(peer_id),
 				best_hash: status.best_hash,
 				best_number: status.best_number,
 				block_request: None,
 				request_timestamp: None,
-				_last_sent_transactions: HashSet::new(),
+				known_transactions: HashSet::new(),
 				next_request_id: 0,
 			};
 			peers.insert(peer_id.clone(), peer);
@@ -347,19 +507,32 @@ impl Protocol {
 			debug!(target: "sync", "Connected {} {}", peer_id, io.peer_info(peer_id));
 		}
 		self.sync.write().new_peer(io, self, peer_id);
+		if status.version < BINARY_ENCODING_VERSION {
+			return;
+		}
+		self.consensus.lock().new_peer(io, self, peer_id, roles, validator_id);
+		if let Some(ref on_demand) = self.on_demand {
+			on_demand.on_connect(peer_id, roles);
+		}
 	}
 
 	/// Send Status message
 	fn send_status(&self, io: &mut SyncIo, peer_id: PeerId) {
 		if let Ok(info) = self.chain.info() {
+			// the validator key signs the local node id, so that the status can't be replayed by another node.
+			let local_node_id = self.local_node_id.read().clone();
+			let (validator_signature, validator_id) = match (self.config.validator_key.as_ref(), local_node_id) {
+				(Some(key), Some(node_id)) => (Some(key.sign(&node_id[..])), Some(key.public().0)),
+				_ => (None, None),
+			};
 			let status = message::Status {
 				version: PROTOCOL_VERSION,
 				genesis_hash: info.chain.genesis_hash,
 				roles: self.config.roles.into(),
 				best_number: info.chain.best_number,
 				best_hash: info.chain.best_hash,
-				validator_signature: None,
-				validator_id: None,
+				validator_signature: validator_signature,
+				validator_id: validator_id,
 				parachain_id: None,
 			};
 			self.send_message(io, peer_id, Message::Status(status))
@@ -368,9 +541,11 @@ impl Protocol {
 
 	pub fn abort(&self) {
 		let mut sync = self.sync.write();
+		let mut consensus = self.consensus.lock();
 		let mut peers = self.peers.write();
 		let mut handshaking_peers = self.handshaking_peers.write();
 		sync.clear();
+		consensus.clear();
 		peers.clear();
 		handshaking_peers.clear();
 	}
@@ -380,18 +555,232 @@ impl Protocol {
 		self.sync.write().on_block_announce(io, self, peer_id, &header);
 	}
 
+	fn on_remote_read_request(&self, io: &mut SyncIo, peer_id: PeerId, request: message::RemoteReadRequest) {
+		trace!(target: "sync", "Remote read request {} from {} ({} at {})", request.id, peer_id, HexDisplay::from(&request.key), request.block);
+		let proof = match self.chain.read_proof(&request.block, &request.key) {
+			Ok(proof) => proof,
+			Err(error) => {
+				trace!(target: "sync", "Remote read request {} from {} ({} at {}) failed with: {}", request.id, peer_id, HexDisplay::from(&request.key), request.block, error);
+				Default::default()
+			},
+		};
+		self.send_message(io, peer_id, Message::RemoteReadResponse(message::RemoteReadResponse {
+			id: request.id,
+			proof,
+		}));
+	}
+
+	fn on_remote_read_response(&self, io: &mut SyncIo, peer_id: PeerId, response: message::RemoteReadResponse) {
+		trace!(target: "sync", "Remote read response {} from {}", response.id, peer_id);
+		let result = match self.on_demand {
+			Some(ref on_demand) => on_demand.on_remote_read_response(peer_id, response),
+			None => {
+				debug!(target: "sync", "Unexpected remote read response from {}", peer_id);
+				Err(Misbehavior::UnexpectedResponse)
+			},
+		};
+		self.on_remote_response_checked(io, peer_id, result);
+	}
+
+	fn on_remote_call_request(&self, io: &mut SyncIo, peer_id: PeerId, request: message::RemoteCallRequest) {
+		trace!(target: "sync", "Remote call request {} from {} ({} at {})", request.id, peer_id, request.method, request.block);
+		let proof = match self.chain.execution_proof(&request.block, &request.method, &request.data) {
+			Ok((_, proof)) => proof,
+			Err(error) => {
+				trace!(target: "sync", "Remote call request {} from {} ({} at {}) failed with: {}", request.id, peer_id, request.method, request.block, error);
+				Default::default()
+			},
+		};
+		self.send_message(io, peer_id, Message::RemoteCallResponse(message::RemoteCallResponse {
+			id: request.id,
+			proof,
+		}));
+	}
+
+	fn on_remote_call_response(&self, io: &mut SyncIo, peer_id: PeerId, response: message::RemoteCallResponse) {
+		trace!(target: "sync", "Remote call response {} from {}", response.id, peer_id);
+		let result = match self.on_demand {
+			Some(ref on_demand) => on_demand.on_remote_call_response(peer_id, response),
+			None => {
+				debug!(target: "sync", "Unexpected remote call response from {}", peer_id);
+				Err(Misbehavior::UnexpectedResponse)
+			},
+		};
+		self.on_remote_response_checked(io, peer_id, result);
+	}
+
+	fn on_remote_header_request(&self, io: &mut SyncIo, peer_id: PeerId, request: message::RemoteHeaderRequest) {
+		trace!(target: "sync", "Remote header request {} from {} (#{})", request.id, peer_id, request.block);
+		let (header, proof) = match self.chain.header_proof(request.block) {
+			Ok((header, proof)) => (Some(header), proof),
+			Err(error) => {
+				trace!(target: "sync", "Remote header request {} from {} (#{}) failed with: {}", request.id, peer_id, request.block, error);
+				(None, Default::default())
+			},
+		};
+		self.send_message(io, peer_id, Message::RemoteHeaderResponse(message::RemoteHeaderResponse {
+			id: request.id,
+			header,
+			proof,
+		}));
+	}
+
+	fn on_remote_header_response(&self, io: &mut SyncIo, peer_id: PeerId, response: message::RemoteHeaderResponse) {
+		trace!(target: "sync", "Remote header response {} from {}", response.id, peer_id);
+		let result = match self.on_demand {
+			Some(ref on_demand) => on_demand.on_remote_header_response(peer_id, response),
+			None => {
+				debug!(target: "sync", "Unexpected remote header response from {}", peer_id);
+				Err(Misbehavior::UnexpectedResponse)
+			},
+		};
+		self.on_remote_response_checked(io, peer_id, result);
+	}
+
+	fn on_remote_response_checked(&self, io: &mut SyncIo, peer_id: PeerId, result: Result<(), Misbehavior>) {
+		match result {
+			Ok(()) => self.reward_peer(io, peer_id, Reward::ValidResponse),
+			Err(misbehavior) => self.report_peer(io, peer_id, misbehavior),
+		}
+	}
+
+	fn on_bft_message(&self, io: &mut SyncIo, peer_id: PeerId, message: message::LocalizedBftMessage) {
+		trace!(target: "bft", "BFT message from {} for round on top of {}", peer_id, message.parent_hash);
+		self.consensus.lock().on_bft_message(io, self, peer_id, message);
+	}
+
+	/// Start the BFT round built on top of the given block. Returns the stream
+	/// of checked messages of the round.
+	pub fn start_bft_round<E>(&self, parent_hash: HeaderHash, authorities: Vec<AuthorityId>) -> BftMessageStream<E> {
+		self.consensus.lock().start_round(parent_hash, authorities)
+	}
+
+	/// Gossip a local BFT message of the round built on top of the given block.
+	pub fn send_bft_message(&self, io: &mut SyncIo, parent_hash: HeaderHash, message: bft::Communication) {
+		self.consensus.lock().send_bft_message(io, self, parent_hash, message);
+	}
+
 	pub fn on_block_imported(&self, header: &Header) {
 		self.sync.write().update_chain_info(&header);
 	}
 
-	pub fn on_new_transactions(&self) {
+	fn on_transactions(&self, io: &mut SyncIo, peer_id: PeerId, transactions: Vec<Vec<u8>>) {
+		// Accept transactions only when fully synced
+		if self.sync.read().status().state != SyncState::Idle {
+			trace!(target: "sync", "{} Ignoring transactions while syncing", peer_id);
+			return;
+		}
+		trace!(target: "sync", "Received {} transactions from {}", transactions.len(), peer_id);
+		let imported: Vec<_> = {
+			let mut peers = self.peers.write();
+			let peer = match peers.get_mut(&peer_id) {
+				Some(peer) => peer,
+				None => return,
+			};
+			if peer.known_transactions.len() + transactions.len() > MAX_KNOWN_TRANSACTIONS {
+				peer.known_transactions.clear();
+			}
+			// the peer knows the transactions it sent, even those the pool rejects.
+			peer.known_transactions.extend(transactions.iter().map(|t| TransactionHash::from(blake2_256(t))));
+			transactions.iter().filter_map(|t| self.transaction_pool.import(t)).collect()
+		};
+
+		if imported.is_empty() {
+			return;
+		}
+
+		{
+			let best_number = self.best_number();
+			let mut stats = self.transaction_stats.write();
+			for hash in imported {
+				stats.entry(hash).or_insert_with(|| TransactionStats {
+					first_seen: best_number,
+					propagated_to: BTreeMap::new(),
+				});
+			}
+		}
+
+		self.propagate_transactions(io);
+	}
+
+	/// Called when new transactions are imported into the pool. Sends the
+	/// transactions to the peers which don't know them yet.
+	pub fn propagate_transactions(&self, io: &mut SyncIo) {
+		// Propagate transactions only when fully synced
+		if self.sync.read().status().state != SyncState::Idle {
+			return;
+		}
+
+		let transactions = self.transaction_pool.transactions();
+		let pool_hashes: HashSet<_> = transactions.iter().map(|&(hash, _)| hash).collect();
+		let mut propagated_to = HashMap::new();
+		let mut messages = Vec::new();
+		{
+			let mut peers = self.peers.write();
+			for (peer_id, peer) in peers.iter_mut() {
+				// light clients don't keep transaction pools.
+				if peer.roles.contains(Role::LIGHT) || peer.protocol_version < BINARY_ENCODING_VERSION {
+					continue;
+				}
+
+				// only the transactions still in the pool need to be remembered.
+				peer.known_transactions.retain(|hash| pool_hashes.contains(hash));
+
+				let (hashes, to_send): (Vec<_>, Vec<_>) = transactions.iter()
+					.filter(|&&(ref hash, _)| peer.known_transactions.insert(*hash))
+					.cloned()
+					.unzip();
+				if to_send.is_empty() {
+					continue;
+				}
+
+				if let Some(node_id) = io.peer_node_id(*peer_id) {
+					for hash in hashes {
+						propagated_to.entry(hash).or_insert_with(Vec::new).push(node_id);
+					}
+				}
+				messages.push((*peer_id, to_send));
+			}
+		}
+
+		for (peer_id, to_send) in messages {
+			trace!(target: "sync", "Sending {} transactions to {}", to_send.len(), peer_id);
+			self.send_message(io, peer_id, Message::Transactions(to_send));
+		}
+
+		// keep the stats of the pool transactions only.
+		let best_number = self.best_number();
+		let mut stats = self.transaction_stats.write();
+		let mut old_stats = mem::replace(&mut *stats, BTreeMap::new());
+		for &(hash, _) in &transactions {
+			let mut entry = old_stats.remove(&hash).unwrap_or_else(|| TransactionStats {
+				first_seen: best_number,
+				propagated_to: BTreeMap::new(),
+			});
+			for node_id in propagated_to.remove(&hash).unwrap_or_default() {
+				*entry.propagated_to.entry(node_id).or_insert(0) += 1;
+			}
+			stats.insert(hash, entry);
+		}
 	}
 
 	pub fn transactions_stats(&self) -> BTreeMap<TransactionHash, TransactionStats> {
-		BTreeMap::new()
+		self.transaction_stats.read().clone()
+	}
+
+	fn best_number(&self) -> BlockNumber {
+		self.chain.info().map(|info| info.chain.best_number).unwrap_or(0)
 	}
 
 	pub fn chain(&self) -> &Client {
 		&*self.chain
 	}
 }
+
+// Whether the message is understood by the peers on the JSON protocol, which only sync blocks.
+fn is_block_sync_message(message: &Message) -> bool {
+	match *message {
+		Message::Status(_) | Message::BlockRequest(_) | Message::BlockResponse(_) | Message::BlockAnnounce(_) => true,
+		_ => false,
+	}
+}
@@ -0,0 +1,241 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Peer reputation. Nodes are tracked by their persistent ID, so that the
+//! reputation survives reconnections. Misbehaving nodes lose reputation and get
+//! banned for a while once it drops below the threshold, useful ones regain it.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use network::NodeId;
+
+// Nodes with reputation below this are banned.
+const BAN_THRESHOLD: i32 = -100;
+// Reputation is capped so that past good behaviour can't cover misbehaviour for long.
+const MAX_REPUTATION: i32 = 100;
+// Reputation the node is left with once its ban expires.
+const PAROLE_REPUTATION: i32 = BAN_THRESHOLD / 2;
+// Ban duration.
+const BAN_DURATION_SEC: u64 = 10 * 60;
+// Nodes are forgotten past this many, the least recently updated first.
+const MAX_NODES: usize = 1024;
+
+/// Peer misbehavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+	/// Packet could not be decoded or was too large.
+	InvalidPacket,
+	/// Peer is on a different chain.
+	GenesisMismatch,
+	/// Peer provided a known bad or incomplete block.
+	BadBlock,
+	/// Response to a request that was not made.
+	UnexpectedResponse,
+	/// Response failed to check.
+	InvalidResponse,
+	/// Consensus message failed to check.
+	InvalidConsensusMessage,
+	/// Validator `Status` signature failed to check.
+	InvalidStatusSignature,
+	/// Request was not answered in time.
+	Timeout,
+}
+
+impl Misbehavior {
+	/// Whether the peer can't be talked to at all, so that the network shouldn't
+	/// connect to it again.
+	pub fn is_fatal(&self) -> bool {
+		match *self {
+			Misbehavior::InvalidPacket | Misbehavior::GenesisMismatch | Misbehavior::InvalidStatusSignature => true,
+			_ => false,
+		}
+	}
+
+	fn penalty(&self) -> i32 {
+		match *self {
+			Misbehavior::InvalidPacket => 100,
+			Misbehavior::GenesisMismatch => 100,
+			Misbehavior::BadBlock => 100,
+			Misbehavior::UnexpectedResponse => 40,
+			Misbehavior::InvalidResponse => 60,
+			Misbehavior::InvalidConsensusMessage => 50,
+			Misbehavior::InvalidStatusSignature => 100,
+			Misbehavior::Timeout => 20,
+		}
+	}
+}
+
+/// Useful peer behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reward {
+	/// Peer provided a block which got imported.
+	ImportedBlock,
+	/// Peer answered a remote request with a valid response.
+	ValidResponse,
+}
+
+impl Reward {
+	fn reward(&self) -> i32 {
+		match *self {
+			Reward::ImportedBlock => 1,
+			Reward::ValidResponse => 5,
+		}
+	}
+}
+
+struct Node {
+	reputation: i32,
+	banned_until: Option<Instant>,
+	// value of the update counter when the node was last reported or rewarded.
+	updated: u64,
+}
+
+/// Reputation of the known nodes.
+#[derive(Default)]
+pub struct Reputation {
+	nodes: HashMap<NodeId, Node>,
+	updates: u64,
+}
+
+impl Reputation {
+	/// Create a new instance.
+	pub fn new() -> Self {
+		Reputation {
+			nodes: HashMap::new(),
+			updates: 0,
+		}
+	}
+
+	/// Get the node reputation.
+	pub fn reputation(&self, node_id: &NodeId) -> i32 {
+		self.nodes.get(node_id).map_or(0, |node| node.reputation)
+	}
+
+	/// Penalize the node. Returns true if the node got banned.
+	pub fn report(&mut self, node_id: &NodeId, misbehavior: Misbehavior) -> bool {
+		let node = self.node(node_id);
+		node.reputation = node.reputation.saturating_sub(misbehavior.penalty());
+		if node.reputation < BAN_THRESHOLD && node.banned_until.is_none() {
+			node.banned_until = Some(Instant::now() + Duration::from_secs(BAN_DURATION_SEC));
+		}
+		node.banned_until.is_some()
+	}
+
+	/// Reward the node.
+	pub fn reward(&mut self, node_id: &NodeId, reward: Reward) {
+		let node = self.node(node_id);
+		node.reputation = cmp::min(node.reputation + reward.reward(), MAX_REPUTATION);
+	}
+
+	/// Check if the node is banned. Lifts the ban once it has expired.
+	pub fn is_banned(&mut self, node_id: &NodeId) -> bool {
+		let now = Instant::now();
+		match self.nodes.get_mut(node_id) {
+			Some(node) => match node.banned_until {
+				Some(until) if until <= now => {
+					node.banned_until = None;
+					node.reputation = PAROLE_REPUTATION;
+					false
+				},
+				Some(_) => true,
+				None => false,
+			},
+			None => false,
+		}
+	}
+
+	// the entry of the node to update, making room for it if needed.
+	fn node(&mut self, node_id: &NodeId) -> &mut Node {
+		if !self.nodes.contains_key(node_id) && self.nodes.len() >= MAX_NODES {
+			self.forget_oldest();
+		}
+		self.updates += 1;
+		let updated = self.updates;
+		let node = self.nodes.entry(*node_id).or_insert_with(|| Node { reputation: 0, banned_until: None, updated });
+		node.updated = updated;
+		node
+	}
+
+	// forget the least recently updated node, keeping the banned ones if possible.
+	fn forget_oldest(&mut self) {
+		let oldest = self.nodes.iter()
+			.min_by_key(|&(_, node)| (node.banned_until.is_some(), node.updated))
+			.map(|(node_id, _)| *node_id);
+		if let Some(node_id) = oldest {
+			self.nodes.remove(&node_id);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn repeated_misbehavior_leads_to_ban() {
+		let mut reputation = Reputation::new();
+		let node = NodeId::from(1);
+		for _ in 0..5 {
+			assert!(!reputation.report(&node, Misbehavior::Timeout));
+		}
+		assert_eq!(reputation.reputation(&node), -100);
+		assert!(!reputation.is_banned(&node));
+		assert!(reputation.report(&node, Misbehavior::Timeout));
+		assert!(reputation.is_banned(&node));
+		assert!(!reputation.is_banned(&NodeId::from(2)));
+	}
+
+	#[test]
+	fn rewards_are_capped() {
+		let mut reputation = Reputation::new();
+		let node = NodeId::from(1);
+		for _ in 0..50 {
+			reputation.reward(&node, Reward::ValidResponse);
+		}
+		assert_eq!(reputation.reputation(&node), MAX_REPUTATION);
+		assert!(!reputation.report(&node, Misbehavior::BadBlock));
+		assert!(!reputation.report(&node, Misbehavior::BadBlock));
+		assert!(reputation.report(&node, Misbehavior::BadBlock));
+	}
+
+	#[test]
+	fn ban_expires() {
+		let mut reputation = Reputation::new();
+		let node = NodeId::from(1);
+		reputation.report(&node, Misbehavior::InvalidPacket);
+		assert!(reputation.report(&node, Misbehavior::InvalidPacket));
+		reputation.nodes.get_mut(&node).unwrap().banned_until = Some(Instant::now());
+		assert!(!reputation.is_banned(&node));
+		assert_eq!(reputation.reputation(&node), PAROLE_REPUTATION);
+	}
+
+	#[test]
+	fn least_recently_updated_nodes_are_forgotten() {
+		let mut reputation = Reputation::new();
+		let banned = NodeId::from(0);
+		reputation.report(&banned, Misbehavior::InvalidPacket);
+		assert!(reputation.report(&banned, Misbehavior::InvalidPacket));
+		for i in 1..MAX_NODES as u64 + 2 {
+			reputation.reward(&NodeId::from(i), Reward::ImportedBlock);
+		}
+		assert_eq!(reputation.nodes.len(), MAX_NODES);
+		assert!(reputation.is_banned(&banned));
+		assert_eq!(reputation.reputation(&NodeId::from(1)), 0);
+		assert_eq!(reputation.reputation(&NodeId::from(2)), 0);
+		assert_eq!(reputation.reputation(&NodeId::from(3)), 1);
+	}
+}
@@ -14,18 +14,26 @@
 // You should have received a copy of the GNU General Public License
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
 
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::collections::{BTreeMap};
-use std::io;
+use std::{io, thread};
+use futures::Stream;
 use network::{NetworkProtocolHandler, NetworkService, NetworkContext, HostInfo, PeerId, ProtocolId,
 NetworkConfiguration , NonReservedPeerMode, ErrorKind};
-use primitives::block::{TransactionHash, Header};
+use primitives::AuthorityId;
+use primitives::block::{HeaderHash, TransactionHash};
 use core_io::{TimerToken};
 use io::NetSyncIo;
 use protocol::{Protocol, ProtocolStatus, PeerInfo as ProtocolPeerInfo, TransactionStats};
 use config::{ProtocolConfig};
 use error::Error;
 use chain::Client;
+use client::ImportNotifications;
+use message::Message;
+use on_demand::{OnDemand, OnDemandNetwork};
+use import_queue::AsyncImportQueue;
+use consensus::{BftNetwork, BftMessageStream};
+use bft;
 
 /// Polkadot devp2p protocol id
 pub const DOT_PROTOCOL_ID: ProtocolId = *b"dot";
@@ -52,6 +60,16 @@ pub trait SyncProvider: Send + Sync {
 	fn transactions_stats(&self) -> BTreeMap<TransactionHash, TransactionStats>;
 }
 
+/// Transaction pool interface used by the network to gossip transactions.
+pub trait TransactionPool: Send + Sync {
+	/// Get the ready transactions in the pool, encoded, along with their hashes.
+	/// A transaction hash is the blake2 hash of its encoding.
+	fn transactions(&self) -> Vec<(TransactionHash, Vec<u8>)>;
+	/// Import an encoded transaction received from the network into the pool.
+	/// Returns the transaction hash if it was imported.
+	fn import(&self, transaction: &[u8]) -> Option<TransactionHash>;
+}
+
 /// Peer connection information
 #[derive(Debug)]
 pub struct PeerInfo {
@@ -77,6 +95,10 @@ pub struct Params {
 	pub network_config: NetworkConfiguration,
 	/// Polkadot relay chain access point.
 	pub chain: Arc<Client>,
+	/// On-demand service reference. Light clients use it to fetch remote data.
+	pub on_demand: Option<Arc<OnDemand>>,
+	/// Transaction pool.
+	pub transaction_pool: Arc<TransactionPool>,
 }
 
 /// Polkadot network service. Handles network IO and manages connectivity.
@@ -92,25 +114,50 @@ impl Service {
 	pub fn new(params: Params) -> Result<Arc<Service>, Error> {
 
 		let service = NetworkService::new(params.network_config.clone(), None)?;
+		let best_blocks = params.chain.best_block_notification_stream();
 
 		let sync = Arc::new(Service {
 			network: service,
 			handler: Arc::new(ProtocolHandler {
-				protocol: Protocol::new(params.config, params.chain.clone())?,
+				protocol: Protocol::new(params.config, params.chain.clone(), Arc::new(AsyncImportQueue::new(params.chain.clone())), params.on_demand.clone(), params.transaction_pool)?,
 			}),
 		});
+		if let Some(on_demand) = params.on_demand {
+			on_demand.set_service_link(Arc::downgrade(&sync));
+		}
+
+		let handler = Arc::downgrade(&sync.handler);
+		thread::Builder::new()
+			.name("network-import-notifications".into())
+			.spawn(move || Self::handle_best_blocks(handler, best_blocks))?;
 
 		Ok(sync)
 	}
 
-	/// Called when a new block is imported by the client.
-	pub fn on_block_imported(&self, header: &Header) {
-		self.handler.protocol.on_block_imported(header)
+	// keep sync informed about the new best blocks until the service is dropped.
+	fn handle_best_blocks(handler: Weak<ProtocolHandler>, best_blocks: ImportNotifications) {
+		for notification in best_blocks.wait() {
+			let notification = match notification {
+				Ok(notification) => notification,
+				Err(_) => break,
+			};
+			match handler.upgrade() {
+				Some(handler) => handler.protocol.on_block_imported(&notification.header),
+				None => break,
+			}
+		}
 	}
 
-	/// Called when new transactons are imported by the client.
+	/// Start gossiping the BFT messages of the round built on top of the given
+	/// block with the given authorities. Returns the stream of their checked
+	/// messages; the stream of the previous round ends.
+	pub fn bft_messages<E>(&self, parent_hash: HeaderHash, authorities: Vec<AuthorityId>) -> BftMessageStream<E> {
+		self.handler.protocol.start_bft_round(parent_hash, authorities)
+	}
+
+	/// Called when new transactons are imported into the pool.
 	pub fn on_new_transactions(&self) {
-		self.handler.protocol.on_new_transactions()
+		self.network.with_context(DOT_PROTOCOL_ID, |ctx| self.handler.protocol.propagate_transactions(&mut NetSyncIo::new(ctx)));
 	}
 
 	fn start(&self) {
@@ -168,13 +215,26 @@ impl SyncProvider for Service {
 	}
 }
 
+impl OnDemandNetwork for Service {
+	fn send_request(&self, peer: PeerId, message: Message) {
+		self.network.with_context(DOT_PROTOCOL_ID, |ctx| self.handler.protocol.send_message(&mut NetSyncIo::new(ctx), peer, message));
+	}
+}
+
+impl BftNetwork for Service {
+	fn send_bft_message(&self, parent_hash: HeaderHash, message: bft::Communication) {
+		self.network.with_context(DOT_PROTOCOL_ID, |ctx| self.handler.protocol.send_bft_message(&mut NetSyncIo::new(ctx), parent_hash, message));
+	}
+}
+
 struct ProtocolHandler {
 	/// Protocol handler
 	protocol: Protocol,
 }
 
 impl NetworkProtocolHandler for ProtocolHandler {
-	fn initialize(&self, io: &NetworkContext, _host_info: &HostInfo) {
+	fn initialize(&self, io: &NetworkContext, host_info: &HostInfo) {
+		self.protocol.set_local_node_id(host_info.id().clone());
 		io.register_timer(0, 1000).expect("Error registering sync timer");
 	}
 
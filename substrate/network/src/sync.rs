@@ -14,18 +14,27 @@
 // You should have received a copy of the GNU General Public License
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
 
+use std::cmp;
 use std::collections::HashMap;
+use std::sync::Arc;
 use io::SyncIo;
 use protocol::Protocol;
 use network::PeerId;
-use client::{ImportResult, BlockStatus, ClientInfo};
+use client::{BlockStatus, ClientInfo};
 use primitives::block::{HeaderHash, Number as BlockNumber, Header, Id as BlockId};
 use blocks::{self, BlockCollection};
+use import_queue::{ImportQueue, ImportOutcome};
 use message::{self, Message};
+use reputation::{Misbehavior, Reward};
+use service::Role;
 use super::header_hash;
 
 // Maximum blocks to request in a single packet.
 const MAX_BLOCKS_TO_REQUEST: usize = 128;
+// Minimum blocks to request from a peer which keeps timing out.
+const MIN_BLOCKS_TO_REQUEST: usize = 8;
+// Maximum blocks downloaded ahead of the best imported block.
+const MAX_BLOCKS_IN_FLIGHT: BlockNumber = 2048;
 
 struct PeerSync {
 	pub common_hash: HeaderHash,
@@ -33,6 +42,7 @@ struct PeerSync {
 	pub best_hash: HeaderHash,
 	pub best_number: BlockNumber,
 	pub state: PeerSyncState,
+	pub request_size: usize,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -50,7 +60,9 @@ pub struct ChainSync {
 	blocks: BlockCollection,
 	best_queued_number: BlockNumber,
 	best_queued_hash: HeaderHash,
+	best_imported_number: BlockNumber,
 	required_block_attributes: Vec<message::BlockAttribute>,
+	import_queue: Arc<ImportQueue>,
 }
 
 /// Reported sync state.
@@ -72,15 +84,23 @@ pub struct Status {
 }
 
 impl ChainSync {
-	/// Create a new instance.
-	pub fn new(info: &ClientInfo) -> ChainSync {
+	/// Create a new instance. Light clients download headers and justifications only.
+	/// Downloaded blocks are passed to the import queue.
+	pub fn new(role: Role, info: &ClientInfo, import_queue: Arc<ImportQueue>) -> ChainSync {
+		let mut required_block_attributes = vec![message::BlockAttribute::Header, message::BlockAttribute::Justification];
+		if role.contains(Role::FULL) {
+			required_block_attributes.push(message::BlockAttribute::Body);
+		}
+
 		ChainSync {
 			genesis_hash: info.chain.genesis_hash,
 			peers: HashMap::new(),
 			blocks: BlockCollection::new(),
 			best_queued_hash: info.best_queued_hash.unwrap_or(info.chain.best_hash),
 			best_queued_number: info.best_queued_number.unwrap_or(info.chain.best_number),
-			required_block_attributes: vec![message::BlockAttribute::Header, message::BlockAttribute::Body, message::BlockAttribute::Justification],
+			best_imported_number: info.chain.best_number,
+			required_block_attributes: required_block_attributes,
+			import_queue: import_queue,
 		}
 	}
 
@@ -99,6 +119,10 @@ impl ChainSync {
 
 	pub fn new_peer(&mut self, io: &mut SyncIo, protocol: &Protocol, peer_id: PeerId) {
 		if let Some(info) = protocol.peer_info(peer_id) {
+			if self.requires_body() && !info.roles.contains(Role::FULL) {
+				trace!(target:"sync", "Not syncing from peer {} which doesn't keep block bodies.", peer_id);
+				return;
+			}
 			match (protocol.chain().block_status(&BlockId::Hash(info.best_hash)), info.best_number) {
 				(Err(e), _) => {
 					debug!(target:"sync", "Error reading blockchain: {:?}", e);
@@ -106,11 +130,11 @@ impl ChainSync {
 				},
 				(Ok(BlockStatus::KnownBad), _) => {
 					debug!(target:"sync", "New peer with known bad best block {} ({}).", info.best_hash, info.best_number);
-					io.disable_peer(peer_id);
+					protocol.report_peer(io, peer_id, Misbehavior::BadBlock);
 				},
 				(Ok(BlockStatus::Unknown), 0) => {
 					debug!(target:"sync", "New peer with unkown genesis hash {} ({}).", info.best_hash, info.best_number);
-					io.disable_peer(peer_id);
+					protocol.report_peer(io, peer_id, Misbehavior::GenesisMismatch);
 				},
 				(Ok(BlockStatus::Unknown), _) => {
 					let our_best = self.best_queued_number;
@@ -122,6 +146,7 @@ impl ChainSync {
 							best_hash: info.best_hash,
 							best_number: info.best_number,
 							state: PeerSyncState::AncestorSearch(our_best),
+							request_size: MAX_BLOCKS_TO_REQUEST,
 						});
 						Self::request_ancestry(io, protocol, peer_id, our_best)
 					} else {
@@ -133,6 +158,7 @@ impl ChainSync {
 							best_hash: info.best_hash,
 							best_number: info.best_number,
 							state: PeerSyncState::Available,
+							request_size: MAX_BLOCKS_TO_REQUEST,
 						});
 						self.download_new(io, protocol, peer_id)
 					}
@@ -145,20 +171,23 @@ impl ChainSync {
 						best_hash: info.best_hash,
 						best_number: info.best_number,
 						state: PeerSyncState::Available,
+						request_size: MAX_BLOCKS_TO_REQUEST,
 					});
 				}
 			}
 		}
 	}
 
-	pub fn on_block_data(&mut self, io: &mut SyncIo, protocol: &Protocol, peer_id: PeerId, _request: message::BlockRequest, response: message::BlockResponse) {
+	pub fn on_block_data(&mut self, io: &mut SyncIo, protocol: &Protocol, peer_id: PeerId, request: message::BlockRequest, response: message::BlockResponse) {
 		let count = response.blocks.len();
-		let mut imported: usize = 0;
 		let new_blocks = if let Some(ref mut peer) = self.peers.get_mut(&peer_id) {
 			match peer.state {
 				PeerSyncState::DownloadingNew(start_block) => {
 					self.blocks.clear_peer_download(peer_id);
 					peer.state = PeerSyncState::Available;
+					if request.max.map_or(false, |max| count >= max as usize) {
+						peer.request_size = cmp::min(peer.request_size * 2, MAX_BLOCKS_TO_REQUEST);
+					}
 
 					self.blocks.insert(start_block, response.blocks, peer_id);
 					self.blocks.drain(self.best_queued_number + 1)
@@ -188,7 +217,7 @@ impl ChainSync {
 									return;
 								},
 								Ok(_) => { // genesis mismatch
-									io.disable_peer(peer_id);
+									protocol.report_peer(io, peer_id, Misbehavior::GenesisMismatch);
 									return;
 								},
 								Err(e) => {
@@ -200,7 +229,7 @@ impl ChainSync {
 						},
 						None => {
 							trace!(target:"sync", "Invalid response when searching for ancestor from {}", peer_id);
-							io.disconnect_peer(peer_id);
+							protocol.report_peer(io, peer_id, Misbehavior::InvalidResponse);
 							return;
 						}
 					}
@@ -212,68 +241,109 @@ impl ChainSync {
 		};
 
 		// Blocks in the response/drain should be in ascending order.
+		let mut queued = Vec::with_capacity(new_blocks.len());
 		for block in new_blocks {
 			let origin = block.origin;
-			let block = block.block;
-			match (block.header, block.justification) {
-				(Some(header), Some(justification)) => {
-					let number = header.number;
-					let hash = header_hash(&header);
-					let parent = header.parent_hash;
-					let result = protocol.chain().import(
-						header,
-						justification,
-						block.body
-					);
-					match result {
-						Ok(ImportResult::AlreadyInChain) => {
-							trace!(target: "sync", "Block already in chain {}: {:?}", number, hash);
-							self.block_imported(&hash, number);
-						},
-						Ok(ImportResult::AlreadyQueued) => {
-							trace!(target: "sync", "Block already queued {}: {:?}", number, hash);
-							self.block_imported(&hash, number);
-						},
-						Ok(ImportResult::Queued) => {
-							trace!(target: "sync", "Block queued {}: {:?}", number, hash);
-							self.block_imported(&hash, number);
-							imported = imported + 1;
-						},
-						Ok(ImportResult::UnknownParent) => {
-							debug!(target: "sync", "Block with unknown parent {}: {:?}, parent: {:?}", number, hash, parent);
-							self.restart(io, protocol);
-							return;
-						},
-						Ok(ImportResult::KnownBad) => {
-							debug!(target: "sync", "Bad block {}: {:?}", number, hash);
-							io.disable_peer(origin); //TODO: use persistent ID
-							self.restart(io, protocol);
-							return;
-						}
-						Err(e) => {
-							debug!(target: "sync", "Error importing block {}: {:?}: {:?}", number, hash, e);
-							self.restart(io, protocol);
-							return;
-						}
-					}
+			if block.block.body.is_none() && self.requires_body() {
+				debug!(target: "sync", "Body for block {} was not provided by {} ", block.block.hash, origin);
+				protocol.report_peer(io, origin, Misbehavior::BadBlock);
+				break;
+			}
+			match (&block.block.header, &block.block.justification) {
+				(&Some(ref header), &Some(_)) => {
+					let hash = header_hash(header);
+					trace!(target: "sync", "Queueing block {}: {:?}", header.number, hash);
+					self.block_imported(&hash, header.number);
 				},
-				(None, _) => {
-					debug!(target: "sync", "Header {} was not provided by {} ", block.hash, origin);
-					io.disable_peer(origin); //TODO: use persistent ID
-					return;
+				(&None, _) => {
+					debug!(target: "sync", "Header {} was not provided by {} ", block.block.hash, origin);
+					protocol.report_peer(io, origin, Misbehavior::BadBlock);
+					break;
 				},
-				(_, None) => {
-					debug!(target: "sync", "Justification set for block {} was not provided by {} ", block.hash, origin);
-					io.disable_peer(origin); //TODO: use persistent ID
-					return;
+				(_, &None) => {
+					debug!(target: "sync", "Justification set for block {} was not provided by {} ", block.block.hash, origin);
+					protocol.report_peer(io, origin, Misbehavior::BadBlock);
+					break;
 				}
 			}
+			queued.push(block);
 		}
-		trace!(target: "sync", "Imported {} of {}", imported, count);
+		trace!(target: "sync", "Queued {} of {}", queued.len(), count);
+		if !queued.is_empty() {
+			self.import_queue.import_blocks(queued);
+		}
+		self.maintain_import(io, protocol);
 		self.maintain_sync(io, protocol);
 	}
 
-	fn maintain_sync(&mut self, io: &mut SyncIo, protocol: &Protocol) {
+	/// Process the outcome of the block imports completed so far. Peers are rewarded for the
+	/// imported blocks and sync is restarted when a block fails to import.
+	pub fn maintain_import(&mut self, io: &mut SyncIo, protocol: &Protocol) {
+		let mut restart = false;
+		for report in self.import_queue.take_reports() {
+			match report.outcome {
+				ImportOutcome::Imported => {
+					trace!(target: "sync", "Block imported {}: {:?}", report.number, report.hash);
+					self.best_imported_number = cmp::max(self.best_imported_number, report.number);
+					protocol.reward_peer(io, report.origin, Reward::ImportedBlock);
+				},
+				ImportOutcome::Known => {
+					trace!(target: "sync", "Block already known {}: {:?}", report.number, report.hash);
+					self.best_imported_number = cmp::max(self.best_imported_number, report.number);
+				},
+				ImportOutcome::Bad => {
+					debug!(target: "sync", "Bad block {}: {:?}", report.number, report.hash);
+					protocol.report_peer(io, report.origin, Misbehavior::BadBlock);
+					restart = true;
+				},
+				ImportOutcome::UnknownParent => {
+					debug!(target: "sync", "Block with unknown parent {}: {:?}", report.number, report.hash);
+					restart = true;
+				},
+				ImportOutcome::Error => {
+					restart = true;
+				},
+			}
+		}
+		if restart {
+			self.restart(io, protocol);
+		}
+	}
+
+	/// Called when a block request to the peer has timed out. The range is released so that other
+	/// peers can download it and the peer is asked for less blocks next time. Returns false if
+	/// the peer should be dropped.
+	pub fn on_request_timeout(&mut self, io: &mut SyncIo, protocol: &Protocol, peer_id: PeerId) -> bool {
+		let keep = match self.peers.get_mut(&peer_id) {
+			Some(ref mut peer) => match peer.state {
+				PeerSyncState::DownloadingNew(_) if peer.request_size > MIN_BLOCKS_TO_REQUEST => {
+					self.blocks.clear_peer_download(peer_id);
+					peer.state = PeerSyncState::Available;
+					peer.request_size = cmp::max(peer.request_size / 2, MIN_BLOCKS_TO_REQUEST);
+					trace!(target: "sync", "Block request to {} timed out, requesting {} blocks next time", peer_id, peer.request_size);
+					true
+				},
+				_ => false,
+			},
+			None => false,
+		};
+		if keep {
+			// offer the released range to the other peers first.
+			let peers: Vec<PeerId> = self.peers.keys().filter(|p| **p != peer_id).map(|p| *p).collect();
+			for peer in peers {
+				self.download_new(io, protocol, peer);
+			}
+			self.download_new(io, protocol, peer_id);
+		}
+		keep
+	}
+
+	fn requires_body(&self) -> bool {
+		self.required_block_attributes.contains(&message::BlockAttribute::Body)
+	}
+
+	/// Issue new block requests to the available peers.
+	pub fn maintain_sync(&mut self, io: &mut SyncIo, protocol: &Protocol) {
 		let peers: Vec<PeerId> = self.peers.keys().map(|p| *p).collect();
 		for peer in peers {
 			self.download_new(io, protocol, peer);
@@ -330,6 +400,7 @@ impl ChainSync {
 
 	fn is_known_or_already_downloading(&self, protocol: &Protocol, hash: &HeaderHash) -> bool {
 		self.peers.iter().any(|(_, p)| p.state == PeerSyncState::DownloadingStale(*hash))
+			|| self.import_queue.is_importing(hash)
 			|| protocol.chain().block_status(&BlockId::Hash(*hash)).ok().map_or(false, |s| s != BlockStatus::Unknown)
 	}
 
@@ -340,25 +411,29 @@ impl ChainSync {
 	}
 
 	pub fn restart(&mut self, io: &mut SyncIo, protocol: &Protocol) {
+		self.import_queue.clear();
 		self.blocks.clear();
-		let ids: Vec<PeerId> = self.peers.keys().map(|p| *p).collect();
-		for id in ids {
-			self.new_peer(io, protocol, id);
-		}
 		match protocol.chain().info() {
 			Ok(info) => {
 				self.best_queued_hash = info.best_queued_hash.unwrap_or(info.chain.best_hash);
 				self.best_queued_number = info.best_queued_number.unwrap_or(info.chain.best_number);
+				self.best_imported_number = info.chain.best_number;
 			},
 			Err(e) => {
 				debug!(target:"sync", "Error reading blockchain: {:?}", e);
 				self.best_queued_hash = self.genesis_hash;
 				self.best_queued_number = 0;
+				self.best_imported_number = 0;
 			}
 		}
+		let ids: Vec<PeerId> = self.peers.keys().map(|p| *p).collect();
+		for id in ids {
+			self.new_peer(io, protocol, id);
+		}
 	}
 
 	pub fn clear(&mut self) {
+		self.import_queue.clear();
 		self.blocks.clear();
 		self.peers.clear();
 	}
@@ -390,7 +465,9 @@ impl ChainSync {
 			trace!(target: "sync", "Considering new block download from {}, common block is {}, best is {:?}", peer_id, peer.common_number, peer.best_number);
 			match peer.state {
 				PeerSyncState::Available => {
-					if let Some(range) = self.blocks.needed_blocks(peer_id, MAX_BLOCKS_TO_REQUEST, peer.best_number, peer.common_number) {
+					// Don't get too far ahead of the import.
+					let max_number = cmp::min(peer.best_number, self.best_imported_number + MAX_BLOCKS_IN_FLIGHT);
+					if let Some(range) = self.blocks.needed_blocks(peer_id, peer.request_size, max_number, peer.common_number) {
 						trace!(target: "sync", "Requesting blocks from {}, ({} to {})", peer_id, range.start, range.end);
 						let request = message::BlockRequest {
 							id: 0,
@@ -0,0 +1,145 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.?
+
+use futures::Stream;
+use primitives::block::HeaderHash;
+use consensus::MAX_PENDING_MESSAGES_PER_PEER;
+use message::{self, Message};
+use serde_json;
+use service::Role;
+use ed25519;
+use super::*;
+
+fn validators(n: usize) -> TestNet {
+	TestNet::new_with_config(n, ProtocolConfig {
+		roles: Role::FULL | Role::VALIDATOR,
+		validator_key: Some(Arc::new(Keyring::Alice.into())),
+	})
+}
+
+fn signed_vote(key: Keyring, parent_hash: HeaderHash, round: usize) -> bft::Communication {
+	let vote = bft::generic::Vote::Prepare(round, [0xff; 32].into());
+	bft::generic::Communication::Consensus(bft::sign_message(vote.into(), &key.into(), parent_hash))
+}
+
+fn send_vote(net: &TestNet, peer: usize, key: Keyring, parent_hash: HeaderHash) {
+	send_round_vote(net, peer, key, parent_hash, 1);
+}
+
+fn send_round_vote(net: &TestNet, peer: usize, key: Keyring, parent_hash: HeaderHash, round: usize) {
+	let peer = net.peer(peer);
+	peer.sync.send_bft_message(&mut TestIo::new(&peer.queue, None), parent_hash, signed_vote(key, parent_hash, round));
+}
+
+fn is_vote(message: Option<Result<bft::Communication, ()>>) -> bool {
+	match message {
+		Some(Ok(bft::generic::Communication::Consensus(bft::generic::LocalizedMessage::Vote(_)))) => true,
+		_ => false,
+	}
+}
+
+#[test]
+fn bft_messages_are_delivered_to_round_authorities() {
+	::env_logger::init().ok();
+	let mut net = validators(3);
+	net.sync();
+	let parent_hash = net.peer(0).client.info().unwrap().chain.genesis_hash;
+	let authorities = vec![Keyring::Alice.to_raw_public()];
+	let mut messages = net.peer(1).sync.start_bft_round::<()>(parent_hash, authorities.clone()).wait();
+	net.peer(0).sync.start_bft_round::<()>(parent_hash, authorities);
+
+	send_vote(&net, 0, Keyring::Alice, parent_hash);
+	net.sync();
+	assert!(is_vote(messages.next()));
+	assert!(net.disconnect_events.is_empty());
+}
+
+#[test]
+fn bft_messages_received_ahead_of_round_are_delivered() {
+	::env_logger::init().ok();
+	let mut net = validators(2);
+	net.sync();
+	let parent_hash = net.peer(0).client.info().unwrap().chain.genesis_hash;
+
+	send_vote(&net, 0, Keyring::Alice, parent_hash);
+	net.sync();
+	let mut messages = net.peer(1).sync.start_bft_round::<()>(parent_hash, vec![Keyring::Alice.to_raw_public()]).wait();
+	assert!(is_vote(messages.next()));
+}
+
+#[test]
+fn bft_message_from_non_authority_disables_peer() {
+	::env_logger::init().ok();
+	let mut net = validators(2);
+	net.sync();
+	let parent_hash = net.peer(0).client.info().unwrap().chain.genesis_hash;
+	net.peer(1).sync.start_bft_round::<()>(parent_hash, vec![Keyring::Alice.to_raw_public()]);
+
+	send_vote(&net, 0, Keyring::Bob, parent_hash);
+	net.sync();
+	assert!(net.disconnect_events.contains(&(0, 0)));
+}
+
+#[test]
+fn bft_messages_are_not_sent_to_non_validators() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(2);
+	net.sync();
+	let parent_hash = net.peer(0).client.info().unwrap().chain.genesis_hash;
+
+	send_vote(&net, 0, Keyring::Alice, parent_hash);
+	assert!(net.done());
+}
+
+#[test]
+fn bft_messages_ahead_of_round_are_capped_per_peer() {
+	::env_logger::init().ok();
+	let mut net = validators(2);
+	net.sync();
+	let parent_hash = net.peer(0).client.info().unwrap().chain.genesis_hash;
+
+	for round in 0..MAX_PENDING_MESSAGES_PER_PEER + 10 {
+		send_round_vote(&net, 0, Keyring::Alice, parent_hash, round);
+	}
+	net.sync();
+	let messages = net.peer(1).sync.start_bft_round::<()>(parent_hash, vec![Keyring::Alice.to_raw_public()]);
+	// starting another round ends the stream of the first one.
+	net.peer(1).sync.start_bft_round::<()>([1; 32].into(), vec![Keyring::Alice.to_raw_public()]);
+	assert_eq!(messages.wait().count(), MAX_PENDING_MESSAGES_PER_PEER);
+}
+
+#[test]
+fn validator_status_signed_for_another_node_is_rejected() {
+	::env_logger::init().ok();
+	let net = validators(2);
+	let peer = net.peer(1);
+	let genesis_hash = peer.client.info().unwrap().chain.genesis_hash;
+	let key: ed25519::Pair = Keyring::Alice.into();
+	let status = Message::Status(message::Status {
+		version: 1,
+		roles: vec![message::Role::Full, message::Role::Validator],
+		best_number: 0,
+		best_hash: genesis_hash,
+		genesis_hash: genesis_hash,
+		validator_signature: Some(key.sign(&node_id(2)[..])),
+		validator_id: Some(Keyring::Alice.to_raw_public()),
+		parachain_id: None,
+	});
+
+	peer.on_connect(0);
+	let disconnected = peer.receive_message(0, TestPacket { data: serde_json::to_vec(&status).unwrap(), recipient: 1 });
+	assert!(disconnected.contains(&0));
+}
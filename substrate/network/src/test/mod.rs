@@ -15,19 +15,23 @@
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
 
 mod sync;
+mod consensus;
+mod transactions;
 
 use std::collections::{VecDeque, HashSet, HashMap};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use client::{self, genesis};
 use client::block_builder::BlockBuilder;
-use primitives::block::Id as BlockId;
+use primitives::block::{Id as BlockId, TransactionHash};
 use primitives;
 use executor;
 use io::SyncIo;
 use protocol::Protocol;
+use import_queue::SyncImportQueue;
+use service::TransactionPool;
 use config::ProtocolConfig;
-use network::{PeerId, SessionInfo, Error as NetworkError};
+use network::{PeerId, NodeId, SessionInfo, Error as NetworkError};
 use test_runtime::genesismap::{GenesisConfig, additional_storage_with_genesis};
 use runtime_support::Hashable;
 use test_runtime;
@@ -93,6 +97,15 @@ impl<'p> SyncIo for TestIo<'p> {
 	fn peer_session_info(&self, _peer_id: PeerId) -> Option<SessionInfo> {
 		None
 	}
+
+	fn peer_node_id(&self, peer_id: PeerId) -> Option<NodeId> {
+		Some(node_id(peer_id))
+	}
+}
+
+/// Node id of the test network peer with the given index.
+pub fn node_id(peer_id: PeerId) -> NodeId {
+	NodeId::from(peer_id as u64)
 }
 
 /// Mocked subprotocol packet
@@ -101,10 +114,32 @@ pub struct TestPacket {
 	pub recipient: PeerId,
 }
 
+#[derive(Default)]
+pub struct TestTransactionPool {
+	transactions: RwLock<Vec<(TransactionHash, Vec<u8>)>>,
+}
+
+impl TransactionPool for TestTransactionPool {
+	fn transactions(&self) -> Vec<(TransactionHash, Vec<u8>)> {
+		self.transactions.read().clone()
+	}
+
+	fn import(&self, transaction: &[u8]) -> Option<TransactionHash> {
+		let hash = primitives::hashing::blake2_256(transaction).into();
+		let mut transactions = self.transactions.write();
+		if transactions.iter().any(|&(h, _)| h == hash) {
+			return None;
+		}
+		transactions.push((hash, transaction.to_vec()));
+		Some(hash)
+	}
+}
+
 pub struct Peer {
 	client: Arc<client::Client<client::in_mem::Backend, executor::NativeExecutor<Executor>>>,
 	pub sync: Protocol,
 	pub queue: RwLock<VecDeque<TestPacket>>,
+	pub transaction_pool: Arc<TestTransactionPool>,
 }
 
 impl Peer {
@@ -246,13 +281,16 @@ impl TestNet {
 			disconnect_events: Vec::new(),
 		};
 
-		for _ in 0..n {
+		for i in 0..n {
 			let client = Arc::new(client::new_in_mem(Executor::new(), Self::prepare_genesis).unwrap());
-			let sync = Protocol::new(config.clone(), client.clone()).unwrap();
+			let transaction_pool = Arc::new(TestTransactionPool::default());
+			let sync = Protocol::new(config.clone(), client.clone(), Arc::new(SyncImportQueue::new(client.clone())), None, transaction_pool.clone()).unwrap();
+			sync.set_local_node_id(node_id(i));
 			net.peers.push(Arc::new(Peer {
 				sync: sync,
 				client: client,
 				queue: RwLock::new(VecDeque::new()),
+				transaction_pool: transaction_pool,
 			}));
 		}
 		net
@@ -15,7 +15,13 @@
 // along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
 
 use client::backend::Backend;
+use codec::Slicable;
+use blocks;
+use import_queue::{ImportQueue, AsyncImportQueue, ImportOutcome};
+use message::{self, Message};
+use serde_json;
 use sync::SyncState;
+use service::Role;
 use super::*;
 
 #[test]
@@ -85,3 +91,256 @@ fn sync_after_fork_works() {
 	assert!(net.peer(2).client.backend().blockchain().canon_equals_to(&peer1_chain));
 }
 
+#[test]
+fn light_peer_syncs_headers_only() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new_with_config(2, ProtocolConfig { roles: Role::LIGHT, validator_key: None });
+	net.peer(1).push_blocks(10, false);
+	net.sync();
+	assert!(net.peer(0).client.backend().blockchain().canon_equals_to(net.peer(1).client.backend().blockchain()));
+	assert!(net.peer(0).client.body(&BlockId::Number(10)).unwrap().is_none());
+}
+
+#[test]
+fn messages_are_encoded_according_to_peer_version() {
+	::env_logger::init().ok();
+	let net = TestNet::new(1);
+	let peer = net.peer(0);
+	let genesis_hash = peer.client.info().unwrap().chain.genesis_hash;
+	let status = |version| Message::Status(message::Status {
+		version: version,
+		roles: vec![message::Role::Full],
+		best_number: 0,
+		best_hash: genesis_hash,
+		genesis_hash: genesis_hash,
+		validator_signature: None,
+		validator_id: None,
+		parachain_id: None,
+	});
+
+	peer.on_connect(1);
+	peer.on_connect(2);
+	peer.receive_message(1, TestPacket { data: serde_json::to_vec(&status(0)).unwrap(), recipient: 0 });
+	peer.receive_message(2, TestPacket { data: serde_json::to_vec(&status(1)).unwrap(), recipient: 0 });
+	peer.queue.write().clear();
+
+	let announce = message::BlockAnnounce { header: peer.client.header(&BlockId::Number(0)).unwrap().unwrap() };
+	peer.sync.send_message(&mut TestIo::new(&peer.queue, None), 1, Message::BlockAnnounce(announce.clone()));
+	peer.sync.send_message(&mut TestIo::new(&peer.queue, None), 2, Message::BlockAnnounce(announce.clone()));
+
+	let packets: Vec<_> = peer.queue.write().drain(..).collect();
+	assert_eq!(packets.len(), 2);
+	assert_eq!(packets[0].recipient, 1);
+	assert_eq!(serde_json::from_slice::<Message>(&packets[0].data).unwrap(), Message::BlockAnnounce(announce.clone()));
+	assert_eq!(packets[1].recipient, 2);
+	assert_eq!(Message::decode(&mut &packets[1].data[..]), Some(Message::BlockAnnounce(announce)));
+}
+
+#[test]
+fn packet_with_huge_length_prefix_is_rejected() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(2);
+	net.sync();
+	// `Transactions` claiming far more entries than the packet holds.
+	let mut data = vec![11];
+	data.extend(u32::max_value().encode());
+	data.extend(&[1, 2, 3]);
+	let disconnected = net.peer(0).receive_message(1, TestPacket { data, recipient: 0 });
+	assert!(disconnected.contains(&1));
+}
+
+#[test]
+fn oversized_packet_disables_peer() {
+	::env_logger::init().ok();
+	let net = TestNet::new(1);
+	let to_disconnect = net.peer(0).receive_message(1, TestPacket { data: vec![0; 16 * 1024 * 1024 + 1], recipient: 0 });
+	assert!(to_disconnect.contains(&1));
+}
+
+/// Deliver the packets the peers have queued so far.
+fn deliver_pending(net: &TestNet) {
+	for (i, peer) in net.peers.iter().enumerate() {
+		let packets: Vec<_> = peer.queue.write().drain(..).collect();
+		for packet in packets {
+			let recipient = packet.recipient;
+			net.peer(recipient).receive_message(i as PeerId, packet);
+		}
+	}
+}
+
+/// Take the block requests queued by the peer, dropping its other packets.
+fn take_block_requests(peer: &Peer) -> Vec<(PeerId, message::BlockRequest)> {
+	peer.queue.write().drain(..).filter_map(|packet| match Message::decode(&mut &packet.data[..]) {
+		Some(Message::BlockRequest(request)) => Some((packet.recipient, request)),
+		_ => None,
+	}).collect()
+}
+
+/// First block and number of blocks of a request.
+fn requested_range(request: &message::BlockRequest) -> (u64, u32) {
+	match request.from {
+		message::FromBlock::Number(n) => (n, request.max.unwrap_or(0)),
+		ref from => panic!("Unexpected request start {:?}", from),
+	}
+}
+
+/// Let `server` answer the request of peer 0 as if the response came from `origin`.
+fn serve(net: &TestNet, server: usize, origin: PeerId, request: message::BlockRequest) {
+	net.peer(server).receive_message(0, TestPacket { data: Message::BlockRequest(request).encode(), recipient: server });
+	let responses: Vec<_> = net.peer(server).queue.write().drain(..).filter(|p| p.recipient == 0).collect();
+	for packet in responses {
+		net.peer(0).receive_message(origin, packet);
+	}
+}
+
+#[test]
+fn block_ranges_are_split_between_peers() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(3);
+	net.peer(1).push_blocks(300, false);
+	net.peer(2).push_blocks(300, false);
+	net.start();
+	deliver_pending(&net);
+
+	let requests: Vec<_> = take_block_requests(net.peer(0)).iter()
+		.map(|&(peer, ref request)| (peer, requested_range(request)))
+		.collect();
+	assert_eq!(requests, vec![(1, (1, 128)), (2, (129, 128))]);
+}
+
+#[test]
+fn timed_out_range_is_requested_from_another_peer() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(3);
+	net.peer(1).push_blocks(500, false);
+	net.peer(2).push_blocks(128, false);
+	net.start();
+	deliver_pending(&net);
+	let requests = take_block_requests(net.peer(0));
+	assert_eq!(requests.len(), 1);
+	assert_eq!((requests[0].0, requested_range(&requests[0].1)), (1, (1, 128)));
+
+	// the range goes to peer 2 and peer 1 is asked for half as many blocks.
+	net.peer(0).sync.expire_requests(&mut TestIo::new(&net.peer(0).queue, None));
+	let requests = take_block_requests(net.peer(0));
+	let ranges: Vec<_> = requests.iter().map(|&(peer, ref request)| (peer, requested_range(request))).collect();
+	assert_eq!(ranges, vec![(2, (1, 128)), (1, (129, 64))]);
+
+	// a full response doubles the request size again.
+	let (_, request) = requests.into_iter().find(|&(peer, _)| peer == 1).unwrap();
+	serve(&net, 1, 1, request);
+	let requests: Vec<_> = take_block_requests(net.peer(0)).iter()
+		.map(|&(peer, ref request)| (peer, requested_range(request)))
+		.collect();
+	assert_eq!(requests, vec![(1, (193, 128))]);
+}
+
+#[test]
+fn blocks_in_flight_are_capped() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(2);
+	net.peer(1).push_blocks(2200, false);
+	let best = net.peer(1).client.info().unwrap().chain;
+
+	// peer 5 never answers, so nothing can be imported until its range is served.
+	let stalled = {
+		let peer = net.peer(0);
+		peer.on_connect(5);
+		peer.receive_message(5, TestPacket { data: serde_json::to_vec(&Message::Status(message::Status {
+			version: 1,
+			roles: vec![message::Role::Full],
+			best_number: best.best_number,
+			best_hash: best.best_hash,
+			genesis_hash: best.genesis_hash,
+			validator_signature: None,
+			validator_id: None,
+			parachain_id: None,
+		})).unwrap(), recipient: 0 });
+		let mut requests = take_block_requests(peer);
+		assert_eq!(requests.len(), 1);
+		requests.remove(0).1
+	};
+	assert_eq!(requested_range(&stalled), (1, 128));
+
+	net.start();
+	deliver_pending(&net);
+	let mut last = None;
+	loop {
+		let requests = take_block_requests(net.peer(0));
+		if requests.is_empty() {
+			break;
+		}
+		for (peer, request) in requests {
+			assert_eq!(peer, 1);
+			last = Some(requested_range(&request));
+			serve(&net, 1, 1, request);
+		}
+	}
+	assert_eq!(last, Some((1921, 128)));
+	assert_eq!(net.peer(0).client.info().unwrap().chain.best_number, 0);
+
+	// once the stalled range is imported, the download continues.
+	serve(&net, 1, 5, stalled);
+	assert_eq!(net.peer(0).client.info().unwrap().chain.best_number, 2048);
+	let requests = take_block_requests(net.peer(0));
+	assert!(requests.iter().any(|&(_, ref request)| requested_range(request).0 == 2049));
+}
+
+#[test]
+fn async_import_queue_imports_blocks() {
+	::env_logger::init().ok();
+	let net = TestNet::new(2);
+	net.peer(1).push_blocks(10, false);
+	let source = &net.peer(1).client;
+	let blocks = (1..11).map(|n| {
+		let hash = source.block_hash(n).unwrap().unwrap();
+		blocks::BlockData {
+			origin: 1,
+			block: message::BlockData {
+				hash: hash,
+				header: source.header(&BlockId::Hash(hash)).unwrap(),
+				body: source.body(&BlockId::Hash(hash)).unwrap(),
+				receipt: None,
+				message_queue: None,
+				justification: source.justification(&BlockId::Hash(hash)).unwrap(),
+			},
+		}
+	}).collect();
+
+	let queue = AsyncImportQueue::new(net.peer(0).client.clone());
+	queue.import_blocks(blocks);
+	queue.wait_idle();
+	let reports = queue.take_reports();
+	assert_eq!(reports.len(), 10);
+	assert_eq!(queue.status().importing_count, 0);
+	assert!(reports.iter().all(|r| r.origin == 1 && r.outcome == ImportOutcome::Imported));
+	assert!(net.peer(0).client.backend().blockchain().equals_to(net.peer(1).client.backend().blockchain()));
+}
+
+#[test]
+fn block_with_bad_justification_is_reported_bad() {
+	::env_logger::init().ok();
+	let net = TestNet::new(2);
+	net.peer(1).push_blocks(1, false);
+	let source = &net.peer(1).client;
+	let hash = source.block_hash(1).unwrap().unwrap();
+	let mut justification = source.justification(&BlockId::Hash(hash)).unwrap().unwrap();
+	justification.signatures.clear();
+	let block = blocks::BlockData {
+		origin: 1,
+		block: message::BlockData {
+			hash: hash,
+			header: source.header(&BlockId::Hash(hash)).unwrap(),
+			body: source.body(&BlockId::Hash(hash)).unwrap(),
+			receipt: None,
+			message_queue: None,
+			justification: Some(justification),
+		},
+	};
+
+	let queue = AsyncImportQueue::new(net.peer(0).client.clone());
+	queue.import_blocks(vec![block]);
+	queue.wait_idle();
+	let reports = queue.take_reports();
+	assert_eq!(reports.iter().map(|r| r.outcome).collect::<Vec<_>>(), vec![ImportOutcome::Bad]);
+}
@@ -0,0 +1,109 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Polkadot.
+
+// Polkadot is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Polkadot is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Polkadot.  If not, see <http://www.gnu.org/licenses/>.
+
+use message::{self, Message};
+use service::{Role, TransactionPool};
+use super::*;
+
+#[test]
+fn transactions_are_propagated_to_all_peers() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(3);
+	net.sync();
+	let hash = net.peer(0).transaction_pool.import(&[1, 2, 3]).unwrap();
+	net.peer(0).sync.propagate_transactions(&mut TestIo::new(&net.peer(0).queue, None));
+	net.sync();
+
+	for peer in 1..3 {
+		assert_eq!(net.peer(peer).transaction_pool.transactions(), vec![(hash, vec![1, 2, 3])]);
+		assert!(net.peer(peer).sync.transactions_stats().contains_key(&hash));
+	}
+	assert!(net.peer(0).sync.transactions_stats().contains_key(&hash));
+}
+
+#[test]
+fn known_transactions_are_not_sent_again() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(2);
+	net.sync();
+	net.peer(0).transaction_pool.import(&[1, 2, 3]).unwrap();
+	net.peer(0).sync.propagate_transactions(&mut TestIo::new(&net.peer(0).queue, None));
+	net.sync();
+
+	net.peer(0).sync.propagate_transactions(&mut TestIo::new(&net.peer(0).queue, None));
+	net.peer(1).sync.propagate_transactions(&mut TestIo::new(&net.peer(1).queue, None));
+	assert!(net.done());
+}
+
+#[test]
+fn transactions_are_not_sent_to_light_clients() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new_with_config(2, ProtocolConfig { roles: Role::LIGHT, validator_key: None });
+	net.sync();
+	net.peer(0).transaction_pool.import(&[1, 2, 3]).unwrap();
+	net.peer(0).sync.propagate_transactions(&mut TestIo::new(&net.peer(0).queue, None));
+	assert!(net.done());
+}
+
+#[test]
+fn transactions_already_in_pool_are_not_sent_back() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(2);
+	net.sync();
+	net.peer(0).transaction_pool.import(&[1, 2, 3]).unwrap();
+	net.peer(1).transaction_pool.import(&[1, 2, 3]).unwrap();
+	net.peer(0).sync.propagate_transactions(&mut TestIo::new(&net.peer(0).queue, None));
+	net.sync();
+
+	net.peer(1).sync.propagate_transactions(&mut TestIo::new(&net.peer(1).queue, None));
+	assert!(net.done());
+}
+
+#[test]
+fn transactions_are_not_propagated_on_tick() {
+	::env_logger::init().ok();
+	let mut net = TestNet::new(2);
+	net.sync();
+	net.peer(0).transaction_pool.import(&[1, 2, 3]).unwrap();
+	net.sync_step_peer(0);
+	assert!(net.done());
+}
+
+#[test]
+fn transactions_are_not_sent_to_version_zero_peers() {
+	::env_logger::init().ok();
+	let net = TestNet::new(1);
+	let peer = net.peer(0);
+	let genesis_hash = peer.client.info().unwrap().chain.genesis_hash;
+	let status = Message::Status(message::Status {
+		version: 0,
+		roles: vec![message::Role::Full],
+		best_number: 0,
+		best_hash: genesis_hash,
+		genesis_hash,
+		validator_signature: None,
+		validator_id: None,
+		parachain_id: None,
+	});
+	peer.on_connect(1);
+	peer.receive_message(1, TestPacket { data: ::serde_json::to_vec(&status).unwrap(), recipient: 0 });
+	assert_eq!(peer.sync.peer_info(1).map(|info| info.protocol_version), Some(0));
+	peer.queue.write().clear();
+
+	peer.transaction_pool.import(&[1, 2, 3]).unwrap();
+	peer.sync.propagate_transactions(&mut TestIo::new(&peer.queue, None));
+	assert!(peer.is_done());
+}
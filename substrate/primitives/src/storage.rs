@@ -20,12 +20,32 @@
 use bytes;
 use rstd::vec::Vec;
 
+/// Prefix of the top-level storage keys under which the roots of child storage
+/// tries are placed.
+pub const CHILD_STORAGE_KEY_PREFIX: &'static [u8] = b":child_storage:";
+
 /// Contract storage key.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug))]
 pub struct StorageKey(#[cfg_attr(feature = "std", serde(with="bytes"))] pub Vec<u8>);
 
 /// Contract storage entry data.
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug))]
 pub struct StorageData(#[cfg_attr(feature = "std", serde(with="bytes"))] pub Vec<u8>);
+
+/// The values of the storage entries which were changed by a block. A `None` value
+/// means that the entry was removed.
+#[derive(PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug))]
+pub struct StorageChangeSet {
+	/// Hash of the block.
+	pub block: ::block::HeaderHash,
+	/// The changed entries along with their new values.
+	pub changes: Vec<(StorageKey, Option<StorageData>)>,
+}
+
+/// Encoded trie node, part of a storage proof.
+#[derive(PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug))]
+pub struct StorageProofItem(#[cfg_attr(feature = "std", serde(with="bytes"))] pub Vec<u8>);
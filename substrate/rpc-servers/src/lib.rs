@@ -22,25 +22,58 @@ extern crate substrate_rpc as apis;
 
 extern crate jsonrpc_core as rpc;
 extern crate jsonrpc_http_server as http;
+extern crate jsonrpc_pubsub as pubsub;
+extern crate jsonrpc_ws_server as ws;
+
+#[macro_use]
+extern crate log;
 
 use std::io;
 
+/// RPC request metadata.
+pub type Metadata = apis::metadata::Metadata;
+/// RPC request handler, supporting pub/sub.
+pub type RpcHandler = pubsub::PubSubHandler<Metadata>;
+
 /// Construct rpc `IoHandler`
-pub fn rpc_handler<S>(state: S) -> rpc::IoHandler where
-	S: apis::state::StateApi,
+pub fn rpc_handler<S, C, A, Y>(state: S, chain: C, author: A, system: Y) -> RpcHandler where
+	S: apis::state::StateApi<Metadata=Metadata>,
+	C: apis::chain::ChainApi<Metadata=Metadata>,
+	A: apis::author::AuthorApi<Metadata=Metadata>,
+	Y: apis::system::SystemApi,
 {
-	let mut io = rpc::IoHandler::new();
+	let mut io = pubsub::PubSubHandler::default();
 	io.extend_with(state.to_delegate());
+	io.extend_with(chain.to_delegate());
+	io.extend_with(author.to_delegate());
+	io.extend_with(system.to_delegate());
 	io
 }
 
 /// Start HTTP server listening on given address.
 pub fn start_http(
 	addr: &std::net::SocketAddr,
-	io: rpc::IoHandler,
+	io: RpcHandler,
 ) -> io::Result<http::Server> {
 	http::ServerBuilder::new(io)
 		.threads(4)
 		.rest_api(http::RestApi::Unsecure)
 		.start_http(addr)
 }
+
+/// Start WS server listening on given address.
+pub fn start_ws(
+	addr: &std::net::SocketAddr,
+	io: RpcHandler,
+) -> io::Result<ws::Server> {
+	ws::ServerBuilder::with_meta_extractor(io, |context: &ws::RequestContext| Metadata::new(context.sender()))
+		.start(addr)
+		.map_err(|err| match err {
+			ws::Error(ws::ErrorKind::Io(io), _) => io,
+			ws::Error(ws::ErrorKind::ConnectionClosed, _) => io::ErrorKind::BrokenPipe.into(),
+			e => {
+				error!("{}", e);
+				io::ErrorKind::Other.into()
+			}
+		})
+}
@@ -0,0 +1,60 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+use rpc;
+
+error_chain! {
+	errors {
+		/// Not implemented yet
+		Unimplemented {
+			description("not yet implemented"),
+			display("Method Not Implemented"),
+		}
+		/// Transaction could not be decoded.
+		BadFormat {
+			description("bad transaction format"),
+			display("Invalid transaction format"),
+		}
+		/// Transaction was rejected by the pool.
+		Verification(e: Box<::std::error::Error + Send>) {
+			description("transaction verification error"),
+			display("Transaction verification error: {}", e.description()),
+		}
+	}
+}
+
+impl From<Error> for rpc::Error {
+	fn from(e: Error) -> Self {
+		match e {
+			Error(ErrorKind::Unimplemented, _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(-1),
+				message: "Not implemented yet".into(),
+				data: None,
+			},
+			Error(ErrorKind::BadFormat, _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(1),
+				message: "Extrinsic has invalid format.".into(),
+				data: None,
+			},
+			Error(ErrorKind::Verification(e), _) => rpc::Error {
+				code: rpc::ErrorCode::ServerError(2),
+				message: e.description().into(),
+				data: Some(format!("{:?}", e).into()),
+			},
+			_ => rpc::Error::internal_error(),
+		}
+	}
+}
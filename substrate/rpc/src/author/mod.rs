@@ -0,0 +1,71 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate block-author/full-node API.
+
+use jsonrpc_macros::pubsub;
+use jsonrpc_pubsub::SubscriptionId;
+use primitives::block;
+use rpc::Result as RpcResult;
+
+pub mod error;
+
+use self::error::Result;
+
+/// Status of a transaction submitted to the pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TransactionStatus {
+	/// In the pool and ready to be included in the next block.
+	Ready,
+	/// In the pool, but waiting for earlier transactions from the same sender.
+	Future,
+	/// Included in the block with the given hash.
+	Included(block::HeaderHash),
+	/// Removed from the pool without being included.
+	Dropped,
+	/// Not known to the pool.
+	Unknown,
+}
+
+build_rpc_trait! {
+	/// Substrate authoring RPC API
+	pub trait AuthorApi {
+		type Metadata;
+
+		/// Submit transaction for inclusion in block.
+		#[rpc(name = "author_submitTransaction")]
+		fn submit_transaction(&self, block::Transaction) -> Result<block::TransactionHash>;
+
+		/// Get all transactions in the pool which are ready to be included.
+		#[rpc(name = "author_pendingTransactions")]
+		fn pending_transactions(&self) -> Result<Vec<block::Transaction>>;
+
+		/// Get the status of a submitted transaction.
+		#[rpc(name = "author_transactionStatus")]
+		fn transaction_status(&self, block::TransactionHash) -> Result<TransactionStatus>;
+
+		#[pubsub(name = "author_transactionUpdate")] {
+			/// Submit transaction and subscribe to updates of its status.
+			#[rpc(name = "author_submitAndWatchTransaction")]
+			fn watch_transaction(&self, Self::Metadata, pubsub::Subscriber<TransactionStatus>, block::Transaction);
+
+			/// Unsubscribe from transaction status updates.
+			#[rpc(name = "author_unwatchTransaction")]
+			fn unwatch_transaction(&self, SubscriptionId) -> RpcResult<bool>;
+		}
+	}
+}
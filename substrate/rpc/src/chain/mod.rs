@@ -16,10 +16,19 @@
 
 //! Substrate blockchain API.
 
+use std::sync::Arc;
+
+use client::{self, Client};
+use jsonrpc_macros::pubsub;
+use jsonrpc_pubsub::SubscriptionId;
 use primitives::block;
-use client;
+use primitives::bft::Justification;
+use rpc::Result as RpcResult;
+use rpc::futures::{Future, Sink, Stream};
 use state_machine;
 
+use subscriptions::Subscriptions;
+
 mod error;
 
 #[cfg(test)]
@@ -27,21 +36,142 @@ mod tests;
 
 use self::error::{Result, ResultExt};
 
+/// Import status of a block.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockStatus {
+	/// Added to the import queue.
+	Queued,
+	/// Already in the blockchain.
+	InChain,
+	/// Block or parent is known to be bad.
+	KnownBad,
+	/// Not in the queue or the blockchain.
+	Unknown,
+}
+
+impl From<client::BlockStatus> for BlockStatus {
+	fn from(status: client::BlockStatus) -> Self {
+		match status {
+			client::BlockStatus::Queued => BlockStatus::Queued,
+			client::BlockStatus::InChain => BlockStatus::InChain,
+			client::BlockStatus::KnownBad => BlockStatus::KnownBad,
+			client::BlockStatus::Unknown => BlockStatus::Unknown,
+		}
+	}
+}
+
 build_rpc_trait! {
 	/// Polkadot blockchain API
 	pub trait ChainApi {
+		type Metadata;
+
 		/// Get header of a relay chain block.
 		#[rpc(name = "chain_getHeader")]
 		fn header(&self, block::HeaderHash) -> Result<Option<block::Header>>;
+
+		/// Get header and body of a relay chain block.
+		#[rpc(name = "chain_getBlock")]
+		fn block(&self, block::HeaderHash) -> Result<Option<block::Block>>;
+
+		/// Get hash of the block with given number in the best chain.
+		#[rpc(name = "chain_getBlockHash")]
+		fn block_hash(&self, block::Number) -> Result<Option<block::HeaderHash>>;
+
+		/// Get hash of the best block.
+		#[rpc(name = "chain_getHead")]
+		fn head(&self) -> Result<block::HeaderHash>;
+
+		/// Get justification of a relay chain block.
+		#[rpc(name = "chain_getJustification")]
+		fn justification(&self, block::HeaderHash) -> Result<Option<Justification>>;
+
+		/// Get import status of a relay chain block.
+		#[rpc(name = "chain_getBlockStatus")]
+		fn block_status(&self, block::HeaderHash) -> Result<BlockStatus>;
+
+		#[pubsub(name = "chain_newHead")] {
+			/// New head subscription
+			#[rpc(name = "chain_subscribeNewHead")]
+			fn subscribe_new_head(&self, Self::Metadata, pubsub::Subscriber<block::Header>);
+
+			/// Unsubscribe from new head subscription.
+			#[rpc(name = "chain_unsubscribeNewHead")]
+			fn unsubscribe_new_head(&self, SubscriptionId) -> RpcResult<bool>;
+		}
 	}
 }
 
-impl<B, E> ChainApi for client::Client<B, E> where
+/// Chain API with subscriptions support.
+pub struct Chain<B: client::backend::Backend, E> {
+	/// Substrate client.
+	client: Arc<Client<B, E>>,
+	/// Current subscriptions.
+	subscriptions: Subscriptions,
+}
+
+impl<B: client::backend::Backend, E> Chain<B, E> {
+	/// Create new Chain API RPC handler.
+	pub fn new(client: Arc<Client<B, E>>, subscriptions: Subscriptions) -> Self {
+		Chain {
+			client,
+			subscriptions,
+		}
+	}
+}
+
+impl<B, E> ChainApi for Chain<B, E> where
 	B: client::backend::Backend + Send + Sync + 'static,
-	E: state_machine::CodeExecutor + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
 	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
 {
+	type Metadata = ::metadata::Metadata;
+
 	fn header(&self, hash: block::HeaderHash) -> Result<Option<block::Header>> {
-		client::Client::header(self, &block::Id::Hash(hash)).chain_err(|| "Blockchain error")
+		self.client.header(&block::Id::Hash(hash)).chain_err(|| "Blockchain error")
+	}
+
+	fn block(&self, hash: block::HeaderHash) -> Result<Option<block::Block>> {
+		let id = block::Id::Hash(hash);
+		let header = self.client.header(&id).chain_err(|| "Blockchain error")?;
+		let body = self.client.body(&id).chain_err(|| "Blockchain error")?;
+		Ok(match (header, body) {
+			(Some(header), Some(transactions)) => Some(block::Block { header, transactions }),
+			_ => None,
+		})
+	}
+
+	fn block_hash(&self, number: block::Number) -> Result<Option<block::HeaderHash>> {
+		self.client.block_hash(number).chain_err(|| "Blockchain error")
+	}
+
+	fn head(&self) -> Result<block::HeaderHash> {
+		Ok(self.client.info().chain_err(|| "Blockchain error")?.chain.best_hash)
+	}
+
+	fn justification(&self, hash: block::HeaderHash) -> Result<Option<Justification>> {
+		self.client.justification(&block::Id::Hash(hash)).chain_err(|| "Blockchain error")
+	}
+
+	fn block_status(&self, hash: block::HeaderHash) -> Result<BlockStatus> {
+		self.client.block_status(&block::Id::Hash(hash))
+			.map(Into::into)
+			.chain_err(|| "Blockchain error")
+	}
+
+	fn subscribe_new_head(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<block::Header>) {
+		let stream = self.client.best_block_notification_stream();
+		self.subscriptions.add(subscriber, |sink| {
+			let stream = stream.map(|notification| Ok(notification.header));
+			sink
+				.sink_map_err(|e| warn!("Error sending notifications: {:?}", e))
+				.send_all(stream)
+				// we ignore the resulting Stream (if the first stream is over we are unsubscribed)
+				.map(|_| ())
+		})
+	}
+
+	fn unsubscribe_new_head(&self, id: SubscriptionId) -> RpcResult<bool> {
+		Ok(self.subscriptions.cancel(id))
 	}
 }
@@ -16,6 +16,7 @@
 
 use substrate_executor as executor;
 use client;
+use test_helpers;
 use runtime_support::Hashable;
 use super::*;
 
@@ -29,10 +30,13 @@ fn should_return_header() {
 		digest: Default::default(),
 	};
 
-	let client = client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap();
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let api = Chain::new(Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap()), Subscriptions::new(remote));
 
 	assert_matches!(
-		ChainApi::header(&client, test_genesis_block.blake2_256().into()),
+		api.header(test_genesis_block.blake2_256().into()),
 		Ok(Some(ref x)) if x == &block::Header {
 			parent_hash: 0.into(),
 			number: 0,
@@ -43,7 +47,60 @@ fn should_return_header() {
 	);
 
 	assert_matches!(
-		ChainApi::header(&client, 5.into()),
+		api.header(5.into()),
 		Ok(None)
 	);
 }
+
+#[test]
+fn should_return_genesis_block_and_head() {
+	let test_genesis_block = block::Header {
+		parent_hash: 0.into(),
+		number: 0,
+		state_root: 0.into(),
+		transaction_root: Default::default(),
+		digest: Default::default(),
+	};
+
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let api = Chain::new(Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap()), Subscriptions::new(remote));
+	let genesis_hash: block::HeaderHash = test_genesis_block.blake2_256().into();
+
+	assert_matches!(
+		api.block(genesis_hash),
+		Ok(Some(ref b)) if b.header == test_genesis_block && b.transactions.is_empty()
+	);
+	assert_matches!(api.block(5.into()), Ok(None));
+
+	assert_matches!(api.block_hash(0), Ok(Some(ref h)) if h == &genesis_hash);
+	assert_matches!(api.block_hash(1), Ok(None));
+	assert_matches!(api.head(), Ok(ref h) if h == &genesis_hash);
+	assert_matches!(api.justification(genesis_hash), Ok(None));
+
+	assert_matches!(api.block_status(genesis_hash), Ok(BlockStatus::InChain));
+	assert_matches!(api.block_status(5.into()), Ok(BlockStatus::Unknown));
+}
+
+#[test]
+fn should_assign_and_cancel_new_head_subscriptions() {
+	let mut core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let client = Arc::new(test_helpers::new_client());
+	let api = Chain::new(client.clone(), Subscriptions::new(remote));
+	let (subscriber, id, transport) = pubsub::Subscriber::new_test("test");
+
+	api.subscribe_new_head(Default::default(), subscriber);
+	let id = core.run(id).unwrap().unwrap();
+	assert_matches!(id, SubscriptionId::Number(_));
+
+	// the header of each new best block is sent.
+	test_helpers::push_block(&client);
+	let (notification, _) = core.run(transport.into_future()).map_err(|_| ()).unwrap();
+	assert!(notification.unwrap().contains(r#""number":1"#));
+
+	assert_matches!(api.unsubscribe_new_head(id.clone()), Ok(true));
+	assert_matches!(api.unsubscribe_new_head(id), Ok(false));
+}
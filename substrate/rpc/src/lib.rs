@@ -19,22 +19,53 @@
 #![warn(missing_docs)]
 
 extern crate jsonrpc_core as rpc;
+extern crate jsonrpc_pubsub;
+extern crate parking_lot;
+extern crate rand;
 extern crate substrate_client as client;
+extern crate substrate_network as network;
 extern crate substrate_primitives as primitives;
 extern crate substrate_state_machine as state_machine;
+extern crate serde;
+extern crate tokio_core;
 
 #[macro_use]
 extern crate error_chain;
 #[macro_use]
 extern crate jsonrpc_macros;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
 
 #[cfg(test)]
+#[macro_use]
 extern crate substrate_executor;
 #[cfg(test)]
 #[macro_use]
 extern crate assert_matches;
 #[cfg(test)]
+extern crate substrate_bft as bft;
+#[cfg(test)]
+extern crate substrate_codec as codec;
+#[cfg(test)]
+extern crate substrate_keyring as keyring;
+#[cfg(test)]
 extern crate substrate_runtime_support as runtime_support;
+#[cfg(test)]
+extern crate substrate_test_runtime as test_runtime;
+
+mod subscriptions;
+#[cfg(test)]
+mod test_helpers;
+
+pub use jsonrpc_macros::pubsub;
+pub use jsonrpc_pubsub::SubscriptionId;
+pub use rpc::Result as RpcResult;
+pub use subscriptions::Subscriptions;
 
+pub mod author;
 pub mod chain;
+pub mod metadata;
 pub mod state;
+pub mod system;
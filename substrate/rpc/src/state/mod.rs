@@ -21,16 +21,28 @@ mod error;
 #[cfg(test)]
 mod tests;
 
+use std::sync::Arc;
+
 use client::{self, Client};
+use client::blockchain::Backend as BlockchainBackend;
+use client::backend::Backend;
+use jsonrpc_macros::{pubsub, Trailing};
+use jsonrpc_pubsub::SubscriptionId;
 use primitives::block;
-use primitives::storage::{StorageKey, StorageData};
+use primitives::storage::{StorageKey, StorageData, StorageProofItem, StorageChangeSet};
+use rpc::Result as RpcResult;
+use rpc::futures::{stream, Future, Sink, Stream};
 use state_machine;
 
+use subscriptions::Subscriptions;
+
 use self::error::Result;
 
 build_rpc_trait! {
 	/// Polkadot state API
 	pub trait StateApi {
+		type Metadata;
+
 		/// Returns a storage entry.
 		#[rpc(name = "state_getStorage")]
 		fn storage(&self, StorageKey, block::HeaderHash) -> Result<StorageData>;
@@ -38,19 +50,190 @@ build_rpc_trait! {
 		/// Call a contract.
 		#[rpc(name = "state_call")]
 		fn call(&self, String, Vec<u8>, block::HeaderHash) -> Result<Vec<u8>>;
+
+		/// Returns proof of storage entries at a specific block's state.
+		#[rpc(name = "state_getReadProof")]
+		fn read_proof(&self, Vec<StorageKey>, block::HeaderHash) -> Result<Vec<StorageProofItem>>;
+
+		/// Query historical storage entries (by key) starting from the given block up to
+		/// the given end block, or the best block if omitted. Returns the values of the keys
+		/// at the starting block, followed by a change set for each later block where any
+		/// of the keys changed. Changes within a child storage trie are reported as changes
+		/// of the key the trie is placed under. The range may span at most
+		/// `MAX_KEY_CHANGES_RANGE` blocks.
+		#[rpc(name = "state_queryStorage")]
+		fn query_storage(&self, Vec<StorageKey>, block::HeaderHash, Trailing<block::HeaderHash>) -> Result<Vec<StorageChangeSet>>;
+
+		#[pubsub(name = "state_storage")] {
+			/// New storage subscription. Sends the current values of the keys, then a change
+			/// set whenever a new best block changes any of them.
+			#[rpc(name = "state_subscribeStorage")]
+			fn subscribe_storage(&self, Self::Metadata, pubsub::Subscriber<StorageChangeSet>, Vec<StorageKey>);
+
+			/// Unsubscribe from storage subscription.
+			#[rpc(name = "state_unsubscribeStorage")]
+			fn unsubscribe_storage(&self, SubscriptionId) -> RpcResult<bool>;
+		}
+	}
+}
+
+/// State API with subscriptions support.
+pub struct State<B: client::backend::Backend, E> {
+	/// Substrate client.
+	client: Arc<Client<B, E>>,
+	/// Current subscriptions.
+	subscriptions: Subscriptions,
+}
+
+impl<B: client::backend::Backend, E> State<B, E> {
+	/// Create new State API RPC handler.
+	pub fn new(client: Arc<Client<B, E>>, subscriptions: Subscriptions) -> Self {
+		State {
+			client,
+			subscriptions,
+		}
+	}
+}
+
+// the value of the storage entry under `key` at the given block, if any.
+fn storage_value<B, E>(client: &Client<B, E>, hash: block::HeaderHash, key: &StorageKey) -> Result<(StorageKey, Option<StorageData>)> where
+	B: client::backend::Backend,
+	E: client::CallExecutor,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let value = match client.storage(&block::Id::Hash(hash), key) {
+		Ok(value) => Some(value),
+		Err(client::error::Error(client::error::ErrorKind::NoValueForKey(_), _)) => None,
+		Err(e) => return Err(e.into()),
+	};
+	Ok((StorageKey(key.0.clone()), value))
+}
+
+// the values of the given storage entries at the best block.
+fn current_values<B, E>(client: &Client<B, E>, keys: &[StorageKey]) -> Result<StorageChangeSet> where
+	B: client::backend::Backend,
+	E: client::CallExecutor,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let best = client.info()?.chain.best_hash;
+	Ok(StorageChangeSet {
+		block: best,
+		changes: keys.iter().map(|key| storage_value(client, best, key)).collect::<Result<_>>()?,
+	})
+}
+
+// the change set of the subscribed keys for a new best block, taking into account the
+// blocks enacted and retracted by a reorganisation. `None` if none of the keys changed.
+fn best_block_changes<B, E>(
+	client: &Client<B, E>,
+	notification: &client::BlockImportNotification,
+	keys: &[StorageKey],
+) -> Result<Option<StorageChangeSet>> where
+	B: client::backend::Backend,
+	E: client::CallExecutor,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	let mut blocks = vec![notification.hash];
+	if let Some(ref route) = notification.tree_route {
+		blocks.extend(route.retracted().iter().chain(route.enacted()).map(|entry| entry.hash));
 	}
+
+	let mut changed = vec![false; keys.len()];
+	for hash in blocks {
+		if let Some(changed_keys) = client.backend().blockchain().changed_keys(block::Id::Hash(hash))? {
+			for (index, key) in keys.iter().enumerate() {
+				changed[index] |= changed_keys.binary_search(&key.0).is_ok();
+			}
+		}
+	}
+
+	if !changed.iter().any(|changed| *changed) {
+		return Ok(None);
+	}
+
+	Ok(Some(StorageChangeSet {
+		block: notification.hash,
+		changes: keys.iter()
+			.zip(changed)
+			.filter(|&(_, changed)| changed)
+			.map(|(key, _)| storage_value(client, notification.hash, key))
+			.collect::<Result<_>>()?,
+	}))
 }
 
-impl<B, E> StateApi for Client<B, E> where
+impl<B, E> StateApi for State<B, E> where
 	B: client::backend::Backend + Send + Sync + 'static,
-	E: state_machine::CodeExecutor + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
 	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
 {
+	type Metadata = ::metadata::Metadata;
+
 	fn storage(&self, key: StorageKey, block: block::HeaderHash) -> Result<StorageData> {
-		Ok(self.storage(&block::Id::Hash(block), &key)?)
+		Ok(self.client.storage(&block::Id::Hash(block), &key)?)
 	}
 
 	fn call(&self, method: String, data: Vec<u8>, block: block::HeaderHash) -> Result<Vec<u8>> {
-		Ok(self.call(&block::Id::Hash(block), &method, &data)?.return_data)
+		Ok(self.client.call(&block::Id::Hash(block), &method, &data)?.return_data)
+	}
+
+	fn read_proof(&self, keys: Vec<StorageKey>, block: block::HeaderHash) -> Result<Vec<StorageProofItem>> {
+		let keys: Vec<_> = keys.into_iter().map(|key| key.0).collect();
+		Ok(self.client.read_proof(&block::Id::Hash(block), &keys)?
+			.into_iter()
+			.map(StorageProofItem)
+			.collect())
+	}
+
+	fn query_storage(&self, keys: Vec<StorageKey>, from: block::HeaderHash, to: Trailing<block::HeaderHash>) -> Result<Vec<StorageChangeSet>> {
+		let to: Option<block::HeaderHash> = to.into();
+		let to = match to {
+			Some(to) => to,
+			None => self.client.info()?.chain.best_hash,
+		};
+
+		// the blocks within the range which changed any of the keys, along with
+		// the indices of the keys they changed.
+		let changed_blocks = self.client.key_changes(&block::Id::Hash(from), &block::Id::Hash(to), &keys)?;
+
+		let mut change_sets = vec![StorageChangeSet {
+			block: from,
+			changes: keys.iter().map(|key| storage_value(&self.client, from, key)).collect::<Result<_>>()?,
+		}];
+		for (_, hash, indices) in changed_blocks.into_iter().filter(|&(_, hash, _)| hash != from) {
+			change_sets.push(StorageChangeSet {
+				block: hash,
+				changes: indices.into_iter().map(|index| storage_value(&self.client, hash, &keys[index])).collect::<Result<_>>()?,
+			});
+		}
+		Ok(change_sets)
+	}
+
+	fn subscribe_storage(&self, _metadata: Self::Metadata, subscriber: pubsub::Subscriber<StorageChangeSet>, keys: Vec<StorageKey>) {
+		let stream = self.client.best_block_notification_stream();
+
+		// send the current values of the keys first.
+		let initial = current_values(&self.client, &keys).map_err(Into::into);
+
+		let client = self.client.clone();
+		self.subscriptions.add(subscriber, |sink| {
+			let changes = stream
+				.filter_map(move |notification| match best_block_changes(&client, &notification, &keys) {
+					Ok(changes) => changes.map(Ok),
+					Err(e) => {
+						warn!("Unable to compute storage changes: {:?}", e);
+						None
+					}
+				});
+
+			sink
+				.sink_map_err(|e| warn!("Error sending notifications: {:?}", e))
+				.send_all(stream::iter_ok::<_, ()>(vec![initial]).chain(changes))
+				// we ignore the resulting Stream (if the first stream is over we are unsubscribed)
+				.map(|_| ())
+		})
+	}
+
+	fn unsubscribe_storage(&self, id: SubscriptionId) -> RpcResult<bool> {
+		Ok(self.subscriptions.cancel(id))
 	}
 }
@@ -19,6 +19,7 @@ use substrate_executor as executor;
 use self::error::{Error, ErrorKind};
 use runtime_support::Hashable;
 use client;
+use test_helpers;
 
 #[test]
 fn should_return_storage() {
@@ -30,15 +31,177 @@ fn should_return_storage() {
 		digest: Default::default(),
 	};
 
-	let client = client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap();
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let api = State::new(Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap()), Subscriptions::new(remote));
 	let genesis_hash = test_genesis_block.blake2_256().into();
 
 	assert_matches!(
-		StateApi::storage(&client, StorageKey(vec![10]), genesis_hash),
+		api.storage(StorageKey(vec![10]), genesis_hash),
 		Err(Error(ErrorKind::Client(client::error::ErrorKind::NoValueForKey(ref k)), _)) if *k == vec![10]
 	)
 }
 
+#[test]
+fn should_return_read_proof() {
+	let test_genesis_block = block::Header {
+		parent_hash: 0.into(),
+		number: 0,
+		state_root: 0.into(),
+		transaction_root: Default::default(),
+		digest: Default::default(),
+	};
+
+	let storage = vec![(vec![10], vec![1, 2, 3]), (vec![11], vec![4, 5, 6])];
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let api = State::new(Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), storage.clone())).unwrap()), Subscriptions::new(remote));
+	let genesis_hash = test_genesis_block.blake2_256().into();
+
+	let proof = api.read_proof(vec![StorageKey(vec![10])], genesis_hash).unwrap();
+	let state_root = ::state_machine::TrieBackend::from_pairs(storage).root().0.into();
+	assert_eq!(
+		client::check_read_proof(state_root, proof.into_iter().map(|item| item.0).collect(), &[vec![10]]).unwrap(),
+		vec![Some(vec![1, 2, 3])]
+	);
+}
+
+#[test]
+fn should_query_storage_at_genesis() {
+	let test_genesis_block = block::Header {
+		parent_hash: 0.into(),
+		number: 0,
+		state_root: 0.into(),
+		transaction_root: Default::default(),
+		digest: Default::default(),
+	};
+
+	let storage = vec![(vec![10], vec![1, 2, 3])];
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let api = State::new(Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), storage.clone())).unwrap()), Subscriptions::new(remote));
+	let genesis_hash = test_genesis_block.blake2_256().into();
+
+	assert_eq!(
+		api.query_storage(vec![StorageKey(vec![10]), StorageKey(vec![11])], genesis_hash, None.into()).unwrap(),
+		vec![StorageChangeSet {
+			block: genesis_hash,
+			changes: vec![
+				(StorageKey(vec![10]), Some(StorageData(vec![1, 2, 3]))),
+				(StorageKey(vec![11]), None),
+			],
+		}]
+	);
+}
+
+#[test]
+fn should_query_storage_changes_over_blocks() {
+	use client::backend::BlockImportOperation;
+
+	let test_genesis_block = block::Header {
+		parent_hash: 0.into(),
+		number: 0,
+		state_root: 0.into(),
+		transaction_root: Default::default(),
+		digest: Default::default(),
+	};
+
+	let storage = vec![(vec![10], vec![1, 2, 3])];
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let client = Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), storage.clone())).unwrap());
+	let genesis_hash: block::HeaderHash = test_genesis_block.blake2_256().into();
+	let import = |parent: block::HeaderHash, changes: Vec<(Vec<u8>, Option<Vec<u8>>)>| {
+		let parent_number = client.header(&block::Id::Hash(parent)).unwrap().unwrap().number;
+		let mut header = block::Header::from_block_number(parent_number + 1);
+		header.parent_hash = parent;
+		let hash = header.blake2_256().into();
+		let mut op = client.backend().begin_operation(block::Id::Hash(parent)).unwrap();
+		op.set_block_data(header, Some(vec![]), None, true).unwrap();
+		op.set_storage(changes.into_iter()).unwrap();
+		client.backend().commit_operation(op).unwrap();
+		hash
+	};
+
+	let block1 = import(genesis_hash, vec![(vec![10], Some(vec![4]))]);
+	let block2 = import(block1, vec![(vec![11], Some(vec![5]))]);
+	let block3 = import(block2, vec![(vec![12], Some(vec![6]))]);
+	let block4 = import(block3, vec![(vec![10], None)]);
+
+	let api = State::new(client, Subscriptions::new(remote));
+	let keys = vec![StorageKey(vec![10]), StorageKey(vec![11])];
+
+	assert_eq!(
+		api.query_storage(keys.clone(), genesis_hash, None.into()).unwrap(),
+		vec![
+			StorageChangeSet {
+				block: genesis_hash,
+				changes: vec![
+					(StorageKey(vec![10]), Some(StorageData(vec![1, 2, 3]))),
+					(StorageKey(vec![11]), None),
+				],
+			},
+			StorageChangeSet {
+				block: block1,
+				changes: vec![(StorageKey(vec![10]), Some(StorageData(vec![4])))],
+			},
+			StorageChangeSet {
+				block: block2,
+				changes: vec![(StorageKey(vec![11]), Some(StorageData(vec![5])))],
+			},
+			StorageChangeSet {
+				block: block4,
+				changes: vec![(StorageKey(vec![10]), None)],
+			},
+		]
+	);
+
+	assert_eq!(
+		api.query_storage(keys.clone(), block1, Some(block2).into()).unwrap(),
+		vec![
+			StorageChangeSet {
+				block: block1,
+				changes: vec![
+					(StorageKey(vec![10]), Some(StorageData(vec![4]))),
+					(StorageKey(vec![11]), None),
+				],
+			},
+			StorageChangeSet {
+				block: block2,
+				changes: vec![(StorageKey(vec![11]), Some(StorageData(vec![5])))],
+			},
+		]
+	);
+
+	assert!(api.query_storage(keys, block2, Some(block1).into()).is_err());
+}
+
+#[test]
+fn should_send_initial_storage_values_on_subscription() {
+	let mut core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let client = Arc::new(test_helpers::new_client());
+	let api = State::new(client.clone(), Subscriptions::new(remote));
+	let (subscriber, id, transport) = pubsub::Subscriber::new_test("test");
+
+	api.subscribe_storage(Default::default(), subscriber, vec![StorageKey(test_helpers::latest_block_hash_key())]);
+	assert_matches!(core.run(id), Ok(Ok(SubscriptionId::Number(_))));
+
+	// the current values are sent straight away.
+	let (initial, transport) = core.run(transport.into_future()).map_err(|_| ()).unwrap();
+	let initial = initial.unwrap();
+
+	// and the new ones once a block changes them.
+	test_helpers::push_block(&client);
+	let (changed, _) = core.run(transport.into_future()).map_err(|_| ()).unwrap();
+	assert!(changed.map_or(false, |changed| changed != initial));
+}
+
 #[test]
 #[ignore]	// TODO: [ToDr] reenable once we can properly mock the wasm executor env
 fn should_call_contract() {
@@ -51,11 +214,14 @@ fn should_call_contract() {
 		digest: Default::default(),
 	};
 
-	let client = client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap();
+	let core = ::tokio_core::reactor::Core::new().unwrap();
+	let remote = core.remote();
+
+	let api = State::new(Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap()), Subscriptions::new(remote));
 	let genesis_hash = test_genesis_block.blake2_256().into();
 
 	assert_matches!(
-		StateApi::call(&client, "balanceOf".into(), vec![1,2,3], genesis_hash),
+		api.call("balanceOf".into(), vec![1,2,3], genesis_hash),
 		Err(Error(ErrorKind::Client(client::error::ErrorKind::Execution(_)), _))
 	)
 }
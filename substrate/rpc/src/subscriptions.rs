@@ -0,0 +1,100 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Management of active pub/sub subscriptions.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use jsonrpc_macros::pubsub;
+use jsonrpc_pubsub::SubscriptionId;
+use parking_lot::Mutex;
+use rpc::futures::{Future, IntoFuture};
+use rpc::futures::sync::oneshot;
+use tokio_core::reactor::Remote;
+
+type Id = u64;
+
+/// Subscriptions manager.
+///
+/// Takes care of assigning unique subscription ids and
+/// driving the sinks into completion. Ids are random, so that
+/// subscriptions of one client can't be guessed and cancelled by another.
+#[derive(Debug)]
+pub struct Subscriptions {
+	active_subscriptions: Arc<Mutex<HashMap<Id, oneshot::Sender<()>>>>,
+	event_loop: Remote,
+}
+
+impl Subscriptions {
+	/// Creates new `Subscriptions` object, spawning the notification futures
+	/// on the given event loop.
+	pub fn new(event_loop: Remote) -> Self {
+		Subscriptions {
+			active_subscriptions: Default::default(),
+			event_loop,
+		}
+	}
+
+	/// Creates new subscription for given subscriber.
+	///
+	/// Second parameter is a function that converts Subscriber sink into a future.
+	/// This future will be driven to completion by the underlying event loop
+	/// or will be cancelled in case #cancel is invoked. The subscription is
+	/// forgotten once the future completes.
+	pub fn add<T, E, G, R, F>(&self, subscriber: pubsub::Subscriber<T, E>, into_future: G) where
+		G: FnOnce(pubsub::Sink<T, E>) -> R,
+		R: IntoFuture<Future=F, Item=(), Error=()>,
+		F: Future<Item=(), Error=()> + Send + 'static,
+	{
+		let mut active_subscriptions = self.active_subscriptions.lock();
+		let id = loop {
+			let id = ::rand::random::<Id>();
+			if !active_subscriptions.contains_key(&id) {
+				break id;
+			}
+		};
+
+		if let Ok(sink) = subscriber.assign_id(SubscriptionId::Number(id)) {
+			let (tx, rx) = oneshot::channel();
+			let subscriptions = self.active_subscriptions.clone();
+			let future = into_future(sink)
+				.into_future()
+				.select(rx.map_err(|e| warn!("Error cancelling subscription: {:?}", e)))
+				.then(move |_| {
+					subscriptions.lock().remove(&id);
+					Ok(())
+				});
+
+			active_subscriptions.insert(id, tx);
+			self.event_loop.spawn(|_| future);
+		}
+	}
+
+	/// Cancel subscription.
+	///
+	/// Returns true if subscription existed or false otherwise.
+	pub fn cancel(&self, id: SubscriptionId) -> bool {
+		if let SubscriptionId::Number(id) = id {
+			if let Some(tx) = self.active_subscriptions.lock().remove(&id) {
+				let _ = tx.send(());
+				return true;
+			}
+		}
+		false
+	}
+}
+
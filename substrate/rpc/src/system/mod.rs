@@ -0,0 +1,165 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Substrate system API.
+
+use std::sync::Arc;
+
+use client::{self, Client};
+use network::{self, SyncProvider};
+use primitives::block;
+use state_machine;
+
+mod error;
+
+#[cfg(test)]
+mod tests;
+
+use self::error::Result;
+
+/// Static information about the running node.
+#[derive(Debug, Clone)]
+pub struct SystemInfo {
+	/// Implementation name.
+	pub impl_name: String,
+	/// Implementation version.
+	pub impl_version: String,
+	/// Chain name.
+	pub chain_name: String,
+}
+
+/// Health of the node.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+	/// Number of connected peers.
+	pub peers: usize,
+	/// Whether the node is catching up with the chain.
+	pub is_syncing: bool,
+	/// Number of the best block.
+	pub best_number: block::Number,
+	/// Number of the best block seen among the peers, if any.
+	pub target_number: Option<block::Number>,
+}
+
+/// Information about a connected peer.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerInfo {
+	/// Public node id.
+	pub node_id: Option<String>,
+	/// Node client version.
+	pub client_version: String,
+	/// Remote endpoint address.
+	pub remote_address: String,
+	/// Roles of the peer, if the protocol handshake is complete.
+	pub roles: Option<String>,
+	/// Best block hash of the peer.
+	pub best_hash: Option<block::HeaderHash>,
+	/// Best block number of the peer.
+	pub best_number: Option<block::Number>,
+	/// Reputation of the peer node.
+	pub reputation: Option<i32>,
+}
+
+impl From<network::PeerInfo> for PeerInfo {
+	fn from(info: network::PeerInfo) -> Self {
+		PeerInfo {
+			node_id: info.id,
+			client_version: info.client_version,
+			remote_address: info.remote_address,
+			roles: info.dot_info.as_ref().map(|dot| format!("{:?}", dot.roles)),
+			best_hash: info.dot_info.as_ref().map(|dot| dot.best_hash),
+			best_number: info.dot_info.as_ref().map(|dot| dot.best_number),
+			reputation: info.dot_info.as_ref().map(|dot| dot.reputation),
+		}
+	}
+}
+
+build_rpc_trait! {
+	/// Substrate system RPC API
+	pub trait SystemApi {
+		/// Get the node's implementation name.
+		#[rpc(name = "system_name")]
+		fn system_name(&self) -> Result<String>;
+
+		/// Get the node implementation's version.
+		#[rpc(name = "system_version")]
+		fn system_version(&self) -> Result<String>;
+
+		/// Get the chain's name.
+		#[rpc(name = "system_chain")]
+		fn system_chain(&self) -> Result<String>;
+
+		/// Get the node's peer count, sync state and best block numbers.
+		#[rpc(name = "system_health")]
+		fn system_health(&self) -> Result<Health>;
+
+		/// Get the currently connected peers.
+		#[rpc(name = "system_peers")]
+		fn system_peers(&self) -> Result<Vec<PeerInfo>>;
+	}
+}
+
+/// System API implementation.
+pub struct System<B: client::backend::Backend, E> {
+	info: SystemInfo,
+	client: Arc<Client<B, E>>,
+	sync: Arc<SyncProvider>,
+}
+
+impl<B: client::backend::Backend, E> System<B, E> {
+	/// Create new System API RPC handler.
+	pub fn new(info: SystemInfo, client: Arc<Client<B, E>>, sync: Arc<SyncProvider>) -> Self {
+		System {
+			info,
+			client,
+			sync,
+		}
+	}
+}
+
+impl<B, E> SystemApi for System<B, E> where
+	B: client::backend::Backend + Send + Sync + 'static,
+	E: client::CallExecutor + Send + Sync + 'static,
+	client::error::Error: From<<<B as client::backend::Backend>::State as state_machine::backend::Backend>::Error>,
+{
+	fn system_name(&self) -> Result<String> {
+		Ok(self.info.impl_name.clone())
+	}
+
+	fn system_version(&self) -> Result<String> {
+		Ok(self.info.impl_version.clone())
+	}
+
+	fn system_chain(&self) -> Result<String> {
+		Ok(self.info.chain_name.clone())
+	}
+
+	fn system_health(&self) -> Result<Health> {
+		let status = self.sync.status();
+		Ok(Health {
+			peers: status.num_peers,
+			is_syncing: status.sync.state == network::SyncState::Downloading,
+			best_number: self.client.info()?.chain.best_number,
+			target_number: status.sync.best_seen_block,
+		})
+	}
+
+	fn system_peers(&self) -> Result<Vec<PeerInfo>> {
+		Ok(self.sync.peers().into_iter().map(Into::into).collect())
+	}
+}
@@ -0,0 +1,123 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+use super::*;
+
+use std::collections::BTreeMap;
+
+use network::{ProtocolStatus, SyncStatus, SyncState, TransactionStats};
+use primitives::block::TransactionHash;
+use substrate_executor as executor;
+
+struct Status {
+	peers: usize,
+	is_downloading: bool,
+}
+
+impl SyncProvider for Status {
+	fn status(&self) -> ProtocolStatus {
+		ProtocolStatus {
+			sync: SyncStatus {
+				state: if self.is_downloading { SyncState::Downloading } else { SyncState::Idle },
+				best_seen_block: if self.peers > 0 { Some(10) } else { None },
+			},
+			num_peers: self.peers,
+			num_active_peers: 0,
+		}
+	}
+
+	fn peers(&self) -> Vec<network::PeerInfo> {
+		(0..self.peers).map(|i| network::PeerInfo {
+			id: Some(format!("{}", i)),
+			client_version: "test".into(),
+			capabilities: vec![],
+			remote_address: "127.0.0.1:30333".into(),
+			local_address: "127.0.0.1:30334".into(),
+			dot_info: None,
+		}).collect()
+	}
+
+	fn node_id(&self) -> Option<String> {
+		None
+	}
+
+	fn transactions_stats(&self) -> BTreeMap<TransactionHash, TransactionStats> {
+		BTreeMap::new()
+	}
+}
+
+fn api(peers: usize, is_downloading: bool) -> System<client::in_mem::Backend, executor::WasmExecutor> {
+	let test_genesis_block = block::Header {
+		parent_hash: 0.into(),
+		number: 0,
+		state_root: 0.into(),
+		transaction_root: Default::default(),
+		digest: Default::default(),
+	};
+
+	let info = SystemInfo {
+		impl_name: "testclient".into(),
+		impl_version: "0.1.0".into(),
+		chain_name: "testchain".into(),
+	};
+	let client = Arc::new(client::new_in_mem(executor::WasmExecutor, || (test_genesis_block.clone(), vec![])).unwrap());
+	System::new(info, client, Arc::new(Status { peers, is_downloading }))
+}
+
+#[test]
+fn system_name_works() {
+	assert_eq!(api(0, false).system_name().unwrap(), "testclient".to_owned());
+}
+
+#[test]
+fn system_version_works() {
+	assert_eq!(api(0, false).system_version().unwrap(), "0.1.0".to_owned());
+}
+
+#[test]
+fn system_chain_works() {
+	assert_eq!(api(0, false).system_chain().unwrap(), "testchain".to_owned());
+}
+
+#[test]
+fn system_health() {
+	assert_eq!(api(0, false).system_health().unwrap(), Health {
+		peers: 0,
+		is_syncing: false,
+		best_number: 0,
+		target_number: None,
+	});
+
+	assert_eq!(api(5, true).system_health().unwrap(), Health {
+		peers: 5,
+		is_syncing: true,
+		best_number: 0,
+		target_number: Some(10),
+	});
+}
+
+#[test]
+fn system_peers() {
+	assert_eq!(api(1, false).system_peers().unwrap(), vec![PeerInfo {
+		node_id: Some("0".into()),
+		client_version: "test".into(),
+		remote_address: "127.0.0.1:30333".into(),
+		roles: None,
+		best_hash: None,
+		best_number: None,
+		reputation: None,
+	}]);
+}
@@ -0,0 +1,80 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A client running the test runtime, able to author and import blocks.
+
+use bft;
+use client::{self, genesis};
+use codec::Slicable;
+use keyring::Keyring;
+use primitives::block;
+use runtime_support::Hashable;
+use substrate_executor::NativeExecutor;
+use test_runtime;
+use test_runtime::genesismap::{GenesisConfig, additional_storage_with_genesis};
+
+native_executor_instance!(pub Executor, test_runtime::api::dispatch, include_bytes!("../../test-runtime/wasm/target/wasm32-unknown-unknown/release/substrate_test_runtime.compact.wasm"));
+
+pub type TestClient = client::Client<client::in_mem::Backend, NativeExecutor<Executor>>;
+
+/// The storage key of the latest block hash, updated by every block of the test runtime.
+pub fn latest_block_hash_key() -> Vec<u8> {
+	b"latest".twox_128().to_vec()
+}
+
+fn prepare_genesis() -> (block::Header, Vec<(Vec<u8>, Vec<u8>)>) {
+	let mut storage = GenesisConfig::new_simple(vec![Keyring::Alice.to_raw_public()], 1000).genesis_map();
+	let block = genesis::construct_genesis_block(&storage);
+	storage.extend(additional_storage_with_genesis(&block));
+	(block::Header::decode(&mut block.header.encode().as_ref()).expect("to_vec() always gives a valid serialisation; qed"), storage.into_iter().collect())
+}
+
+/// Create a new client with Alice as the only authority.
+pub fn new_client() -> TestClient {
+	client::new_in_mem(Executor::new(), prepare_genesis).unwrap()
+}
+
+/// Author an empty block on top of the best block and import it.
+pub fn push_block(client: &TestClient) -> block::HeaderHash {
+	let block = client.new_block().unwrap().bake().unwrap();
+	let hash = block.header.blake2_256().into();
+	let justification = justify(&block.header);
+	let justified = client.check_justification(block.header, justification).unwrap();
+	client.import_block(justified, Some(block.transactions)).unwrap();
+	hash
+}
+
+fn justify(header: &block::Header) -> bft::UncheckedJustification {
+	let hash = header.blake2_256().into();
+	let authorities = vec![Keyring::Alice.into()];
+
+	bft::UncheckedJustification {
+		digest: hash,
+		signatures: authorities.iter().map(|key| {
+			let msg = bft::sign_message(
+				bft::generic::Vote::Commit(1, hash).into(),
+				key,
+				header.parent_hash
+			);
+
+			match msg {
+				bft::generic::LocalizedMessage::Vote(vote) => vote.signature,
+				_ => panic!("signing vote leads to signed vote"),
+			}
+		}).collect(),
+		round_number: 1,
+	}
+}
@@ -36,7 +36,7 @@ environmental!(ext: trait Externalities);
 
 /// Get `key` from storage and return a `Vec`, empty if there's a problem.
 pub fn storage(key: &[u8]) -> Option<Vec<u8>> {
-	ext::with(|ext| ext.storage(key).map(|s| s.to_vec()))
+	ext::with(|ext| ext.storage(key))
 		.expect("read_storage cannot be called outside of an Externalities-provided environment.")
 }
 
@@ -66,6 +66,80 @@ pub fn clear_storage(key: &[u8]) {
 	);
 }
 
+/// Clear the storage of all keys starting with the given prefix.
+pub fn clear_prefix(prefix: &[u8]) {
+	ext::with(|ext|
+		ext.clear_prefix(prefix)
+	);
+}
+
+/// Get the first key in storage which is lexicographically greater than the given one.
+pub fn next_key(key: &[u8]) -> Option<Vec<u8>> {
+	ext::with(|ext| ext.next_key(key))
+		.expect("next_key cannot be called outside of an Externalities-provided environment.")
+}
+
+/// Get all keys in storage starting with the given prefix, in lexicographic order.
+pub fn keys_with_prefix(prefix: &[u8]) -> Vec<Vec<u8>> {
+	ext::with(|ext| ext.keys_with_prefix(prefix))
+		.expect("keys_with_prefix cannot be called outside of an Externalities-provided environment.")
+}
+
+/// Get `key` from the child storage trie placed under `storage_key`.
+pub fn child_storage(storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+	ext::with(|ext| ext.child_storage(storage_key, key))
+		.expect("child_storage cannot be called outside of an Externalities-provided environment.")
+}
+
+/// Set the value of `key` in the child storage trie placed under `storage_key`.
+pub fn set_child_storage(storage_key: &[u8], key: &[u8], value: &[u8]) {
+	ext::with(|ext|
+		ext.set_child_storage(storage_key.to_vec(), key.to_vec(), value.to_vec())
+	);
+}
+
+/// Clear `key` in the child storage trie placed under `storage_key`.
+pub fn clear_child_storage(storage_key: &[u8], key: &[u8]) {
+	ext::with(|ext|
+		ext.clear_child_storage(storage_key, key)
+	);
+}
+
+/// Remove the child storage trie placed under `storage_key` along with all its entries.
+pub fn kill_child_storage(storage_key: &[u8]) {
+	ext::with(|ext|
+		ext.kill_child_storage(storage_key)
+	);
+}
+
+/// Get the root of the child storage trie placed under `storage_key`, or None if it is empty.
+pub fn child_storage_root(storage_key: &[u8]) -> Option<[u8; 32]> {
+	ext::with(|ext| ext.child_storage_root(storage_key))
+		.expect("child_storage_root cannot be called outside of an Externalities-provided environment.")
+}
+
+/// Start a nested storage transaction. All storage changes made until the matching
+/// `commit_transaction` or `rollback_transaction` call are kept or discarded together.
+pub fn start_transaction() {
+	ext::with(|ext|
+		ext.start_transaction()
+	);
+}
+
+/// Commit the innermost storage transaction into the enclosing one.
+pub fn commit_transaction() {
+	ext::with(|ext|
+		ext.commit_transaction().expect("Commit of a storage transaction which was never started")
+	);
+}
+
+/// Discard all storage changes made within the innermost storage transaction.
+pub fn rollback_transaction() {
+	ext::with(|ext|
+		ext.rollback_transaction().expect("Rollback of a storage transaction which was never started")
+	);
+}
+
 /// The current relay chain identifier.
 pub fn chain_id() -> u64 {
 	ext::with(|ext|
@@ -183,6 +257,70 @@ mod std_tests {
 		}));
 	}
 
+	#[test]
+	fn prefix_iteration_works() {
+		let mut t: TestExternalities = map![
+			b"aaa".to_vec() => b"1".to_vec(),
+			b"abc".to_vec() => b"2".to_vec(),
+			b"abd".to_vec() => b"3".to_vec(),
+			b"b".to_vec() => b"4".to_vec()
+		];
+
+		with_externalities(&mut t, || {
+			assert_eq!(keys_with_prefix(b"ab"), vec![b"abc".to_vec(), b"abd".to_vec()]);
+			assert_eq!(next_key(b"aaa"), Some(b"abc".to_vec()));
+			assert_eq!(next_key(b"b"), None);
+
+			clear_prefix(b"ab");
+			assert!(keys_with_prefix(b"ab").is_empty());
+			assert_eq!(next_key(b"aaa"), Some(b"b".to_vec()));
+		});
+	}
+
+	#[test]
+	fn child_storage_works() {
+		let child = b":child_storage:default:1";
+		let mut t = TestExternalities::new();
+
+		with_externalities(&mut t, || {
+			let root = storage_root();
+			set_child_storage(child, b"dog", b"puppy");
+			assert_eq!(child_storage(child, b"dog"), Some(b"puppy".to_vec()));
+			assert!(child_storage_root(child).is_some());
+			assert!(storage_root() != root);
+
+			clear_child_storage(child, b"dog");
+			assert_eq!(child_storage(child, b"dog"), None);
+			assert_eq!(child_storage_root(child), None);
+
+			set_child_storage(child, b"cat", b"kitten");
+			kill_child_storage(child);
+			assert_eq!(child_storage(child, b"cat"), None);
+			assert_eq!(storage_root(), root);
+		});
+	}
+
+	#[test]
+	fn storage_transactions_work() {
+		let mut t: TestExternalities = map![
+			b"foo".to_vec() => b"bar".to_vec()
+		];
+
+		with_externalities(&mut t, || {
+			start_transaction();
+			set_storage(b"foo", b"baz");
+			start_transaction();
+			clear_storage(b"foo");
+			set_storage(b"hello", b"world");
+			rollback_transaction();
+			assert_eq!(storage(b"foo"), Some(b"baz".to_vec()));
+			assert_eq!(storage(b"hello"), None);
+			commit_transaction();
+		});
+
+		assert_eq!(t.storage(b"foo"), Some(b"baz".to_vec()));
+	}
+
 	#[test]
 	fn read_storage_works() {
 		let mut t: TestExternalities = map![
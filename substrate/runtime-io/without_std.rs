@@ -44,11 +44,22 @@ extern "C" {
 	fn ext_print_num(value: u64);
 	fn ext_set_storage(key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32);
 	fn ext_clear_storage(key_data: *const u8, key_len: u32);
+	fn ext_clear_prefix(prefix_data: *const u8, prefix_len: u32);
+	fn ext_next_key(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8;
+	fn ext_keys_with_prefix(prefix_data: *const u8, prefix_len: u32, written_out: *mut u32) -> *mut u8;
+	fn ext_get_allocated_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8;
+	fn ext_set_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32, value_data: *const u8, value_len: u32);
+	fn ext_clear_child_storage(storage_key_data: *const u8, storage_key_len: u32, key_data: *const u8, key_len: u32);
+	fn ext_kill_child_storage(storage_key_data: *const u8, storage_key_len: u32);
+	fn ext_child_storage_root(storage_key_data: *const u8, storage_key_len: u32, result: *mut u8) -> u32;
 	fn ext_get_allocated_storage(key_data: *const u8, key_len: u32, written_out: *mut u32) -> *mut u8;
 	fn ext_get_storage_into(key_data: *const u8, key_len: u32, value_data: *mut u8, value_len: u32, value_offset: u32) -> u32;
 	fn ext_storage_root(result: *mut u8);
 	fn ext_enumerated_trie_root(values_data: *const u8, lens_data: *const u32, lens_len: u32, result: *mut u8);
 	fn ext_chain_id() -> u64;
+	fn ext_start_transaction();
+	fn ext_commit_transaction();
+	fn ext_rollback_transaction();
 	fn ext_blake2_256(data: *const u8, len: u32, out: *mut u8);
 	fn ext_twox_128(data: *const u8, len: u32, out: *mut u8);
 	fn ext_twox_256(data: *const u8, len: u32, out: *mut u8);
@@ -87,6 +98,98 @@ pub fn clear_storage(key: &[u8]) {
 	}
 }
 
+/// Clear the storage of all keys starting with the given prefix.
+pub fn clear_prefix(prefix: &[u8]) {
+	unsafe {
+		ext_clear_prefix(
+			prefix.as_ptr(), prefix.len() as u32
+		);
+	}
+}
+
+/// Get the first key in storage which is lexicographically greater than the given one.
+pub fn next_key(key: &[u8]) -> Option<Vec<u8>> {
+	let mut length: u32 = 0;
+	unsafe {
+		let ptr = ext_next_key(key.as_ptr(), key.len() as u32, &mut length);
+		if length == u32::max_value() {
+			None
+		} else {
+			Some(Vec::from_raw_parts(ptr, length as usize, length as usize))
+		}
+	}
+}
+
+/// Get all keys in storage starting with the given prefix, in lexicographic order.
+pub fn keys_with_prefix(prefix: &[u8]) -> Vec<Vec<u8>> {
+	let mut length: u32 = 0;
+	let encoded = unsafe {
+		let ptr = ext_keys_with_prefix(prefix.as_ptr(), prefix.len() as u32, &mut length);
+		Vec::from_raw_parts(ptr, length as usize, length as usize)
+	};
+	<Vec<Vec<u8>> as codec::Slicable>::decode(&mut &encoded[..])
+		.expect("keys are encoded as `Vec<Vec<u8>>` by the host; qed")
+}
+
+/// Get `key` from the child storage trie placed under `storage_key`.
+pub fn child_storage(storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+	let mut length: u32 = 0;
+	unsafe {
+		let ptr = ext_get_allocated_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32,
+			key.as_ptr(), key.len() as u32,
+			&mut length
+		);
+		if length == u32::max_value() {
+			None
+		} else {
+			Some(Vec::from_raw_parts(ptr, length as usize, length as usize))
+		}
+	}
+}
+
+/// Set the value of `key` in the child storage trie placed under `storage_key`.
+pub fn set_child_storage(storage_key: &[u8], key: &[u8], value: &[u8]) {
+	unsafe {
+		ext_set_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32,
+			key.as_ptr(), key.len() as u32,
+			value.as_ptr(), value.len() as u32
+		);
+	}
+}
+
+/// Clear `key` in the child storage trie placed under `storage_key`.
+pub fn clear_child_storage(storage_key: &[u8], key: &[u8]) {
+	unsafe {
+		ext_clear_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32,
+			key.as_ptr(), key.len() as u32
+		);
+	}
+}
+
+/// Remove the child storage trie placed under `storage_key` along with all its entries.
+pub fn kill_child_storage(storage_key: &[u8]) {
+	unsafe {
+		ext_kill_child_storage(
+			storage_key.as_ptr(), storage_key.len() as u32
+		);
+	}
+}
+
+/// Get the root of the child storage trie placed under `storage_key`, or None if it is empty.
+pub fn child_storage_root(storage_key: &[u8]) -> Option<[u8; 32]> {
+	let mut result: [u8; 32] = Default::default();
+	let exists = unsafe {
+		ext_child_storage_root(storage_key.as_ptr(), storage_key.len() as u32, result.as_mut_ptr())
+	};
+	match exists {
+		0 => None,
+		_ => Some(result),
+	}
+}
+
 /// Get `key` from storage, placing the value into `value_out` (as much as possible) and return
 /// the number of bytes that the key in storage was beyond the offset.
 pub fn read_storage(key: &[u8], value_out: &mut [u8], value_offset: usize) -> Option<usize> {
@@ -126,6 +229,28 @@ pub fn enumerated_trie_root(values: &[&[u8]]) -> [u8; 32] {
 	result
 }
 
+/// Start a nested storage transaction. All storage changes made until the matching
+/// `commit_transaction` or `rollback_transaction` call are kept or discarded together.
+pub fn start_transaction() {
+	unsafe {
+		ext_start_transaction();
+	}
+}
+
+/// Commit the innermost storage transaction into the enclosing one.
+pub fn commit_transaction() {
+	unsafe {
+		ext_commit_transaction();
+	}
+}
+
+/// Discard all storage changes made within the innermost storage transaction.
+pub fn rollback_transaction() {
+	unsafe {
+		ext_rollback_transaction();
+	}
+}
+
 /// The current relay chain identifier.
 pub fn chain_id() -> u64 {
 	unsafe {
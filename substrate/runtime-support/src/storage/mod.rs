@@ -459,6 +459,11 @@ pub mod unhashed {
 		runtime_io::clear_storage(key);
 	}
 
+	/// Ensure keys with the given `prefix` have no entries in storage.
+	pub fn kill_prefix(prefix: &[u8]) {
+		runtime_io::clear_prefix(prefix);
+	}
+
 	/// Get a Vec of bytes from storage.
 	pub fn get_raw(key: &[u8]) -> Option<Vec<u8>> {
 		runtime_io::storage(key)
@@ -522,6 +527,53 @@ pub mod unhashed {
 	}
 }
 
+/// Storage of child tries. Each child trie lives under its own storage key and has its own
+/// root, which is committed into the main storage trie. A child trie can be removed as a whole.
+pub mod child {
+	use super::{runtime_io, Slicable, Vec};
+
+	pub use primitives::storage::CHILD_STORAGE_KEY_PREFIX;
+
+	/// Return the value of the item under `key` in the child trie `storage_key`, or `None` if
+	/// there is no explicit entry.
+	pub fn get<T: Slicable + Sized>(storage_key: &[u8], key: &[u8]) -> Option<T> {
+		runtime_io::child_storage(storage_key, key).map(|value| {
+			Slicable::decode(&mut &value[..]).expect("storage is not null, therefore must be a valid type")
+		})
+	}
+
+	/// Return the value of the item under `key` in the child trie `storage_key`, or the type's
+	/// default if there is no explicit entry.
+	pub fn get_or_default<T: Slicable + Sized + Default>(storage_key: &[u8], key: &[u8]) -> T {
+		get(storage_key, key).unwrap_or_else(Default::default)
+	}
+
+	/// Put `value` under `key` in the child trie `storage_key`.
+	pub fn put<T: Slicable>(storage_key: &[u8], key: &[u8], value: &T) {
+		value.using_encoded(|slice| runtime_io::set_child_storage(storage_key, key, slice));
+	}
+
+	/// Ensure `key` has no explicit entry in the child trie `storage_key`.
+	pub fn kill(storage_key: &[u8], key: &[u8]) {
+		runtime_io::clear_child_storage(storage_key, key);
+	}
+
+	/// Remove the child trie `storage_key` along with all its entries.
+	pub fn kill_storage(storage_key: &[u8]) {
+		runtime_io::kill_child_storage(storage_key);
+	}
+
+	/// Get a Vec of bytes from the child trie `storage_key`.
+	pub fn get_raw(storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		runtime_io::child_storage(storage_key, key)
+	}
+
+	/// Put a raw byte slice into the child trie `storage_key`.
+	pub fn put_raw(storage_key: &[u8], key: &[u8], value: &[u8]) {
+		runtime_io::set_child_storage(storage_key, key, value)
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -588,4 +640,20 @@ mod tests {
 			assert_eq!(x, y);
 		});
 	}
+
+	#[test]
+	fn child_tries_can_be_stored_and_killed() {
+		let mut t = TestExternalities::new();
+		let storage_key = b":child_storage:test";
+		with_externalities(&mut t, || {
+			child::put(storage_key, b"a", &69u32);
+			child::put(storage_key, b"b", &42u32);
+			assert_eq!(child::get::<u32>(storage_key, b"a"), Some(69));
+			assert_eq!(child::get::<u32>(b":child_storage:other", b"a"), None);
+
+			child::kill_storage(storage_key);
+			assert_eq!(child::get::<u32>(storage_key, b"a"), None);
+			assert_eq!(child::get_or_default::<u32>(storage_key, b"b"), 0);
+		});
+	}
 }
@@ -18,6 +18,10 @@
 
 use std::{error, fmt};
 use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use triehash::trie_root;
+use trie_backend::TrieBackend;
 
 /// A state backend is used to read state data and can have changes committed
 /// to it.
@@ -26,14 +30,50 @@ pub trait Backend {
 	type Error: super::Error;
 
 	/// Get keyed storage associated with specific address, or None if there is nothing associated.
-	fn storage(&self, key: &[u8]) -> Result<Option<&[u8]>, Self::Error>;
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+	/// Get keyed storage of the child trie placed under `storage_key`, or None if there is
+	/// nothing associated.
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
+
+	/// Get all keys of the child trie placed under `storage_key`, in lexicographic order.
+	fn child_keys(&self, storage_key: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error>;
+
+	/// Get all keys starting with the given prefix, in lexicographic order.
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error>;
+
+	/// Get the first key which is lexicographically greater than the given one.
+	fn next_key(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error>;
 
 	/// Commit updates to the backend and get new state.
-	fn commit<I>(&mut self, changes: I)
+	fn commit<I>(&mut self, changes: I) -> Result<(), Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
+
+	/// Commit updates to the child trie placed under `storage_key`. The new root of the
+	/// child trie is written to `storage_key` in the top-level trie, or the key is
+	/// removed if the child trie has become empty.
+	fn commit_child<I>(&mut self, storage_key: Vec<u8>, changes: I) -> Result<(), Self::Error>
 		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
 
 	/// Get all key/value pairs into a Vec.
-	fn pairs(&self) -> Vec<(&[u8], &[u8])>;
+	fn pairs(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error>;
+
+	/// Calculate the storage root, with given delta over what is already stored in
+	/// the backend. The backend itself is left untouched.
+	fn storage_root<I>(&self, delta: I) -> Result<[u8; 32], Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
+
+	/// Calculate the root of the child trie placed under `storage_key`, with given delta
+	/// over what is already stored in the backend. Returns None if the child trie would
+	/// be empty. The backend itself is left untouched.
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> Result<Option<[u8; 32]>, Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>;
+}
+
+/// Try convert into trie-based backend.
+pub trait TryIntoTrieBackend {
+	/// Try to convert self into trie backend.
+	fn try_into_trie_backend(self) -> Option<TrieBackend>;
 }
 
 /// Error impossible.
@@ -53,29 +93,137 @@ impl error::Error for Void {
 
 /// In-memory backend. Fully recomputes tries on each commit but useful for
 /// tests.
-pub type InMemory = HashMap<Vec<u8>, Vec<u8>>;
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InMemory {
+	top: HashMap<Vec<u8>, Vec<u8>>,
+	children: HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl InMemory {
+	/// Get the key/value pairs of all child tries, keyed by their storage key.
+	pub fn children(&self) -> &HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>> {
+		&self.children
+	}
+}
+
+impl Deref for InMemory {
+	type Target = HashMap<Vec<u8>, Vec<u8>>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.top
+	}
+}
+
+impl From<HashMap<Vec<u8>, Vec<u8>>> for InMemory {
+	fn from(top: HashMap<Vec<u8>, Vec<u8>>) -> Self {
+		InMemory {
+			top,
+			children: HashMap::new(),
+		}
+	}
+}
+
+impl FromIterator<(Vec<u8>, Vec<u8>)> for InMemory {
+	fn from_iter<I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>>(iter: I) -> Self {
+		InMemory::from(iter.into_iter().collect::<HashMap<_, _>>())
+	}
+}
+
+fn apply_changes<I>(map: &mut HashMap<Vec<u8>, Vec<u8>>, changes: I)
+	where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+{
+	for (key, val) in changes {
+		match val {
+			Some(v) => { map.insert(key, v); },
+			None => { map.remove(&key); },
+		}
+	}
+}
 
 impl Backend for InMemory {
 	type Error = Void;
 
-	fn storage(&self, key: &[u8]) -> Result<Option<&[u8]>, Self::Error> {
-		Ok(self.get(key).map(AsRef::as_ref))
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		Ok(self.top.get(key).cloned())
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		Ok(self.children.get(storage_key).and_then(|child| child.get(key)).cloned())
+	}
+
+	fn child_keys(&self, storage_key: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		let mut keys: Vec<_> = self.children.get(storage_key)
+			.map(|child| child.keys().cloned().collect())
+			.unwrap_or_default();
+		keys.sort();
+		Ok(keys)
+	}
+
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		let mut keys: Vec<_> = self.top.keys().filter(|k| k.starts_with(prefix)).cloned().collect();
+		keys.sort();
+		Ok(keys)
+	}
+
+	fn next_key(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		Ok(self.top.keys().filter(|k| &k[..] > key).min().cloned())
+	}
+
+	fn commit<I>(&mut self, changes: I) -> Result<(), Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		apply_changes(&mut self.top, changes);
+		Ok(())
 	}
 
-	fn commit<I>(&mut self, changes: I)
+	fn commit_child<I>(&mut self, storage_key: Vec<u8>, changes: I) -> Result<(), Self::Error>
 		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
 	{
-		for (key, val) in changes {
-			match val {
-				Some(v) => { self.insert(key, v); },
-				None => { self.remove(&key); },
+		let root = {
+			let child = self.children.entry(storage_key.clone()).or_insert_with(HashMap::new);
+			apply_changes(child, changes);
+			match child.is_empty() {
+				true => None,
+				false => Some(trie_root(child.clone()).0.to_vec()),
 			}
+		};
+
+		match root {
+			Some(root) => { self.top.insert(storage_key, root); },
+			None => {
+				self.children.remove(&storage_key);
+				self.top.remove(&storage_key);
+			},
 		}
+		Ok(())
 	}
 
-	fn pairs(&self) -> Vec<(&[u8], &[u8])> {
-		self.iter().map(|(k, v)| (&k[..], &v[..])).collect()
+	fn pairs(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error> {
+		Ok(self.top.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+	}
+
+	fn storage_root<I>(&self, delta: I) -> Result<[u8; 32], Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut updated = self.top.clone();
+		apply_changes(&mut updated, delta);
+		Ok(trie_root(updated).0)
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> Result<Option<[u8; 32]>, Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut updated = self.children.get(storage_key).cloned().unwrap_or_default();
+		apply_changes(&mut updated, delta);
+		Ok(match updated.is_empty() {
+			true => None,
+			false => Some(trie_root(updated).0),
+		})
 	}
 }
 
-// TODO: DB-based backend
+impl TryIntoTrieBackend for InMemory {
+	fn try_into_trie_backend(self) -> Option<TrieBackend> {
+		Some(TrieBackend::from_pairs_with_children(self.top, self.children))
+	}
+}
@@ -17,10 +17,11 @@
 //! Conrete externalities implementation.
 
 use std::{error, fmt};
+use std::collections::BTreeSet;
+#[cfg(test)]
 use std::collections::HashMap;
-use triehash::trie_root;
 use backend::Backend;
-use {Externalities, OverlayedChanges};
+use {Externalities, ExecutionError, OverlayedChanges, is_child_storage_key};
 
 /// Errors that can occur when interacting with the externalities.
 #[derive(Debug, Copy, Clone)]
@@ -59,13 +60,25 @@ pub struct Ext<'a, B: 'a> {
 	pub backend: &'a B,
 }
 
-#[cfg(test)]
 impl<'a, B: 'a + Backend> Ext<'a, B> {
+	// the changes to the child trie placed under `storage_key`, oldest first.
+	fn child_delta(&self, storage_key: &[u8]) -> Vec<(Vec<u8>, Option<Vec<u8>>)> {
+		self.overlay.changed_child_keys(storage_key).into_iter()
+			.map(|key| {
+				let value = self.overlay.child_storage(storage_key, &key)
+					.and_then(|v| v.map(|v| v.to_vec()));
+				(key, value)
+			})
+			.collect()
+	}
+
+	#[cfg(test)]
 	pub fn storage_pairs(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
-		self.backend.pairs().iter()
-			.map(|&(ref k, ref v)| (k.to_vec(), Some(v.to_vec())))
-			.chain(self.overlay.committed.clone().into_iter())
-			.chain(self.overlay.prospective.clone().into_iter())
+		self.backend.pairs().expect("Externalities not allowed to fail within runtime").into_iter()
+			.map(|(k, v)| (k, Some(v)))
+			.chain(self.overlay.committed.top.clone().into_iter())
+			.chain(self.overlay.prospective.top.clone().into_iter())
+			.chain(self.overlay.transactions.iter().flat_map(|t| t.top.clone().into_iter()))
 			.collect::<HashMap<_, _>>()
 			.into_iter()
 			.filter_map(|(k, maybe_val)| maybe_val.map(|val| (k, val)))
@@ -76,26 +89,117 @@ impl<'a, B: 'a + Backend> Ext<'a, B> {
 impl<'a, B: 'a> Externalities for Ext<'a, B>
 	where B: Backend
 {
-	fn storage(&self, key: &[u8]) -> Option<&[u8]> {
-		self.overlay.storage(key).unwrap_or_else(||
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.overlay.storage(key).map(|x| x.map(|x| x.to_vec())).unwrap_or_else(||
 			self.backend.storage(key).expect("Externalities not allowed to fail within runtime"))
 	}
 
 	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if is_child_storage_key(&key) {
+			warn!(target: "state", "Refusing to directly set the root of a child storage trie");
+			return;
+		}
+
 		self.overlay.set_storage(key, value);
 	}
 
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		self.overlay.child_storage(storage_key, key).map(|x| x.map(|x| x.to_vec())).unwrap_or_else(||
+			self.backend.child_storage(storage_key, key).expect("Externalities not allowed to fail within runtime"))
+	}
+
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Option<Vec<u8>>) {
+		if !is_child_storage_key(&storage_key) {
+			warn!(target: "state", "Refusing to set child storage under a non-child storage key");
+			return;
+		}
+
+		self.overlay.set_child_storage(storage_key, key, value);
+	}
+
+	fn kill_child_storage(&mut self, storage_key: &[u8]) {
+		let mut keys: BTreeSet<_> = self.backend.child_keys(storage_key)
+			.expect("Externalities not allowed to fail within runtime")
+			.into_iter()
+			.collect();
+		keys.extend(self.overlay.changed_child_keys(storage_key));
+
+		for key in keys {
+			self.place_child_storage(storage_key.to_vec(), key, None);
+		}
+	}
+
+	fn child_storage_root(&self, storage_key: &[u8]) -> Option<[u8; 32]> {
+		self.backend.child_storage_root(storage_key, self.child_delta(storage_key))
+			.expect("Externalities not allowed to fail within runtime")
+	}
+
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+		let mut keys: BTreeSet<_> = self.backend.keys_with_prefix(prefix)
+			.expect("Externalities not allowed to fail within runtime")
+			.into_iter()
+			.collect();
+		for key in self.overlay.changed_keys_with_prefix(prefix) {
+			match self.overlay.storage(&key) {
+				Some(Some(_)) => { keys.insert(key); },
+				_ => { keys.remove(&key); },
+			}
+		}
+		keys.into_iter().collect()
+	}
+
+	fn next_key(&self, key: &[u8]) -> Option<Vec<u8>> {
+		// skip over backend keys which have been cleared in the overlay.
+		let mut backend_next = self.backend.next_key(key)
+			.expect("Externalities not allowed to fail within runtime");
+		while let Some(next) = backend_next.take() {
+			match self.overlay.storage(&next) {
+				Some(None) => backend_next = self.backend.next_key(&next)
+					.expect("Externalities not allowed to fail within runtime"),
+				_ => {
+					backend_next = Some(next);
+					break;
+				},
+			}
+		}
+
+		match (backend_next, self.overlay.next_key(key)) {
+			(Some(a), Some(b)) => Some(::std::cmp::min(a, b)),
+			(a, b) => a.or(b),
+		}
+	}
+
 	fn chain_id(&self) -> u64 {
 		42
 	}
 
 	fn storage_root(&self) -> [u8; 32] {
-		trie_root(self.backend.pairs().iter()
-			.map(|&(ref k, ref v)| (k.to_vec(), Some(v.to_vec())))
-			.chain(self.overlay.committed.clone().into_iter())
-			.chain(self.overlay.prospective.clone().into_iter())
-			.collect::<HashMap<_, _>>()
-			.into_iter()
-			.filter_map(|(k, maybe_val)| maybe_val.map(|val| (k, val)))).0
+		// roots of the changed child tries go into the top-level trie.
+		let child_roots: Vec<_> = self.overlay.changed_children().into_iter()
+			.map(|storage_key| {
+				let root = self.child_storage_root(&storage_key);
+				(storage_key, root.map(|r| r.to_vec()))
+			})
+			.collect();
+
+		let delta = self.overlay.committed.top.iter()
+			.chain(self.overlay.prospective.top.iter())
+			.chain(self.overlay.transactions.iter().flat_map(|t| t.top.iter()))
+			.map(|(k, v)| (k.clone(), v.clone()))
+			.chain(child_roots);
+
+		self.backend.storage_root(delta).expect("Externalities not allowed to fail within runtime")
+	}
+
+	fn start_transaction(&mut self) {
+		self.overlay.start_transaction();
+	}
+
+	fn commit_transaction(&mut self) -> Result<(), ExecutionError> {
+		self.overlay.commit_transaction()
+	}
+
+	fn rollback_transaction(&mut self) -> Result<(), ExecutionError> {
+		self.overlay.rollback_transaction()
 	}
 }
@@ -23,6 +23,7 @@ extern crate substrate_primitives as primitives;
 #[cfg_attr(test, macro_use)]
 extern crate hex_literal;
 
+extern crate ethcore_bigint;
 extern crate hashdb;
 extern crate memorydb;
 
@@ -31,59 +32,200 @@ extern crate triehash;
 
 extern crate byteorder;
 
-use std::collections::HashMap;
+#[macro_use]
+extern crate log;
+
+use std::collections::{HashMap, BTreeSet};
 use std::collections::hash_map::Drain;
 use std::fmt;
 
 pub mod backend;
 mod ext;
 mod testing;
+mod proving_backend;
+mod trie_backend;
 
 pub use testing::TestExternalities;
 pub use ext::Ext;
 pub use backend::Backend;
+pub use trie_backend::{TrieBackend, TrieBackendStorage, Storage as TrieStorage};
+pub use proving_backend::{ProvingBackend, create_proof_check_backend};
+
+pub use primitives::storage::CHILD_STORAGE_KEY_PREFIX;
+
+/// Whether the given top-level storage key can hold the root of a child storage trie.
+/// Values under such keys can only be changed through the child storage functions.
+pub fn is_child_storage_key(key: &[u8]) -> bool {
+	key.starts_with(CHILD_STORAGE_KEY_PREFIX)
+}
+
+/// A set of changes to the top-level storage and to the child storage tries.
+#[derive(Debug, Default, Clone)]
+struct OverlayLayer {
+	top: HashMap<Vec<u8>, Option<Vec<u8>>>,
+	children: HashMap<Vec<u8>, HashMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl OverlayLayer {
+	fn is_empty(&self) -> bool {
+		self.top.is_empty() && self.children.is_empty()
+	}
+
+	fn clear(&mut self) {
+		self.top.clear();
+		self.children.clear();
+	}
+
+	fn extend(&mut self, other: OverlayLayer) {
+		self.top.extend(other.top);
+		for (storage_key, child) in other.children {
+			self.children.entry(storage_key).or_insert_with(HashMap::new).extend(child);
+		}
+	}
+}
 
 /// The overlayed changes to state to be queried on top of the backend.
 ///
 /// A transaction shares all prospective changes within an inner overlay
-/// that can be cleared.
+/// that can be cleared. Prospective changes may be further split into a stack
+/// of nested transactions, each of which can be committed into the enclosing
+/// one or rolled back on its own.
 #[derive(Debug, Default, Clone)]
 pub struct OverlayedChanges {
-	prospective: HashMap<Vec<u8>, Option<Vec<u8>>>,
-	committed: HashMap<Vec<u8>, Option<Vec<u8>>>,
+	prospective: OverlayLayer,
+	committed: OverlayLayer,
+	transactions: Vec<OverlayLayer>,
 }
 
 impl OverlayedChanges {
+	// all layers, from the innermost transaction to the committed changes.
+	fn layers<'a>(&'a self) -> Box<Iterator<Item=&'a OverlayLayer> + 'a> {
+		Box::new(self.transactions.iter().rev()
+			.chain(::std::iter::once(&self.prospective))
+			.chain(::std::iter::once(&self.committed)))
+	}
+
+	fn top_layer(&mut self) -> &mut OverlayLayer {
+		match self.transactions.last_mut() {
+			Some(transaction) => transaction,
+			None => &mut self.prospective,
+		}
+	}
+
 	/// Returns a double-Option: None if the key is unknown (i.e. and the query should be refered
 	/// to the backend); Some(None) if the key has been deleted. Some(Some(...)) for a key whose
 	/// value has been set.
 	pub fn storage(&self, key: &[u8]) -> Option<Option<&[u8]>> {
-		self.prospective.get(key)
-			.or_else(|| self.committed.get(key))
+		self.layers()
+			.filter_map(|layer| layer.top.get(key))
+			.next()
+			.map(|x| x.as_ref().map(AsRef::as_ref))
+	}
+
+	/// Returns a double-Option like `storage`, for a key of the child trie placed under
+	/// `storage_key`.
+	pub fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Option<&[u8]>> {
+		self.layers()
+			.filter_map(|layer| layer.children.get(storage_key).and_then(|child| child.get(key)))
+			.next()
 			.map(|x| x.as_ref().map(AsRef::as_ref))
 	}
 
+	/// Get all keys starting with the given prefix which have been set or cleared
+	/// in the overlay, in lexicographic order.
+	pub fn changed_keys_with_prefix(&self, prefix: &[u8]) -> BTreeSet<Vec<u8>> {
+		self.layers()
+			.flat_map(|layer| layer.top.keys())
+			.filter(|k| k.starts_with(prefix))
+			.cloned()
+			.collect()
+	}
+
+	/// Get the storage keys of all child tries which have been changed in the overlay.
+	pub fn changed_children(&self) -> BTreeSet<Vec<u8>> {
+		self.layers()
+			.flat_map(|layer| layer.children.keys())
+			.cloned()
+			.collect()
+	}
+
+	/// Get all keys of the child trie placed under `storage_key` which have been set
+	/// or cleared in the overlay, in lexicographic order.
+	pub fn changed_child_keys(&self, storage_key: &[u8]) -> BTreeSet<Vec<u8>> {
+		self.layers()
+			.filter_map(|layer| layer.children.get(storage_key))
+			.flat_map(|child| child.keys())
+			.cloned()
+			.collect()
+	}
+
+	/// Get the first key which is lexicographically greater than the given one and
+	/// has a value set in the overlay.
+	pub fn next_key(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.layers()
+			.flat_map(|layer| layer.top.keys())
+			.filter(|k| &k[..] > key)
+			.filter(|k| self.storage(k).map_or(false, |v| v.is_some()))
+			.min()
+			.cloned()
+	}
+
 	fn set_storage(&mut self, key: Vec<u8>, val: Option<Vec<u8>>) {
-		self.prospective.insert(key, val);
+		self.top_layer().top.insert(key, val);
+	}
+
+	fn set_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, val: Option<Vec<u8>>) {
+		self.top_layer().children.entry(storage_key).or_insert_with(HashMap::new).insert(key, val);
+	}
+
+	/// Start a nested transaction. Changes made after this call can be discarded
+	/// with `rollback_transaction` without affecting any earlier changes.
+	pub fn start_transaction(&mut self) {
+		self.transactions.push(OverlayLayer::default());
 	}
 
-	/// Discard prospective changes to state.
+	/// Commit the innermost nested transaction into the enclosing one. Fails if
+	/// there is no transaction to commit.
+	pub fn commit_transaction(&mut self) -> Result<(), ExecutionError> {
+		let transaction = self.transactions.pop().ok_or(ExecutionError::NoOpenTransaction)?;
+		self.top_layer().extend(transaction);
+		Ok(())
+	}
+
+	/// Discard all changes made within the innermost nested transaction. Fails if
+	/// there is no transaction to discard.
+	pub fn rollback_transaction(&mut self) -> Result<(), ExecutionError> {
+		self.transactions.pop().map(|_| ()).ok_or(ExecutionError::NoOpenTransaction)
+	}
+
+	/// Discard prospective changes to state, including all nested transactions.
 	pub fn discard_prospective(&mut self) {
+		self.transactions.clear();
 		self.prospective.clear();
 	}
 
-	/// Commit prospective changes to state.
+	/// Commit prospective changes to state. Nested transactions which are still
+	/// open are committed as well.
 	pub fn commit_prospective(&mut self) {
+		while self.commit_transaction().is_ok() {}
+
 		if self.committed.is_empty() {
 			::std::mem::swap(&mut self.prospective, &mut self.committed);
 		} else {
-			self.committed.extend(self.prospective.drain());
+			let prospective = ::std::mem::replace(&mut self.prospective, OverlayLayer::default());
+			self.committed.extend(prospective);
 		}
 	}
 
-	/// Drain prospective changes to an iterator.
+	/// Drain committed changes to the top-level storage to an iterator.
 	pub fn drain(&mut self) -> Drain<Vec<u8>, Option<Vec<u8>>> {
-		self.committed.drain()
+		self.committed.top.drain()
+	}
+
+	/// Drain committed changes to child storage tries to an iterator, grouped by the
+	/// storage key of the child trie.
+	pub fn drain_children(&mut self) -> Drain<Vec<u8>, HashMap<Vec<u8>, Option<Vec<u8>>>> {
+		self.committed.children.drain()
 	}
 }
 
@@ -101,7 +243,11 @@ impl<E> Error for E where E: 'static + fmt::Debug + fmt::Display + Send {}
 #[derive(Debug, Eq, PartialEq)]
 pub enum ExecutionError {
 	/// The entry `:code` doesn't exist in storage so there's no way we can execute anything.
-	CodeEntryDoesNotExist
+	CodeEntryDoesNotExist,
+	/// Given proof doesn't contain the requested state root.
+	InvalidProof,
+	/// A storage transaction was committed or rolled back without being started.
+	NoOpenTransaction,
 }
 
 impl fmt::Display for ExecutionError {
@@ -111,7 +257,7 @@ impl fmt::Display for ExecutionError {
 /// Externalities: pinned to specific active address.
 pub trait Externalities {
 	/// Read storage of current contract being called.
-	fn storage(&self, key: &[u8]) -> Option<&[u8]>;
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>>;
 
 	/// Set storage entry `key` of current contract being called (effective immediately).
 	fn set_storage(&mut self, key: Vec<u8>, value: Vec<u8>) {
@@ -123,14 +269,65 @@ pub trait Externalities {
 		self.place_storage(key.to_vec(), None);
 	}
 
+	/// Clear all storage entries whose keys start with the given prefix (effective immediately).
+	fn clear_prefix(&mut self, prefix: &[u8]) {
+		for key in self.keys_with_prefix(prefix) {
+			self.place_storage(key, None);
+		}
+	}
+
+	/// Get the keys of all storage entries starting with the given prefix, in lexicographic order.
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>>;
+
+	/// Get the first key of a storage entry which is lexicographically greater than the given one.
+	fn next_key(&self, key: &[u8]) -> Option<Vec<u8>>;
+
 	/// Set or clear a storage entry (`key`) of current contract being called (effective immediately).
 	fn place_storage(&mut self, key: Vec<u8>, value: Option<Vec<u8>>);
 
+	/// Read the entry `key` of the child storage trie placed under `storage_key`.
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>>;
+
+	/// Set the entry `key` of the child storage trie placed under `storage_key` (effective immediately).
+	fn set_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Vec<u8>) {
+		self.place_child_storage(storage_key, key, Some(value));
+	}
+
+	/// Clear the entry `key` of the child storage trie placed under `storage_key` (effective immediately).
+	fn clear_child_storage(&mut self, storage_key: &[u8], key: &[u8]) {
+		self.place_child_storage(storage_key.to_vec(), key.to_vec(), None);
+	}
+
+	/// Clear all entries of the child storage trie placed under `storage_key`, removing
+	/// the trie altogether (effective immediately).
+	fn kill_child_storage(&mut self, storage_key: &[u8]);
+
+	/// Set or clear the entry `key` of the child storage trie placed under `storage_key`
+	/// (effective immediately). Changes are ignored unless `storage_key` starts with
+	/// `CHILD_STORAGE_KEY_PREFIX`.
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, value: Option<Vec<u8>>);
+
+	/// Get the trie root of the child storage trie placed under `storage_key`, or None
+	/// if the child trie is empty.
+	fn child_storage_root(&self, storage_key: &[u8]) -> Option<[u8; 32]>;
+
 	/// Get the identity of the chain.
 	fn chain_id(&self) -> u64;
 
 	/// Get the trie root of the current storage map.
 	fn storage_root(&self) -> [u8; 32];
+
+	/// Start a nested storage transaction. All storage changes made until the matching
+	/// `commit_transaction` or `rollback_transaction` call are kept or discarded together.
+	fn start_transaction(&mut self);
+
+	/// Commit the innermost storage transaction into the enclosing one. Fails if
+	/// no transaction was started.
+	fn commit_transaction(&mut self) -> Result<(), ExecutionError>;
+
+	/// Discard all storage changes made within the innermost storage transaction.
+	/// Fails if no transaction was started.
+	fn rollback_transaction(&mut self) -> Result<(), ExecutionError>;
 }
 
 /// Code execution engine.
@@ -170,8 +367,7 @@ pub fn execute<B: backend::Backend, Exec: CodeExecutor>(
 		};
 		// make a copy.
 		let code = externalities.storage(b":code")
-			.ok_or(Box::new(ExecutionError::CodeEntryDoesNotExist) as Box<Error>)?
-			.to_vec();
+			.ok_or(Box::new(ExecutionError::CodeEntryDoesNotExist) as Box<Error>)?;
 
 		exec.call(
 			&mut externalities,
@@ -193,12 +389,80 @@ pub fn execute<B: backend::Backend, Exec: CodeExecutor>(
 	}
 }
 
+/// Generate a storage read proof for the given keys on top of the trie backend.
+///
+/// Returns the read values along with the set of trie nodes that must be
+/// supplied to `read_proof_check` in order to verify them.
+pub fn prove_read(
+	backend: TrieBackend,
+	keys: &[Vec<u8>],
+) -> Result<(Vec<Option<Vec<u8>>>, Vec<Vec<u8>>), Box<Error>>
+{
+	let proving_backend = ProvingBackend::new(backend);
+	let values = keys.iter()
+		.map(|key| proving_backend.storage(key).map_err(|e| Box::new(e) as Box<Error>))
+		.collect::<Result<Vec<_>, _>>()?;
+	Ok((values, proving_backend.extract_proof()))
+}
+
+/// Check a storage read proof generated by `prove_read` against the given state
+/// root, returning the values of the keys.
+pub fn read_proof_check(
+	root: [u8; 32],
+	proof: Vec<Vec<u8>>,
+	keys: &[Vec<u8>],
+) -> Result<Vec<Option<Vec<u8>>>, Box<Error>>
+{
+	let backend = create_proof_check_backend(root.into(), proof)?;
+	keys.iter()
+		.map(|key| backend.storage(key).map_err(|e| Box::new(e) as Box<Error>))
+		.collect()
+}
+
+/// Execute a call on top of the trie backend, generating a proof of execution.
+///
+/// Returns the call result along with the set of trie nodes that must be
+/// supplied to `execution_proof_check` in order to re-execute the call.
+pub fn prove_execution<Exec: CodeExecutor>(
+	backend: TrieBackend,
+	overlay: &mut OverlayedChanges,
+	exec: &Exec,
+	method: &str,
+	call_data: &[u8],
+) -> Result<(Vec<u8>, Vec<Vec<u8>>), Box<Error>>
+{
+	let proving_backend = ProvingBackend::new(backend);
+	let result = execute(&proving_backend, overlay, exec, method, call_data)?;
+	Ok((result, proving_backend.extract_proof()))
+}
+
+/// Check an execution proof generated by `prove_execution` against the given
+/// state root by re-executing the call on top of the proof.
+pub fn execution_proof_check<Exec: CodeExecutor>(
+	root: [u8; 32],
+	proof: Vec<Vec<u8>>,
+	overlay: &mut OverlayedChanges,
+	exec: &Exec,
+	method: &str,
+	call_data: &[u8],
+) -> Result<Vec<u8>, Box<Error>>
+{
+	let backend = create_proof_check_backend(root.into(), proof)?;
+	execute(&backend, overlay, exec, method, call_data)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use super::backend::InMemory;
+	use super::backend::{InMemory, TryIntoTrieBackend};
 	use super::ext::Ext;
 
+	macro_rules! map {
+		($( $name:expr => $value:expr ),*) => (
+			vec![ $( ( $name, $value ) ),* ].into_iter().collect()
+		)
+	}
+
 	#[test]
 	fn overlayed_storage_works() {
 		let mut overlayed = OverlayedChanges::default();
@@ -227,30 +491,104 @@ mod tests {
 		assert!(overlayed.storage(&key).unwrap().is_none());
 	}
 
-	macro_rules! map {
-		($( $name:expr => $value:expr ),*) => (
-			vec![ $( ( $name, $value ) ),* ].into_iter().collect()
-		)
+	#[test]
+	fn nested_transactions_work() {
+		let mut overlayed = OverlayedChanges::default();
+		let key = vec![42, 69, 169, 142];
+		let other_key = vec![1, 2, 3];
+
+		overlayed.set_storage(key.clone(), Some(vec![1]));
+
+		overlayed.start_transaction();
+		overlayed.set_storage(key.clone(), Some(vec![2]));
+		overlayed.set_storage(other_key.clone(), Some(vec![2]));
+
+		overlayed.start_transaction();
+		overlayed.set_storage(key.clone(), None);
+		assert!(overlayed.storage(&key).unwrap().is_none());
+		overlayed.rollback_transaction().unwrap();
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[2][..]));
+
+		overlayed.start_transaction();
+		overlayed.set_storage(other_key.clone(), Some(vec![3]));
+		overlayed.commit_transaction().unwrap();
+		assert_eq!(overlayed.storage(&other_key).unwrap(), Some(&[3][..]));
+
+		overlayed.rollback_transaction().unwrap();
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[1][..]));
+		assert!(overlayed.storage(&other_key).is_none());
+
+		overlayed.start_transaction();
+		overlayed.set_storage(other_key.clone(), Some(vec![4]));
+		overlayed.commit_prospective();
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[1][..]));
+		assert_eq!(overlayed.storage(&other_key).unwrap(), Some(&[4][..]));
+
+		overlayed.start_transaction();
+		overlayed.set_storage(key.clone(), Some(vec![5]));
+		overlayed.discard_prospective();
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[1][..]));
+
+		// unbalanced calls fail and leave the changes untouched.
+		assert_eq!(overlayed.rollback_transaction(), Err(ExecutionError::NoOpenTransaction));
+		assert_eq!(overlayed.commit_transaction(), Err(ExecutionError::NoOpenTransaction));
+		assert_eq!(overlayed.storage(&key).unwrap(), Some(&[1][..]));
+	}
+
+	#[test]
+	fn overlayed_keys_are_merged_with_backend() {
+		let backend: InMemory = map![
+			b"doe".to_vec() => b"reindeer".to_vec(),
+			b"dog".to_vec() => b"puppy".to_vec(),
+			b"dogglesworth".to_vec() => b"cat".to_vec(),
+			b"doug".to_vec() => b"notadog".to_vec()
+		];
+		let mut overlay = OverlayedChanges::default();
+		overlay.set_storage(b"dog".to_vec(), None);
+		overlay.set_storage(b"dogfood".to_vec(), Some(b"yummy".to_vec()));
+		overlay.commit_prospective();
+		overlay.set_storage(b"dogz".to_vec(), Some(b"z".to_vec()));
+
+		let mut ext = Ext {
+			backend: &backend,
+			overlay: &mut overlay,
+		};
+		assert_eq!(ext.keys_with_prefix(b"dog"), vec![b"dogfood".to_vec(), b"dogglesworth".to_vec(), b"dogz".to_vec()]);
+		assert_eq!(ext.next_key(b"doe"), Some(b"dogfood".to_vec()));
+		assert_eq!(ext.next_key(b"dogglesworth"), Some(b"dogz".to_vec()));
+		assert_eq!(ext.next_key(b"doug"), None);
+
+		ext.clear_prefix(b"dog");
+		assert!(ext.keys_with_prefix(b"dog").is_empty());
+		assert_eq!(ext.next_key(b"doe"), Some(b"doug".to_vec()));
+		assert_eq!(ext.storage(b"doe"), Some(b"reindeer".to_vec()));
 	}
 
 	#[test]
 	fn overlayed_storage_root_works() {
-		let mut backend = InMemory::from(map![
+		let mut backend: InMemory = map![
 			b"doe".to_vec() => b"reindeer".to_vec(),
 			b"dog".to_vec() => b"puppyXXX".to_vec(),
 			b"dogglesworth".to_vec() => b"catXXX".to_vec(),
 			b"doug".to_vec() => b"notadog".to_vec()
-		]);
+		];
 		let mut overlay = OverlayedChanges {
-			committed: map![
-				b"dog".to_vec() => Some(b"puppy".to_vec()),
-				b"dogglesworth".to_vec() => Some(b"catYYY".to_vec()),
-				b"doug".to_vec() => Some(vec![])
-			],
-			prospective: map![
-				b"dogglesworth".to_vec() => Some(b"cat".to_vec()),
-				b"doug".to_vec() => None
-			],
+			committed: OverlayLayer {
+				top: map![
+					b"dog".to_vec() => Some(b"puppy".to_vec()),
+					b"dogglesworth".to_vec() => Some(b"catYYY".to_vec()),
+					b"doug".to_vec() => Some(vec![])
+				],
+				children: HashMap::new(),
+			},
+			prospective: OverlayLayer {
+				top: map![
+					b"dogglesworth".to_vec() => Some(b"cat".to_vec()),
+					b"doug".to_vec() => None
+				],
+				children: HashMap::new(),
+			},
+			transactions: Vec::new(),
 		};
 		let ext = Ext {
 			backend: &mut backend,
@@ -259,4 +597,102 @@ mod tests {
 		const ROOT: [u8; 32] = hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
 		assert_eq!(ext.storage_root(), ROOT);
 	}
+
+	#[test]
+	fn child_storage_works() {
+		let child = b":child_storage:default:1".to_vec();
+		let mut backend: InMemory = map![
+			b"doe".to_vec() => b"reindeer".to_vec()
+		];
+		backend.commit_child(child.clone(), vec![(b"dog".to_vec(), Some(b"puppy".to_vec()))]);
+		let trie = backend.clone().try_into_trie_backend().unwrap();
+		assert_eq!(trie.child_storage(&child, b"dog").unwrap(), Some(b"puppy".to_vec()));
+
+		let mut overlay = OverlayedChanges::default();
+		{
+			let mut ext = Ext {
+				backend: &backend,
+				overlay: &mut overlay,
+			};
+			assert_eq!(ext.child_storage(&child, b"dog"), Some(b"puppy".to_vec()));
+
+			// direct writes to child trie roots are ignored.
+			let root = ext.storage(&child);
+			ext.set_storage(child.clone(), b"garbage".to_vec());
+			assert_eq!(ext.storage(&child), root);
+
+			ext.set_child_storage(child.clone(), b"cat".to_vec(), b"kitten".to_vec());
+			ext.clear_child_storage(&child, b"dog");
+			assert_eq!(ext.child_storage(&child, b"dog"), None);
+			assert_eq!(ext.child_storage(&child, b"cat"), Some(b"kitten".to_vec()));
+			assert!(ext.child_storage_root(&child).is_some());
+		}
+		overlay.commit_prospective();
+		let root = Ext { backend: &trie, overlay: &mut overlay }.storage_root();
+		assert_eq!(Ext { backend: &backend, overlay: &mut overlay }.storage_root(), root);
+
+		let mut committed = trie.clone();
+		committed.commit(overlay.drain()).unwrap();
+		for (storage_key, changes) in overlay.drain_children() {
+			committed.commit_child(storage_key, changes).unwrap();
+		}
+		assert_eq!(committed.root().0, root);
+		assert_eq!(committed.child_keys(&child).unwrap(), vec![b"cat".to_vec()]);
+
+		// killing the child trie removes its root from the top-level trie.
+		let mut overlay = OverlayedChanges::default();
+		let mut ext = Ext {
+			backend: &committed,
+			overlay: &mut overlay,
+		};
+		ext.kill_child_storage(&child);
+		assert_eq!(ext.child_storage(&child, b"cat"), None);
+		assert_eq!(ext.child_storage_root(&child), None);
+		let expected: InMemory = map![
+			b"doe".to_vec() => b"reindeer".to_vec()
+		];
+		assert_eq!(ext.storage_root(), expected.storage_root(vec![]));
+	}
+
+	#[test]
+	fn prove_read_and_proof_check_works() {
+		let backend = TrieBackend::from_pairs(vec![
+			(b"doe".to_vec(), b"reindeer".to_vec()),
+			(b"dog".to_vec(), b"puppy".to_vec()),
+			(b"dogglesworth".to_vec(), b"cat".to_vec()),
+		]);
+		let root = backend.root().0;
+		let keys = vec![b"dog".to_vec(), b"doug".to_vec()];
+
+		let (values, proof) = prove_read(backend, &keys).unwrap();
+		assert_eq!(values, vec![Some(b"puppy".to_vec()), None]);
+		assert_eq!(read_proof_check(root, proof.clone(), &keys).unwrap(), values);
+		assert!(read_proof_check([1; 32], proof, &keys).is_err());
+	}
+
+	struct DummyCodeExecutor;
+
+	impl CodeExecutor for DummyCodeExecutor {
+		type Error = u8;
+
+		fn call<E: Externalities>(&self, ext: &mut E, _code: &[u8], _method: &str, _data: &[u8]) -> Result<Vec<u8>, Self::Error> {
+			Ok(vec![ext.storage(b"value1").ok_or(0)?[0] + ext.storage(b"value2").ok_or(0)?[0]])
+		}
+	}
+
+	#[test]
+	fn prove_execution_and_proof_check_works() {
+		let backend = TrieBackend::from_pairs(vec![
+			(b":code".to_vec(), b"code".to_vec()),
+			(b"value1".to_vec(), vec![40]),
+			(b"value2".to_vec(), vec![2]),
+			(b"value3".to_vec(), vec![100]),
+		]);
+		let root = backend.root().0;
+
+		let (result, proof) = prove_execution(backend, &mut Default::default(), &DummyCodeExecutor, "test", &[]).unwrap();
+		assert_eq!(result, vec![42]);
+		assert_eq!(execution_proof_check(root, proof.clone(), &mut Default::default(), &DummyCodeExecutor, "test", &[]).unwrap(), vec![42]);
+		assert!(execution_proof_check([1; 32], proof, &mut Default::default(), &DummyCodeExecutor, "test", &[]).is_err());
+	}
 }
@@ -0,0 +1,177 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Proving state machine backend.
+
+use std::cell::RefCell;
+use ethcore_bigint::hash::H256 as TrieH256;
+use hashdb::HashDB;
+use memorydb::MemoryDB;
+use patricia_trie::{TrieDB, TrieError, Trie, Recorder};
+use trie_backend::{TrieBackend, Ephemeral};
+use {Error, ExecutionError, Backend};
+
+/// Patricia trie-based backend which also tracks all touched storage trie values.
+/// These can be sent to a remote node and used as a proof of execution.
+pub struct ProvingBackend {
+	backend: TrieBackend,
+	proof_recorder: RefCell<Recorder>,
+}
+
+impl ProvingBackend {
+	/// Create new proving backend.
+	pub fn new(backend: TrieBackend) -> Self {
+		ProvingBackend {
+			backend,
+			proof_recorder: RefCell::new(Recorder::new()),
+		}
+	}
+
+	/// Consume the backend, extracting the gathered proof in lexicographical order
+	/// by value.
+	pub fn extract_proof(self) -> Vec<Vec<u8>> {
+		let mut proof: Vec<Vec<u8>> = self.proof_recorder.into_inner().drain()
+			.into_iter()
+			.map(|n| n.data.to_vec())
+			.collect();
+		proof.sort();
+		proof.dedup();
+		proof
+	}
+
+	// read the value of `key` from the trie with given root, recording all touched nodes.
+	fn read_recorded(&self, root: &TrieH256, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral::new(self.backend.backend_storage(), self.backend.overlay(), &mut read_overlay);
+
+		let map_e = |e: Box<TrieError>| eph.take_error().unwrap_or_else(|| format!("Trie lookup error: {}", e));
+
+		let mut proof_recorder = self.proof_recorder.borrow_mut();
+		TrieDB::new(&eph, root).map_err(map_e)?
+			.get_with(key, &mut *proof_recorder)
+			.map(|x| x.map(|val| val.to_vec()))
+			.map_err(map_e)
+	}
+}
+
+impl Backend for ProvingBackend {
+	type Error = String;
+
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		self.read_recorded(self.backend.root(), key)
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		match self.storage(storage_key)? {
+			Some(ref root) if root.len() == 32 => self.read_recorded(&TrieH256::from_slice(root), key),
+			Some(_) => Err(format!("Invalid child trie root under key {:?}", storage_key)),
+			None => Ok(None),
+		}
+	}
+
+	fn child_keys(&self, storage_key: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		self.backend.child_keys(storage_key)
+	}
+
+	// TODO: record the nodes touched while iterating over keys.
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		self.backend.keys_with_prefix(prefix)
+	}
+
+	fn next_key(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		self.backend.next_key(key)
+	}
+
+	fn commit<I>(&mut self, changes: I) -> Result<(), Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		self.backend.commit(changes)
+	}
+
+	fn commit_child<I>(&mut self, storage_key: Vec<u8>, changes: I) -> Result<(), Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		self.backend.commit_child(storage_key, changes)
+	}
+
+	fn pairs(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error> {
+		self.backend.pairs()
+	}
+
+	fn storage_root<I>(&self, delta: I) -> Result<[u8; 32], Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		self.backend.storage_root(delta)
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> Result<Option<[u8; 32]>, Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		self.backend.child_storage_root(storage_key, delta)
+	}
+}
+
+/// Create in-memory backend from the given proof, checking that it contains
+/// the node with given root.
+pub fn create_proof_check_backend(root: TrieH256, proof: Vec<Vec<u8>>) -> Result<TrieBackend, Box<Error>> {
+	let mut db = MemoryDB::new();
+	for item in proof {
+		db.insert(&item);
+	}
+
+	if !db.contains(&root) {
+		return Err(Box::new(ExecutionError::InvalidProof) as Box<Error>);
+	}
+
+	Ok(TrieBackend::with_memorydb(db, root))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn test_trie() -> TrieBackend {
+		TrieBackend::from_pairs((0..64).map(|i| (vec![i], vec![i; 4])))
+	}
+
+	#[test]
+	fn proof_is_empty_until_value_is_read() {
+		assert!(ProvingBackend::new(test_trie()).extract_proof().is_empty());
+	}
+
+	#[test]
+	fn proof_is_non_empty_after_value_is_read() {
+		let backend = ProvingBackend::new(test_trie());
+		assert_eq!(backend.storage(&[42]).unwrap(), Some(vec![42; 4]));
+		assert!(!backend.extract_proof().is_empty());
+	}
+
+	#[test]
+	fn proof_recorded_and_checked() {
+		let trie = test_trie();
+		let root = *trie.root();
+
+		let backend = ProvingBackend::new(trie);
+		assert_eq!(backend.storage(&[42]).unwrap(), Some(vec![42; 4]));
+		let proof = backend.extract_proof();
+
+		let checker = create_proof_check_backend(root, proof.clone()).unwrap();
+		assert_eq!(checker.storage(&[42]).unwrap(), Some(vec![42; 4]));
+		assert!(checker.storage(&[19]).is_err());
+
+		assert!(create_proof_check_backend(TrieH256::from(42), proof).is_err());
+	}
+}
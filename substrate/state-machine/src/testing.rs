@@ -17,28 +17,175 @@
 //! Test implementation for Externalities.
 
 use std::collections::HashMap;
-use super::Externalities;
+use std::iter::FromIterator;
+use std::ops::{Deref, DerefMut};
+use super::{Externalities, ExecutionError, is_child_storage_key};
 use triehash::trie_root;
 
 /// Simple HashMap based Externalities impl.
-pub type TestExternalities = HashMap<Vec<u8>, Vec<u8>>;
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TestExternalities {
+	inner: HashMap<Vec<u8>, Vec<u8>>,
+	children: HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>>,
+	// prior values of the keys changed within each open transaction, along with
+	// the storage key of the child trie they belong to, if any.
+	transactions: Vec<HashMap<(Option<Vec<u8>>, Vec<u8>), Option<Vec<u8>>>>,
+}
+
+impl TestExternalities {
+	/// Create a new instance of `TestExternalities` with empty storage.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Get the key/value pairs of all child tries, keyed by their storage key.
+	pub fn children(&self) -> &HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>> {
+		&self.children
+	}
+
+	fn write(&mut self, storage_key: Option<Vec<u8>>, key: Vec<u8>, maybe_value: Option<Vec<u8>>) {
+		let map = match storage_key {
+			Some(ref storage_key) => self.children.entry(storage_key.clone()).or_insert_with(HashMap::new),
+			None => &mut self.inner,
+		};
+
+		if let Some(transaction) = self.transactions.last_mut() {
+			let prior = map.get(&key).cloned();
+			transaction.entry((storage_key.clone(), key.clone())).or_insert(prior);
+		}
+
+		match maybe_value {
+			Some(value) => { map.insert(key, value); }
+			None => { map.remove(&key); }
+		}
+
+		if let Some(storage_key) = storage_key {
+			if self.children.get(&storage_key).map_or(false, |child| child.is_empty()) {
+				self.children.remove(&storage_key);
+			}
+		}
+	}
+}
+
+impl Deref for TestExternalities {
+	type Target = HashMap<Vec<u8>, Vec<u8>>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.inner
+	}
+}
+
+impl DerefMut for TestExternalities {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.inner
+	}
+}
+
+impl From<HashMap<Vec<u8>, Vec<u8>>> for TestExternalities {
+	fn from(inner: HashMap<Vec<u8>, Vec<u8>>) -> Self {
+		TestExternalities {
+			inner,
+			children: HashMap::new(),
+			transactions: Vec::new(),
+		}
+	}
+}
+
+impl FromIterator<(Vec<u8>, Vec<u8>)> for TestExternalities {
+	fn from_iter<I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>>(iter: I) -> Self {
+		TestExternalities::from(iter.into_iter().collect::<HashMap<_, _>>())
+	}
+}
+
+impl IntoIterator for TestExternalities {
+	type Item = (Vec<u8>, Vec<u8>);
+	type IntoIter = ::std::collections::hash_map::IntoIter<Vec<u8>, Vec<u8>>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.inner.into_iter()
+	}
+}
 
 impl Externalities for TestExternalities {
-	fn storage(&self, key: &[u8]) -> Option<&[u8]> {
-		self.get(key).map(AsRef::as_ref)
+	fn storage(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.get(key).cloned()
 	}
 
 	fn place_storage(&mut self, key: Vec<u8>, maybe_value: Option<Vec<u8>>) {
-		match maybe_value {
-			Some(value) => { self.insert(key, value); }
-			None => { self.remove(&key); }
+		if is_child_storage_key(&key) {
+			warn!(target: "state", "Refusing to directly set the root of a child storage trie");
+			return;
+		}
+
+		self.write(None, key, maybe_value);
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Option<Vec<u8>> {
+		self.children.get(storage_key).and_then(|child| child.get(key)).cloned()
+	}
+
+	fn place_child_storage(&mut self, storage_key: Vec<u8>, key: Vec<u8>, maybe_value: Option<Vec<u8>>) {
+		if !is_child_storage_key(&storage_key) {
+			warn!(target: "state", "Refusing to set child storage under a non-child storage key");
+			return;
+		}
+
+		self.write(Some(storage_key), key, maybe_value);
+	}
+
+	fn kill_child_storage(&mut self, storage_key: &[u8]) {
+		let keys: Vec<_> = self.children.get(storage_key)
+			.map(|child| child.keys().cloned().collect())
+			.unwrap_or_default();
+		for key in keys {
+			self.write(Some(storage_key.to_vec()), key, None);
 		}
 	}
 
+	fn child_storage_root(&self, storage_key: &[u8]) -> Option<[u8; 32]> {
+		self.children.get(storage_key).map(|child| trie_root(child.clone()).0)
+	}
+
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Vec<u8>> {
+		let mut keys: Vec<_> = self.inner.keys().filter(|k| k.starts_with(prefix)).cloned().collect();
+		keys.sort();
+		keys
+	}
+
+	fn next_key(&self, key: &[u8]) -> Option<Vec<u8>> {
+		self.inner.keys().filter(|k| &k[..] > key).min().cloned()
+	}
+
 	fn chain_id(&self) -> u64 { 42 }
 
 	fn storage_root(&self) -> [u8; 32] {
-		trie_root(self.clone()).0
+		let child_roots = self.children.iter()
+			.map(|(storage_key, child)| (storage_key.clone(), trie_root(child.clone()).0.to_vec()));
+		trie_root(self.inner.clone().into_iter().chain(child_roots)).0
+	}
+
+	fn start_transaction(&mut self) {
+		self.transactions.push(HashMap::new());
+	}
+
+	fn commit_transaction(&mut self) -> Result<(), ExecutionError> {
+		let committed = self.transactions.pop().ok_or(ExecutionError::NoOpenTransaction)?;
+		// the enclosing transaction must still be able to restore the values
+		// from before the committed one.
+		if let Some(parent) = self.transactions.last_mut() {
+			for (key, prior) in committed {
+				parent.entry(key).or_insert(prior);
+			}
+		}
+		Ok(())
+	}
+
+	fn rollback_transaction(&mut self) -> Result<(), ExecutionError> {
+		let transaction = self.transactions.pop().ok_or(ExecutionError::NoOpenTransaction)?;
+		for ((storage_key, key), prior) in transaction {
+			self.write(storage_key, key, prior);
+		}
+		Ok(())
 	}
 }
 
@@ -55,4 +202,47 @@ mod tests {
 		const ROOT: [u8; 32] = hex!("8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3");
 		assert_eq!(ext.storage_root(), ROOT);
 	}
+
+	#[test]
+	fn nested_transactions_should_work() {
+		let mut ext = TestExternalities::new();
+		ext.set_storage(b"doe".to_vec(), b"reindeer".to_vec());
+
+		ext.start_transaction();
+		ext.set_storage(b"doe".to_vec(), b"deer".to_vec());
+		ext.start_transaction();
+		ext.set_storage(b"dog".to_vec(), b"puppy".to_vec());
+		ext.clear_storage(b"doe");
+		ext.commit_transaction().unwrap();
+		assert_eq!(ext.storage(b"doe"), None);
+		assert_eq!(ext.storage(b"dog"), Some(b"puppy".to_vec()));
+
+		ext.rollback_transaction().unwrap();
+		assert_eq!(ext.storage(b"doe"), Some(b"reindeer".to_vec()));
+		assert_eq!(ext.storage(b"dog"), None);
+		assert!(ext.rollback_transaction().is_err());
+	}
+
+	#[test]
+	fn child_storage_should_work() {
+		let child = b":child_storage:default:1".to_vec();
+		let mut ext = TestExternalities::new();
+		ext.set_storage(b"doe".to_vec(), b"reindeer".to_vec());
+		let root = ext.storage_root();
+
+		ext.set_child_storage(child.clone(), b"dog".to_vec(), b"puppy".to_vec());
+		assert_eq!(ext.child_storage(&child, b"dog"), Some(b"puppy".to_vec()));
+		assert!(ext.child_storage_root(&child).is_some());
+		assert!(ext.storage_root() != root);
+
+		ext.start_transaction();
+		ext.kill_child_storage(&child);
+		assert_eq!(ext.child_storage(&child, b"dog"), None);
+		assert_eq!(ext.storage_root(), root);
+		ext.rollback_transaction().unwrap();
+		assert_eq!(ext.child_storage(&child, b"dog"), Some(b"puppy".to_vec()));
+
+		ext.set_child_storage(b"dogs".to_vec(), b"dog".to_vec(), b"puppy".to_vec());
+		assert_eq!(ext.child_storage(b"dogs", b"dog"), None);
+	}
 }
@@ -0,0 +1,544 @@
+// Copyright 2017 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Trie-based state machine backend.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use ethcore_bigint::hash::H256 as TrieH256;
+use hashdb::{HashDB, DBValue};
+use memorydb::MemoryDB;
+use patricia_trie::{TrieDB, TrieDBMut, TrieError, Trie, TrieMut, TrieIterator};
+use backend::{Backend, TryIntoTrieBackend};
+
+/// Backend trie storage trait. Gives read access to trie nodes that have been
+/// persisted elsewhere (e.g. in the client database).
+pub trait Storage: Send + Sync {
+	/// Get a trie node.
+	fn get(&self, key: &TrieH256) -> Result<Option<DBValue>, String>;
+}
+
+/// Trie nodes storage of the backend.
+#[derive(Clone)]
+pub enum TrieBackendStorage {
+	/// Nodes are read from external storage.
+	Storage(Arc<Storage>),
+	/// All nodes are kept in memory.
+	MemoryDb(MemoryDB),
+}
+
+impl TrieBackendStorage {
+	/// Get a trie node.
+	pub fn get(&self, key: &TrieH256) -> Result<Option<DBValue>, String> {
+		match *self {
+			TrieBackendStorage::Storage(ref db) => db.get(key)
+				.map_err(|e| format!("Trie lookup error: {}", e)),
+			TrieBackendStorage::MemoryDb(ref db) => Ok(db.get(key)),
+		}
+	}
+}
+
+/// Patricia trie-based backend. Nodes created by `commit` are kept in an
+/// in-memory overlay on top of the backend storage, and the root is updated
+/// incrementally.
+#[derive(Clone)]
+pub struct TrieBackend {
+	storage: TrieBackendStorage,
+	overlay: MemoryDB,
+	root: TrieH256,
+}
+
+impl TrieBackend {
+	/// Create new trie-based backend on top of existing storage.
+	pub fn with_storage(db: Arc<Storage>, root: TrieH256) -> Self {
+		TrieBackend {
+			storage: TrieBackendStorage::Storage(db),
+			overlay: MemoryDB::default(),
+			root,
+		}
+	}
+
+	/// Create new trie-based backend with an empty trie on top of existing storage.
+	pub fn with_storage_for_genesis(db: Arc<Storage>) -> Self {
+		let mut overlay = MemoryDB::default();
+		let mut root = TrieH256::default();
+		TrieDBMut::new(&mut overlay, &mut root);
+
+		TrieBackend {
+			storage: TrieBackendStorage::Storage(db),
+			overlay,
+			root,
+		}
+	}
+
+	/// Create new trie-based backend backed by MemoryDb storage.
+	pub fn with_memorydb(db: MemoryDB, root: TrieH256) -> Self {
+		TrieBackend {
+			storage: TrieBackendStorage::MemoryDb(db),
+			overlay: MemoryDB::default(),
+			root,
+		}
+	}
+
+	/// Create new trie-based backend containing given key/value pairs.
+	pub fn from_pairs<I>(pairs: I) -> Self
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+	{
+		Self::from_pairs_with_children(pairs, HashMap::new())
+	}
+
+	/// Create new trie-based backend containing given key/value pairs and child tries.
+	/// The roots of the child tries must already be present in the top-level pairs.
+	pub fn from_pairs_with_children<I>(pairs: I, children: HashMap<Vec<u8>, HashMap<Vec<u8>, Vec<u8>>>) -> Self
+		where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+	{
+		let mut db = MemoryDB::default();
+		for (_, child) in children {
+			let mut child_root = TrieH256::default();
+			insert_pairs(&mut db, &mut child_root, child);
+		}
+
+		let mut root = TrieH256::default();
+		insert_pairs(&mut db, &mut root, pairs);
+		TrieBackend::with_memorydb(db, root)
+	}
+
+	/// Get backend storage reference.
+	pub fn backend_storage(&self) -> &TrieBackendStorage {
+		&self.storage
+	}
+
+	/// Get trie root.
+	pub fn root(&self) -> &TrieH256 {
+		&self.root
+	}
+
+	/// Get the trie nodes that were created or removed by `commit` and not yet
+	/// persisted to the underlying storage.
+	pub fn overlay(&self) -> &MemoryDB {
+		&self.overlay
+	}
+
+	/// Take the trie nodes that were created or removed by `commit` since the
+	/// backend was created. Nodes with positive reference count should be
+	/// inserted into the underlying storage.
+	pub fn drain_overlay(&mut self) -> MemoryDB {
+		::std::mem::replace(&mut self.overlay, MemoryDB::default())
+	}
+
+	// get the root of the child trie placed under `storage_key`, if any.
+	fn child_root(&self, storage_key: &[u8]) -> Result<Option<TrieH256>, String> {
+		match self.storage(storage_key)? {
+			Some(ref root) if root.len() == 32 => Ok(Some(TrieH256::from_slice(root))),
+			Some(_) => Err(format!("Invalid child trie root under key {:?}", storage_key)),
+			None => Ok(None),
+		}
+	}
+
+	// call `f` for each key of the trie with given root, starting from the given one
+	// in lexicographic order, until it returns false.
+	fn for_keys_from<F: FnMut(&[u8]) -> bool>(&self, root: &TrieH256, from: &[u8], mut f: F) -> Result<(), String> {
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral::new(&self.storage, &self.overlay, &mut read_overlay);
+
+		let map_e = |e: Box<TrieError>| eph.take_error().unwrap_or_else(|| format!("Trie lookup error: {}", e));
+
+		let trie = TrieDB::new(&eph, root).map_err(map_e)?;
+		let mut iter = trie.iter().map_err(map_e)?;
+		iter.seek(from).map_err(map_e)?;
+		for item in iter {
+			let (key, _) = item.map_err(map_e)?;
+			if !f(&key) {
+				break;
+			}
+		}
+		Ok(())
+	}
+
+	// apply changes to the trie with given root. nodes are read from the storage and
+	// `committed`, created nodes go to `overlay`.
+	fn apply<I>(&self, committed: &MemoryDB, overlay: &mut MemoryDB, root: &mut TrieH256, changes: I) -> Result<(), String>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut eph = Ephemeral::new(&self.storage, committed, overlay);
+
+		let result = {
+			let apply_all = || -> Result<(), String> {
+				let mut trie = TrieDBMut::from_existing(&mut eph, root)
+					.map_err(|e| format!("Failed to open trie at prior state root: {}", e))?;
+
+				for (key, change) in changes {
+					match change {
+						Some(val) => trie.insert(&key, &val).map(|_| ()),
+						None => trie.remove(&key).map(|_| ()),
+					}.map_err(|e| format!("Failed to write to trie: {}", e))?;
+				}
+				Ok(())
+			};
+			apply_all()
+		};
+		result.map_err(|e| eph.take_error().unwrap_or(e))
+	}
+
+	// apply changes to the child trie placed under `storage_key`. returns the new
+	// child root, or None if the child trie has become empty.
+	fn apply_child<I>(&self, committed: &MemoryDB, overlay: &mut MemoryDB, storage_key: &[u8], changes: I) -> Result<Option<TrieH256>, String>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut root = match self.child_root(storage_key)? {
+			Some(root) => root,
+			None => {
+				let mut root = TrieH256::default();
+				let mut eph = Ephemeral::new(&self.storage, committed, overlay);
+				TrieDBMut::new(&mut eph, &mut root);
+				root
+			},
+		};
+
+		self.apply(committed, overlay, &mut root, changes)?;
+		match root == empty_trie_root() {
+			true => Ok(None),
+			false => Ok(Some(root)),
+		}
+	}
+}
+
+fn insert_pairs<I>(db: &mut MemoryDB, root: &mut TrieH256, pairs: I)
+	where I: IntoIterator<Item=(Vec<u8>, Vec<u8>)>
+{
+	let mut trie = TrieDBMut::new(db, root);
+	for (key, value) in pairs {
+		if let Err(e) = trie.insert(&key, &value) {
+			warn!(target: "trie", "Failed to write to trie: {}", e);
+		}
+	}
+}
+
+fn empty_trie_root() -> TrieH256 {
+	let mut db = MemoryDB::default();
+	let mut root = TrieH256::default();
+	TrieDBMut::new(&mut db, &mut root);
+	root
+}
+
+impl Backend for TrieBackend {
+	type Error = String;
+
+	fn storage(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral::new(&self.storage, &self.overlay, &mut read_overlay);
+
+		let map_e = |e: Box<TrieError>| eph.take_error().unwrap_or_else(|| format!("Trie lookup error: {}", e));
+
+		TrieDB::new(&eph, &self.root).map_err(map_e)?
+			.get(key).map(|x| x.map(|val| val.to_vec())).map_err(map_e)
+	}
+
+	fn child_storage(&self, storage_key: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		let root = match self.child_root(storage_key)? {
+			Some(root) => root,
+			None => return Ok(None),
+		};
+
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral::new(&self.storage, &self.overlay, &mut read_overlay);
+
+		let map_e = |e: Box<TrieError>| eph.take_error().unwrap_or_else(|| format!("Trie lookup error: {}", e));
+
+		TrieDB::new(&eph, &root).map_err(map_e)?
+			.get(key).map(|x| x.map(|val| val.to_vec())).map_err(map_e)
+	}
+
+	fn child_keys(&self, storage_key: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		let mut keys = Vec::new();
+		if let Some(root) = self.child_root(storage_key)? {
+			self.for_keys_from(&root, &[], |key| {
+				keys.push(key.to_vec());
+				true
+			})?;
+		}
+		Ok(keys)
+	}
+
+	fn keys_with_prefix(&self, prefix: &[u8]) -> Result<Vec<Vec<u8>>, Self::Error> {
+		let mut keys = Vec::new();
+		self.for_keys_from(&self.root, prefix, |key| {
+			if !key.starts_with(prefix) {
+				return false;
+			}
+			keys.push(key.to_vec());
+			true
+		})?;
+		Ok(keys)
+	}
+
+	fn next_key(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Self::Error> {
+		let mut next = None;
+		self.for_keys_from(&self.root, key, |k| {
+			if k == key {
+				return true;
+			}
+			next = Some(k.to_vec());
+			false
+		})?;
+		Ok(next)
+	}
+
+	fn commit<I>(&mut self, changes: I) -> Result<(), Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		// new nodes are collected apart, so the backend is left untouched on failure.
+		let mut delta = MemoryDB::default();
+		let mut root = self.root;
+		self.apply(&self.overlay, &mut delta, &mut root, changes)?;
+		self.overlay.consolidate(delta);
+		self.root = root;
+		Ok(())
+	}
+
+	fn commit_child<I>(&mut self, storage_key: Vec<u8>, changes: I) -> Result<(), Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut delta = MemoryDB::default();
+		let mut root = self.root;
+		let child_root = self.apply_child(&self.overlay, &mut delta, &storage_key, changes)?;
+		self.apply(&self.overlay, &mut delta, &mut root, ::std::iter::once((storage_key, child_root.map(|r| r.to_vec()))))?;
+		self.overlay.consolidate(delta);
+		self.root = root;
+		Ok(())
+	}
+
+	fn pairs(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Self::Error> {
+		let mut read_overlay = MemoryDB::default();
+		let eph = Ephemeral::new(&self.storage, &self.overlay, &mut read_overlay);
+
+		let collect_all = || -> Result<_, Box<TrieError>> {
+			let trie = TrieDB::new(&eph, &self.root)?;
+			let mut v = Vec::new();
+			for x in trie.iter()? {
+				let (key, value) = x?;
+				v.push((key.to_vec(), value.to_vec()));
+			}
+
+			Ok(v)
+		};
+
+		collect_all().map_err(|e| eph.take_error().unwrap_or_else(|| format!("Error extracting trie values: {}", e)))
+	}
+
+	fn storage_root<I>(&self, delta: I) -> Result<[u8; 32], Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut overlay = MemoryDB::default();
+		let mut root = self.root;
+		self.apply(&self.overlay, &mut overlay, &mut root, delta)?;
+		Ok(root.0)
+	}
+
+	fn child_storage_root<I>(&self, storage_key: &[u8], delta: I) -> Result<Option<[u8; 32]>, Self::Error>
+		where I: IntoIterator<Item=(Vec<u8>, Option<Vec<u8>>)>
+	{
+		let mut overlay = MemoryDB::default();
+		let root = self.apply_child(&self.overlay, &mut overlay, storage_key, delta)?;
+		Ok(root.map(|r| r.0))
+	}
+}
+
+impl TryIntoTrieBackend for TrieBackend {
+	fn try_into_trie_backend(self) -> Option<TrieBackend> {
+		Some(self)
+	}
+}
+
+impl PartialEq for TrieBackend {
+	fn eq(&self, other: &TrieBackend) -> bool {
+		self.root == other.root
+	}
+}
+
+impl Eq for TrieBackend { }
+
+/// Read-write `HashDB` view over a backend storage and nodes that have already
+/// been committed on top of it. All writes go to a separate in-memory overlay.
+pub struct Ephemeral<'a> {
+	storage: &'a TrieBackendStorage,
+	committed: &'a MemoryDB,
+	overlay: &'a mut MemoryDB,
+	error: Mutex<Option<String>>,
+}
+
+impl<'a> Ephemeral<'a> {
+	/// Create new ephemeral view.
+	pub fn new(storage: &'a TrieBackendStorage, committed: &'a MemoryDB, overlay: &'a mut MemoryDB) -> Self {
+		Ephemeral {
+			storage,
+			committed,
+			overlay,
+			error: Mutex::new(None),
+		}
+	}
+
+	/// Take the first error returned by the backend storage, if any. The trie sees
+	/// a failed read as a missing node, this gives the actual cause.
+	pub fn take_error(&self) -> Option<String> {
+		self.error.lock().ok().and_then(|mut error| error.take())
+	}
+}
+
+impl<'a> HashDB for Ephemeral<'a> {
+	fn keys(&self) -> HashMap<TrieH256, i32> {
+		self.overlay.keys() // TODO: iterate backing
+	}
+
+	fn get(&self, key: &TrieH256) -> Option<DBValue> {
+		// nodes are addressed by their hash, so a node which is referenced anywhere
+		// has the same value everywhere. removals only matter when persisting.
+		if let Some((val, i)) = self.overlay.raw(key) {
+			if i > 0 {
+				return Some(val);
+			}
+		}
+		if let Some((val, i)) = self.committed.raw(key) {
+			if i > 0 {
+				return Some(val);
+			}
+		}
+
+		match self.storage.get(key) {
+			Ok(x) => x,
+			Err(e) => {
+				if let Ok(mut error) = self.error.lock() {
+					if error.is_none() {
+						*error = Some(e);
+					}
+				}
+				None
+			},
+		}
+	}
+
+	fn contains(&self, key: &TrieH256) -> bool {
+		self.get(key).is_some()
+	}
+
+	fn insert(&mut self, value: &[u8]) -> TrieH256 {
+		self.overlay.insert(value)
+	}
+
+	fn emplace(&mut self, key: TrieH256, value: DBValue) {
+		self.overlay.emplace(key, value)
+	}
+
+	fn remove(&mut self, key: &TrieH256) {
+		self.overlay.remove(key)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use backend::InMemory;
+
+	fn test_pairs() -> Vec<(Vec<u8>, Vec<u8>)> {
+		vec![
+			(b"doe".to_vec(), b"reindeer".to_vec()),
+			(b"dog".to_vec(), b"puppyXXX".to_vec()),
+			(b"dogglesworth".to_vec(), b"catXXX".to_vec()),
+			(b"doug".to_vec(), b"notadog".to_vec()),
+		]
+	}
+
+	#[test]
+	fn read_from_storage_returns_some() {
+		let backend = TrieBackend::from_pairs(test_pairs());
+		assert_eq!(backend.storage(b"dog").unwrap(), Some(b"puppyXXX".to_vec()));
+		assert_eq!(backend.storage(b"cat").unwrap(), None);
+	}
+
+	#[test]
+	fn pairs_are_not_empty_on_non_empty_storage() {
+		let mut pairs = TrieBackend::from_pairs(test_pairs()).pairs().unwrap();
+		pairs.sort();
+		assert_eq!(pairs, test_pairs());
+	}
+
+	#[test]
+	fn root_matches_in_memory_backend() {
+		let in_memory: InMemory = test_pairs().into_iter().collect();
+		let trie = TrieBackend::from_pairs(test_pairs());
+		let delta = vec![
+			(b"dog".to_vec(), Some(b"puppy".to_vec())),
+			(b"doug".to_vec(), None),
+			(b"horse".to_vec(), Some(b"stallion".to_vec())),
+		];
+
+		assert_eq!(trie.storage_root(vec![]).unwrap(), in_memory.storage_root(vec![]).unwrap());
+		assert_eq!(trie.storage_root(delta.clone()).unwrap(), in_memory.storage_root(delta).unwrap());
+	}
+
+	#[test]
+	fn keys_are_enumerated_in_order() {
+		let backend = TrieBackend::from_pairs(test_pairs());
+		assert_eq!(backend.keys_with_prefix(b"dog").unwrap(), vec![b"dog".to_vec(), b"dogglesworth".to_vec()]);
+		assert!(backend.keys_with_prefix(b"cat").unwrap().is_empty());
+
+		assert_eq!(backend.next_key(b"").unwrap(), Some(b"doe".to_vec()));
+		assert_eq!(backend.next_key(b"dog").unwrap(), Some(b"dogglesworth".to_vec()));
+		assert_eq!(backend.next_key(b"dogz").unwrap(), Some(b"doug".to_vec()));
+		assert_eq!(backend.next_key(b"doug").unwrap(), None);
+	}
+
+	#[test]
+	fn commit_updates_root_incrementally() {
+		let mut trie = TrieBackend::from_pairs(test_pairs());
+		let delta = vec![
+			(b"dog".to_vec(), Some(b"puppy".to_vec())),
+			(b"doug".to_vec(), None),
+		];
+		let expected_root = trie.storage_root(delta.clone()).unwrap();
+
+		trie.commit(delta).unwrap();
+		assert_eq!(trie.root().0, expected_root);
+		assert_eq!(trie.storage(b"dog").unwrap(), Some(b"puppy".to_vec()));
+		assert_eq!(trie.storage(b"doug").unwrap(), None);
+		assert_eq!(trie.storage(b"doe").unwrap(), Some(b"reindeer".to_vec()));
+	}
+
+	#[test]
+	fn missing_trie_nodes_are_reported() {
+		let mut trie = TrieBackend::with_memorydb(MemoryDB::default(), TrieH256::from(1));
+		let delta = vec![(b"dog".to_vec(), Some(b"puppy".to_vec()))];
+
+		assert!(trie.storage_root(delta.clone()).is_err());
+		assert!(trie.commit(delta).is_err());
+		assert_eq!(trie.root(), &TrieH256::from(1));
+	}
+
+	#[test]
+	fn storage_errors_are_returned() {
+		struct FailingStorage;
+		impl Storage for FailingStorage {
+			fn get(&self, _key: &TrieH256) -> Result<Option<DBValue>, String> {
+				Err("Storage failure".into())
+			}
+		}
+
+		let mut trie = TrieBackend::with_storage(Arc::new(FailingStorage), TrieH256::from(1));
+		assert_eq!(trie.storage(b"dog"), Err("Storage failure".to_string()));
+		assert_eq!(trie.pairs(), Err("Storage failure".to_string()));
+		assert_eq!(trie.commit(vec![(b"dog".to_vec(), None)]), Err("Storage failure".to_string()));
+	}
+}